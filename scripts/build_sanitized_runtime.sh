@@ -0,0 +1,27 @@
+#!/usr/bin/env bash
+set -euo pipefail
+
+# Builds libkorlang_rt_address.a and libkorlang_rt_thread.a, the runtime
+# instrumented for `korlang build --sanitize=address|thread`, and installs
+# them next to libkorlang_rt.a (default: the workspace's target/debug).
+# Needs a nightly toolchain for -Zsanitizer.
+
+ROOT="$(cd "$(dirname "${BASH_SOURCE[0]}")/.." && pwd)"
+DEST="${1:-$ROOT/target/debug}"
+TARGET="$(rustc -vV | sed -n 's/^host: //p')"
+
+mkdir -p "$DEST"
+cd "$ROOT"
+for san in address thread; do
+  # The standard library is not rebuilt with the sanitizer.
+  flags="-Zsanitizer=$san -Cunsafe-allow-abi-mismatch=sanitizer"
+  if [[ "$san" == thread ]]; then
+    # __tsan_memcpy and friends are missing from older libtsan (GCC 12);
+    # the interceptors still see these calls.
+    flags="$flags -Cllvm-args=-tsan-instrument-memintrinsics=0"
+  fi
+  RUSTFLAGS="$flags" cargo +nightly build -p korlang-runtime \
+    --target "$TARGET" --target-dir "$ROOT/target/sanitize-$san"
+  cp "$ROOT/target/sanitize-$san/$TARGET/debug/libkorlang_rt.a" "$DEST/libkorlang_rt_$san.a"
+  echo "Installed $DEST/libkorlang_rt_$san.a"
+done
@@ -17,6 +17,20 @@ pub enum Item {
     Stmt(Stmt),
     Interface(InterfaceDecl),
     Sealed(SealedDecl),
+    Extern(ExternDecl),
+}
+
+/// `extern "C" fun name(params) -> Ret;`, a function defined outside Korlang
+/// and called through the platform C ABI. A trailing `...` in the parameter
+/// list accepts further arguments, as in `printf`.
+#[derive(Debug, Clone)]
+pub struct ExternDecl {
+    pub abi: String,
+    pub name: String,
+    pub params: Vec<Param>,
+    pub variadic: bool,
+    pub ret: Option<TypeRef>,
+    pub span: Span,
 }
 
 #[derive(Debug, Clone)]
@@ -40,6 +54,8 @@ pub struct FunSig {
     pub params: Vec<Param>,
     pub ret: Option<TypeRef>,
     pub is_async: bool,
+    /// Default implementation used by structs that do not provide their own.
+    pub body: Option<Block>,
     pub span: Span,
 }
 
@@ -54,6 +70,7 @@ pub struct SealedDecl {
 #[derive(Debug, Clone)]
 pub struct FunDecl {
     pub receiver: Option<TypeRef>,
+    pub self_param: SelfParam,
     pub name: String,
     pub generic_params: Vec<GenericParam>,
     pub params: Vec<Param>,
@@ -61,9 +78,21 @@ pub struct FunDecl {
     pub body: Block,
     pub nogc: bool,
     pub is_async: bool,
+    /// `@export`: emitted under its own name as a C symbol.
+    pub export: bool,
     pub span: Span,
 }
 
+/// How a method receives `self`. Extension functions (`fun T.name()`) take it
+/// implicitly, even when they spell it out; methods in an `impl` block take
+/// `self` or `mut self`, and only the latter may assign through it.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum SelfParam {
+    Implicit,
+    Value,
+    Mut,
+}
+
 #[derive(Debug, Clone)]
 pub struct Param {
     pub name: String,
@@ -152,6 +181,8 @@ pub struct ResourceEntry {
 pub struct VarDecl {
     pub mutable: bool,
     pub name: String,
+    /// `let (a, b) = ..` binds through a pattern; `name` is then empty.
+    pub pattern: Option<Pattern>,
     pub ty: Option<TypeRef>,
     pub value: Expr,
     pub span: Span,
@@ -162,12 +193,15 @@ pub enum Stmt {
     Var(VarDecl),
     Expr(Expr, Span),
     Return(Option<Expr>, Span),
-    Break(Span),
-    Continue(Span),
+    /// `break`, `break outer` or `break value`; a value may only leave a `loop`.
+    Break(Option<String>, Option<Expr>, Span),
+    Continue(Option<String>, Span),
     If(Expr, Block, Option<Box<Stmt>>, Span),
-    While(Expr, Block, Span),
-    For(String, Expr, Block, Span),
+    While(Expr, Block, Option<String>, Span),
+    For(String, Expr, Block, Option<String>, Span),
     Match(Expr, Vec<MatchArm>, Span),
+    /// `defer { .. }` runs when the enclosing block is left, by any path.
+    Defer(Block, Span),
     Block(Block),
 }
 
@@ -189,6 +223,7 @@ pub struct MatchArm {
 pub enum Expr {
     Literal(Literal, Span),
     Ident(String, Span),
+    Instantiate { name: String, type_args: Vec<TypeRef>, span: Span },
     StructLit { name: String, fields: Vec<(String, Expr)>, span: Span },
     Unary { op: UnaryOp, expr: Box<Expr>, span: Span },
     Binary { left: Box<Expr>, op: BinaryOp, right: Box<Expr>, span: Span },
@@ -196,10 +231,17 @@ pub enum Expr {
     Call { callee: Box<Expr>, args: Vec<Expr>, span: Span },
     Member { target: Box<Expr>, name: String, span: Span },
     Index { target: Box<Expr>, index: Box<Expr>, span: Span },
+    /// `start..end`, or `start..=end` when `inclusive`.
+    Range { start: Box<Expr>, end: Box<Expr>, inclusive: bool, span: Span },
+    /// `expr as T`, an explicit numeric conversion.
+    Cast { expr: Box<Expr>, ty: TypeRef, span: Span },
     If { cond: Box<Expr>, then_block: Block, else_block: Block, span: Span },
     Match { expr: Box<Expr>, arms: Vec<MatchArm>, span: Span },
+    /// `loop { .. }` repeats until a `break`, whose value it takes.
+    Loop { label: Option<String>, body: Block, span: Span },
     Block(Block),
     Array(Vec<Expr>, Span),
+    Tuple(Vec<Expr>, Span),
     Tensor(Vec<Vec<Expr>>, Span),
     Interpolated { parts: Vec<Expr>, span: Span },
 }
@@ -272,6 +314,7 @@ pub enum TypeRef {
     Named(String, Vec<TypeRef>, Span),
     Tuple(Vec<TypeRef>, Span),
     Array(Box<TypeRef>, Span),
+    FixedArray { elem: Box<TypeRef>, len: Box<Expr>, span: Span },
     Tensor { elem: Box<TypeRef>, shape: Vec<ShapeDim>, span: Span },
     Optional(Box<TypeRef>, Span),
     NonNull(Box<TypeRef>, Span),
@@ -281,5 +324,6 @@ pub enum TypeRef {
 pub enum ShapeDim {
     Int(i64),
     Ident(String),
+    Expr(Expr),
     Unknown,
 }
@@ -0,0 +1,318 @@
+use std::collections::HashMap;
+
+use inkwell::module::{Linkage, Module};
+use inkwell::types::BasicTypeEnum;
+use inkwell::values::{BasicValueEnum, FunctionValue, GlobalValue, PointerValue};
+use inkwell::AddressSpace;
+
+use crate::ast::Block;
+use crate::coverage::{Anchor, FunctionCoverage, SourceMap};
+
+/// Coverage mapping format version 6 (stored as 5): the first filename is
+/// the compilation directory. Readable by `llvm-cov` 13 and later.
+const COVMAP_VERSION: u32 = 5;
+
+const GAP_REGION_BIT: u64 = 1 << 31;
+
+/// The counters of each instrumented function, and the LLVM coverage
+/// mapping that ties them to source regions for `llvm-cov`.
+///
+/// Counters are plain `[N x i64]` globals. A module constructor hands them
+/// to `korlang_coverage_register`, and the runtime writes them at exit in
+/// the text format `llvm-profdata merge` reads, under the same function
+/// names and hashes as the mapping records.
+pub struct Coverage<'ctx> {
+    map: SourceMap,
+    functions: Vec<CoveredFunction<'ctx>>,
+    anchors: HashMap<Anchor, u32>,
+    counters: Option<GlobalValue<'ctx>>,
+}
+
+struct CoveredFunction<'ctx> {
+    name: String,
+    coverage: FunctionCoverage,
+    counters: GlobalValue<'ctx>,
+}
+
+impl<'ctx> Coverage<'ctx> {
+    pub fn new(map: SourceMap) -> Self {
+        Self { map, functions: Vec::new(), anchors: HashMap::new(), counters: None }
+    }
+
+    /// Allocates the counters of `func`, whose body is `body`.
+    pub fn begin_function(&mut self, module: &Module<'ctx>, func: FunctionValue<'ctx>, body: &Block) {
+        let coverage = FunctionCoverage::of(body);
+        let name = func.get_name().to_string_lossy().to_string();
+        let ty = module.get_context().i64_type().array_type(coverage.counters());
+        let counters = module.add_global(ty, None, &format!("__korlang_cov.{}", name));
+        counters.set_initializer(&ty.const_zero());
+        counters.set_linkage(Linkage::Private);
+        self.anchors = coverage.anchors.clone();
+        self.counters = Some(counters);
+        self.functions.push(CoveredFunction { name, coverage, counters });
+    }
+
+    /// The counter array of the current function and the index counting
+    /// `anchor`, if one does.
+    pub fn counter(&self, anchor: Anchor) -> Option<(GlobalValue<'ctx>, u32)> {
+        Some((self.counters?, *self.anchors.get(&anchor)?))
+    }
+
+    pub fn emit(self, module: &Module<'ctx>) {
+        let context = module.get_context();
+        let (i8t, i32t, i64t) = (context.i8_type(), context.i32_type(), context.i64_type());
+        let i8p = i8t.ptr_type(AddressSpace::default());
+        let comp_dir = std::env::current_dir().map(|d| d.display().to_string()).unwrap_or_default();
+
+        let mut names = Vec::new();
+        let mut filenames = vec![comp_dir];
+        filenames.extend(self.map.files.iter().cloned());
+        let filenames = encode_filenames(&filenames);
+        let filenames_ref = md5_low(&filenames);
+        let mut used = Vec::new();
+        for f in &self.functions {
+            let Some(data) = encode_function(&f.coverage, &self.map) else { continue };
+            let name_ref = md5_low(f.name.as_bytes());
+            names.push(f.name.as_str());
+            let record = context.const_struct(
+                &[
+                    i64t.const_int(name_ref, false).into(),
+                    i32t.const_int(data.len() as u64, false).into(),
+                    i64t.const_int(f.coverage.hash(), false).into(),
+                    i64t.const_int(filenames_ref, false).into(),
+                    bytes(module, &data),
+                ],
+                true,
+            );
+            used.push(section_global(module, record.into(), &format!("__covrec_{:X}", name_ref), "__llvm_covfun", 8));
+        }
+        if names.is_empty() {
+            return;
+        }
+
+        let header = context.const_struct(
+            &[
+                i32t.const_zero().into(),
+                i32t.const_int(filenames.len() as u64, false).into(),
+                i32t.const_zero().into(),
+                i32t.const_int(COVMAP_VERSION as u64, false).into(),
+            ],
+            false,
+        );
+        let covmap = context.const_struct(&[header.into(), bytes(module, &filenames)], false);
+        used.push(section_global(module, covmap.into(), "__llvm_coverage_mapping", "__llvm_covmap", 8));
+
+        // `llvm-cov` finds the function names for the records' name hashes here.
+        let joined = names.join("\u{1}");
+        let mut prf_names = Vec::new();
+        uleb(&mut prf_names, joined.len() as u64);
+        uleb(&mut prf_names, 0);
+        prf_names.extend_from_slice(joined.as_bytes());
+        used.push(section_global(module, bytes(module, &prf_names), "__llvm_prf_nm", "__llvm_prf_names", 1));
+
+        let used_values: Vec<PointerValue> = used.iter().map(|g| g.as_pointer_value().const_cast(i8p)).collect();
+        let used_ty = i8p.array_type(used_values.len() as u32);
+        let llvm_used = module.add_global(used_ty, None, "llvm.used");
+        llvm_used.set_initializer(&i8p.const_array(&used_values));
+        llvm_used.set_linkage(Linkage::Appending);
+        llvm_used.set_section(Some("llvm.metadata"));
+
+        self.emit_registration(module);
+    }
+
+    /// A constructor passing `{ name, hash, counters, counts }` for every
+    /// function to the runtime.
+    fn emit_registration(&self, module: &Module<'ctx>) {
+        let context = module.get_context();
+        let (i64t, i8p) = (context.i64_type(), context.i8_type().ptr_type(AddressSpace::default()));
+        let i64p = i64t.ptr_type(AddressSpace::default());
+        let entry_ty = context.struct_type(&[i8p.into(), i64t.into(), i64t.into(), i64p.into()], false);
+        let entries: Vec<_> = self
+            .functions
+            .iter()
+            .map(|f| {
+                let name = context.const_string(f.name.as_bytes(), true);
+                let name = section_global(module, name.into(), "__korlang_cov.name", "", 1);
+                entry_ty.const_named_struct(&[
+                    name.as_pointer_value().const_cast(i8p).into(),
+                    i64t.const_int(f.coverage.hash(), false).into(),
+                    i64t.const_int(f.coverage.counters() as u64, false).into(),
+                    f.counters.as_pointer_value().const_cast(i64p).into(),
+                ])
+            })
+            .collect();
+        let table = module.add_global(entry_ty.array_type(entries.len() as u32), None, "__korlang_cov.table");
+        table.set_initializer(&entry_ty.const_array(&entries));
+        table.set_constant(true);
+        table.set_linkage(Linkage::Private);
+
+        let register = module.get_function("korlang_coverage_register").unwrap_or_else(|| {
+            let ty = context.void_type().fn_type(&[i8p.into(), i64t.into()], false);
+            module.add_function("korlang_coverage_register", ty, Some(Linkage::External))
+        });
+        let ctor = module.add_function("korlang.coverage.init", context.void_type().fn_type(&[], false), Some(Linkage::Internal));
+        let builder = context.create_builder();
+        builder.position_at_end(context.append_basic_block(ctor, "entry"));
+        let args = [table.as_pointer_value().const_cast(i8p).into(), i64t.const_int(entries.len() as u64, false).into()];
+        let _ = builder.build_call(register, &args, "");
+        let _ = builder.build_return(None);
+
+        let ctor_ty = context.struct_type(
+            &[context.i32_type().into(), ctor.get_type().ptr_type(AddressSpace::default()).into(), i8p.into()],
+            false,
+        );
+        let ctor_entry = ctor_ty.const_named_struct(&[
+            context.i32_type().const_int(65535, false).into(),
+            ctor.as_global_value().as_pointer_value().into(),
+            i8p.const_null().into(),
+        ]);
+        let ctors = module.add_global(ctor_ty.array_type(1), None, "llvm.global_ctors");
+        ctors.set_initializer(&ctor_ty.const_array(&[ctor_entry]));
+        ctors.set_linkage(Linkage::Appending);
+    }
+}
+
+fn bytes<'ctx>(module: &Module<'ctx>, data: &[u8]) -> BasicValueEnum<'ctx> {
+    module.get_context().const_string(data, false).into()
+}
+
+/// A private constant global holding `value`, in `section` unless empty.
+fn section_global<'ctx>(module: &Module<'ctx>, value: BasicValueEnum<'ctx>, name: &str, section: &str, align: u32) -> GlobalValue<'ctx> {
+    let ty: BasicTypeEnum = value.get_type();
+    let global = module.add_global(ty, None, name);
+    global.set_initializer(&value);
+    global.set_constant(true);
+    global.set_linkage(Linkage::Private);
+    global.set_alignment(align);
+    if !section.is_empty() {
+        global.set_section(Some(section));
+    }
+    global
+}
+
+/// The translation unit's filenames: count, total size, compressed size
+/// (0, uncompressed), then each name prefixed by its length.
+fn encode_filenames(files: &[String]) -> Vec<u8> {
+    let mut names = Vec::new();
+    for f in files {
+        uleb(&mut names, f.len() as u64);
+        names.extend_from_slice(f.as_bytes());
+    }
+    let mut out = Vec::new();
+    uleb(&mut out, files.len() as u64);
+    uleb(&mut out, names.len() as u64);
+    uleb(&mut out, 0);
+    out.extend(names);
+    out
+}
+
+/// One function's mapping: a single file, no counter expressions, and its
+/// regions sorted by start, each as a counter reference followed by the
+/// start line (relative to the previous region), start column, line count
+/// and end column, whose top bit marks a gap region. `None` when no region lies in a source file.
+fn encode_function(coverage: &FunctionCoverage, map: &SourceMap) -> Option<Vec<u8>> {
+    let mut file = None;
+    let mut regions = Vec::new();
+    for r in &coverage.regions {
+        let (Some((start_file, start)), Some((end_file, end))) = (map.locate(r.span.start.line), map.locate(r.span.end.line)) else {
+            continue;
+        };
+        if start_file != end_file || *file.get_or_insert(start_file) != start_file {
+            continue;
+        }
+        regions.push((start, r.span.start.column, end, r.span.end.column, r.counter, r.gap));
+    }
+    let file = file?;
+    regions.sort();
+    let mut out = Vec::new();
+    uleb(&mut out, 1);
+    // Index 0 is the compilation directory.
+    uleb(&mut out, file as u64 + 1);
+    uleb(&mut out, 0);
+    uleb(&mut out, regions.len() as u64);
+    let mut prev_line = 0;
+    for (line, col, end_line, end_col, counter, gap) in regions {
+        uleb(&mut out, (counter as u64) << 2 | 1);
+        uleb(&mut out, (line - prev_line) as u64);
+        uleb(&mut out, col as u64);
+        uleb(&mut out, (end_line - line) as u64);
+        uleb(&mut out, end_col as u64 | if gap { GAP_REGION_BIT } else { 0 });
+        prev_line = line;
+    }
+    Some(out)
+}
+
+fn uleb(out: &mut Vec<u8>, mut v: u64) {
+    loop {
+        let byte = (v & 0x7f) as u8;
+        v >>= 7;
+        if v == 0 {
+            out.push(byte);
+            return;
+        }
+        out.push(byte | 0x80);
+    }
+}
+
+/// LLVM's `MD5Hash`: the first eight bytes of the MD5 digest, little endian.
+/// Function names and the filenames blob are referred to by it.
+fn md5_low(data: &[u8]) -> u64 {
+    let digest = md5(data);
+    u64::from_le_bytes(digest[..8].try_into().unwrap())
+}
+
+fn md5(data: &[u8]) -> [u8; 16] {
+    const SHIFTS: [[u32; 4]; 4] = [[7, 12, 17, 22], [5, 9, 14, 20], [4, 11, 16, 23], [6, 10, 15, 21]];
+    let k: Vec<u32> = (0..64).map(|i| ((i as f64 + 1.0).sin().abs() * 4294967296.0) as u32).collect();
+    let mut state = [0x6745_2301u32, 0xefcd_ab89, 0x98ba_dcfe, 0x1032_5476];
+    let mut msg = data.to_vec();
+    msg.push(0x80);
+    while msg.len() % 64 != 56 {
+        msg.push(0);
+    }
+    msg.extend_from_slice(&(data.len() as u64).wrapping_mul(8).to_le_bytes());
+    for chunk in msg.chunks(64) {
+        let m: Vec<u32> = chunk.chunks(4).map(|w| u32::from_le_bytes([w[0], w[1], w[2], w[3]])).collect();
+        let [mut a, mut b, mut c, mut d] = state;
+        for i in 0..64 {
+            let (f, g) = match i / 16 {
+                0 => ((b & c) | (!b & d), i),
+                1 => ((d & b) | (!d & c), (5 * i + 1) % 16),
+                2 => (b ^ c ^ d, (3 * i + 5) % 16),
+                _ => (c ^ (b | !d), (7 * i) % 16),
+            };
+            let rotated = a.wrapping_add(f).wrapping_add(k[i]).wrapping_add(m[g]).rotate_left(SHIFTS[i / 16][i % 4]);
+            a = d;
+            d = c;
+            c = b;
+            b = b.wrapping_add(rotated);
+        }
+        for (s, v) in state.iter_mut().zip([a, b, c, d]) {
+            *s = s.wrapping_add(v);
+        }
+    }
+    let mut out = [0u8; 16];
+    for (i, s) in state.iter().enumerate() {
+        out[i * 4..i * 4 + 4].copy_from_slice(&s.to_le_bytes());
+    }
+    out
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{encode_filenames, md5, uleb};
+
+    fn hex(bytes: &[u8]) -> String {
+        bytes.iter().map(|b| format!("{:02x}", b)).collect()
+    }
+
+    #[test]
+    fn encodings_match_llvm() {
+        assert_eq!(hex(&md5(b"")), "d41d8cd98f00b204e9800998ecf8427e");
+        assert_eq!(hex(&md5(b"The quick brown fox jumps over the lazy dog")), "9e107d9d372bb6826bd81d3542a419d6");
+        let mut out = Vec::new();
+        uleb(&mut out, 624485);
+        assert_eq!(out, [0xe5, 0x8e, 0x26]);
+        assert_eq!(encode_filenames(&["/w".to_string(), "a.kor".to_string()]), b"\x02\x09\x00\x02/w\x05a.kor");
+    }
+}
@@ -0,0 +1,100 @@
+use inkwell::builder::Builder;
+use inkwell::llvm_sys::core::{LLVMBuildCall2, LLVMBuildInBoundsGEP2, LLVMBuildLoad2, LLVMBuildStructGEP2, LLVMGetTypeKind, LLVMTypeOf};
+use inkwell::llvm_sys::prelude::LLVMValueRef;
+use inkwell::llvm_sys::LLVMTypeKind;
+use inkwell::types::{AsTypeRef, BasicTypeEnum, FunctionType, StructType};
+use inkwell::values::{
+    ArrayValue, AsValueRef, BasicMetadataValueEnum, BasicValueEnum, FloatValue, IntValue, PointerValue, StructValue,
+    VectorValue,
+};
+use std::ffi::CString;
+
+// Loads, GEPs and indirect calls go through the LLVM C entry points that take
+// explicit types. Their inkwell wrappers change shape between LLVM 14 (typed
+// pointers) and 15+ (opaque pointers); these do not.
+
+pub fn load<'ctx>(builder: &Builder<'ctx>, ty: BasicTypeEnum<'ctx>, ptr: PointerValue<'ctx>, name: &str) -> BasicValueEnum<'ctx> {
+    let name = CString::new(name).unwrap_or_default();
+    unsafe {
+        let v = LLVMBuildLoad2(builder.as_mut_ptr(), ty.as_type_ref(), ptr.as_value_ref(), name.as_ptr());
+        wrap(ty, v)
+    }
+}
+
+/// Address of field `index` of the `struct_ty` that `ptr` points to.
+pub fn struct_gep<'ctx>(
+    builder: &Builder<'ctx>,
+    struct_ty: StructType<'ctx>,
+    ptr: PointerValue<'ctx>,
+    index: u32,
+    name: &str,
+) -> PointerValue<'ctx> {
+    let name = CString::new(name).unwrap_or_default();
+    unsafe {
+        PointerValue::new(LLVMBuildStructGEP2(
+            builder.as_mut_ptr(),
+            struct_ty.as_type_ref(),
+            ptr.as_value_ref(),
+            index,
+            name.as_ptr(),
+        ))
+    }
+}
+
+/// Address of element `index` in an array of `elem_ty` starting at `ptr`.
+pub fn elem_gep<'ctx>(
+    builder: &Builder<'ctx>,
+    elem_ty: BasicTypeEnum<'ctx>,
+    ptr: PointerValue<'ctx>,
+    index: IntValue<'ctx>,
+    name: &str,
+) -> PointerValue<'ctx> {
+    let name = CString::new(name).unwrap_or_default();
+    let mut indices = [index.as_value_ref()];
+    unsafe {
+        PointerValue::new(LLVMBuildInBoundsGEP2(
+            builder.as_mut_ptr(),
+            elem_ty.as_type_ref(),
+            ptr.as_value_ref(),
+            indices.as_mut_ptr(),
+            1,
+            name.as_ptr(),
+        ))
+    }
+}
+
+/// Calls the function pointer `callee` of type `fn_ty`. Returns `None` for void calls.
+pub fn call_indirect<'ctx>(
+    builder: &Builder<'ctx>,
+    fn_ty: FunctionType<'ctx>,
+    callee: PointerValue<'ctx>,
+    args: &[BasicMetadataValueEnum<'ctx>],
+    name: &str,
+) -> Option<BasicValueEnum<'ctx>> {
+    let ret = fn_ty.get_return_type();
+    let name = CString::new(if ret.is_some() { name } else { "" }).unwrap_or_default();
+    let mut arg_refs: Vec<LLVMValueRef> = args.iter().map(|a| a.as_value_ref()).collect();
+    unsafe {
+        let v = LLVMBuildCall2(
+            builder.as_mut_ptr(),
+            fn_ty.as_type_ref(),
+            callee.as_value_ref(),
+            arg_refs.as_mut_ptr(),
+            arg_refs.len() as u32,
+            name.as_ptr(),
+        );
+        ret.map(|ty| wrap(ty, v))
+    }
+}
+
+unsafe fn wrap<'ctx>(ty: BasicTypeEnum<'ctx>, v: LLVMValueRef) -> BasicValueEnum<'ctx> {
+    debug_assert!(LLVMGetTypeKind(LLVMTypeOf(v)) != LLVMTypeKind::LLVMVoidTypeKind);
+    match ty {
+        BasicTypeEnum::IntType(_) => IntValue::new(v).into(),
+        BasicTypeEnum::FloatType(_) => FloatValue::new(v).into(),
+        BasicTypeEnum::PointerType(_) => PointerValue::new(v).into(),
+        BasicTypeEnum::StructType(_) => StructValue::new(v).into(),
+        BasicTypeEnum::ArrayType(_) => ArrayValue::new(v).into(),
+        BasicTypeEnum::VectorType(_) => VectorValue::new(v).into(),
+    }
+}
@@ -1,3 +1,6 @@
 pub mod llvm;
 pub mod pgo;
 pub mod optimize;
+pub mod ir;
+pub mod sanitize;
+pub mod coverage;
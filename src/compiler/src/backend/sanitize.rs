@@ -0,0 +1,117 @@
+use inkwell::attributes::{Attribute, AttributeLoc};
+use inkwell::module::{Linkage, Module};
+use inkwell::AddressSpace;
+use inkwell::passes::PassBuilderOptions;
+use inkwell::targets::TargetMachine;
+
+use crate::linker::Sanitizer;
+
+/// Marks the functions defined in `module` with the attribute of each
+/// sanitizer and runs its instrumentation passes. UBSan has neither in
+/// LLVM: codegen reports failed runtime checks through its handlers
+/// instead (see `Codegen::with_sanitizers`).
+pub fn instrument(module: &Module, machine: &TargetMachine, sanitizers: &[Sanitizer]) -> Result<(), String> {
+    if sanitizers.is_empty() {
+        return Ok(());
+    }
+    // The passes size stack slots and globals with the module's layout,
+    // which must match the one codegen folded `size_of` with.
+    module.set_data_layout(&machine.get_target_data().get_data_layout());
+    for &san in sanitizers {
+        // The module pass instruments every function too; LLVM 15 renamed
+        // it from `asan-module` to `asan`. The first pipeline that parses
+        // is used.
+        let (attr, pipelines): (&str, &[&str]) = match san {
+            Sanitizer::Address => ("sanitize_address", &["asan-module", "asan"]),
+            Sanitizer::Thread => ("sanitize_thread", &["function(tsan),tsan-module"]),
+            Sanitizer::Undefined => continue,
+        };
+        let context = module.get_context();
+        let kind = Attribute::get_named_enum_kind_id(attr);
+        for func in module.get_functions() {
+            if func.count_basic_blocks() > 0 {
+                func.add_attribute(AttributeLoc::Function, context.create_enum_attribute(kind, 0));
+            }
+        }
+        let mut error = String::new();
+        let ran = pipelines.iter().any(|passes| match module.run_passes(passes, machine, PassBuilderOptions::create()) {
+            Ok(()) => true,
+            Err(e) => {
+                error = e.to_string();
+                false
+            }
+        });
+        if !ran {
+            return Err(format!("{} sanitizer instrumentation failed: {}", san.name(), error));
+        }
+        if san == Sanitizer::Address {
+            disable_leak_detection(module);
+        }
+    }
+    Ok(())
+}
+
+/// Runtime lists and maps are never freed, so LeakSanitizer would report
+/// each one still alive at exit. `ASAN_OPTIONS=detect_leaks=1` overrides
+/// this default.
+fn disable_leak_detection(module: &Module) {
+    let name = "__asan_default_options";
+    if module.get_function(name).is_some() {
+        return;
+    }
+    let context = module.get_context();
+    let i8_ptr = context.i8_type().ptr_type(AddressSpace::default());
+    let options = context.const_string(b"detect_leaks=0", true);
+    let global = module.add_global(options.get_type(), None, "asan.options");
+    global.set_initializer(&options);
+    global.set_constant(true);
+    global.set_linkage(Linkage::Private);
+
+    let func = module.add_function(name, i8_ptr.fn_type(&[], false), Some(Linkage::External));
+    let builder = context.create_builder();
+    builder.position_at_end(context.append_basic_block(func, "entry"));
+    let _ = builder.build_return(Some(&global.as_pointer_value().const_cast(i8_ptr)));
+}
+
+#[cfg(test)]
+mod tests {
+    use inkwell::attributes::{Attribute, AttributeLoc};
+    use inkwell::context::Context;
+    use inkwell::targets::{InitializationConfig, RelocMode, Target, TargetMachine};
+    use inkwell::{AddressSpace, OptimizationLevel};
+
+    use super::instrument;
+    use crate::backend::ir;
+    use crate::linker::Sanitizer;
+
+    #[test]
+    fn instruments_defined_functions() {
+        Target::initialize_native(&InitializationConfig::default()).expect("native target");
+        let triple = TargetMachine::get_default_triple();
+        let machine = Target::from_triple(&triple)
+            .expect("target")
+            .create_target_machine(&triple, "generic", "", OptimizationLevel::Default, RelocMode::Default, inkwell::targets::CodeModel::Default)
+            .expect("target machine");
+        for (san, attr, hooks) in [(Sanitizer::Address, "sanitize_address", "__asan_"), (Sanitizer::Thread, "sanitize_thread", "__tsan_")] {
+            let context = Context::create();
+            let module = context.create_module("m");
+            let i64t = context.i64_type();
+            let ptr = i64t.ptr_type(AddressSpace::default());
+            let load = module.add_function("load", i64t.fn_type(&[ptr.into()], false), None);
+            module.add_function("korlang_free", context.void_type().fn_type(&[ptr.into()], false), None);
+            let builder = context.create_builder();
+            builder.position_at_end(context.append_basic_block(load, "entry"));
+            let p = load.get_nth_param(0).unwrap().into_pointer_value();
+            let v = ir::load(&builder, i64t.into(), p, "v");
+            builder.build_return(Some(&v)).unwrap();
+
+            instrument(&module, &machine, &[san]).expect("instrument");
+            let kind = Attribute::get_named_enum_kind_id(attr);
+            assert!(load.get_enum_attribute(AttributeLoc::Function, kind).is_some());
+            let free = module.get_function("korlang_free").unwrap();
+            assert!(free.get_enum_attribute(AttributeLoc::Function, kind).is_none());
+            assert!(module.get_functions().any(|f| f.get_name().to_str().unwrap().starts_with(hooks)));
+            assert_eq!(module.get_function("__asan_default_options").is_some(), san == Sanitizer::Address);
+        }
+    }
+}
@@ -1,5 +1,5 @@
-use crate::ast::*;
-use crate::sema::{Type, Sema};
+use crate::cfg::{BlockId, Cfg, Event, VarId};
+use crate::sema::Sema;
 use crate::diag::{Diagnostic, Span};
 use std::collections::HashMap;
 
@@ -11,103 +11,106 @@ pub enum OwnershipState {
     Moved,
 }
 
+/// Forward dataflow over a function's CFG that tracks live borrows. A `for`
+/// loop borrows the collection it iterates, so the body may not move or
+/// reassign it.
 pub struct BorrowChecker<'a> {
     sema: &'a mut Sema,
-    locals: HashMap<String, OwnershipState>,
+    cfg: &'a Cfg,
 }
 
 impl<'a> BorrowChecker<'a> {
-    pub fn new(sema: &'a mut Sema) -> Self {
-        Self {
-            sema,
-            locals: HashMap::new(),
-        }
+    pub fn new(sema: &'a mut Sema, cfg: &'a Cfg) -> Self {
+        Self { sema, cfg }
     }
 
-    pub fn check_stmt(&mut self, stmt: &Stmt) {
-        match stmt {
-            Stmt::Var(v) => {
-                self.check_expr(&v.value);
-                self.locals.insert(v.name.clone(), OwnershipState::Owned);
-            }
-            Stmt::Expr(e, _) => {
-                self.check_expr(e);
-            }
-            Stmt::Return(e, _) => {
-                if let Some(expr) = e {
-                    self.check_expr(expr);
+    pub fn check(&mut self) {
+        let order = self.cfg.reverse_postorder();
+        let preds = self.cfg.preds();
+        let mut outs: HashMap<BlockId, HashMap<VarId, Span>> = HashMap::new();
+        let mut changed = true;
+        while changed {
+            changed = false;
+            for &b in &order {
+                let mut live = self.entry_state(b, &preds, &outs);
+                self.transfer(b, &mut live, false);
+                let differs = match outs.get(&b) {
+                    Some(old) => old.len() != live.len() || live.keys().any(|k| !old.contains_key(k)),
+                    None => true,
+                };
+                if differs {
+                    outs.insert(b, live);
+                    changed = true;
                 }
             }
-            Stmt::If(cond, then_block, else_stmt, _) => {
-                self.check_expr(cond);
-                self.check_block(then_block);
-                if let Some(else_s) = else_stmt {
-                    self.check_stmt(else_s);
-                }
-            }
-            Stmt::While(cond, body, _) => {
-                self.check_expr(cond);
-                self.check_block(body);
-            }
-            Stmt::Block(b) => {
-                self.check_block(b);
-            }
-            _ => {}
+        }
+        for &b in &order {
+            let mut live = self.entry_state(b, &preds, &outs);
+            self.transfer(b, &mut live, true);
         }
     }
 
-    pub fn check_block(&mut self, block: &Block) {
-        for stmt in &block.stmts {
-            self.check_stmt(stmt);
-        }
-        if let Some(tail) = &block.tail {
-            self.check_expr(tail);
+    /// A borrow live on any incoming path is live here.
+    fn entry_state(
+        &self,
+        block: BlockId,
+        preds: &[Vec<BlockId>],
+        outs: &HashMap<BlockId, HashMap<VarId, Span>>,
+    ) -> HashMap<VarId, Span> {
+        let mut live = HashMap::new();
+        for p in &preds[block] {
+            if let Some(out) = outs.get(p) {
+                for (v, span) in out {
+                    live.entry(*v).or_insert(*span);
+                }
+            }
         }
+        live
     }
 
-    pub fn check_expr(&mut self, expr: &Expr) {
-        match expr {
-            Expr::Ident(name, span) => {
-                if let Some(state) = self.locals.get(name) {
-                    if *state == OwnershipState::Moved {
-                        self.sema.diags.push(Diagnostic::error(
-                            format!("use of moved value: '{}'", name),
-                            *span,
-                        ));
-                    }
+    fn transfer(&mut self, block: BlockId, live: &mut HashMap<VarId, Span>, report: bool) {
+        for event in &self.cfg.blocks[block].events {
+            match *event {
+                Event::Borrow(v, span) => {
+                    live.insert(v, span);
                 }
-            }
-            Expr::Assign { left, right, .. } => {
-                self.check_expr(right);
-                if let Expr::Ident(name, _) = &**left {
-                    // Re-owning a variable
-                    self.locals.insert(name.clone(), OwnershipState::Owned);
+                Event::Release(v) => {
+                    live.remove(&v);
                 }
-            }
-            Expr::Call { callee, args, .. } => {
-                self.check_expr(callee);
-                for arg in args {
-                    self.check_expr(arg);
-                    // Simple move semantics: passing an ident to a function moves it
-                    // unless we implement borrowing syntax later
-                    if let Expr::Ident(name, _) = arg {
-                        self.locals.insert(name.clone(), OwnershipState::Moved);
-                    }
+                Event::Move(v, span) if report && !self.sema.is_shared(&self.cfg.vars[v].ty) => {
+                    self.conflict(v, span, live, "move out of");
                 }
+                Event::Assign(v, span) if report => self.conflict(v, span, live, "assign to"),
+                _ => {}
             }
-            Expr::Binary { left, right, .. } => {
-                self.check_expr(left);
-                self.check_expr(right);
-            }
-            Expr::Unary { expr, .. } => {
-                self.check_expr(expr);
-            }
-            Expr::If { cond, then_block, else_block, .. } => {
-                self.check_expr(cond);
-                self.check_block(then_block);
-                self.check_block(else_block);
-            }
-            _ => {}
         }
     }
+
+    fn conflict(&mut self, v: VarId, span: Span, live: &HashMap<VarId, Span>, action: &str) {
+        if let Some(borrowed_at) = live.get(&v) {
+            let name = &self.cfg.vars[v].name;
+            self.sema.diags.push(Diagnostic::error(
+                format!("cannot {} '{}' while it is borrowed by a for loop", action, name),
+                span,
+            ));
+            self.sema.diags.push(Diagnostic::note(format!("'{}' is borrowed here", name), *borrowed_at));
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::test_support::errors;
+
+    #[test]
+    fn iterated_list_cannot_be_moved_or_reassigned() {
+        let src = "fun f(xs: [Int]) -> Int {\n\
+                       var ys = xs;\n\
+                       for y in (ys) { let zs = ys; ys = [y]; }\n\
+                       0\n\
+                   }";
+        let messages = errors(src);
+        assert!(messages.contains(&"cannot move out of 'ys' while it is borrowed by a for loop".to_string()), "{:?}", messages);
+        assert!(messages.contains(&"cannot assign to 'ys' while it is borrowed by a for loop".to_string()), "{:?}", messages);
+    }
 }
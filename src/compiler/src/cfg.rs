@@ -0,0 +1,530 @@
+use crate::ast::*;
+use crate::diag::Span;
+use crate::sema::{Sema, Type};
+use std::collections::HashMap;
+
+// Control-flow graph of one function body, reduced to the events the
+// ownership checkers care about. Every local binding gets its own `VarId`, so
+// shadowed names are distinct variables.
+
+pub type VarId = usize;
+pub type BlockId = usize;
+
+#[derive(Debug, Clone)]
+pub struct Var {
+    pub name: String,
+    pub ty: Type,
+    pub span: Span,
+}
+
+#[derive(Debug, Clone)]
+pub enum Event {
+    /// The binding comes into scope, initialized.
+    Def(VarId),
+    /// Read without giving up ownership (operands, arguments, receivers, conditions).
+    Use(VarId, Span),
+    /// Ownership leaves the variable (initializers, returns, fields, list elements).
+    Move(VarId, Span),
+    /// `x = ...` re-initializes the variable.
+    Assign(VarId, Span),
+    /// `for _ in x` borrows `x` until the loop exits.
+    Borrow(VarId, Span),
+    Release(VarId),
+}
+
+#[derive(Debug, Clone, Default)]
+pub struct BasicBlock {
+    pub events: Vec<Event>,
+    pub succs: Vec<BlockId>,
+    /// Nesting depth of loops around the block.
+    pub loop_depth: usize,
+}
+
+#[derive(Debug, Default)]
+pub struct Cfg {
+    pub vars: Vec<Var>,
+    pub blocks: Vec<BasicBlock>,
+}
+
+impl Cfg {
+    pub const ENTRY: BlockId = 0;
+
+    pub fn build(sema: &Sema, fun: &FunDecl) -> Cfg {
+        let mut b = Builder {
+            sema,
+            cfg: Cfg::default(),
+            current: 0,
+            scopes: vec![HashMap::new()],
+            loops: Vec::new(),
+            defers: Vec::new(),
+        };
+        b.cfg.blocks.push(BasicBlock::default());
+        if fun.receiver.is_some() {
+            b.define("self", fun.span);
+        }
+        for p in &fun.params {
+            b.define(&p.name, p.span);
+        }
+        b.value_block(&fun.body);
+        b.cfg
+    }
+
+    pub fn preds(&self) -> Vec<Vec<BlockId>> {
+        let mut preds = vec![Vec::new(); self.blocks.len()];
+        for (id, block) in self.blocks.iter().enumerate() {
+            for &s in &block.succs {
+                preds[s].push(id);
+            }
+        }
+        preds
+    }
+
+    /// Blocks in reverse postorder from the entry; unreachable blocks are left out.
+    pub fn reverse_postorder(&self) -> Vec<BlockId> {
+        let mut seen = vec![false; self.blocks.len()];
+        let mut order = Vec::new();
+        let mut stack = vec![(Self::ENTRY, 0)];
+        seen[Self::ENTRY] = true;
+        while let Some((id, next)) = stack.pop() {
+            match self.blocks[id].succs.get(next) {
+                Some(&s) => {
+                    stack.push((id, next + 1));
+                    if !seen[s] {
+                        seen[s] = true;
+                        stack.push((s, 0));
+                    }
+                }
+                None => order.push(id),
+            }
+        }
+        order.reverse();
+        order
+    }
+}
+
+/// A loop that `break` and `continue` can leave, and the blocks they jump to.
+/// Codegen keeps the same bookkeeping with LLVM blocks and a result slot.
+pub(crate) struct LoopTarget<B, R = ()> {
+    pub(crate) label: Option<String>,
+    pub(crate) continue_to: B,
+    pub(crate) break_to: B,
+    /// Deferred blocks that were pending when the loop was entered.
+    pub(crate) defers: usize,
+    /// Where a `loop` expression collects the values it is broken out of with.
+    pub(crate) result: Option<R>,
+}
+
+impl<B, R> LoopTarget<B, R> {
+    /// The index of the loop a `break` or `continue` to `label` leaves,
+    /// innermost first.
+    pub(crate) fn find(loops: &[Self], label: &Option<String>) -> Option<usize> {
+        match label {
+            Some(label) => loops.iter().rposition(|l| l.label.as_ref() == Some(label)),
+            None => loops.len().checked_sub(1),
+        }
+    }
+}
+
+/// A `defer` body with the bindings it saw, replayed on every exit path.
+struct Deferred {
+    body: Block,
+    scopes: Vec<HashMap<String, VarId>>,
+}
+
+struct Builder<'a> {
+    sema: &'a Sema,
+    cfg: Cfg,
+    current: BlockId,
+    scopes: Vec<HashMap<String, VarId>>,
+    loops: Vec<LoopTarget<BlockId>>,
+    defers: Vec<Deferred>,
+}
+
+impl<'a> Builder<'a> {
+    fn new_block(&mut self) -> BlockId {
+        let loop_depth = self.loops.len();
+        self.cfg.blocks.push(BasicBlock { loop_depth, ..BasicBlock::default() });
+        self.cfg.blocks.len() - 1
+    }
+
+    fn edge(&mut self, from: BlockId, to: BlockId) {
+        self.cfg.blocks[from].succs.push(to);
+    }
+
+    fn goto(&mut self, to: BlockId) {
+        self.edge(self.current, to);
+        self.current = to;
+    }
+
+    /// Ends the current block with a jump and continues in a fresh, unreachable one.
+    fn jump_away(&mut self, to: Option<BlockId>) {
+        if let Some(to) = to {
+            self.edge(self.current, to);
+        }
+        self.current = self.new_block();
+    }
+
+    fn emit(&mut self, event: Event) {
+        self.cfg.blocks[self.current].events.push(event);
+    }
+
+    fn define(&mut self, name: &str, span: Span) -> VarId {
+        let ty = self.sema.binding_type(name, span).unwrap_or(Type::Unknown);
+        self.cfg.vars.push(Var { name: name.to_string(), ty, span });
+        let id = self.cfg.vars.len() - 1;
+        if let Some(scope) = self.scopes.last_mut() {
+            scope.insert(name.to_string(), id);
+        }
+        self.emit(Event::Def(id));
+        id
+    }
+
+    fn resolve(&self, name: &str) -> Option<VarId> {
+        self.scopes.iter().rev().find_map(|s| s.get(name)).copied()
+    }
+
+    fn block(&mut self, block: &Block) {
+        let defers = self.defers.len();
+        self.scopes.push(HashMap::new());
+        for stmt in &block.stmts {
+            self.stmt(stmt);
+        }
+        if let Some(tail) = &block.tail {
+            self.expr(tail);
+        }
+        self.leave_block(defers);
+    }
+
+    /// A block whose tail is the value of the enclosing expression.
+    fn value_block(&mut self, block: &Block) {
+        let defers = self.defers.len();
+        self.scopes.push(HashMap::new());
+        for stmt in &block.stmts {
+            self.stmt(stmt);
+        }
+        if let Some(tail) = &block.tail {
+            self.value(tail);
+        }
+        self.leave_block(defers);
+    }
+
+    fn leave_block(&mut self, defers: usize) {
+        self.run_defers(defers);
+        self.defers.truncate(defers);
+        self.scopes.pop();
+    }
+
+    /// Replays the deferred blocks above `depth`, innermost first, in the
+    /// scopes they were declared in.
+    fn run_defers(&mut self, depth: usize) {
+        let pending = self.defers.split_off(depth.min(self.defers.len()));
+        for d in pending.iter().rev() {
+            let scopes = std::mem::replace(&mut self.scopes, d.scopes.clone());
+            self.block(&d.body);
+            self.scopes = scopes;
+        }
+        self.defers.extend(pending);
+    }
+
+    fn jump_target(&self, label: &Option<String>) -> Option<&LoopTarget<BlockId>> {
+        LoopTarget::find(&self.loops, label).map(|i| &self.loops[i])
+    }
+
+    fn stmt(&mut self, stmt: &Stmt) {
+        match stmt {
+            Stmt::Var(v) => {
+                self.value(&v.value);
+                match &v.pattern {
+                    Some(pat) => self.bind_pattern(pat),
+                    None => {
+                        self.define(&v.name, v.span);
+                    }
+                }
+            }
+            Stmt::Expr(e, _) => self.expr(e),
+            Stmt::Return(e, _) => {
+                if let Some(e) = e {
+                    self.value(e);
+                }
+                self.run_defers(0);
+                self.jump_away(None);
+            }
+            Stmt::Break(label, value, _) => {
+                if let Some(e) = value {
+                    self.value(e);
+                }
+                let target = self.jump_target(label).map(|l| (l.break_to, l.defers));
+                if let Some((_, defers)) = target {
+                    self.run_defers(defers);
+                }
+                self.jump_away(target.map(|(to, _)| to));
+            }
+            Stmt::Continue(label, _) => {
+                let target = self.jump_target(label).map(|l| (l.continue_to, l.defers));
+                if let Some((_, defers)) = target {
+                    self.run_defers(defers);
+                }
+                self.jump_away(target.map(|(to, _)| to));
+            }
+            Stmt::Defer(body, _) => {
+                self.defers.push(Deferred { body: body.clone(), scopes: self.scopes.clone() });
+            }
+            Stmt::If(cond, then_block, else_stmt, _) => {
+                self.expr(cond);
+                let (start, join) = (self.current, self.new_block());
+                let then_bb = self.new_block();
+                self.edge(start, then_bb);
+                self.current = then_bb;
+                self.block(then_block);
+                self.edge(self.current, join);
+                match else_stmt {
+                    Some(s) => {
+                        let else_bb = self.new_block();
+                        self.edge(start, else_bb);
+                        self.current = else_bb;
+                        self.stmt(s);
+                        self.edge(self.current, join);
+                    }
+                    None => self.edge(start, join),
+                }
+                self.current = join;
+            }
+            Stmt::While(cond, body, label, _) => {
+                let header = self.new_block();
+                self.goto(header);
+                self.expr(cond);
+                let exit = self.new_block();
+                self.edge(self.current, exit);
+                self.loop_body(header, exit, label, None, body);
+                self.current = exit;
+            }
+            Stmt::For(name, iter, body, label, span) => {
+                self.expr(iter);
+                let borrowed = match iter {
+                    Expr::Ident(n, s) => self.resolve(n).map(|id| (id, *s)),
+                    _ => None,
+                };
+                if let Some((id, s)) = borrowed {
+                    self.emit(Event::Borrow(id, s));
+                }
+                let header = self.new_block();
+                self.goto(header);
+                let exit = self.new_block();
+                self.edge(header, exit);
+                self.loop_body(header, exit, label, Some((name, *span)), body);
+                self.current = exit;
+                if let Some((id, _)) = borrowed {
+                    self.emit(Event::Release(id));
+                }
+            }
+            Stmt::Match(scrutinee, arms, _) => self.match_arms(scrutinee, arms),
+            Stmt::Block(b) => self.block(b),
+        }
+    }
+
+    fn loop_body(&mut self, header: BlockId, exit: BlockId, label: &Option<String>, binding: Option<(&String, Span)>, body: &Block) {
+        let defers = self.defers.len();
+        self.loops.push(LoopTarget { label: label.clone(), continue_to: header, break_to: exit, defers, result: None });
+        let body_bb = self.new_block();
+        self.edge(self.current, body_bb);
+        self.current = body_bb;
+        self.scopes.push(HashMap::new());
+        if let Some((name, span)) = binding {
+            self.define(name, span);
+        }
+        self.block(body);
+        self.scopes.pop();
+        self.edge(self.current, header);
+        self.loops.pop();
+    }
+
+    fn match_arms(&mut self, scrutinee: &Expr, arms: &[MatchArm]) {
+        self.expr(scrutinee);
+        let start = self.current;
+        let join = self.new_block();
+        for arm in arms {
+            let arm_bb = self.new_block();
+            self.edge(start, arm_bb);
+            self.current = arm_bb;
+            self.scopes.push(HashMap::new());
+            self.bind_pattern(&arm.pat);
+            self.value(&arm.body);
+            self.scopes.pop();
+            self.edge(self.current, join);
+        }
+        if arms.is_empty() {
+            self.edge(start, join);
+        }
+        self.current = join;
+    }
+
+    fn bind_pattern(&mut self, pat: &Pattern) {
+        match pat {
+            Pattern::Ident(name, span) => {
+                // Bare names that resolve to enum variants are constructors, not bindings.
+                if self.sema.binding_type(name, *span).is_some() {
+                    self.define(name, *span);
+                }
+            }
+            Pattern::Tuple(parts, _) | Pattern::Variant { args: parts, .. } => {
+                for p in parts {
+                    self.bind_pattern(p);
+                }
+            }
+            Pattern::Struct { fields, .. } => {
+                for (_, p) in fields {
+                    self.bind_pattern(p);
+                }
+            }
+            Pattern::Is(_, inner, _) => self.bind_pattern(inner),
+            Pattern::Literal(..) | Pattern::Wildcard(_) => {}
+        }
+    }
+
+    /// An expression whose result is taken by value.
+    fn value(&mut self, expr: &Expr) {
+        match expr {
+            Expr::Ident(name, span) => {
+                if let Some(id) = self.resolve(name) {
+                    self.emit(Event::Move(id, *span));
+                }
+            }
+            Expr::Block(b) => self.value_block(b),
+            Expr::If { cond, then_block, else_block, .. } => self.branches(cond, then_block, else_block, true),
+            _ => self.expr(expr),
+        }
+    }
+
+    fn expr(&mut self, expr: &Expr) {
+        match expr {
+            Expr::Ident(name, span) => {
+                if let Some(id) = self.resolve(name) {
+                    self.emit(Event::Use(id, *span));
+                }
+            }
+            Expr::Literal(..) | Expr::Instantiate { .. } => {}
+            // Fields that only read a local run before the fields that move
+            // one, so `S { base: s, len: s.length() }` lends `s` first.
+            Expr::StructLit { fields, .. } => {
+                let (moves, rest): (Vec<_>, Vec<_>) = fields.iter().map(|(_, e)| e).partition(|e| matches!(e, Expr::Ident(..)));
+                for e in rest.into_iter().chain(moves) {
+                    self.value(e);
+                }
+            }
+            Expr::Array(items, _) | Expr::Tuple(items, _) => {
+                for e in items {
+                    self.value(e);
+                }
+            }
+            Expr::Tensor(rows, _) => {
+                for e in rows.iter().flatten() {
+                    self.expr(e);
+                }
+            }
+            Expr::Interpolated { parts, .. } => {
+                for e in parts {
+                    self.expr(e);
+                }
+            }
+            Expr::Unary { expr, .. } | Expr::Cast { expr, .. } => self.expr(expr),
+            Expr::Binary { left, op: BinaryOp::And | BinaryOp::Or | BinaryOp::NullCoalesce, right, .. } => {
+                self.expr(left);
+                let (start, rhs, join) = (self.current, self.new_block(), self.new_block());
+                self.edge(start, rhs);
+                self.edge(start, join);
+                self.current = rhs;
+                self.expr(right);
+                self.goto(join);
+            }
+            Expr::Binary { left, right, .. } | Expr::Range { start: left, end: right, .. } => {
+                self.expr(left);
+                self.expr(right);
+            }
+            Expr::Assign { left, op, right, span } => {
+                self.value(right);
+                match &**left {
+                    Expr::Ident(name, _) => {
+                        if let Some(id) = self.resolve(name) {
+                            if !matches!(op, AssignOp::Assign) {
+                                self.emit(Event::Use(id, *span));
+                            }
+                            self.emit(Event::Assign(id, *span));
+                        }
+                    }
+                    other => self.expr(other),
+                }
+            }
+            Expr::Call { callee, args, .. } => {
+                match &**callee {
+                    // The receiver is lent to the method for the call.
+                    Expr::Member { target, .. } => self.expr(target),
+                    Expr::Ident(..) | Expr::Instantiate { .. } => {}
+                    other => self.expr(other),
+                }
+                // Arguments are lent to the callee, whose parameters borrow;
+                // only the builtin `push` of a list keeps its argument.
+                let stores = match &**callee {
+                    Expr::Member { target, name, .. } => name == "push" && matches!(self.type_of(target), Type::Array(_)),
+                    _ => false,
+                };
+                for arg in args {
+                    if stores {
+                        self.value(arg);
+                    } else {
+                        self.expr(arg);
+                    }
+                }
+            }
+            Expr::Member { target, .. } => self.expr(target),
+            Expr::Index { target, index, .. } => {
+                self.expr(target);
+                self.expr(index);
+            }
+            Expr::If { cond, then_block, else_block, .. } => self.branches(cond, then_block, else_block, false),
+            Expr::Match { expr, arms, .. } => self.match_arms(expr, arms),
+            Expr::Block(b) => self.block(b),
+            // Only a `break` reaches the exit of a `loop`.
+            Expr::Loop { label, body, .. } => {
+                let header = self.new_block();
+                self.goto(header);
+                let exit = self.new_block();
+                self.loop_body(header, exit, label, None, body);
+                self.current = exit;
+            }
+        }
+    }
+
+    /// The type of a receiver: a local, or a field path through struct
+    /// types. Anything else is `Unknown`.
+    fn type_of(&self, expr: &Expr) -> Type {
+        let ty = match expr {
+            Expr::Ident(name, _) => self.resolve(name).map(|id| self.cfg.vars[id].ty.clone()),
+            Expr::Member { target, name, .. } => match self.type_of(target) {
+                Type::Named(s) => self.sema.structs.get(&s).and_then(|d| d.fields.iter().find(|f| &f.name == name)).map(|f| self.sema.type_from_ref(&f.ty)),
+                _ => None,
+            },
+            _ => None,
+        };
+        match ty {
+            Some(Type::Optional(inner)) => *inner,
+            ty => ty.unwrap_or(Type::Unknown),
+        }
+    }
+
+    fn branches(&mut self, cond: &Expr, then_block: &Block, else_block: &Block, by_value: bool) {
+        self.expr(cond);
+        let (start, join) = (self.current, self.new_block());
+        for block in [then_block, else_block] {
+            let bb = self.new_block();
+            self.edge(start, bb);
+            self.current = bb;
+            if by_value {
+                self.value_block(block);
+            } else {
+                self.block(block);
+            }
+            self.edge(self.current, join);
+        }
+        self.current = join;
+    }
+}
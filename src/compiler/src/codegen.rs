@@ -1,13 +1,77 @@
 use crate::ast::*;
-use crate::diag::Diagnostic;
+use crate::backend::coverage::Coverage;
+use crate::backend::ir;
+use crate::cfg::LoopTarget;
+use crate::constexpr::{ConstValue, ConstexprEval};
+use crate::coverage::{Anchor, SourceMap};
+use crate::diag::{Diagnostic, Span};
 use crate::escape::{analyze_escape, EscapeResult};
+use crate::linker::Sanitizer;
+use crate::mangle;
+use crate::specialization::{Resolution, SpecQuery, SpecializationEngine};
+use crate::typeck::INT_INTRINSICS;
+use crate::types::Type;
+use inkwell::basic_block::BasicBlock;
 use inkwell::builder::Builder;
 use inkwell::context::Context;
-use inkwell::module::Module;
-use inkwell::types::{BasicMetadataTypeEnum, BasicType, BasicTypeEnum};
-use inkwell::values::{BasicValue, BasicValueEnum};
-use inkwell::AddressSpace;
-use std::collections::HashMap;
+use inkwell::module::{Linkage, Module};
+use inkwell::types::{BasicMetadataTypeEnum, BasicType, BasicTypeEnum, FunctionType, PointerType, StructType};
+use inkwell::values::{BasicMetadataValueEnum, BasicValue, BasicValueEnum, FunctionValue, IntValue, PointerValue};
+use inkwell::{AddressSpace, FloatPredicate, IntPredicate};
+use std::collections::{HashMap, HashSet};
+
+/// A lowered value together with its source-level type.
+#[derive(Clone)]
+struct Value<'ctx> {
+    val: BasicValueEnum<'ctx>,
+    ty: Type,
+}
+
+#[derive(Clone)]
+struct Local<'ctx> {
+    ptr: PointerValue<'ctx>,
+    ty: Type,
+    llvm: BasicTypeEnum<'ctx>,
+}
+
+/// A `defer` body and the bindings it closes over, emitted again at every
+/// exit of its block.
+struct Deferred<'ctx> {
+    body: Block,
+    scopes: Vec<HashMap<String, Local<'ctx>>>,
+    consts: HashMap<String, ConstValue>,
+}
+
+/// Parameter and return types of an emitted function, excluding the receiver.
+#[derive(Clone)]
+struct FnSig {
+    params: Vec<Type>,
+    ret: Type,
+}
+
+/// A struct's copy of an interface default method it does not override.
+struct DefaultImpl {
+    symbol: String,
+    struct_name: String,
+    interface: String,
+    self_ty: Type,
+    method: FunSig,
+    subst: HashMap<String, Type>,
+}
+
+/// A failed runtime check as UBSan reports it: the `__ubsan_handle_*`
+/// handler and the integer operands it prints.
+struct UbsanReport<'ctx> {
+    handler: &'static str,
+    signed: bool,
+    operands: Vec<IntValue<'ctx>>,
+}
+
+enum Branch<'a> {
+    Block(&'a Block),
+    Stmt(&'a Stmt),
+    Empty,
+}
 
 pub struct Codegen<'ctx> {
     context: &'ctx Context,
@@ -15,7 +79,31 @@ pub struct Codegen<'ctx> {
     builder: Builder<'ctx>,
     diags: Vec<Diagnostic>,
     escape_map: HashMap<String, EscapeResult>,
-    local_literals: HashMap<String, Literal>,
+    local_consts: HashMap<String, ConstValue>,
+    consts: ConstexprEval,
+    specializations: SpecializationEngine,
+    structs: HashMap<String, StructDecl>,
+    struct_types: HashMap<String, StructType<'ctx>>,
+    interfaces: HashMap<String, InterfaceDecl>,
+    variants: HashSet<String>,
+    signatures: HashMap<String, FnSig>,
+    /// `extern` functions declared with a trailing `...`.
+    variadic_externs: HashSet<String>,
+    default_impls: Vec<DefaultImpl>,
+    scopes: Vec<HashMap<String, Local<'ctx>>>,
+    loops: Vec<LoopTarget<BasicBlock<'ctx>, ResultSlot<'ctx>>>,
+    defers: Vec<Deferred<'ctx>>,
+    current_ret: Type,
+    /// Integer overflow panics instead of wrapping.
+    overflow_checks: bool,
+    /// File name used in the locations of runtime panics.
+    source_name: String,
+    /// Building a library: only `@export` functions stay visible.
+    library: bool,
+    /// Failed checks are reported through the UBSan runtime.
+    ubsan: bool,
+    /// Counters for `--coverage`, mapped back to the files of the program.
+    coverage: Option<Coverage<'ctx>>,
 }
 
 impl<'ctx> Codegen<'ctx> {
@@ -28,23 +116,95 @@ impl<'ctx> Codegen<'ctx> {
             builder,
             diags: Vec::new(),
             escape_map: HashMap::new(),
-            local_literals: HashMap::new(),
+            local_consts: HashMap::new(),
+            consts: ConstexprEval::new(),
+            specializations: SpecializationEngine::new(),
+            structs: HashMap::new(),
+            struct_types: HashMap::new(),
+            interfaces: HashMap::new(),
+            variants: HashSet::new(),
+            signatures: HashMap::new(),
+            variadic_externs: HashSet::new(),
+            default_impls: Vec::new(),
+            scopes: Vec::new(),
+            loops: Vec::new(),
+            defers: Vec::new(),
+            current_ret: Type::Unit,
+            overflow_checks: true,
+            source_name: module_name.to_string(),
+            library: false,
+            ubsan: false,
+            coverage: None,
         }
     }
 
+    /// Debug builds panic on integer overflow; release builds wrap.
+    /// Division by zero panics either way.
+    pub fn with_overflow_checks(mut self, on: bool) -> Self {
+        self.overflow_checks = on;
+        self
+    }
+
+    pub fn with_source_name(mut self, name: &str) -> Self {
+        self.source_name = name.to_string();
+        self
+    }
+
+    /// Libraries keep everything but their `@export` functions internal, so
+    /// their `main` and mangled symbols cannot clash with the host program.
+    pub fn with_library(mut self, on: bool) -> Self {
+        self.library = on;
+        self
+    }
+
+    /// With `undefined`, overflow and division checks report through
+    /// UBSan's handlers instead of panicking. ASan and TSan instrument the
+    /// finished module (`backend::sanitize`).
+    pub fn with_sanitizers(mut self, sanitizers: &[Sanitizer]) -> Self {
+        self.ubsan = sanitizers.contains(&Sanitizer::Undefined);
+        self
+    }
+
+    /// Counts how often each region of every function body runs. `map`
+    /// locates the lines of the program in its source files.
+    pub fn with_coverage(mut self, map: Option<SourceMap>) -> Self {
+        self.coverage = map.map(Coverage::new);
+        self
+    }
+
     pub fn emit_program(mut self, program: &Program) -> Result<Module<'ctx>, Vec<Diagnostic>> {
         self.escape_map = analyze_escape(program);
+        self.specializations = SpecializationEngine::from_program(program);
+        self.consts = ConstexprEval::from_program(program);
+        // Failures were already reported by Sema; such consts are simply not folded.
+        let _ = self.consts.eval_consts(program);
+        self.collect_types(&crate::iter::prelude());
+        self.collect_types(&program.items);
+        self.declare_struct_types();
         for item in &program.items {
-            if let Item::Fun(f) = item {
-                self.declare_function(f);
+            match item {
+                Item::Fun(f) => self.declare_function(f),
+                Item::Extern(e) => self.declare_extern(e),
+                _ => {}
             }
         }
+        self.declare_default_methods();
+        self.emit_vtables();
 
         for item in &program.items {
             if let Item::Fun(f) = item {
                 self.emit_function(f);
             }
         }
+        for i in 0..self.default_impls.len() {
+            self.emit_default_method(i);
+        }
+        if self.library {
+            self.hide_internal_symbols(program);
+        }
+        if let Some(coverage) = self.coverage.take() {
+            coverage.emit(&self.module);
+        }
 
         if self.diags.is_empty() {
             Ok(self.module)
@@ -53,79 +213,1390 @@ impl<'ctx> Codegen<'ctx> {
         }
     }
 
-    fn declare_function(&mut self, fun: &FunDecl) {
-        let param_types: Vec<BasicMetadataTypeEnum> = fun
-            .params
-            .iter()
-            .map(|p| BasicMetadataTypeEnum::from(self.llvm_type(&p.ty)))
-            .collect();
-        let fn_type = if let Some(ret) = &fun.ret {
-            self.llvm_type(ret).fn_type(&param_types, false)
-        } else {
-            self.context.void_type().fn_type(&param_types, false)
+    fn hide_internal_symbols(&self, program: &Program) {
+        let exports: HashSet<&str> = program
+            .items
+            .iter()
+            .filter_map(|item| match item {
+                Item::Fun(f) if f.export => Some(f.name.as_str()),
+                _ => None,
+            })
+            .collect();
+        for func in self.module.get_functions() {
+            let name = func.get_name().to_string_lossy();
+            if func.count_basic_blocks() > 0 && !exports.contains(name.as_ref()) {
+                func.set_linkage(Linkage::Internal);
+            }
+        }
+        for global in self.module.get_globals() {
+            if global.get_initializer().is_some() && global.get_linkage() == Linkage::External {
+                global.set_linkage(Linkage::Internal);
+            }
+        }
+    }
+
+    fn collect_types(&mut self, items: &[Item]) {
+        for item in items {
+            match item {
+                Item::Struct(s) => {
+                    self.structs.insert(s.name.clone(), s.clone());
+                }
+                Item::Interface(i) => {
+                    self.interfaces.insert(i.name.clone(), i.clone());
+                }
+                Item::Enum(e) => {
+                    self.variants.extend(e.variants.iter().map(|v| v.name.clone()));
+                }
+                Item::Sealed(s) => self.collect_types(&s.items),
+                _ => {}
+            }
+        }
+    }
+
+    /// Structs are heap objects referenced through `i8*`; the named LLVM type
+    /// gives their field layout in declaration order.
+    fn declare_struct_types(&mut self) {
+        for name in self.struct_names() {
+            let ty = self.context.opaque_struct_type(&name);
+            self.struct_types.insert(name, ty);
+        }
+        for name in self.struct_names() {
+            let fields: Vec<BasicTypeEnum> = self.structs[&name].fields.iter().map(|f| self.llvm_type(&f.ty)).collect();
+            self.struct_types[&name].set_body(&fields, false);
+        }
+    }
+
+    fn struct_names(&self) -> Vec<String> {
+        let mut names: Vec<String> = self.structs.keys().cloned().collect();
+        names.sort();
+        names
+    }
+
+    fn declare_function(&mut self, fun: &FunDecl) {
+        let mut param_types: Vec<BasicMetadataTypeEnum> = Vec::new();
+        if let Some(recv) = &fun.receiver {
+            param_types.push(self.llvm_type(recv).into());
+        }
+        let params: Vec<Type> = fun.params.iter().map(|p| Type::from_type_ref(&p.ty)).collect();
+        param_types.extend(params.iter().map(|t| BasicMetadataTypeEnum::from(self.llvm_ty(t))));
+        let ret = fun.ret.as_ref().map(Type::from_type_ref).unwrap_or(Type::Unit);
+        let fn_type = self.fn_type(&param_types, &ret);
+        let symbol = self.specializations.symbol_of(fun);
+        self.module.add_function(&symbol, fn_type, None);
+        self.signatures.insert(symbol, FnSig { params, ret });
+    }
+
+    /// `extern "C"` functions keep their C name and are only declared.
+    fn declare_extern(&mut self, decl: &ExternDecl) {
+        let params: Vec<Type> = decl.params.iter().map(|p| Type::from_type_ref(&p.ty)).collect();
+        let ret = decl.ret.as_ref().map(Type::from_type_ref).unwrap_or(Type::Unit);
+        let param_types: Vec<BasicMetadataTypeEnum> = params.iter().map(|t| self.llvm_ty(t).into()).collect();
+        let fn_type = if is_void(&ret) {
+            self.context.void_type().fn_type(&param_types, decl.variadic)
+        } else {
+            self.llvm_ty(&ret).fn_type(&param_types, decl.variadic)
+        };
+        if self.module.get_function(&decl.name).is_none() {
+            self.module.add_function(&decl.name, fn_type, Some(Linkage::External));
+        }
+        if decl.variadic {
+            self.variadic_externs.insert(decl.name.clone());
+        }
+        self.signatures.insert(decl.name.clone(), FnSig { params, ret });
+    }
+
+    fn emit_function(&mut self, fun: &FunDecl) {
+        let symbol = self.specializations.symbol_of(fun);
+        let func = match self.module.get_function(&symbol) {
+            Some(f) => f,
+            None => return,
+        };
+        let _escapes = self.escape_map.get(&fun.name);
+        let ret = self.signatures[&symbol].ret.clone();
+        self.begin_function(func, ret);
+
+        let mut names = Vec::new();
+        if let Some(recv) = &fun.receiver {
+            names.push(("self".to_string(), Type::from_type_ref(recv)));
+        }
+        names.extend(fun.params.iter().map(|p| (p.name.clone(), Type::from_type_ref(&p.ty))));
+        for ((name, ty), param) in names.into_iter().zip(func.get_param_iter()) {
+            self.bind_local(&name, ty, param);
+        }
+        self.finish_function(func, &fun.body);
+    }
+
+    fn begin_function(&mut self, func: FunctionValue<'ctx>, ret: Type) {
+        let entry = self.context.append_basic_block(func, "entry");
+        self.builder.position_at_end(entry);
+        self.scopes = vec![HashMap::new()];
+        self.loops.clear();
+        self.defers.clear();
+        self.local_consts.clear();
+        self.current_ret = ret;
+    }
+
+    fn finish_function(&mut self, func: FunctionValue<'ctx>, body: &Block) {
+        if let Some(coverage) = &mut self.coverage {
+            coverage.begin_function(&self.module, func, body);
+        }
+        let tail = self.emit_block(body);
+        if !self.is_terminated() {
+            self.emit_return(tail);
+        }
+        if !func.verify(false) {
+            // Constructs the backend cannot lower yet must not take the whole
+            // module down; the function degrades to returning a zero value.
+            for bb in func.get_basic_blocks() {
+                unsafe {
+                    let _ = bb.delete();
+                }
+            }
+            let entry = self.context.append_basic_block(func, "entry");
+            self.builder.position_at_end(entry);
+            self.emit_return(None);
+        }
+    }
+
+    // ---------- Interfaces and vtables ----------
+
+    /// Interfaces `s` declares it implements, with their type arguments.
+    fn implemented_interfaces(&self, s: &StructDecl) -> Vec<(InterfaceDecl, Vec<Type>)> {
+        s.implements
+            .iter()
+            .filter_map(|r| {
+                let (name, args) = match Type::from_type_ref(r) {
+                    Type::Named(n) => (n, Vec::new()),
+                    Type::Generic(n, args) => (n, args),
+                    _ => return None,
+                };
+                self.interfaces.get(&name).map(|i| (i.clone(), args))
+            })
+            .collect()
+    }
+
+    fn method_sig(&self, method: &FunSig, subst: &HashMap<String, Type>) -> FnSig {
+        FnSig {
+            params: method.params.iter().map(|p| Type::from_type_ref(&p.ty).substitute(subst)).collect(),
+            ret: method.ret.as_ref().map(Type::from_type_ref).unwrap_or(Type::Unit).substitute(subst),
+        }
+    }
+
+    /// `(i8* self, params...) -> ret`, the shape shared by every vtable slot.
+    fn method_fn_type(&self, sig: &FnSig) -> FunctionType<'ctx> {
+        let mut params: Vec<BasicMetadataTypeEnum> = vec![self.i8_ptr().into()];
+        params.extend(sig.params.iter().map(|t| BasicMetadataTypeEnum::from(self.llvm_ty(t))));
+        self.fn_type(&params, &sig.ret)
+    }
+
+    /// Symbol of method `name` for a receiver of type `recv`: a matching
+    /// extension method, or the struct's copy of an interface default.
+    fn method_symbol(&self, recv: &Type, name: &str) -> Option<String> {
+        let query = SpecQuery { generic_args: &[], receiver: Some(recv), args: None };
+        if let Resolution::Selected(spec, _) = self.specializations.find_best_match(name, &query) {
+            return Some(spec.symbol.clone());
+        }
+        let type_name = match recv {
+            Type::Named(n) | Type::Generic(n, _) => n,
+            _ => return None,
+        };
+        self.default_impls
+            .iter()
+            .find(|imp| &imp.struct_name == type_name && imp.method.name == name)
+            .map(|imp| imp.symbol.clone())
+    }
+
+    fn declare_default_methods(&mut self) {
+        for name in self.struct_names() {
+            let s = self.structs[&name].clone();
+            let recv = Type::Named(name.clone());
+            for (iface, args) in self.implemented_interfaces(&s) {
+                let subst = interface_subst(&iface, &args);
+                for method in iface.methods.iter().filter(|m| m.body.is_some()) {
+                    if self.method_symbol(&recv, &method.name).is_some() {
+                        continue;
+                    }
+                    let sig = self.method_sig(method, &subst);
+                    let symbol = mangle::function_symbol(Some(&recv), &method.name, &[], &sig.params);
+                    self.module.add_function(&symbol, self.method_fn_type(&sig), None);
+                    self.signatures.insert(symbol.clone(), sig);
+                    let self_ty = if args.is_empty() { Type::Named(iface.name.clone()) } else { Type::Generic(iface.name.clone(), args.clone()) };
+                    self.default_impls.push(DefaultImpl {
+                        symbol,
+                        struct_name: name.clone(),
+                        interface: iface.name.clone(),
+                        self_ty,
+                        method: method.clone(),
+                        subst: subst.clone(),
+                    });
+                }
+            }
+        }
+    }
+
+    /// One constant table of method pointers per `struct S implements I`, in the
+    /// order the interface declares its methods.
+    fn emit_vtables(&mut self) {
+        let i8p = self.i8_ptr();
+        for name in self.struct_names() {
+            let s = self.structs[&name].clone();
+            let recv = Type::Named(name.clone());
+            for (iface, _) in self.implemented_interfaces(&s) {
+                let slots: Vec<PointerValue> = iface
+                    .methods
+                    .iter()
+                    .map(|m| {
+                        self.method_symbol(&recv, &m.name)
+                            .and_then(|sym| self.module.get_function(&sym))
+                            .map(|f| f.as_global_value().as_pointer_value().const_cast(i8p))
+                            .unwrap_or_else(|| i8p.const_null())
+                    })
+                    .collect();
+                let table = self.module.add_global(i8p.array_type(slots.len() as u32), None, &vtable_symbol(&name, &iface.name));
+                table.set_initializer(&i8p.const_array(&slots));
+                table.set_constant(true);
+                table.set_linkage(Linkage::Private);
+            }
+        }
+    }
+
+    fn emit_default_method(&mut self, index: usize) {
+        let (symbol, struct_name, interface, self_ty, method, subst) = {
+            let imp = &self.default_impls[index];
+            (imp.symbol.clone(), imp.struct_name.clone(), imp.interface.clone(), imp.self_ty.clone(), imp.method.clone(), imp.subst.clone())
+        };
+        let func = match self.module.get_function(&symbol) {
+            Some(f) => f,
+            None => return,
+        };
+        let body = match &method.body {
+            Some(b) => b,
+            None => return,
+        };
+        let ret = self.signatures[&symbol].ret.clone();
+        self.begin_function(func, ret);
+        let mut params = func.get_param_iter();
+        if let Some(this) = params.next().and_then(|data| self.make_dyn(data, &struct_name, &interface)) {
+            self.bind_local("self", self_ty, this);
+        }
+        for (p, value) in method.params.iter().zip(params) {
+            self.bind_local(&p.name, Type::from_type_ref(&p.ty).substitute(&subst), value);
+        }
+        self.finish_function(func, body);
+    }
+
+    fn dyn_type(&self) -> StructType<'ctx> {
+        let i8p = self.i8_ptr();
+        self.context.struct_type(&[i8p.into(), i8p.ptr_type(AddressSpace::default()).into()], false)
+    }
+
+    fn interface_of(&self, ty: &Type) -> Option<String> {
+        match ty {
+            Type::Named(n) | Type::Generic(n, _) if self.interfaces.contains_key(n) => Some(n.clone()),
+            _ => None,
+        }
+    }
+
+    /// Packs a struct reference and its vtable for `iface` into a fat pointer.
+    fn make_dyn(&self, data: BasicValueEnum<'ctx>, struct_name: &str, iface: &str) -> Option<BasicValueEnum<'ctx>> {
+        let table = self.module.get_global(&vtable_symbol(struct_name, iface))?;
+        let i8p = self.i8_ptr();
+        if !data.is_pointer_value() {
+            return None;
+        }
+        let data = self.builder.build_pointer_cast(data.into_pointer_value(), i8p, "data").ok()?;
+        let table = table.as_pointer_value().const_cast(i8p.ptr_type(AddressSpace::default()));
+        let fat = self.builder.build_insert_value(self.dyn_type().get_undef(), data, 0, "dyn").ok()?;
+        let fat = self.builder.build_insert_value(fat, table, 1, "dyn").ok()?;
+        Some(fat.into_struct_value().as_basic_value_enum())
+    }
+
+    /// Calls `name` through the vtable of an interface-typed value.
+    fn emit_dyn_call(&mut self, recv: Value<'ctx>, name: &str, args: &[Expr]) -> Option<Value<'ctx>> {
+        let (iface_name, iface_args) = match &recv.ty {
+            Type::Named(n) => (n.clone(), Vec::new()),
+            Type::Generic(n, args) => (n.clone(), args.clone()),
+            _ => return None,
+        };
+        let iface = self.interfaces.get(&iface_name)?.clone();
+        let slot = iface.methods.iter().position(|m| m.name == name)?;
+        let sig = self.method_sig(&iface.methods[slot], &interface_subst(&iface, &iface_args));
+        let args = self.emit_args(args, Some(&sig.params))?;
+
+        let fat = recv.val.into_struct_value();
+        let data = self.builder.build_extract_value(fat, 0, "data").ok()?;
+        let table = self.builder.build_extract_value(fat, 1, "vtable").ok()?.into_pointer_value();
+        let i8p = self.i8_ptr();
+        let index = self.context.i64_type().const_int(slot as u64, false);
+        let slot_ptr = ir::elem_gep(&self.builder, i8p.into(), table, index, "slot");
+        let raw = ir::load(&self.builder, i8p.into(), slot_ptr, "method").into_pointer_value();
+        let fn_ty = self.method_fn_type(&sig);
+        let callee = self.builder.build_pointer_cast(raw, fn_ty.ptr_type(AddressSpace::default()), "method").ok()?;
+
+        let mut call_args: Vec<BasicMetadataValueEnum> = vec![data.into()];
+        for (arg, ty) in args.into_iter().zip(&sig.params) {
+            call_args.push(self.coerce(arg, ty)?.into());
+        }
+        ir::call_indirect(&self.builder, fn_ty, callee, &call_args, "dyn_call").map(|val| Value { val, ty: sig.ret })
+    }
+
+    // ---------- Statements ----------
+
+    fn emit_block(&mut self, block: &Block) -> Option<Value<'ctx>> {
+        self.scopes.push(HashMap::new());
+        let saved_consts = self.local_consts.clone();
+        let defers = self.defers.len();
+        let mut value = None;
+        self.cover(Anchor::block(block));
+        for (i, stmt) in block.stmts.iter().enumerate() {
+            if self.is_terminated() {
+                break;
+            }
+            self.cover(Anchor::split(block, i));
+            if i + 1 == block.stmts.len() && block.tail.is_none() {
+                value = self.emit_stmt_value(stmt);
+            } else {
+                self.emit_stmt(stmt);
+            }
+        }
+        if let Some(tail) = &block.tail {
+            if !self.is_terminated() {
+                self.cover(Anchor::split(block, block.stmts.len()));
+                value = self.emit_expr(tail);
+            }
+        }
+        self.run_defers(defers);
+        self.defers.truncate(defers);
+        self.local_consts = saved_consts;
+        self.scopes.pop();
+        value
+    }
+
+    /// Emits the deferred blocks above `depth`, innermost first, on the
+    /// current path. They stay registered for the block's other exits.
+    fn run_defers(&mut self, depth: usize) {
+        let pending = self.defers.split_off(depth.min(self.defers.len()));
+        for d in pending.iter().rev() {
+            if self.is_terminated() {
+                break;
+            }
+            let scopes = std::mem::replace(&mut self.scopes, d.scopes.clone());
+            let consts = std::mem::replace(&mut self.local_consts, d.consts.clone());
+            self.emit_block(&d.body);
+            self.scopes = scopes;
+            self.local_consts = consts;
+        }
+        self.defers.extend(pending);
+    }
+
+    fn loop_target(&self, label: &Option<String>) -> Option<usize> {
+        LoopTarget::find(&self.loops, label)
+    }
+
+    /// Statements that can end a block and give it a value.
+    fn emit_stmt_value(&mut self, stmt: &Stmt) -> Option<Value<'ctx>> {
+        match stmt {
+            Stmt::If(cond, then_block, else_stmt, _) => {
+                let else_branch = else_stmt.as_deref().map(Branch::Stmt).unwrap_or(Branch::Empty);
+                self.emit_if(cond, Branch::Block(then_block), else_branch, true)
+            }
+            Stmt::Match(expr, arms, _) => self.emit_match(expr, arms, true),
+            Stmt::Block(b) => self.emit_block(b),
+            _ => {
+                self.emit_stmt(stmt);
+                None
+            }
+        }
+    }
+
+    fn emit_stmt(&mut self, stmt: &Stmt) {
+        match stmt {
+            Stmt::Var(v) if v.pattern.is_some() => {
+                let ann = v.ty.as_ref().map(Type::from_type_ref);
+                if let (Some(pat), Some(value)) = (&v.pattern, self.emit_expr_expecting(&v.value, ann.as_ref())) {
+                    self.bind_pattern(pat, value);
+                }
+            }
+            Stmt::Var(v) => {
+                let ann = v.ty.as_ref().map(Type::from_type_ref);
+                let value = self.emit_expr_expecting(&v.value, ann.as_ref());
+                let sized = ann.as_ref().or(value.as_ref().map(|v| &v.ty)).is_some_and(Type::is_sized);
+                if !v.mutable && !sized {
+                    match self.consts.eval_with(&v.value, &self.local_consts) {
+                        Ok(c) => {
+                            self.local_consts.insert(v.name.clone(), c);
+                        }
+                        Err(_) => {
+                            self.local_consts.remove(&v.name);
+                        }
+                    }
+                } else {
+                    self.local_consts.remove(&v.name);
+                }
+                if let Some(value) = value {
+                    let ty = match ann {
+                        Some(t) if !matches!(t, Type::Unknown | Type::Any) => t,
+                        _ => value.ty.clone(),
+                    };
+                    self.bind_local(&v.name, ty, value.val);
+                }
+            }
+            Stmt::Expr(e, _) => {
+                let _ = self.emit_expr(e);
+            }
+            Stmt::Return(e, _) => {
+                let ret = self.current_ret.clone();
+                let value = e.as_ref().and_then(|e| self.emit_expr_expecting(e, Some(&ret)));
+                self.run_defers(0);
+                if !self.is_terminated() {
+                    self.emit_return(value);
+                }
+            }
+            Stmt::Break(label, value, _) => {
+                let i = match self.loop_target(label) {
+                    Some(i) => i,
+                    None => return,
+                };
+                let value = value.as_ref().and_then(|e| self.emit_expr(e));
+                if let Some(mut result) = self.loops[i].result.take() {
+                    self.store_result(&mut result, value);
+                    self.loops[i].result = Some(result);
+                }
+                self.run_defers(self.loops[i].defers);
+                if !self.is_terminated() {
+                    let _ = self.builder.build_unconditional_branch(self.loops[i].break_to);
+                }
+            }
+            Stmt::Continue(label, _) => {
+                let i = match self.loop_target(label) {
+                    Some(i) => i,
+                    None => return,
+                };
+                self.run_defers(self.loops[i].defers);
+                if !self.is_terminated() {
+                    let _ = self.builder.build_unconditional_branch(self.loops[i].continue_to);
+                }
+            }
+            Stmt::Defer(body, _) => {
+                let deferred = Deferred { body: body.clone(), scopes: self.scopes.clone(), consts: self.local_consts.clone() };
+                self.defers.push(deferred);
+            }
+            Stmt::If(..) | Stmt::Match(..) | Stmt::Block(_) => {
+                let _ = self.emit_stmt_value(stmt);
+            }
+            Stmt::While(cond, body, label, _) => self.emit_while(label, cond, body),
+            Stmt::For(name, iter, body, label, _) => self.emit_for(label, name, iter, body),
+        }
+    }
+
+    fn push_loop(&mut self, label: &Option<String>, continue_to: BasicBlock<'ctx>, break_to: BasicBlock<'ctx>, result: Option<ResultSlot<'ctx>>) {
+        let defers = self.defers.len();
+        self.loops.push(LoopTarget { label: label.clone(), continue_to, break_to, defers, result });
+    }
+
+    /// `loop { .. }` is left only through `break`, whose values merge into
+    /// the loop's value.
+    fn emit_loop(&mut self, label: &Option<String>, body: &Block) -> Option<Value<'ctx>> {
+        let func = self.current_function()?;
+        let body_bb = self.context.append_basic_block(func, "loop.body");
+        let exit_bb = self.context.append_basic_block(func, "loop.end");
+        let _ = self.builder.build_unconditional_branch(body_bb);
+        self.builder.position_at_end(body_bb);
+        self.push_loop(label, body_bb, exit_bb, Some(ResultSlot::new(true)));
+        self.emit_block(body);
+        let target = self.loops.pop()?;
+        if !self.is_terminated() {
+            let _ = self.builder.build_unconditional_branch(body_bb);
+        }
+        self.builder.position_at_end(exit_bb);
+        self.load_result(target.result?)
+    }
+
+    fn emit_return(&mut self, value: Option<Value<'ctx>>) {
+        let ret = self.current_ret.clone();
+        if is_void(&ret) {
+            let _ = self.builder.build_return(None);
+            return;
+        }
+        let llvm = self.llvm_ty(&ret);
+        let v = value.and_then(|v| self.coerce(v, &ret)).unwrap_or_else(|| llvm.const_zero());
+        let _ = self.builder.build_return(Some(&v));
+    }
+
+    fn emit_while(&mut self, label: &Option<String>, cond: &Expr, body: &Block) {
+        let func = match self.current_function() {
+            Some(f) => f,
+            None => return,
+        };
+        let cond_bb = self.context.append_basic_block(func, "while.cond");
+        let body_bb = self.context.append_basic_block(func, "while.body");
+        let exit_bb = self.context.append_basic_block(func, "while.end");
+        let _ = self.builder.build_unconditional_branch(cond_bb);
+        self.builder.position_at_end(cond_bb);
+        match self.emit_cond(cond) {
+            Some(c) => {
+                let _ = self.builder.build_conditional_branch(c, body_bb, exit_bb);
+            }
+            None => {
+                let _ = self.builder.build_unconditional_branch(exit_bb);
+            }
+        }
+        self.builder.position_at_end(body_bb);
+        self.push_loop(label, cond_bb, exit_bb, None);
+        self.emit_block(body);
+        self.loops.pop();
+        if !self.is_terminated() {
+            let _ = self.builder.build_unconditional_branch(cond_bb);
+        }
+        self.builder.position_at_end(exit_bb);
+    }
+
+    /// `for x in e` counts through a range, walks a list by index, or calls
+    /// `next()` on an iterator until it returns null. An iterable is iterated
+    /// through the iterator its `iter()` returns.
+    fn emit_for(&mut self, label: &Option<String>, name: &str, iter: &Expr, body: &Block) {
+        if let Some(value) = self.emit_expr(iter).and_then(|v| self.unwrap_optional(v)) {
+            self.emit_for_value(label, name, value, body);
+        }
+    }
+
+    fn emit_for_value(&mut self, label: &Option<String>, name: &str, value: Value<'ctx>, body: &Block) {
+        if let Some(elem_ty) = value.ty.range_element() {
+            self.emit_for_range(label, name, value, elem_ty, body);
+        } else if let Some(elem_ty) = value.ty.list_element() {
+            self.emit_for_list(label, name, value, elem_ty, body);
+        } else if self.has_method(&value.ty, "next") {
+            self.emit_for_iterator(label, name, value, body);
+        } else if self.has_method(&value.ty, "iter") {
+            if let Some(iterator) = self.call_method(value, "iter", &[]).and_then(|v| self.unwrap_optional(v)) {
+                self.emit_for_value(label, name, iterator, body);
+            }
+        }
+    }
+
+    /// Counts from `start` up to `end`. The step compares against `end`
+    /// before the incremented counter is used, so `0..=MAX` ends instead of
+    /// wrapping around.
+    fn emit_for_range(&mut self, label: &Option<String>, name: &str, range: Value<'ctx>, elem_ty: Type, body: &Block) -> Option<()> {
+        let func = self.current_function()?;
+        let bounds = match range.val {
+            BasicValueEnum::StructValue(v) => v,
+            _ => return None,
+        };
+        let start = self.builder.build_extract_value(bounds, 0, "start").ok()?.into_int_value();
+        let end = self.builder.build_extract_value(bounds, 1, "end").ok()?.into_int_value();
+        let inclusive = self.builder.build_extract_value(bounds, 2, "inclusive").ok()?.into_int_value();
+        let counter_ty = start.get_type();
+        let counter = self.entry_alloca(counter_ty.into(), "for.index");
+        let _ = self.builder.build_store(counter, start);
+
+        let cond_bb = self.context.append_basic_block(func, "for.cond");
+        let body_bb = self.context.append_basic_block(func, "for.body");
+        let step_bb = self.context.append_basic_block(func, "for.step");
+        let exit_bb = self.context.append_basic_block(func, "for.end");
+        let _ = self.builder.build_unconditional_branch(cond_bb);
+
+        self.builder.position_at_end(cond_bb);
+        let i = ir::load(&self.builder, counter_ty.into(), counter, "i").into_int_value();
+        let below = if matches!(elem_ty.int_info(), Some((_, false))) { IntPredicate::ULT } else { IntPredicate::SLT };
+        let before_end = self.builder.build_int_compare(below, i, end, "before_end").ok()?;
+        let at_end = self.builder.build_int_compare(IntPredicate::EQ, i, end, "at_end").ok()?;
+        let last = self.builder.build_and(inclusive, at_end, "last").ok()?;
+        let more = self.builder.build_or(before_end, last, "more").ok()?;
+        let _ = self.builder.build_conditional_branch(more, body_bb, exit_bb);
+
+        self.builder.position_at_end(body_bb);
+        self.scopes.push(HashMap::new());
+        self.bind_local(name, elem_ty, i.into());
+        self.push_loop(label, step_bb, exit_bb, None);
+        self.emit_block(body);
+        self.loops.pop();
+        self.scopes.pop();
+        if !self.is_terminated() {
+            let _ = self.builder.build_unconditional_branch(step_bb);
+        }
+
+        self.builder.position_at_end(step_bb);
+        let i = ir::load(&self.builder, counter_ty.into(), counter, "i").into_int_value();
+        let next = self.builder.build_int_add(i, counter_ty.const_int(1, false), "next").ok()?;
+        let _ = self.builder.build_store(counter, next);
+        let done = self.builder.build_int_compare(IntPredicate::EQ, i, end, "done").ok()?;
+        let _ = self.builder.build_conditional_branch(done, exit_bb, cond_bb);
+        self.builder.position_at_end(exit_bb);
+        Some(())
+    }
+
+    fn emit_for_list(&mut self, label: &Option<String>, name: &str, list: Value<'ctx>, elem_ty: Type, body: &Block) -> Option<()> {
+        let func = self.current_function()?;
+        if !list.val.is_pointer_value() {
+            return None;
+        }
+        let i64t = self.context.i64_type();
+        let len = self.list_len(list.val)?;
+        let index = self.entry_alloca(i64t.into(), "for.index");
+        let _ = self.builder.build_store(index, i64t.const_zero());
+
+        let cond_bb = self.context.append_basic_block(func, "for.cond");
+        let body_bb = self.context.append_basic_block(func, "for.body");
+        let step_bb = self.context.append_basic_block(func, "for.step");
+        let exit_bb = self.context.append_basic_block(func, "for.end");
+        let _ = self.builder.build_unconditional_branch(cond_bb);
+
+        self.builder.position_at_end(cond_bb);
+        let i = ir::load(&self.builder, i64t.into(), index, "i").into_int_value();
+        let more = self.builder.build_int_compare(IntPredicate::SLT, i, len, "more").ok();
+        match more {
+            Some(c) => {
+                let _ = self.builder.build_conditional_branch(c, body_bb, exit_bb);
+            }
+            None => {
+                let _ = self.builder.build_unconditional_branch(exit_bb);
+            }
+        }
+
+        self.builder.position_at_end(body_bb);
+        self.scopes.push(HashMap::new());
+        if let Some((ptr, llvm)) = self.list_slot(list.val, i, &elem_ty) {
+            let elem = ir::load(&self.builder, llvm, ptr, name);
+            self.bind_local(name, elem_ty, elem);
+        }
+        self.push_loop(label, step_bb, exit_bb, None);
+        self.emit_block(body);
+        self.loops.pop();
+        self.scopes.pop();
+        if !self.is_terminated() {
+            let _ = self.builder.build_unconditional_branch(step_bb);
+        }
+
+        self.builder.position_at_end(step_bb);
+        let i = ir::load(&self.builder, i64t.into(), index, "i").into_int_value();
+        if let Ok(next) = self.builder.build_int_add(i, i64t.const_int(1, false), "next") {
+            let _ = self.builder.build_store(index, next);
+        }
+        let _ = self.builder.build_unconditional_branch(cond_bb);
+        self.builder.position_at_end(exit_bb);
+        Some(())
+    }
+
+    /// Calls `next()` at the top of every iteration and leaves on null.
+    fn emit_for_iterator(&mut self, label: &Option<String>, name: &str, iter: Value<'ctx>, body: &Block) -> Option<()> {
+        let func = self.current_function()?;
+        let cond_bb = self.context.append_basic_block(func, "for.next");
+        let body_bb = self.context.append_basic_block(func, "for.body");
+        let exit_bb = self.context.append_basic_block(func, "for.end");
+        let _ = self.builder.build_unconditional_branch(cond_bb);
+
+        self.builder.position_at_end(cond_bb);
+        let item = match self.call_method(iter, "next", &[]) {
+            Some(item) => item,
+            None => {
+                let _ = self.builder.build_unconditional_branch(exit_bb);
+                self.builder.position_at_end(exit_bb);
+                return None;
+            }
+        };
+        let present = self.is_some(&item)?;
+        let _ = self.builder.build_conditional_branch(present, body_bb, exit_bb);
+
+        self.builder.position_at_end(body_bb);
+        self.scopes.push(HashMap::new());
+        if let Some(v) = self.unwrap_optional(item) {
+            self.bind_local(name, v.ty, v.val);
+        }
+        self.push_loop(label, cond_bb, exit_bb, None);
+        self.emit_block(body);
+        self.loops.pop();
+        self.scopes.pop();
+        if !self.is_terminated() {
+            let _ = self.builder.build_unconditional_branch(cond_bb);
+        }
+        self.builder.position_at_end(exit_bb);
+        Some(())
+    }
+
+    /// Lowers `if`/`else` and, when `want` is set and every open branch yields
+    /// a value, returns the merged value.
+    fn emit_if(&mut self, cond: &Expr, then_branch: Branch, else_branch: Branch, want: bool) -> Option<Value<'ctx>> {
+        let func = self.current_function()?;
+        let c = self.emit_cond(cond)?;
+        let then_bb = self.context.append_basic_block(func, "if.then");
+        let else_bb = self.context.append_basic_block(func, "if.else");
+        let merge_bb = self.context.append_basic_block(func, "if.end");
+        let _ = self.builder.build_conditional_branch(c, then_bb, else_bb);
+
+        let mut result = ResultSlot::new(want);
+        for (bb, branch) in [(then_bb, then_branch), (else_bb, else_branch)] {
+            self.builder.position_at_end(bb);
+            let v = match branch {
+                Branch::Block(b) => self.emit_block(b),
+                Branch::Stmt(s) => {
+                    if let Stmt::If(.., span) = s {
+                        self.cover(Anchor::Else(span.start.offset));
+                    }
+                    self.emit_stmt_value(s)
+                }
+                Branch::Empty => None,
+            };
+            if !self.is_terminated() {
+                self.store_result(&mut result, v);
+                let _ = self.builder.build_unconditional_branch(merge_bb);
+            }
+        }
+        self.builder.position_at_end(merge_bb);
+        self.load_result(result)
+    }
+
+    /// Lowers `match` over literal, binding and wildcard patterns. Other
+    /// patterns are left to later stages and skip the whole match.
+    fn emit_match(&mut self, scrutinee: &Expr, arms: &[MatchArm], want: bool) -> Option<Value<'ctx>> {
+        let func = self.current_function()?;
+        let v = self.emit_expr(scrutinee)?;
+        if !arms.iter().all(|a| self.lowerable_pattern(&a.pat, &v.ty)) {
+            return None;
+        }
+        let merge_bb = self.context.append_basic_block(func, "match.end");
+        let mut result = ResultSlot::new(want);
+        for arm in arms {
+            let arm_bb = self.context.append_basic_block(func, "match.arm");
+            let next_bb = self.context.append_basic_block(func, "match.next");
+            match &arm.pat {
+                Pattern::Literal(lit, _) => {
+                    let expected = self.emit_const(lit.clone());
+                    match self.emit_binary_values(v.clone(), BinaryOp::Eq, expected, arm.span) {
+                        Some(eq) => {
+                            let _ = self.builder.build_conditional_branch(eq.val.into_int_value(), arm_bb, next_bb);
+                        }
+                        None => {
+                            let _ = self.builder.build_unconditional_branch(next_bb);
+                        }
+                    }
+                }
+                Pattern::Is(..) if matches!(v.ty, Type::Optional(_)) => match self.is_some(&v) {
+                    Some(some) => {
+                        let _ = self.builder.build_conditional_branch(some, arm_bb, next_bb);
+                    }
+                    None => {
+                        let _ = self.builder.build_unconditional_branch(next_bb);
+                    }
+                },
+                _ => {
+                    let _ = self.builder.build_unconditional_branch(arm_bb);
+                }
+            }
+            self.builder.position_at_end(arm_bb);
+            self.scopes.push(HashMap::new());
+            match &arm.pat {
+                Pattern::Ident(name, _) => self.bind_local(name, v.ty.clone(), v.val),
+                Pattern::Is(_, inner, _) => {
+                    if let Pattern::Ident(name, _) = &**inner {
+                        if let Some(payload) = self.unwrap_optional(v.clone()) {
+                            self.bind_local(name, payload.ty, payload.val);
+                        }
+                    }
+                }
+                _ => {}
+            }
+            self.cover(Anchor::arm(arm));
+            let value = self.emit_expr(&arm.body);
+            self.scopes.pop();
+            if !self.is_terminated() {
+                self.store_result(&mut result, value);
+                let _ = self.builder.build_unconditional_branch(merge_bb);
+            }
+            self.builder.position_at_end(next_bb);
+        }
+        // Sema has checked exhaustiveness, so falling off the last arm cannot happen.
+        let _ = self.builder.build_unreachable();
+        self.builder.position_at_end(merge_bb);
+        self.load_result(result)
+    }
+
+    fn lowerable_pattern(&self, pat: &Pattern, ty: &Type) -> bool {
+        match pat {
+            Pattern::Wildcard(_) => true,
+            Pattern::Literal(..) => !matches!(ty, Type::Optional(_)),
+            Pattern::Ident(name, _) => !name.contains('.') && !self.variants.contains(name),
+            // `is T` on a `T?` is a null test.
+            Pattern::Is(tr, inner, _) => {
+                let target = Type::from_type_ref(tr);
+                let tested = match ty {
+                    Type::Optional(inner) => &**inner,
+                    other => other,
+                };
+                &target == tested && matches!(&**inner, Pattern::Wildcard(_) | Pattern::Ident(..))
+            }
+            _ => false,
+        }
+    }
+
+    fn store_result(&mut self, result: &mut ResultSlot<'ctx>, value: Option<Value<'ctx>>) {
+        if !result.ok {
+            return;
+        }
+        let value = match value {
+            Some(v) => v,
+            None => {
+                result.ok = false;
+                return;
+            }
+        };
+        match &result.slot {
+            None => {
+                let slot = self.entry_alloca(value.val.get_type(), "result");
+                let _ = self.builder.build_store(slot, value.val);
+                result.slot = Some(Local { ptr: slot, ty: value.ty, llvm: value.val.get_type() });
+            }
+            Some(local) => match self.coerce(value, &local.ty).and_then(|v| self.cast_to(v, local.llvm)) {
+                Some(v) => {
+                    let _ = self.builder.build_store(local.ptr, v);
+                }
+                None => result.ok = false,
+            },
+        }
+    }
+
+    fn load_result(&mut self, result: ResultSlot<'ctx>) -> Option<Value<'ctx>> {
+        if !result.ok {
+            return None;
+        }
+        let local = result.slot?;
+        Some(Value { val: ir::load(&self.builder, local.llvm, local.ptr, "result"), ty: local.ty })
+    }
+
+    // ---------- Expressions ----------
+
+    fn emit_expr_expecting(&mut self, expr: &Expr, expected: Option<&Type>) -> Option<Value<'ctx>> {
+        let expected = expected.filter(|t| !matches!(t, Type::Unknown | Type::Any));
+        if let (Expr::Array(items, _), Some(t)) = (expr, expected) {
+            return self.emit_array(items, Some(t));
+        }
+        if let (Expr::Tuple(items, _), Some(Type::Tuple(tys))) = (expr, expected) {
+            if items.len() == tys.len() {
+                return self.emit_tuple(items, tys);
+            }
+        }
+        let v = self.emit_expr(expr)?;
+        match expected {
+            Some(t) => {
+                let val = self.coerce(v, t)?;
+                Some(Value { val, ty: t.clone() })
+            }
+            None => Some(v),
+        }
+    }
+
+    fn emit_expr(&mut self, expr: &Expr) -> Option<Value<'ctx>> {
+        let shadowed = matches!(expr, Expr::Ident(name, _) if self.lookup_local(name).is_some() && !self.local_consts.contains_key(name));
+        if !shadowed && !mentions_cast(expr) {
+            if let Some(lit) = self.const_literal(expr) {
+                return Some(self.emit_const(lit));
+            }
+        }
+        match expr {
+            Expr::Literal(lit, _) => Some(self.emit_const(lit.clone())),
+            Expr::Ident(name, _) => {
+                if name == "null" {
+                    return Some(Value { val: self.i8_ptr().const_null().into(), ty: Type::Nothing });
+                }
+                let local = self.lookup_local(name)?;
+                Some(Value { val: ir::load(&self.builder, local.llvm, local.ptr, name), ty: local.ty })
+            }
+            Expr::StructLit { name, fields, .. } => self.emit_struct_lit(name, fields),
+            Expr::Unary { op, expr, span } => self.emit_unary(*op, expr, *span),
+            Expr::Binary { left, op, right, span } => self.emit_binary(left, *op, right, *span),
+            Expr::Assign { left, op, right, span } => {
+                self.emit_assign(left, *op, right, *span);
+                None
+            }
+            Expr::Call { callee, args, .. } => self.emit_call(callee, args),
+            Expr::Member { target, name, .. } => {
+                if let Ok(index) = name.parse::<u32>() {
+                    return self.emit_tuple_field(target, index);
+                }
+                let (ptr, ty, llvm) = self.field_ptr(target, name)?;
+                Some(Value { val: ir::load(&self.builder, llvm, ptr, name), ty })
+            }
+            Expr::Index { target, index, .. } => {
+                let (ptr, ty, llvm) = self.element_ptr(target, index)?;
+                Some(Value { val: ir::load(&self.builder, llvm, ptr, "elem"), ty })
+            }
+            Expr::Range { start, end, inclusive, .. } => self.emit_range(start, end, *inclusive),
+            Expr::Cast { expr, ty, .. } => {
+                let v = self.emit_expr(expr)?;
+                let to = Type::from_type_ref(ty);
+                let val = self.convert_number(v, &to)?;
+                Some(Value { val, ty: to })
+            }
+            Expr::If { cond, then_block, else_block, .. } => {
+                self.emit_if(cond, Branch::Block(then_block), Branch::Block(else_block), true)
+            }
+            Expr::Match { expr, arms, .. } => self.emit_match(expr, arms, true),
+            Expr::Block(b) => self.emit_block(b),
+            Expr::Loop { label, body, .. } => self.emit_loop(label, body),
+            Expr::Array(items, _) => self.emit_array(items, None),
+            Expr::Tuple(items, _) => self.emit_tuple(items, &[]),
+            Expr::Instantiate { .. } | Expr::Tensor(..) | Expr::Interpolated { .. } => None,
+        }
+    }
+
+    fn emit_const(&self, lit: Literal) -> Value<'ctx> {
+        match lit {
+            Literal::Int(v) => Value { val: self.context.i64_type().const_int(v as u64, true).into(), ty: Type::Int },
+            Literal::Float(v) => Value { val: self.context.f64_type().const_float(v).into(), ty: Type::Float },
+            Literal::Bool(v) => Value { val: self.context.bool_type().const_int(v as u64, false).into(), ty: Type::Bool },
+            Literal::Char(c) => Value { val: self.context.i32_type().const_int(c as u64, false).into(), ty: Type::Char },
+            Literal::String(s) => Value {
+                val: self
+                    .builder
+                    .build_global_string_ptr(&s, "str")
+                    .expect("global string")
+                    .as_pointer_value()
+                    .into(),
+                ty: Type::String,
+            },
+        }
+    }
+
+    fn emit_struct_lit(&mut self, name: &str, fields: &[(String, Expr)]) -> Option<Value<'ctx>> {
+        let decl = self.structs.get(name)?.clone();
+        let st = *self.struct_types.get(name)?;
+        let i64t = self.context.i64_type();
+        let alloc = self.runtime_fn("korlang_gc_alloc", &[i64t.into(), i64t.into()], Some(self.i8_ptr().into()));
+        let size = st.size_of()?;
+        let raw = self
+            .builder
+            .build_call(alloc, &[size.into(), i64t.const_int(8, false).into()], "obj")
+            .ok()?
+            .try_as_basic_value()
+            .left()?
+            .into_pointer_value();
+        let obj = self.builder.build_pointer_cast(raw, st.ptr_type(AddressSpace::default()), name).ok()?;
+        for (i, field) in decl.fields.iter().enumerate() {
+            let ty = Type::from_type_ref(&field.ty);
+            let llvm = self.llvm_ty(&ty);
+            let value = fields
+                .iter()
+                .find(|(n, _)| n == &field.name)
+                .and_then(|(_, e)| self.emit_expr_expecting(e, Some(&ty)))
+                .and_then(|v| self.cast_to(v.val, llvm))
+                .unwrap_or_else(|| llvm.const_zero());
+            let slot = ir::struct_gep(&self.builder, st, obj, i as u32, &field.name);
+            let _ = self.builder.build_store(slot, value);
+        }
+        Some(Value { val: raw.into(), ty: Type::Named(name.to_string()) })
+    }
+
+    /// A range is a `{ T start, T end, i1 inclusive }` value.
+    fn emit_range(&mut self, start: &Expr, end: &Expr, inclusive: bool) -> Option<Value<'ctx>> {
+        let l = self.emit_expr(start)?;
+        let r = self.emit_expr(end)?;
+        let (l, r) = self.match_operands(l, r)?;
+        let ty = Type::Generic("Range".to_string(), vec![l.ty.clone()]);
+        let pair = self.llvm_ty(&ty).into_struct_type();
+        let end = self.cast_to(r.val, l.val.get_type())?;
+        let flag = self.context.bool_type().const_int(inclusive as u64, false);
+        let v = self.builder.build_insert_value(pair.get_undef(), l.val, 0, "range").ok()?;
+        let v = self.builder.build_insert_value(v, end, 1, "range").ok()?;
+        let v = self.builder.build_insert_value(v, flag, 2, "range").ok()?;
+        Some(Value { val: v.into_struct_value().into(), ty })
+    }
+
+    /// Tuples are LLVM struct values, so they are copied like scalars.
+    /// `expected` gives element types to convert to, when known.
+    fn emit_tuple(&mut self, items: &[Expr], expected: &[Type]) -> Option<Value<'ctx>> {
+        let values = items
+            .iter()
+            .enumerate()
+            .map(|(i, item)| self.emit_expr_expecting(item, expected.get(i)))
+            .collect::<Option<Vec<_>>>()?;
+        let ty = Type::Tuple(values.iter().map(|v| v.ty.clone()).collect());
+        let tuple = self.llvm_ty(&ty).into_struct_type();
+        let mut agg = tuple.get_undef();
+        for (i, v) in values.into_iter().enumerate() {
+            agg = self.builder.build_insert_value(agg, v.val, i as u32, "tuple").ok()?.into_struct_value();
+        }
+        Some(Value { val: agg.into(), ty })
+    }
+
+    fn emit_tuple_field(&mut self, target: &Expr, index: u32) -> Option<Value<'ctx>> {
+        let tuple = self.emit_expr(target)?;
+        let tuple = self.unwrap_optional(tuple)?;
+        let ty = match &tuple.ty {
+            Type::Tuple(tys) => tys.get(index as usize)?.clone(),
+            _ => return None,
+        };
+        let val = self.builder.build_extract_value(tuple.val.into_struct_value(), index, "field").ok()?;
+        Some(Value { val, ty })
+    }
+
+    fn emit_array(&mut self, items: &[Expr], expected: Option<&Type>) -> Option<Value<'ctx>> {
+        let expected_elem = expected.and_then(|t| t.list_element()).filter(|t| !matches!(t, Type::Unknown | Type::Any));
+        let mut values = Vec::new();
+        for item in items {
+            values.push(self.emit_expr_expecting(item, expected_elem.as_ref())?);
+        }
+        let elem_ty = match expected_elem {
+            Some(t) => t,
+            None => match values.first() {
+                Some(first) if values.iter().all(|v| v.ty == first.ty) => first.ty.clone(),
+                _ => Type::Any,
+            },
+        };
+        let llvm = self.llvm_ty(&elem_ty);
+        let i64t = self.context.i64_type();
+        let i8p = self.i8_ptr();
+        let new = self.runtime_fn("korlang_list_new", &[i64t.into(), i64t.into()], Some(i8p.into()));
+        let push = self.runtime_fn("korlang_list_push", &[i8p.into(), i8p.into()], None);
+        let size = llvm.size_of()?;
+        let cap = i64t.const_int(items.len() as u64, false);
+        let list = self.builder.build_call(new, &[size.into(), cap.into()], "list").ok()?.try_as_basic_value().left()?;
+        let slot = self.entry_alloca(llvm, "elem");
+        let slot_bytes = self.builder.build_pointer_cast(slot, i8p, "elem").ok()?;
+        for v in values {
+            let v = self.cast_to(v.val, llvm)?;
+            let _ = self.builder.build_store(slot, v);
+            let _ = self.builder.build_call(push, &[list.into(), slot_bytes.into()], "");
+        }
+        Some(Value { val: list, ty: Type::Array(Box::new(elem_ty)) })
+    }
+
+    fn emit_unary(&mut self, op: UnaryOp, expr: &Expr, span: Span) -> Option<Value<'ctx>> {
+        let v = self.emit_expr(expr)?;
+        let v = self.unwrap_optional(v)?;
+        let val: BasicValueEnum = match (op, v.val) {
+            (UnaryOp::Pos, _) => return Some(v),
+            (UnaryOp::Not | UnaryOp::BitNot, BasicValueEnum::IntValue(i)) => self.builder.build_not(i, "not").ok()?.into(),
+            (UnaryOp::Neg, BasicValueEnum::IntValue(i)) if self.overflow_checks && matches!(v.ty.int_info(), Some((_, true))) => {
+                let (neg, overflow) = self.overflowing(BinaryOp::Sub, i.get_type().const_zero(), i, true)?;
+                let report = UbsanReport { handler: "negate_overflow", signed: true, operands: vec![i] };
+                self.panic_if(overflow, "attempt to negate with overflow", span, Some(report))?;
+                neg.into()
+            }
+            (UnaryOp::Neg, BasicValueEnum::IntValue(i)) => self.builder.build_int_neg(i, "neg").ok()?.into(),
+            (UnaryOp::Neg, BasicValueEnum::FloatValue(f)) => self.builder.build_float_neg(f, "neg").ok()?.into(),
+            _ => return None,
+        };
+        Some(Value { val, ty: v.ty })
+    }
+
+    fn emit_binary(&mut self, left: &Expr, op: BinaryOp, right: &Expr, span: Span) -> Option<Value<'ctx>> {
+        match op {
+            BinaryOp::And | BinaryOp::Or => return self.emit_logical(left, op, right),
+            BinaryOp::NullCoalesce => return self.emit_coalesce(left, right),
+            _ => {}
+        }
+        let l = self.emit_expr(left)?;
+        let r = self.emit_expr(right)?;
+        self.emit_binary_values(l, op, r, span)
+    }
+
+    fn emit_binary_values(&mut self, l: Value<'ctx>, op: BinaryOp, r: Value<'ctx>, span: Span) -> Option<Value<'ctx>> {
+        use BinaryOp::*;
+        if matches!(op, Eq | NotEq) && (l.ty == Type::Nothing || r.ty == Type::Nothing) {
+            return self.emit_null_test(&l, op, &r);
+        }
+        // Sema only lets a nullable operand through once it is known to be non-null.
+        let l = self.unwrap_optional(l)?;
+        let r = self.unwrap_optional(r)?;
+        let (l, r) = self.match_operands(l, r)?;
+        let (lv, rv) = match (l.val, r.val) {
+            (BasicValueEnum::IntValue(_), BasicValueEnum::FloatValue(_)) if l.ty != Type::Bool => {
+                (self.convert_number(l.clone(), &r.ty)?, r.val)
+            }
+            (BasicValueEnum::FloatValue(_), BasicValueEnum::IntValue(_)) if r.ty != Type::Bool => {
+                (l.val, self.convert_number(r.clone(), &l.ty)?)
+            }
+            pair => pair,
+        };
+        let b = &self.builder;
+        match (lv, rv) {
+            (BasicValueEnum::IntValue(a), BasicValueEnum::IntValue(c)) if a.get_type() == c.get_type() => {
+                let unsigned = matches!(l.ty.int_info(), Some((_, false)));
+                let arith = |v: Result<IntValue<'ctx>, _>| v.ok().map(|v| Value { val: v.into(), ty: l.ty.clone() });
+                if self.overflow_checks && matches!(op, Add | Sub | Mul) {
+                    let (v, overflow) = self.overflowing(op, a, c, !unsigned)?;
+                    let (verb, handler) = match op {
+                        Add => ("add", "add_overflow"),
+                        Sub => ("subtract", "sub_overflow"),
+                        _ => ("multiply", "mul_overflow"),
+                    };
+                    let report = UbsanReport { handler, signed: !unsigned, operands: vec![a, c] };
+                    self.panic_if(overflow, &format!("attempt to {} with overflow", verb), span, Some(report))?;
+                    return Some(Value { val: v.into(), ty: l.ty.clone() });
+                }
+                if matches!(op, Div | Mod) {
+                    let v = self.emit_int_division(op, a, c, unsigned, span)?;
+                    return Some(Value { val: v.into(), ty: l.ty.clone() });
+                }
+                let pred = match op {
+                    Add => return arith(b.build_int_add(a, c, "add")),
+                    Sub => return arith(b.build_int_sub(a, c, "sub")),
+                    Mul => return arith(b.build_int_mul(a, c, "mul")),
+                    Eq => IntPredicate::EQ,
+                    NotEq => IntPredicate::NE,
+                    Lt if unsigned => IntPredicate::ULT,
+                    Lt => IntPredicate::SLT,
+                    LtEq if unsigned => IntPredicate::ULE,
+                    LtEq => IntPredicate::SLE,
+                    Gt if unsigned => IntPredicate::UGT,
+                    Gt => IntPredicate::SGT,
+                    GtEq if unsigned => IntPredicate::UGE,
+                    GtEq => IntPredicate::SGE,
+                    _ => return None,
+                };
+                let v = b.build_int_compare(pred, a, c, "cmp").ok()?;
+                Some(Value { val: v.into(), ty: Type::Bool })
+            }
+            (BasicValueEnum::FloatValue(a), BasicValueEnum::FloatValue(c)) => {
+                let fty = if a.get_type() == self.context.f32_type() { Type::Float32 } else { Type::Float };
+                let arith = |v: Result<inkwell::values::FloatValue<'ctx>, _>| v.ok().map(|v| Value { val: v.into(), ty: fty.clone() });
+                let pred = match op {
+                    Add => return arith(b.build_float_add(a, c, "add")),
+                    Sub => return arith(b.build_float_sub(a, c, "sub")),
+                    Mul => return arith(b.build_float_mul(a, c, "mul")),
+                    Div => return arith(b.build_float_div(a, c, "div")),
+                    Mod => return arith(b.build_float_rem(a, c, "rem")),
+                    Eq => FloatPredicate::OEQ,
+                    NotEq => FloatPredicate::UNE,
+                    Lt => FloatPredicate::OLT,
+                    LtEq => FloatPredicate::OLE,
+                    Gt => FloatPredicate::OGT,
+                    GtEq => FloatPredicate::OGE,
+                    _ => return None,
+                };
+                let v = b.build_float_compare(pred, a, c, "cmp").ok()?;
+                Some(Value { val: v.into(), ty: Type::Bool })
+            }
+            (BasicValueEnum::PointerValue(a), BasicValueEnum::PointerValue(c)) => {
+                let pred = match op {
+                    Eq => IntPredicate::EQ,
+                    NotEq => IntPredicate::NE,
+                    _ => return None,
+                };
+                let i64t = self.context.i64_type();
+                let (x, y) = if l.ty == Type::String && r.ty == Type::String {
+                    let i8p = self.i8_ptr();
+                    let strcmp = self.runtime_fn("strcmp", &[i8p.into(), i8p.into()], Some(self.context.i32_type().into()));
+                    let order = self.builder.build_call(strcmp, &[a.into(), c.into()], "strcmp").ok()?.try_as_basic_value().left()?;
+                    (order.into_int_value(), self.context.i32_type().const_zero())
+                } else {
+                    (self.builder.build_ptr_to_int(a, i64t, "addr").ok()?, self.builder.build_ptr_to_int(c, i64t, "addr").ok()?)
+                };
+                let v = self.builder.build_int_compare(pred, x, y, "cmp").ok()?;
+                Some(Value { val: v.into(), ty: Type::Bool })
+            }
+            _ => None,
+        }
+    }
+
+    /// `x == null` / `x != null`.
+    fn emit_null_test(&mut self, l: &Value<'ctx>, op: BinaryOp, r: &Value<'ctx>) -> Option<Value<'ctx>> {
+        let operand = if l.ty == Type::Nothing { r } else { l };
+        let some = self.is_some(operand)?;
+        let val = match op {
+            BinaryOp::NotEq => some,
+            _ => self.builder.build_not(some, "is_null").ok()?,
+        };
+        Some(Value { val: val.into(), ty: Type::Bool })
+    }
+
+    fn emit_logical(&mut self, left: &Expr, op: BinaryOp, right: &Expr) -> Option<Value<'ctx>> {
+        let func = self.current_function()?;
+        let l = self.emit_cond(left)?;
+        let lhs_bb = self.builder.get_insert_block()?;
+        let rhs_bb = self.context.append_basic_block(func, "logic.rhs");
+        let merge_bb = self.context.append_basic_block(func, "logic.end");
+        let short_circuit = matches!(op, BinaryOp::Or);
+        if short_circuit {
+            let _ = self.builder.build_conditional_branch(l, merge_bb, rhs_bb);
+        } else {
+            let _ = self.builder.build_conditional_branch(l, rhs_bb, merge_bb);
+        }
+        self.builder.position_at_end(rhs_bb);
+        self.cover(Anchor::rhs(right));
+        let bool_t = self.context.bool_type();
+        let r = self.emit_cond(right).unwrap_or_else(|| bool_t.const_int(short_circuit as u64, false));
+        let rhs_end = self.builder.get_insert_block()?;
+        let _ = self.builder.build_unconditional_branch(merge_bb);
+        self.builder.position_at_end(merge_bb);
+        let phi = self.builder.build_phi(bool_t, "logic").ok()?;
+        let short = bool_t.const_int(short_circuit as u64, false);
+        phi.add_incoming(&[(&short, lhs_bb), (&r, rhs_end)]);
+        Some(Value { val: phi.as_basic_value(), ty: Type::Bool })
+    }
+
+    /// `a ?? b` evaluates `b` only when `a` is null.
+    fn emit_coalesce(&mut self, left: &Expr, right: &Expr) -> Option<Value<'ctx>> {
+        let func = self.current_function()?;
+        let l = self.emit_expr(left)?;
+        match l.ty {
+            Type::Optional(_) => {}
+            Type::Nothing => return self.emit_expr(right),
+            _ => return Some(l),
+        }
+        let some = self.is_some(&l)?;
+        let payload = self.unwrap_optional(l.clone())?;
+        let lhs_bb = self.builder.get_insert_block()?;
+        let rhs_bb = self.context.append_basic_block(func, "coalesce.rhs");
+        let merge_bb = self.context.append_basic_block(func, "coalesce.end");
+        let _ = self.builder.build_conditional_branch(some, merge_bb, rhs_bb);
+
+        self.builder.position_at_end(rhs_bb);
+        let r = self.emit_expr(right)?;
+        // A nullable fallback keeps the result nullable.
+        let (lhs, ty) = match r.ty {
+            Type::Optional(_) | Type::Nothing => (l.val, l.ty.clone()),
+            _ => (payload.val, payload.ty.clone()),
+        };
+        let r = self.coerce(r, &ty)?;
+        let rhs_end = self.builder.get_insert_block()?;
+        let _ = self.builder.build_unconditional_branch(merge_bb);
+
+        self.builder.position_at_end(merge_bb);
+        let phi = self.builder.build_phi(lhs.get_type(), "coalesce").ok()?;
+        phi.add_incoming(&[(&lhs, lhs_bb), (&r, rhs_end)]);
+        Some(Value { val: phi.as_basic_value(), ty })
+    }
+
+    fn emit_assign(&mut self, left: &Expr, op: AssignOp, right: &Expr, span: Span) -> Option<()> {
+        if let Expr::Ident(name, _) = left {
+            self.local_consts.remove(name);
+        }
+        let (ptr, ty, llvm) = self.emit_place(left)?;
+        let rhs = self.emit_expr_expecting(right, Some(&ty))?;
+        let value = match op {
+            AssignOp::Assign => rhs,
+            _ => {
+                let current = Value { val: ir::load(&self.builder, llvm, ptr, "cur"), ty: ty.clone() };
+                let bin = match op {
+                    AssignOp::AddAssign => BinaryOp::Add,
+                    AssignOp::SubAssign => BinaryOp::Sub,
+                    AssignOp::MulAssign => BinaryOp::Mul,
+                    AssignOp::DivAssign => BinaryOp::Div,
+                    AssignOp::ModAssign => BinaryOp::Mod,
+                    AssignOp::Assign => unreachable!(),
+                };
+                self.emit_binary_values(current, bin, rhs, span)?
+            }
+        };
+        let v = self.coerce(value, &ty).and_then(|v| self.cast_to(v, llvm))?;
+        self.builder.build_store(ptr, v).ok()?;
+        Some(())
+    }
+
+    fn emit_place(&mut self, expr: &Expr) -> Option<(PointerValue<'ctx>, Type, BasicTypeEnum<'ctx>)> {
+        match expr {
+            Expr::Ident(name, _) => self.lookup_local(name).map(|l| (l.ptr, l.ty, l.llvm)),
+            Expr::Member { target, name, .. } => match name.parse::<u32>() {
+                Ok(index) => self.tuple_field_ptr(target, index),
+                Err(_) => self.field_ptr(target, name),
+            },
+            Expr::Index { target, index, .. } => self.element_ptr(target, index),
+            _ => None,
+        }
+    }
+
+    fn tuple_field_ptr(&mut self, target: &Expr, index: u32) -> Option<(PointerValue<'ctx>, Type, BasicTypeEnum<'ctx>)> {
+        let (ptr, ty, llvm) = self.emit_place(target)?;
+        let ty = match ty {
+            Type::Tuple(tys) => tys.get(index as usize)?.clone(),
+            _ => return None,
+        };
+        let tuple = match llvm {
+            BasicTypeEnum::StructType(t) => t,
+            _ => return None,
         };
-        self.module.add_function(&fun.name, fn_type, None);
+        let slot = ir::struct_gep(&self.builder, tuple, ptr, index, "field");
+        Some((slot, ty, tuple.get_field_type_at_index(index)?))
     }
 
-    fn emit_function(&mut self, fun: &FunDecl) {
-        let func = match self.module.get_function(&fun.name) {
-            Some(f) => f,
-            None => return,
+    fn field_ptr(&mut self, target: &Expr, name: &str) -> Option<(PointerValue<'ctx>, Type, BasicTypeEnum<'ctx>)> {
+        let obj = self.emit_expr(target)?;
+        let obj = self.unwrap_optional(obj)?;
+        let struct_name = match &obj.ty {
+            Type::Named(n) | Type::Generic(n, _) => n.clone(),
+            _ => return None,
         };
-        let _escapes = self.escape_map.get(&fun.name);
-        let entry = self.context.append_basic_block(func, "entry");
-        self.builder.position_at_end(entry);
-        let mut returned = false;
-        self.local_literals.clear();
-
-        // Emit basic statement calls for the self-hosted driver.
-        for stmt in &fun.body.stmts {
-            match stmt {
-                Stmt::Var(v) => {
-                    if let Expr::Literal(lit, _) = self.fold_expr(&v.value) {
-                        self.local_literals.insert(v.name.clone(), lit);
-                    }
-                }
-                Stmt::Expr(Expr::Call { callee, args, .. }, _) => {
-                    let _ = self.emit_call(callee, args);
-                }
-                _ => {}
-            }
+        let decl = self.structs.get(&struct_name)?;
+        let index = decl.fields.iter().position(|f| f.name == name)?;
+        let ty = Type::from_type_ref(&decl.fields[index].ty);
+        let st = *self.struct_types.get(&struct_name)?;
+        if !obj.val.is_pointer_value() {
+            return None;
         }
+        let ptr = self.builder.build_pointer_cast(obj.val.into_pointer_value(), st.ptr_type(AddressSpace::default()), "obj").ok()?;
+        let slot = ir::struct_gep(&self.builder, st, ptr, index as u32, name);
+        Some((slot, ty, st.get_field_type_at_index(index as u32)?))
+    }
 
-        if let Some(ret) = &fun.ret {
-            if let Some(val) = self.try_emit_return(&fun.body, ret) {
-                let _ = self.builder.build_return(Some(&val));
-                returned = true;
-            }
+    fn element_ptr(&mut self, target: &Expr, index: &Expr) -> Option<(PointerValue<'ctx>, Type, BasicTypeEnum<'ctx>)> {
+        let list = self.emit_expr(target)?;
+        let list = self.unwrap_optional(list)?;
+        let elem_ty = list.ty.list_element()?;
+        let index = self.emit_expr(index)?;
+        let i = match self.unwrap_optional(index)?.val {
+            BasicValueEnum::IntValue(i) => i,
+            _ => return None,
+        };
+        let (ptr, llvm) = self.list_slot(list.val, i, &elem_ty)?;
+        Some((ptr, elem_ty, llvm))
+    }
+
+    /// Bounds-checked address of element `index`, typed for `elem_ty`.
+    fn list_slot(&mut self, list: BasicValueEnum<'ctx>, index: IntValue<'ctx>, elem_ty: &Type) -> Option<(PointerValue<'ctx>, BasicTypeEnum<'ctx>)> {
+        let i8p = self.i8_ptr();
+        let i64t = self.context.i64_type();
+        let at = self.runtime_fn("korlang_list_at", &[i8p.into(), i64t.into()], Some(i8p.into()));
+        let index = self.builder.build_int_cast(index, i64t, "index").ok()?;
+        let raw = self.builder.build_call(at, &[list.into(), index.into()], "at").ok()?.try_as_basic_value().left()?;
+        let llvm = self.llvm_ty(elem_ty);
+        let ptr = self.builder.build_pointer_cast(raw.into_pointer_value(), llvm.ptr_type(AddressSpace::default()), "slot").ok()?;
+        Some((ptr, llvm))
+    }
+
+    fn list_len(&mut self, list: BasicValueEnum<'ctx>) -> Option<IntValue<'ctx>> {
+        let i8p = self.i8_ptr();
+        let len = self.runtime_fn("korlang_list_len", &[i8p.into()], Some(self.context.i64_type().into()));
+        let v = self.builder.build_call(len, &[list.into()], "len").ok()?.try_as_basic_value().left()?;
+        Some(v.into_int_value())
+    }
+
+    fn emit_cond(&mut self, expr: &Expr) -> Option<IntValue<'ctx>> {
+        let v = self.emit_expr(expr)?;
+        match self.unwrap_optional(v)?.val {
+            BasicValueEnum::IntValue(i) if i.get_type().get_bit_width() == 1 => Some(i),
+            _ => None,
+        }
+    }
+
+    // ---------- Calls ----------
+
+    fn emit_call(&mut self, callee: &Expr, args: &[Expr]) -> Option<Value<'ctx>> {
+        if let Expr::Instantiate { name, type_args, .. } = callee {
+            let values = self.emit_args(args, None)?;
+            let arg_tys: Vec<Type> = values.iter().map(|v| v.ty.clone()).collect();
+            let symbol = self.select_specialization(name, type_args, &arg_tys)?;
+            return self.build_call(&symbol, None, values);
         }
-        if !returned {
-            if let Some(ret) = &fun.ret {
-                if let Some(zero) = self.emit_zero(ret) {
-                    let _ = self.builder.build_return(Some(&zero));
-                    return;
+        if let Expr::Member { target, name, .. } = callee {
+            // `Point.origin()` calls the static method `Point.origin`.
+            if let Expr::Ident(ty, span) = &**target {
+                let qualified = format!("{}.{}", ty, name);
+                if self.lookup_local(ty).is_none() && !self.specializations.candidates(&qualified, false).is_empty() {
+                    return self.emit_call(&Expr::Ident(qualified, *span), args);
                 }
             }
-            let _ = self.builder.build_return(None);
+            return self.emit_method_call(target, name, args);
         }
-    }
-
-    fn emit_call(&mut self, callee: &Expr, args: &[Expr]) -> Option<BasicValueEnum<'ctx>> {
         // Minimal FFI: @import("symbol") calls an extern symbol with no args.
         if let Expr::Ident(name, _) = callee {
             if (name == "print" || name == "println") && !args.is_empty() {
-                let is_ln = name == "println";
-                let folded = self.fold_expr(&args[0]);
-                if let Expr::Literal(lit, _) = folded {
-                    self.emit_print_literal(&lit, is_ln);
-                    return None;
-                }
-                if let Expr::Ident(id, _) = &args[0] {
-                    if let Some(lit) = self.local_literals.get(id).cloned() {
-                        self.emit_print_literal(&lit, is_ln);
-                        return None;
-                    }
-                }
+                self.emit_print(&args[0], name == "println");
+                return None;
+            }
+            if name == "staticAssert" {
+                // Checked entirely at compile time by Sema.
+                return None;
             }
             if name == "@import" {
                 if let Some(Expr::Literal(Literal::String(sym), _)) = args.get(0) {
@@ -142,47 +1613,220 @@ impl<'ctx> Codegen<'ctx> {
                     .get_function("korlang_ui_demo_window")
                     .unwrap_or_else(|| self.module.add_function("korlang_ui_demo_window", ty, None));
                 let call = self.builder.build_call(f, &[], "ui_demo").ok()?;
-                return call.try_as_basic_value().left();
+                return call.try_as_basic_value().left().map(|val| Value { val, ty: Type::Int });
+            }
+
+            let user_defined = !self.specializations.candidates(name, false).is_empty();
+            if INT_INTRINSICS.contains(&name.as_str()) && !user_defined && self.lookup_local(name).is_none() {
+                return self.emit_int_intrinsic(name, args);
+            }
+
+            if self.variadic_externs.contains(name) && self.lookup_local(name).is_none() {
+                return self.emit_variadic_call(name, args);
             }
 
             // Regular function calls for user-defined functions and externs.
-            if let Some(f) = self.module.get_function(name) {
-                let mut arg_values = Vec::new();
-                for a in args {
-                    if let Some(v) = self.emit_arg_value(a) {
-                        arg_values.push(v.into());
-                    } else {
-                        return None;
-                    }
-                }
-                let call = self.builder.build_call(f, &arg_values, "call").ok()?;
-                return call.try_as_basic_value().left();
+            if self.specializations.needs_selection(name) {
+                let values = self.emit_args(args, None)?;
+                let arg_tys: Vec<Type> = values.iter().map(|v| v.ty.clone()).collect();
+                let symbol = self.select_specialization(name, &[], &arg_tys)?;
+                return self.build_call(&symbol, None, values);
             }
+            let symbol = match self.specializations.candidates(name, false) {
+                [spec] => spec.symbol.clone(),
+                _ => name.clone(),
+            };
+            self.module.get_function(&symbol)?;
+            let params = self.signatures.get(&symbol).map(|s| s.params.clone());
+            let values = self.emit_args(args, params.as_deref())?;
+            return self.build_call(&symbol, None, values);
         }
         None
     }
 
-    fn emit_arg_value(&self, expr: &Expr) -> Option<BasicValueEnum<'ctx>> {
-        match self.fold_expr(expr) {
-            Expr::Literal(lit, _) => self.emit_literal_for_call(lit),
-            Expr::Ident(id, _) => self.local_literals.get(&id).and_then(|lit| self.emit_literal_for_call(lit.clone())),
+    fn emit_method_call(&mut self, target: &Expr, name: &str, args: &[Expr]) -> Option<Value<'ctx>> {
+        let recv = self.emit_expr(target)?;
+        let recv = self.unwrap_optional(recv)?;
+        self.call_method(recv, name, args)
+    }
+
+    fn has_method(&self, recv: &Type, name: &str) -> bool {
+        match self.interface_of(recv) {
+            Some(iface) => self.interfaces[&iface].methods.iter().any(|m| m.name == name),
+            None => self.method_symbol(recv, name).is_some(),
+        }
+    }
+
+    fn call_method(&mut self, recv: Value<'ctx>, name: &str, args: &[Expr]) -> Option<Value<'ctx>> {
+        if self.interface_of(&recv.ty).is_some() {
+            return self.emit_dyn_call(recv, name, args);
+        }
+        if recv.ty.list_element().is_some() {
+            match (name, args) {
+                ("len", []) => {
+                    let len = self.list_len(recv.val)?;
+                    return Some(Value { val: len.into(), ty: Type::Int });
+                }
+                ("push", [item]) => {
+                    let elem_ty = recv.ty.list_element()?;
+                    let v = self.emit_expr_expecting(item, Some(&elem_ty))?;
+                    let llvm = self.llvm_ty(&elem_ty);
+                    let v = self.cast_to(v.val, llvm)?;
+                    let i8p = self.i8_ptr();
+                    let push = self.runtime_fn("korlang_list_push", &[i8p.into(), i8p.into()], None);
+                    let slot = self.entry_alloca(llvm, "elem");
+                    let _ = self.builder.build_store(slot, v);
+                    let bytes = self.builder.build_pointer_cast(slot, i8p, "elem").ok()?;
+                    let _ = self.builder.build_call(push, &[recv.val.into(), bytes.into()], "");
+                    return None;
+                }
+                _ => {}
+            }
+        }
+        if recv.ty == Type::String && name == "len" && args.is_empty() {
+            let i8p = self.i8_ptr();
+            let strlen = self.runtime_fn("strlen", &[i8p.into()], Some(self.context.i64_type().into()));
+            let v = self.builder.build_call(strlen, &[recv.val.into()], "len").ok()?.try_as_basic_value().left()?;
+            return Some(Value { val: v, ty: Type::Int });
+        }
+        let symbol = self.method_symbol(&recv.ty, name)?;
+        let params = self.signatures.get(&symbol).map(|s| s.params.clone());
+        let values = self.emit_args(args, params.as_deref())?;
+        self.build_call(&symbol, Some(recv.val), values)
+    }
+
+    fn emit_args(&mut self, args: &[Expr], params: Option<&[Type]>) -> Option<Vec<Value<'ctx>>> {
+        args.iter()
+            .enumerate()
+            .map(|(i, a)| self.emit_expr_expecting(a, params.and_then(|p| p.get(i))))
+            .collect()
+    }
+
+    /// Calls `symbol`, converting arguments to its declared parameter types.
+    /// Calls whose arguments cannot be converted are dropped.
+    fn build_call(&mut self, symbol: &str, receiver: Option<BasicValueEnum<'ctx>>, args: Vec<Value<'ctx>>) -> Option<Value<'ctx>> {
+        let f = self.module.get_function(symbol)?;
+        let sig = self.signatures.get(symbol).cloned();
+        let param_tys = f.get_type().get_param_types();
+        let mut values = Vec::new();
+        values.extend(receiver);
+        for (i, a) in args.into_iter().enumerate() {
+            let v = match sig.as_ref().and_then(|s| s.params.get(i)) {
+                Some(t) => self.coerce(a, t)?,
+                None => a.val,
+            };
+            values.push(v);
+        }
+        if values.len() != param_tys.len() {
+            return None;
+        }
+        let mut call_args: Vec<BasicMetadataValueEnum> = Vec::new();
+        for (v, t) in values.into_iter().zip(param_tys) {
+            call_args.push(self.cast_to(v, t)?.into());
+        }
+        let call = self.builder.build_call(f, &call_args, "call").ok()?;
+        let ty = sig.map(|s| s.ret).unwrap_or(Type::Unknown);
+        call.try_as_basic_value().left().map(|val| Value { val, ty })
+    }
+
+    /// Arguments past the declared parameters get C's default promotions:
+    /// integers narrower than `int` widen to it and `Float32` becomes `double`.
+    fn emit_variadic_call(&mut self, name: &str, args: &[Expr]) -> Option<Value<'ctx>> {
+        let f = self.module.get_function(name)?;
+        let sig = self.signatures.get(name).cloned()?;
+        let values = self.emit_args(args, Some(&sig.params))?;
+        let mut call_args: Vec<BasicMetadataValueEnum> = Vec::new();
+        for (i, v) in values.into_iter().enumerate() {
+            let val = match sig.params.get(i) {
+                Some(t) => {
+                    let v = self.coerce(v, t)?;
+                    self.cast_to(v, self.llvm_ty(t))?
+                }
+                None => self.promote_vararg(v)?,
+            };
+            call_args.push(val.into());
+        }
+        let call = self.builder.build_call(f, &call_args, "call").ok()?;
+        call.try_as_basic_value().left().map(|val| Value { val, ty: sig.ret })
+    }
+
+    fn promote_vararg(&self, v: Value<'ctx>) -> Option<BasicValueEnum<'ctx>> {
+        let v = self.unwrap_optional(v)?;
+        match v.val {
+            BasicValueEnum::FloatValue(f) if v.ty == Type::Float32 => {
+                self.builder.build_float_ext(f, self.context.f64_type(), "promote").ok().map(Into::into)
+            }
+            BasicValueEnum::IntValue(i) if i.get_type().get_bit_width() < 32 => {
+                let signed = v.ty.int_info().is_some_and(|(_, signed)| signed);
+                let int = self.context.i32_type();
+                self.builder.build_int_cast_sign_flag(i, int, signed, "promote").ok().map(Into::into)
+            }
+            val => Some(val),
+        }
+    }
+
+    /// Monomorphization-time choice of implementation from the lowered argument types.
+    fn select_specialization(&self, name: &str, type_args: &[TypeRef], arg_tys: &[Type]) -> Option<String> {
+        let generic_args: Vec<Type> = type_args.iter().map(Type::from_type_ref).collect();
+        let query = SpecQuery { generic_args: &generic_args, receiver: None, args: Some(arg_tys) };
+        match self.specializations.find_best_match(name, &query) {
+            Resolution::Selected(spec, _) => Some(spec.symbol.clone()),
             _ => None,
         }
     }
 
-    fn emit_literal_for_call(&self, lit: Literal) -> Option<BasicValueEnum<'ctx>> {
-        match lit {
-            Literal::Int(v) => Some(self.context.i64_type().const_int(v as u64, true).as_basic_value_enum()),
-            Literal::Float(v) => Some(self.context.f64_type().const_float(v).as_basic_value_enum()),
-            Literal::Bool(v) => Some(self.context.bool_type().const_int(v as u64, false).as_basic_value_enum()),
-            Literal::Char(c) => Some(self.context.i32_type().const_int(c as u64, false).as_basic_value_enum()),
-            Literal::String(s) => Some(
-                self.builder
-                    .build_global_string_ptr(&s, "arg_str")
-                    .expect("global string")
-                    .as_pointer_value()
-                    .as_basic_value_enum(),
-            ),
+    fn emit_print(&mut self, arg: &Expr, newline: bool) {
+        if let Some(lit) = self.const_literal(arg).filter(|_| !mentions_cast(arg)) {
+            self.emit_print_literal(&lit, newline);
+            return;
+        }
+        if let Expr::Interpolated { parts, .. } = arg {
+            for (i, part) in parts.iter().enumerate() {
+                self.emit_print(part, newline && i + 1 == parts.len());
+            }
+            return;
+        }
+        let v = match self.emit_expr(arg).and_then(|v| self.unwrap_optional(v)) {
+            Some(v) => v,
+            None => return,
+        };
+        let suffix = if newline { "ln" } else { "" };
+        match (&v.ty, v.val) {
+            (t, BasicValueEnum::IntValue(_)) if t.is_integer() => {
+                let i = match self.convert_number(v.clone(), &Type::Int) {
+                    Some(i) => i,
+                    None => return,
+                };
+                let f = self.get_or_declare_print_i64(&format!("korlang_io_print{}_i64", suffix));
+                let _ = self.builder.build_call(f, &[i.into()], "print_i64");
+            }
+            (Type::Float32, BasicValueEnum::FloatValue(_)) => {
+                let x = match self.convert_number(v.clone(), &Type::Float) {
+                    Some(x) => x,
+                    None => return,
+                };
+                let f = self.get_or_declare_print_f64(&format!("korlang_io_print{}_f64", suffix));
+                let _ = self.builder.build_call(f, &[x.into()], "print_f64");
+            }
+            (Type::Float, BasicValueEnum::FloatValue(x)) => {
+                let f = self.get_or_declare_print_f64(&format!("korlang_io_print{}_f64", suffix));
+                let _ = self.builder.build_call(f, &[x.into()], "print_f64");
+            }
+            (Type::Bool, BasicValueEnum::IntValue(b)) => {
+                let f = self.get_or_declare_print_bool(&format!("korlang_io_print{}_bool", suffix));
+                let _ = self.builder.build_call(f, &[b.into()], "print_bool");
+            }
+            (t, BasicValueEnum::PointerValue(s)) if *t == Type::String || *t == Type::Named("CString".to_string()) => {
+                let i8p = self.i8_ptr();
+                let strlen = self.runtime_fn("strlen", &[i8p.into()], Some(self.context.i64_type().into()));
+                let len = match self.builder.build_call(strlen, &[s.into()], "len").ok().and_then(|c| c.try_as_basic_value().left()) {
+                    Some(l) => l,
+                    None => return,
+                };
+                let f = self.get_or_declare_print_bytes(&format!("korlang_io_print{}", suffix));
+                let _ = self.builder.build_call(f, &[s.into(), len.into()], "print");
+            }
+            _ => {}
         }
     }
 
@@ -262,126 +1906,726 @@ impl<'ctx> Codegen<'ctx> {
         self.module.add_function(name, ty, None)
     }
 
-    fn try_emit_return(&self, body: &Block, ret: &TypeRef) -> Option<BasicValueEnum<'ctx>> {
-        if let Some(Expr::Literal(lit, _)) = body.tail.as_deref().map(|e| self.fold_expr(e)) {
-            return self.emit_literal(lit, ret);
+    fn runtime_fn(&self, name: &str, params: &[BasicMetadataTypeEnum<'ctx>], ret: Option<BasicTypeEnum<'ctx>>) -> FunctionValue<'ctx> {
+        if let Some(f) = self.module.get_function(name) {
+            return f;
         }
-        for stmt in body.stmts.iter().rev() {
-            if let Stmt::Return(Some(expr), _) = stmt {
-                if let Expr::Literal(lit, _) = self.fold_expr(expr) {
-                    return self.emit_literal(lit, ret);
+        let ty = match ret {
+            Some(r) => r.fn_type(params, false),
+            None => self.context.void_type().fn_type(params, false),
+        };
+        self.module.add_function(name, ty, None)
+    }
+
+    // ---------- Sized numbers ----------
+
+    /// Numeric conversion for `as` and for literals meeting sized types.
+    /// Integers extend by the signedness of the source; float to integer
+    /// saturates, with NaN becoming zero.
+    fn convert_number(&self, v: Value<'ctx>, to: &Type) -> Option<BasicValueEnum<'ctx>> {
+        let target = self.llvm_ty(to);
+        let from_signed = matches!(v.ty.int_info(), Some((_, true)));
+        let b = &self.builder;
+        match (v.val, target) {
+            (BasicValueEnum::IntValue(i), BasicTypeEnum::IntType(t)) => {
+                if i.get_type() == t {
+                    return Some(i.into());
                 }
+                b.build_int_cast_sign_flag(i, t, from_signed, "conv").ok().map(Into::into)
             }
+            (BasicValueEnum::IntValue(i), BasicTypeEnum::FloatType(t)) if from_signed => {
+                b.build_signed_int_to_float(i, t, "conv").ok().map(Into::into)
+            }
+            (BasicValueEnum::IntValue(i), BasicTypeEnum::FloatType(t)) => {
+                b.build_unsigned_int_to_float(i, t, "conv").ok().map(Into::into)
+            }
+            (BasicValueEnum::FloatValue(x), BasicTypeEnum::IntType(t)) => {
+                let signed = !matches!(to.int_info(), Some((_, false))) && *to != Type::Bool && *to != Type::Char;
+                let fbits = if x.get_type() == self.context.f32_type() { 32 } else { 64 };
+                let name = format!("llvm.fpto{}i.sat.i{}.f{}", if signed { "s" } else { "u" }, t.get_bit_width(), fbits);
+                let f = self.runtime_fn(&name, &[x.get_type().into()], Some(t.into()));
+                b.build_call(f, &[x.into()], "conv").ok()?.try_as_basic_value().left()
+            }
+            (BasicValueEnum::FloatValue(x), BasicTypeEnum::FloatType(t)) => {
+                b.build_float_cast(x, t, "conv").ok().map(Into::into)
+            }
+            _ => None,
         }
-        None
     }
 
-    fn fold_expr(&self, expr: &Expr) -> Expr {
-        match expr {
-            Expr::Unary { op, expr, span } => {
-                let inner = self.fold_expr(expr);
-                if let Expr::Literal(lit, _) = inner {
-                    return match (op, lit.clone()) {
-                        (UnaryOp::Neg, Literal::Int(v)) => Expr::Literal(Literal::Int(-v), *span),
-                        (UnaryOp::Neg, Literal::Float(v)) => Expr::Literal(Literal::Float(-v), *span),
-                        (UnaryOp::Pos, Literal::Int(v)) => Expr::Literal(Literal::Int(v), *span),
-                        (UnaryOp::Pos, Literal::Float(v)) => Expr::Literal(Literal::Float(v), *span),
-                        (UnaryOp::Not, Literal::Bool(v)) => Expr::Literal(Literal::Bool(!v), *span),
-                        _ => Expr::Unary { op: *op, expr: Box::new(Expr::Literal(lit, *span)), span: *span },
-                    };
+    /// Brings an unsized operand (a literal or plain `Int`/`Float`) to the
+    /// width of a sized one, so both sides of a binary operator agree.
+    fn match_operands(&self, l: Value<'ctx>, r: Value<'ctx>) -> Option<(Value<'ctx>, Value<'ctx>)> {
+        if l.ty == r.ty || !l.ty.is_numeric() || !r.ty.is_numeric() || l.ty.is_float() != r.ty.is_float() {
+            return Some((l, r));
+        }
+        if r.ty.is_sized() && !l.ty.is_sized() {
+            let val = self.convert_number(l, &r.ty)?;
+            return Some((Value { val, ty: r.ty.clone() }, r));
+        }
+        if l.ty.is_sized() && !r.ty.is_sized() {
+            let val = self.convert_number(r, &l.ty)?;
+            return Some((l.clone(), Value { val, ty: l.ty }));
+        }
+        Some((l, r))
+    }
+
+    /// `a op c` through `llvm.*.with.overflow`, returning the wrapped result
+    /// and the overflow bit.
+    fn overflowing(&self, op: BinaryOp, a: IntValue<'ctx>, c: IntValue<'ctx>, signed: bool) -> Option<(IntValue<'ctx>, IntValue<'ctx>)> {
+        let kind = match op {
+            BinaryOp::Add => "add",
+            BinaryOp::Sub => "sub",
+            _ => "mul",
+        };
+        let it = a.get_type();
+        let name = format!("llvm.{}{}.with.overflow.i{}", if signed { "s" } else { "u" }, kind, it.get_bit_width());
+        let ret = self.context.struct_type(&[it.into(), self.context.bool_type().into()], false);
+        let f = self.runtime_fn(&name, &[it.into(), it.into()], Some(ret.into()));
+        let pair = self.builder.build_call(f, &[a.into(), c.into()], kind).ok()?.try_as_basic_value().left()?.into_struct_value();
+        let v = self.builder.build_extract_value(pair, 0, "value").ok()?.into_int_value();
+        let overflow = self.builder.build_extract_value(pair, 1, "overflow").ok()?.into_int_value();
+        Some((v, overflow))
+    }
+
+    /// Calls `korlang_panic` with `msg` and the location of `span` when
+    /// `cond` holds, and continues in a fresh block otherwise. Under UBSan
+    /// the check is reported through `report` instead.
+    fn panic_if(&self, cond: IntValue<'ctx>, msg: &str, span: Span, report: Option<UbsanReport<'ctx>>) -> Option<()> {
+        let f = self.current_function()?;
+        let panic_bb = self.context.append_basic_block(f, "panic");
+        let cont_bb = self.context.append_basic_block(f, "cont");
+        self.builder.build_conditional_branch(cond, panic_bb, cont_bb).ok()?;
+        self.builder.position_at_end(panic_bb);
+        if let Some(report) = report.filter(|_| self.ubsan) {
+            self.emit_ubsan_report(report, span)?;
+            self.builder.build_unreachable().ok()?;
+            self.builder.position_at_end(cont_bb);
+            return Some(());
+        }
+        let text = format!("{} at {}:{}:{}", msg, self.source_name, span.start.line, span.start.column);
+        let text = self.builder.build_global_string_ptr(&text, "panic.msg").ok()?.as_pointer_value();
+        let panic = self.runtime_fn("korlang_panic", &[self.i8_ptr().into()], None);
+        self.builder.build_call(panic, &[text.into()], "").ok()?;
+        self.builder.build_unreachable().ok()?;
+        self.builder.position_at_end(cont_bb);
+        Some(())
+    }
+
+    /// Calls the aborting variant of a UBSan handler. Its data starts with
+    /// the source location, which the runtime writes to, followed by the
+    /// operand type descriptor: kind 0 (integer), log2 of the width shifted
+    /// left once with the sign in bit 0, and the quoted name.
+    fn emit_ubsan_report(&self, report: UbsanReport<'ctx>, span: Span) -> Option<()> {
+        let (i8p, i16t, i32t, i64t) = (self.i8_ptr(), self.context.i16_type(), self.context.i32_type(), self.context.i64_type());
+        let bits = report.operands.first()?.get_type().get_bit_width();
+        let name = match (bits, report.signed) {
+            (64, true) => "Int".to_string(),
+            (64, false) => "UInt".to_string(),
+            (b, true) => format!("Int{}", b),
+            (b, false) => format!("UInt{}", b),
+        };
+        let info = (bits.trailing_zeros() << 1) | report.signed as u32;
+        let name = self.context.const_string(format!("'{}'", name).as_bytes(), true);
+        let desc = self.context.const_struct(&[i16t.const_zero().into(), i16t.const_int(info as u64, false).into(), name.into()], false);
+        let desc_global = self.module.add_global(desc.get_type(), None, "ubsan.type");
+        desc_global.set_initializer(&desc);
+        desc_global.set_constant(true);
+        desc_global.set_linkage(Linkage::Private);
+
+        let file = self.context.const_string(self.source_name.as_bytes(), true);
+        let file_global = self.module.add_global(file.get_type(), None, "ubsan.file");
+        file_global.set_initializer(&file);
+        file_global.set_constant(true);
+        file_global.set_linkage(Linkage::Private);
+
+        let data = self.context.const_struct(
+            &[
+                file_global.as_pointer_value().const_cast(i8p).into(),
+                i32t.const_int(span.start.line as u64, false).into(),
+                i32t.const_int(span.start.column as u64, false).into(),
+                desc_global.as_pointer_value().const_cast(i8p).into(),
+            ],
+            false,
+        );
+        let data_global = self.module.add_global(data.get_type(), None, "ubsan.data");
+        data_global.set_initializer(&data);
+        data_global.set_linkage(Linkage::Private);
+
+        let mut params: Vec<BasicMetadataTypeEnum> = vec![i8p.into()];
+        let mut args: Vec<BasicMetadataValueEnum> = vec![data_global.as_pointer_value().const_cast(i8p).into()];
+        for v in &report.operands {
+            params.push(i64t.into());
+            // Operands narrower than a pointer are passed inline, zero-extended.
+            args.push(self.builder.build_int_z_extend_or_bit_cast(*v, i64t, "ubsan.val").ok()?.into());
+        }
+        let handler = self.runtime_fn(&format!("__ubsan_handle_{}_abort", report.handler), &params, None);
+        self.builder.build_call(handler, &args, "").ok()?;
+        Some(())
+    }
+
+    /// `/` and `%` panic on a zero divisor. `MIN / -1` panics with overflow
+    /// checks on and wraps to `MIN` (remainder 0) without them.
+    fn emit_int_division(&self, op: BinaryOp, a: IntValue<'ctx>, c: IntValue<'ctx>, unsigned: bool, span: Span) -> Option<IntValue<'ctx>> {
+        let div = matches!(op, BinaryOp::Div);
+        let it = a.get_type();
+        let zero = self.builder.build_int_compare(IntPredicate::EQ, c, it.const_zero(), "div.zero").ok()?;
+        let msg = if div { "attempt to divide by zero" } else { "attempt to calculate the remainder with a divisor of zero" };
+        let report = |a, c| UbsanReport { handler: "divrem_overflow", signed: !unsigned, operands: vec![a, c] };
+        self.panic_if(zero, msg, span, Some(report(a, c)))?;
+        let b = &self.builder;
+        if unsigned {
+            return if div { b.build_int_unsigned_div(a, c, "div") } else { b.build_int_unsigned_rem(a, c, "rem") }.ok();
+        }
+        let min = it.const_int(1u64 << (it.get_bit_width() - 1), false);
+        let is_min = b.build_int_compare(IntPredicate::EQ, a, min, "is_min").ok()?;
+        let is_neg1 = b.build_int_compare(IntPredicate::EQ, c, it.const_all_ones(), "is_neg1").ok()?;
+        let overflow = b.build_and(is_min, is_neg1, "div.overflow").ok()?;
+        let c = if self.overflow_checks {
+            let msg = if div { "attempt to divide with overflow" } else { "attempt to calculate the remainder with overflow" };
+            self.panic_if(overflow, msg, span, Some(report(a, c)))?;
+            c
+        } else {
+            // Dividing by one instead gives the wrapped result without LLVM's undefined behavior.
+            b.build_select(overflow, it.const_int(1, false), c, "divisor").ok()?.into_int_value()
+        };
+        if div { self.builder.build_int_signed_div(a, c, "div") } else { self.builder.build_int_signed_rem(a, c, "rem") }.ok()
+    }
+
+    /// `wrappingAdd`-style intrinsics wrap silently; `checkedAdd`-style ones
+    /// return `T?`, null on overflow or division by zero.
+    fn emit_int_intrinsic(&mut self, name: &str, args: &[Expr]) -> Option<Value<'ctx>> {
+        let l = self.emit_expr(args.first()?)?;
+        let r = self.emit_expr(args.get(1)?)?;
+        let (l, r) = self.match_operands(l, r)?;
+        let (a, c) = (l.val.into_int_value(), r.val.into_int_value());
+        let signed = !matches!(l.ty.int_info(), Some((_, false)));
+        let op = match name.trim_start_matches("wrapping").trim_start_matches("checked") {
+            "Add" => BinaryOp::Add,
+            "Sub" => BinaryOp::Sub,
+            "Mul" => BinaryOp::Mul,
+            _ => BinaryOp::Div,
+        };
+        let b = &self.builder;
+        if name.starts_with("wrapping") {
+            let v = match op {
+                BinaryOp::Add => b.build_int_add(a, c, "wadd"),
+                BinaryOp::Sub => b.build_int_sub(a, c, "wsub"),
+                _ => b.build_int_mul(a, c, "wmul"),
+            };
+            return Some(Value { val: v.ok()?.into(), ty: l.ty });
+        }
+        let (v, present) = if matches!(op, BinaryOp::Div) {
+            let it = a.get_type();
+            let nonzero = b.build_int_compare(IntPredicate::NE, c, it.const_zero(), "nonzero").ok()?;
+            let present = if signed {
+                let min = it.const_int(1u64 << (it.get_bit_width() - 1), false);
+                let is_min = b.build_int_compare(IntPredicate::EQ, a, min, "is_min").ok()?;
+                let is_neg1 = b.build_int_compare(IntPredicate::EQ, c, it.const_all_ones(), "is_neg1").ok()?;
+                let both = b.build_and(is_min, is_neg1, "overflow").ok()?;
+                let ok = b.build_not(both, "no_overflow").ok()?;
+                b.build_and(nonzero, ok, "present").ok()?
+            } else {
+                nonzero
+            };
+            // Divide by one on the null path so the division itself cannot trap.
+            let safe = b.build_select(present, c, it.const_int(1, false), "divisor").ok()?.into_int_value();
+            let q = if signed { b.build_int_signed_div(a, safe, "div") } else { b.build_int_unsigned_div(a, safe, "div") };
+            (q.ok()?, present)
+        } else {
+            let (v, overflow) = self.overflowing(op, a, c, signed)?;
+            (v, self.builder.build_not(overflow, "present").ok()?)
+        };
+        let ty = Type::Optional(Box::new(l.ty));
+        let pair = self.llvm_ty(&ty).into_struct_type();
+        let tagged = self.builder.build_insert_value(pair.get_undef(), present, 0, "checked").ok()?;
+        let tagged = self.builder.build_insert_value(tagged, v, 1, "checked").ok()?;
+        Some(Value { val: tagged.into_struct_value().into(), ty })
+    }
+
+    // ---------- Values and types ----------
+
+    /// Folds `expr` through the constexpr interpreter, seeing the function's
+    /// constant locals and the program's const items.
+    fn const_literal(&self, expr: &Expr) -> Option<Literal> {
+        self.consts.eval_with(expr, &self.local_consts).ok()?.to_literal()
+    }
+
+    /// Converts `v` to the representation of `to`, boxing struct references
+    /// into fat pointers when `to` is an interface they implement.
+    fn coerce(&self, v: Value<'ctx>, to: &Type) -> Option<BasicValueEnum<'ctx>> {
+        match to {
+            Type::Optional(inner) if !matches!(v.ty, Type::Optional(_)) => return self.wrap_optional(v, inner),
+            Type::Optional(_) | Type::Unknown | Type::Any => {}
+            _ => return self.coerce_unwrapped(self.unwrap_optional(v)?, to),
+        }
+        self.coerce_unwrapped(v, to)
+    }
+
+    fn coerce_unwrapped(&self, v: Value<'ctx>, to: &Type) -> Option<BasicValueEnum<'ctx>> {
+        if let Some(iface) = self.interface_of(to) {
+            if self.interface_of(&v.ty).is_none() {
+                if let Type::Named(s) = &v.ty {
+                    return self.make_dyn(v.val, s, &iface);
                 }
-                Expr::Unary { op: *op, expr: Box::new(inner), span: *span }
-            }
-            Expr::Binary { left, op, right, span } => {
-                let l = self.fold_expr(left);
-                let r = self.fold_expr(right);
-                if let (Expr::Literal(lit_l, _), Expr::Literal(lit_r, _)) = (&l, &r) {
-                    if let Some(lit) = self.fold_binary(lit_l, *op, lit_r) {
-                        return Expr::Literal(lit, *span);
-                    }
+            }
+        }
+        if matches!(to, Type::Unknown | Type::Any) {
+            return Some(v.val);
+        }
+        if let (Type::Tuple(from), Type::Tuple(tys), BasicValueEnum::StructValue(tuple)) = (&v.ty, to, v.val) {
+            if from != tys && from.len() == tys.len() {
+                let mut agg = self.llvm_ty(to).into_struct_type().get_undef();
+                for (i, (from, ty)) in from.iter().zip(tys).enumerate() {
+                    let val = self.builder.build_extract_value(tuple, i as u32, "elem").ok()?;
+                    let val = self.coerce(Value { val, ty: from.clone() }, ty)?;
+                    agg = self.builder.build_insert_value(agg, val, i as u32, "tuple").ok()?.into_struct_value();
                 }
-                Expr::Binary { left: Box::new(l), op: *op, right: Box::new(r), span: *span }
-            }
-            _ => expr.clone(),
-        }
-    }
-
-    fn fold_binary(&self, l: &Literal, op: BinaryOp, r: &Literal) -> Option<Literal> {
-        match (l, r) {
-            (Literal::Int(a), Literal::Int(b)) => match op {
-                BinaryOp::Add => Some(Literal::Int(a + b)),
-                BinaryOp::Sub => Some(Literal::Int(a - b)),
-                BinaryOp::Mul => Some(Literal::Int(a * b)),
-                BinaryOp::Div => Some(Literal::Int(a / b)),
-                BinaryOp::Mod => Some(Literal::Int(a % b)),
-                BinaryOp::Eq => Some(Literal::Bool(a == b)),
-                BinaryOp::NotEq => Some(Literal::Bool(a != b)),
-                BinaryOp::Lt => Some(Literal::Bool(a < b)),
-                BinaryOp::LtEq => Some(Literal::Bool(a <= b)),
-                BinaryOp::Gt => Some(Literal::Bool(a > b)),
-                BinaryOp::GtEq => Some(Literal::Bool(a >= b)),
-                _ => None,
-            },
-            (Literal::Float(a), Literal::Float(b)) => match op {
-                BinaryOp::Add => Some(Literal::Float(a + b)),
-                BinaryOp::Sub => Some(Literal::Float(a - b)),
-                BinaryOp::Mul => Some(Literal::Float(a * b)),
-                BinaryOp::Div => Some(Literal::Float(a / b)),
-                BinaryOp::Eq => Some(Literal::Bool(a == b)),
-                BinaryOp::NotEq => Some(Literal::Bool(a != b)),
-                BinaryOp::Lt => Some(Literal::Bool(a < b)),
-                BinaryOp::LtEq => Some(Literal::Bool(a <= b)),
-                BinaryOp::Gt => Some(Literal::Bool(a > b)),
-                BinaryOp::GtEq => Some(Literal::Bool(a >= b)),
-                _ => None,
-            },
-            (Literal::Bool(a), Literal::Bool(b)) => match op {
-                BinaryOp::And => Some(Literal::Bool(*a && *b)),
-                BinaryOp::Or => Some(Literal::Bool(*a || *b)),
-                BinaryOp::Eq => Some(Literal::Bool(a == b)),
-                BinaryOp::NotEq => Some(Literal::Bool(a != b)),
-                _ => None,
-            },
-            _ => None,
+                return Some(agg.into());
+            }
+        }
+        // Literals are emitted at full width and narrowed where a sized type is expected.
+        if v.ty.is_numeric() && to.is_numeric() && v.ty.is_float() == to.is_float() {
+            return self.convert_number(v, to);
         }
+        self.cast_to(v.val, self.llvm_ty(to))
     }
 
-    fn emit_literal(&self, lit: Literal, _ret: &TypeRef) -> Option<BasicValueEnum<'ctx>> {
-        match lit {
-            Literal::Int(v) => Some(self.context.i64_type().const_int(v as u64, true).as_basic_value_enum()),
-            Literal::Float(v) => Some(self.context.f64_type().const_float(v).as_basic_value_enum()),
-            Literal::Bool(v) => Some(self.context.bool_type().const_int(v as u64, false).as_basic_value_enum()),
+    /// Representation-preserving conversions only: pointer casts and integer
+    /// width changes. Anything else is rejected.
+    fn cast_to(&self, v: BasicValueEnum<'ctx>, ty: BasicTypeEnum<'ctx>) -> Option<BasicValueEnum<'ctx>> {
+        if v.get_type() == ty {
+            return Some(v);
+        }
+        match (v, ty) {
+            (BasicValueEnum::PointerValue(p), BasicTypeEnum::PointerType(t)) => {
+                self.builder.build_pointer_cast(p, t, "cast").ok().map(Into::into)
+            }
+            (BasicValueEnum::IntValue(i), BasicTypeEnum::IntType(t)) => {
+                let unsigned = i.get_type().get_bit_width() == 1;
+                self.builder.build_int_cast_sign_flag(i, t, !unsigned, "cast").ok().map(Into::into)
+            }
             _ => None,
         }
     }
 
-    fn emit_zero(&self, ret: &TypeRef) -> Option<BasicValueEnum<'ctx>> {
-        match ret {
-            TypeRef::Named(name, _, _) => match name.as_str() {
-                "Int" | "UInt" => Some(self.context.i64_type().const_int(0, false).as_basic_value_enum()),
-                "Float" => Some(self.context.f64_type().const_float(0.0).as_basic_value_enum()),
-                "Bool" => Some(self.context.bool_type().const_int(0, false).as_basic_value_enum()),
-                _ => None,
+    /// Nullable values are a null pointer when the payload is already a
+    /// reference, and a `{ i1 present, T }` pair for plain values.
+    fn optional_is_tagged(&self, inner: &Type) -> bool {
+        matches!(self.llvm_ty(inner), BasicTypeEnum::IntType(_) | BasicTypeEnum::FloatType(_))
+            || matches!(inner, Type::Tuple(_))
+            || inner.range_element().is_some()
+    }
+
+    fn wrap_optional(&self, v: Value<'ctx>, inner: &Type) -> Option<BasicValueEnum<'ctx>> {
+        let null = v.ty == Type::Nothing;
+        if !self.optional_is_tagged(inner) {
+            return if null { Some(self.llvm_ty(inner).const_zero()) } else { self.coerce(v, inner) };
+        }
+        let pair = self.llvm_ty(&Type::Optional(Box::new(inner.clone()))).into_struct_type();
+        if null {
+            return Some(pair.const_zero().into());
+        }
+        let payload = self.coerce(v, inner)?;
+        let present = self.context.bool_type().const_int(1, false);
+        let tagged = self.builder.build_insert_value(pair.get_undef(), present, 0, "some").ok()?;
+        let tagged = self.builder.build_insert_value(tagged, payload, 1, "some").ok()?;
+        Some(tagged.into_struct_value().into())
+    }
+
+    /// The payload of a nullable value; other values are returned unchanged.
+    fn unwrap_optional(&self, v: Value<'ctx>) -> Option<Value<'ctx>> {
+        let inner = match v.ty {
+            Type::Optional(inner) => *inner,
+            _ => return Some(v),
+        };
+        let val = match v.val {
+            BasicValueEnum::StructValue(pair) if self.optional_is_tagged(&inner) => {
+                self.builder.build_extract_value(pair, 1, "payload").ok()?
+            }
+            val => val,
+        };
+        Some(Value { val, ty: inner })
+    }
+
+    fn is_some(&self, v: &Value<'ctx>) -> Option<IntValue<'ctx>> {
+        let bool_t = self.context.bool_type();
+        match (&v.ty, v.val) {
+            (Type::Nothing, _) => Some(bool_t.const_zero()),
+            (Type::Optional(inner), BasicValueEnum::StructValue(pair)) if self.optional_is_tagged(inner) => {
+                self.builder.build_extract_value(pair, 0, "present").ok().map(|b| b.into_int_value())
+            }
+            (_, BasicValueEnum::PointerValue(p)) => self.builder.build_is_not_null(p, "present").ok(),
+            // Interface values are null when their data pointer is.
+            (_, BasicValueEnum::StructValue(fat)) if self.interface_of(&v.ty).is_some() || matches!(v.ty, Type::Optional(_)) => {
+                let data = self.builder.build_extract_value(fat, 0, "data").ok()?.into_pointer_value();
+                self.builder.build_is_not_null(data, "present").ok()
+            }
+            _ => Some(bool_t.const_int(1, false)),
+        }
+    }
+
+    /// Binds the names of an irrefutable `let` pattern to the parts of `value`.
+    fn bind_pattern(&mut self, pat: &Pattern, value: Value<'ctx>) {
+        match (pat, &value.ty, value.val) {
+            (Pattern::Ident(name, _), ..) => {
+                self.local_consts.remove(name);
+                self.bind_local(name, value.ty, value.val);
+            }
+            (Pattern::Tuple(..), Type::Optional(_), _) => {
+                // Null was ruled out by narrowing before the value could be destructured.
+                if let Some(inner) = self.unwrap_optional(value) {
+                    self.bind_pattern(pat, inner);
+                }
+            }
+            (Pattern::Tuple(parts, _), Type::Tuple(tys), BasicValueEnum::StructValue(tuple)) => {
+                for (i, (part, ty)) in parts.iter().zip(tys).enumerate() {
+                    if let Ok(val) = self.builder.build_extract_value(tuple, i as u32, "elem") {
+                        self.bind_pattern(part, Value { val, ty: ty.clone() });
+                    }
+                }
+            }
+            _ => {}
+        }
+    }
+
+    fn bind_local(&mut self, name: &str, ty: Type, val: BasicValueEnum<'ctx>) {
+        let llvm = val.get_type();
+        let ptr = self.entry_alloca(llvm, name);
+        let _ = self.builder.build_store(ptr, val);
+        if let Some(scope) = self.scopes.last_mut() {
+            scope.insert(name.to_string(), Local { ptr, ty, llvm });
+        }
+    }
+
+    fn lookup_local(&self, name: &str) -> Option<Local<'ctx>> {
+        self.scopes.iter().rev().find_map(|s| s.get(name)).cloned()
+    }
+
+    /// Allocas live in the entry block so loops do not grow the stack.
+    fn entry_alloca(&self, ty: BasicTypeEnum<'ctx>, name: &str) -> PointerValue<'ctx> {
+        let entry = self.current_function().and_then(|f| f.get_first_basic_block());
+        let b = self.context.create_builder();
+        match entry {
+            Some(bb) => match bb.get_first_instruction() {
+                Some(first) => b.position_before(&first),
+                None => b.position_at_end(bb),
             },
-            _ => None,
+            None => return self.builder.build_alloca(ty, name).expect("alloca"),
+        }
+        b.build_alloca(ty, name).expect("alloca")
+    }
+
+    fn current_function(&self) -> Option<FunctionValue<'ctx>> {
+        self.builder.get_insert_block().and_then(|bb| bb.get_parent())
+    }
+
+    /// Increments the coverage counter of `anchor`, if it has one.
+    fn cover(&self, anchor: Anchor) {
+        let Some((counters, index)) = self.coverage.as_ref().and_then(|c| c.counter(anchor)) else { return };
+        if self.is_terminated() {
+            return;
+        }
+        let i64t = self.context.i64_type();
+        let base = counters.as_pointer_value().const_cast(i64t.ptr_type(AddressSpace::default()));
+        let slot = ir::elem_gep(&self.builder, i64t.into(), base, i64t.const_int(index as u64, false), "cov");
+        let count = ir::load(&self.builder, i64t.into(), slot, "cov").into_int_value();
+        if let Ok(next) = self.builder.build_int_add(count, i64t.const_int(1, false), "cov") {
+            let _ = self.builder.build_store(slot, next);
+        }
+    }
+
+    fn is_terminated(&self) -> bool {
+        self.builder.get_insert_block().and_then(|bb| bb.get_terminator()).is_some()
+    }
+
+    fn i8_ptr(&self) -> PointerType<'ctx> {
+        self.context.i8_type().ptr_type(AddressSpace::default())
+    }
+
+    fn fn_type(&self, params: &[BasicMetadataTypeEnum<'ctx>], ret: &Type) -> FunctionType<'ctx> {
+        if is_void(ret) {
+            self.context.void_type().fn_type(params, false)
+        } else {
+            self.llvm_ty(ret).fn_type(params, false)
         }
     }
 
     fn llvm_type(&self, ty: &TypeRef) -> BasicTypeEnum<'ctx> {
+        self.llvm_ty(&Type::from_type_ref(ty))
+    }
+
+    fn llvm_ty(&self, ty: &Type) -> BasicTypeEnum<'ctx> {
         match ty {
-            TypeRef::Named(name, _, _) => match name.as_str() {
-                "Int" => self.context.i64_type().as_basic_type_enum(),
-                "UInt" => self.context.i64_type().as_basic_type_enum(),
-                "Float" => self.context.f64_type().as_basic_type_enum(),
-                "Bool" => self.context.bool_type().as_basic_type_enum(),
-                "Char" => self.context.i32_type().as_basic_type_enum(),
-                _ => self.context.i8_type().ptr_type(AddressSpace::default()).as_basic_type_enum(),
-            },
-            TypeRef::Tuple(_, _) => self.context.i8_type().ptr_type(AddressSpace::default()).as_basic_type_enum(),
-            TypeRef::Array(_, _) => self.context.i8_type().ptr_type(AddressSpace::default()).as_basic_type_enum(),
-            TypeRef::Tensor { .. } => self.context.i8_type().ptr_type(AddressSpace::default()).as_basic_type_enum(),
-            TypeRef::Optional(inner, _) => self.llvm_type(inner),
-            TypeRef::NonNull(inner, _) => self.llvm_type(inner),
+            Type::Int | Type::UInt => self.context.i64_type().as_basic_type_enum(),
+            Type::Float => self.context.f64_type().as_basic_type_enum(),
+            Type::SizedInt { bits, .. } => self.context.custom_width_int_type(*bits).as_basic_type_enum(),
+            Type::Float32 => self.context.f32_type().as_basic_type_enum(),
+            Type::Bool => self.context.bool_type().as_basic_type_enum(),
+            Type::Char => self.context.i32_type().as_basic_type_enum(),
+            Type::Optional(inner) if self.optional_is_tagged(inner) => {
+                self.context.struct_type(&[self.context.bool_type().into(), self.llvm_ty(inner)], false).as_basic_type_enum()
+            }
+            Type::Optional(inner) => self.llvm_ty(inner),
+            Type::Tuple(items) => {
+                let fields: Vec<BasicTypeEnum> = items.iter().map(|t| self.llvm_ty(t)).collect();
+                self.context.struct_type(&fields, false).as_basic_type_enum()
+            }
+            Type::Generic(n, args) if n == "Range" && args.len() == 1 => {
+                let bound = self.llvm_ty(&args[0]);
+                self.context.struct_type(&[bound, bound, self.context.bool_type().into()], false).as_basic_type_enum()
+            }
+            Type::Named(n) | Type::Generic(n, _) if self.interfaces.contains_key(n) => self.dyn_type().as_basic_type_enum(),
+            _ => self.i8_ptr().as_basic_type_enum(),
+        }
+    }
+}
+
+/// Merge point for the value of an `if`/`match` used as an expression.
+struct ResultSlot<'ctx> {
+    ok: bool,
+    slot: Option<Local<'ctx>>,
+}
+
+impl<'ctx> ResultSlot<'ctx> {
+    fn new(want: bool) -> Self {
+        Self { ok: want, slot: None }
+    }
+}
+
+fn is_void(ty: &Type) -> bool {
+    matches!(ty, Type::Unit | Type::Nothing)
+}
+
+fn vtable_symbol(struct_name: &str, interface: &str) -> String {
+    format!("{}.vtable.{}", struct_name, interface)
+}
+
+fn interface_subst(iface: &InterfaceDecl, args: &[Type]) -> HashMap<String, Type> {
+    iface.generic_params.iter().map(|p| p.name.clone()).zip(args.iter().cloned()).collect()
+}
+
+/// Constant folding works on 64-bit values, so anything narrowed with `as`
+/// is left to the emitted code.
+fn mentions_cast(expr: &Expr) -> bool {
+    match expr {
+        Expr::Cast { .. } => true,
+        Expr::Unary { expr, .. } => mentions_cast(expr),
+        Expr::Binary { left, right, .. } => mentions_cast(left) || mentions_cast(right),
+        Expr::Call { args, .. } => args.iter().any(mentions_cast),
+        _ => false,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::Codegen;
+    use crate::lexer::Lexer;
+    use crate::mangle;
+    use crate::parser::Parser;
+    use crate::sema::Sema;
+    use crate::types::Type;
+    use inkwell::context::Context;
+    use inkwell::execution_engine::ExecutionEngine;
+    use inkwell::module::Module;
+    use inkwell::targets::{InitializationConfig, Target};
+    use inkwell::OptimizationLevel;
+
+    const SHAPES: &str = "interface Shape {\n\
+                              fun area() -> Float;\n\
+                              fun sides() -> Int;\n\
+                              fun double() -> Float { 2.0 * self.area() }\n\
+                          }\n\
+                          struct Circle { radius: Float } implements Shape;\n\
+                          struct Square { side: Float } implements Shape;\n\
+                          fun Circle.area() -> Float { 3.0 * self.radius * self.radius }\n\
+                          fun Circle.sides() -> Int { 0 }\n\
+                          fun Square.area() -> Float { self.side * self.side }\n\
+                          fun Square.sides() -> Int { 4 }\n\
+                          fun Square.double() -> Float { 100.0 }\n";
+
+    fn compile<'ctx>(context: &'ctx Context, src: &str) -> Module<'ctx> {
+        compile_with(context, src, true)
+    }
+
+    fn compile_with<'ctx>(context: &'ctx Context, src: &str, overflow_checks: bool) -> Module<'ctx> {
+        let tokens = Lexer::new(src).tokenize().expect("lex");
+        let program = Parser::new(tokens).parse_program().expect("parse");
+        Sema::new().check_program(&program).expect("sema");
+        Codegen::new(context, "m")
+            .with_source_name("t.kor")
+            .with_overflow_checks(overflow_checks)
+            .emit_program(&program)
+            .expect("codegen")
+    }
+
+    /// A JIT for `module`, with the runtime functions it calls stubbed out.
+    fn jit<'ctx>(module: &Module<'ctx>) -> ExecutionEngine<'ctx> {
+        Target::initialize_native(&InitializationConfig::default()).expect("native target");
+        let engine = module.create_jit_execution_engine(OptimizationLevel::None).expect("jit");
+        for (name, addr) in [("korlang_gc_alloc", gc_alloc as *const () as usize), ("korlang_panic", panic as *const () as usize)] {
+            if let Some(f) = module.get_function(name) {
+                engine.add_global_mapping(&f, addr);
+            }
+        }
+        engine
+    }
+
+    extern "C" fn gc_alloc(size: u64, _align: u64) -> *mut u8 {
+        Box::leak(vec![0u8; size as usize].into_boxed_slice()).as_mut_ptr()
+    }
+
+    extern "C" fn panic(_msg: *const u8) {
+        std::process::abort();
+    }
+
+    fn symbol(name: &str) -> String {
+        mangle::function_symbol(None, name, &[], &[Type::Int, Type::Int])
+    }
+
+    #[test]
+    fn vtables_list_methods_in_interface_order() {
+        let context = Context::create();
+        let module = compile(&context, SHAPES);
+        let ir = module.print_to_string().to_string();
+        for ty in ["Circle", "Square"] {
+            let table = ir.lines().find(|l| l.starts_with(&format!("@{}.vtable.Shape = private constant [3 x", ty))).expect(ty);
+            let slots: Vec<usize> = ["area", "sides", "double"]
+                .iter()
+                .map(|m| table.find(&mangle::function_symbol(Some(&Type::Named(ty.to_string())), m, &[], &[])).expect(m))
+                .collect();
+            assert!(slots.windows(2).all(|w| w[0] < w[1]), "{}", table);
+        }
+    }
+
+    #[test]
+    fn interface_calls_dispatch_through_the_vtable() {
+        let src = format!(
+            "{SHAPES}fun run() -> Float {{\n\
+                 let a: Shape = Circle {{ radius: 1.0 }};\n\
+                 let b: Shape = Square {{ side: 2.0 }};\n\
+                 a.double() + b.double() + b.area() + 10.0 * (a.sides() + b.sides()) as Float\n\
+             }}\n"
+        );
+        let context = Context::create();
+        let module = compile(&context, &src);
+        let engine = jit(&module);
+        let run = unsafe { engine.get_function::<unsafe extern "C" fn() -> f64>(&mangle::function_symbol(None, "run", &[], &[])) }.expect("run");
+        // Circle.double is the default method, Square.double overrides it.
+        assert_eq!(unsafe { run.call() }, 6.0 + 100.0 + 4.0 + 40.0);
+    }
+
+    #[test]
+    fn parameter_arguments_select_the_specialized_implementation() {
+        let src = "fun show<T>(x: T) -> Int { 0 }\n\
+                   fun show(x: Int) -> Int { 1 }\n\
+                   fun run(n: Int) -> Int { show(n) }\n";
+        let context = Context::create();
+        let module = compile(&context, src);
+        let specialized = mangle::function_symbol(None, "show", &[], &[Type::Int]);
+        let run_symbol = mangle::function_symbol(None, "run", &[], &[Type::Int]);
+        let ir = module.print_to_string().to_string();
+        let body = ir.split(&format!("@{}(", run_symbol)).nth(1).and_then(|rest| rest.split("\n}").next()).expect("run body");
+        assert!(body.contains(&format!("@{}(", specialized)), "{}", body);
+        let engine = jit(&module);
+        let run = unsafe { engine.get_function::<unsafe extern "C" fn(i64) -> i64>(&run_symbol) }.expect("run");
+        assert_eq!(unsafe { run.call(5) }, 1);
+    }
+
+    const ARITH: &str = "fun add(a: Int, b: Int) -> Int {\n    a + b\n}\n\
+                         fun mul(a: Int, b: Int) -> Int {\n    a * b\n}\n\
+                         fun div(a: Int, b: Int) -> Int {\n    a / b\n}\n\
+                         fun rem(a: Int, b: Int) -> Int {\n    a % b\n}\n";
+
+    #[test]
+    fn arithmetic_panics_name_the_operation_and_location() {
+        let context = Context::create();
+        let ir = compile(&context, ARITH).print_to_string().to_string();
+        for msg in [
+            "attempt to add with overflow at t.kor:2:5",
+            "attempt to multiply with overflow at t.kor:5:5",
+            "attempt to divide by zero at t.kor:8:5",
+            "attempt to divide with overflow at t.kor:8:5",
+            "attempt to calculate the remainder with a divisor of zero at t.kor:11:5",
+        ] {
+            assert!(ir.contains(&format!("c\"{}\\00\"", msg)), "missing {:?}", msg);
         }
     }
+
+    #[test]
+    fn arithmetic_wraps_without_overflow_checks() {
+        let context = Context::create();
+        let module = compile_with(&context, ARITH, false);
+        let ir = module.print_to_string().to_string();
+        assert!(!ir.contains("with overflow at"));
+        assert!(ir.contains("attempt to divide by zero at t.kor:8:5"));
+        let engine = jit(&module);
+        type BinOp = unsafe extern "C" fn(i64, i64) -> i64;
+        let call = |name: &str, a: i64, b: i64| unsafe { engine.get_function::<BinOp>(&symbol(name)).expect(name).call(a, b) };
+        assert_eq!(call("add", i64::MAX, 1), i64::MIN);
+        assert_eq!(call("mul", i64::MAX, 2), -2);
+        assert_eq!(call("div", i64::MIN, -1), i64::MIN);
+        assert_eq!(call("rem", i64::MIN, -1), 0);
+        assert_eq!(call("div", 7, 2), 3);
+    }
+
+    /// Each function returns the digits its statements and defers appended
+    /// to `n`, in the order they ran.
+    const DEFERS: &str = "struct Log { n: Int; }\n\
+                          fun leave(log: Log) -> Int {\n\
+                              defer { log.n = log.n * 10 + 1; }\n\
+                              defer { log.n = log.n * 10 + 2; }\n\
+                              if (log.n == 0) { return 5; }\n\
+                              9\n\
+                          }\n\
+                          fun onReturn() -> Int { var log = Log { n: 0 }; let r = leave(log); log.n * 10 + r }\n\
+                          fun onBreak() -> Int {\n\
+                              var n = 0;\n\
+                              var i = 0;\n\
+                              while (true) {\n\
+                                  defer { n = n * 10 + 1; }\n\
+                                  i += 1;\n\
+                                  if (i == 2) { break; }\n\
+                                  n = n * 10 + 2;\n\
+                              }\n\
+                              n\n\
+                          }\n\
+                          fun onContinue() -> Int {\n\
+                              var n = 0;\n\
+                              var i = 0;\n\
+                              while (i < 3) {\n\
+                                  defer { n = n * 10 + 1; }\n\
+                                  i += 1;\n\
+                                  if (i == 2) { continue; }\n\
+                                  n = n * 10 + 2;\n\
+                              }\n\
+                              n\n\
+                          }\n\
+                          fun onLabeledBreak() -> Int {\n\
+                              var n = 0;\n\
+                              outer: while (true) {\n\
+                                  defer { n = n * 10 + 1; }\n\
+                                  while (true) {\n\
+                                      defer { n = n * 10 + 2; }\n\
+                                      break outer;\n\
+                                  }\n\
+                                  n = 9;\n\
+                              }\n\
+                              n\n\
+                          }\n";
+
+    #[test]
+    fn defers_run_in_reverse_on_every_exit() {
+        let context = Context::create();
+        let module = compile(&context, DEFERS);
+        let engine = jit(&module);
+        let call = |name: &str| unsafe {
+            let f = engine.get_function::<unsafe extern "C" fn() -> i64>(&mangle::function_symbol(None, name, &[], &[]));
+            f.expect(name).call()
+        };
+        assert_eq!(call("onReturn"), 215);
+        assert_eq!(call("onBreak"), 211);
+        assert_eq!(call("onContinue"), 21121);
+        assert_eq!(call("onLabeledBreak"), 21);
+    }
 }
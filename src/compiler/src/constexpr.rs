@@ -1,13 +1,940 @@
 use crate::ast::*;
-use crate::sema::Type;
+use crate::diag::{Diagnostic, Span};
+use std::collections::HashMap;
+use std::fmt;
 
-pub struct ConstexprEval;
+/// Evaluation steps allowed before a constant expression is rejected.
+pub const DEFAULT_STEP_LIMIT: usize = 1_000_000;
+const MAX_CALL_DEPTH: usize = 256;
+
+/// A value produced at compile time.
+#[derive(Debug, Clone, PartialEq)]
+pub enum ConstValue {
+    Int(i64),
+    Float(f64),
+    Bool(bool),
+    Char(char),
+    String(String),
+    Array(Vec<ConstValue>),
+    Tuple(Vec<ConstValue>),
+    Struct(String, Vec<(String, ConstValue)>),
+    Unit,
+}
+
+impl ConstValue {
+    pub fn from_literal(lit: &Literal) -> Self {
+        match lit {
+            Literal::Int(v) => ConstValue::Int(*v),
+            Literal::Float(v) => ConstValue::Float(*v),
+            Literal::Bool(v) => ConstValue::Bool(*v),
+            Literal::Char(c) => ConstValue::Char(*c),
+            Literal::String(s) => ConstValue::String(s.clone()),
+        }
+    }
+
+    /// Scalar values map back to literals; aggregates have no literal form.
+    pub fn to_literal(&self) -> Option<Literal> {
+        match self {
+            ConstValue::Int(v) => Some(Literal::Int(*v)),
+            ConstValue::Float(v) => Some(Literal::Float(*v)),
+            ConstValue::Bool(v) => Some(Literal::Bool(*v)),
+            ConstValue::Char(c) => Some(Literal::Char(*c)),
+            ConstValue::String(s) => Some(Literal::String(s.clone())),
+            _ => None,
+        }
+    }
+
+    pub fn type_name(&self) -> &str {
+        match self {
+            ConstValue::Int(_) => "Int",
+            ConstValue::Float(_) => "Float",
+            ConstValue::Bool(_) => "Bool",
+            ConstValue::Char(_) => "Char",
+            ConstValue::String(_) => "String",
+            ConstValue::Array(_) => "Array",
+            ConstValue::Tuple(_) => "Tuple",
+            ConstValue::Struct(name, _) => name,
+            ConstValue::Unit => "Unit",
+        }
+    }
+}
+
+impl fmt::Display for ConstValue {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            ConstValue::Int(v) => write!(f, "{}", v),
+            ConstValue::Float(v) => write!(f, "{}", v),
+            ConstValue::Bool(v) => write!(f, "{}", v),
+            ConstValue::Char(c) => write!(f, "{}", c),
+            ConstValue::String(s) => write!(f, "{}", s),
+            ConstValue::Array(items) => {
+                write!(f, "[")?;
+                for (i, it) in items.iter().enumerate() {
+                    if i > 0 {
+                        write!(f, ", ")?;
+                    }
+                    write!(f, "{}", it)?;
+                }
+                write!(f, "]")
+            }
+            ConstValue::Tuple(items) => {
+                write!(f, "(")?;
+                for (i, it) in items.iter().enumerate() {
+                    if i > 0 {
+                        write!(f, ", ")?;
+                    }
+                    write!(f, "{}", it)?;
+                }
+                write!(f, ")")
+            }
+            ConstValue::Struct(name, fields) => {
+                write!(f, "{} {{ ", name)?;
+                for (i, (field, value)) in fields.iter().enumerate() {
+                    if i > 0 {
+                        write!(f, ", ")?;
+                    }
+                    write!(f, "{}: {}", field, value)?;
+                }
+                write!(f, " }}")
+            }
+            ConstValue::Unit => write!(f, "()"),
+        }
+    }
+}
+
+/// Why an expression could not be evaluated, pointing at the offending sub-expression.
+#[derive(Debug, Clone)]
+pub struct ConstError {
+    pub message: String,
+    pub span: Span,
+}
+
+impl ConstError {
+    fn new(message: impl Into<String>, span: Span) -> Self {
+        Self { message: message.into(), span }
+    }
+
+    /// Reports the failure against the construct that required a constant.
+    pub fn report(&self, what: &str, span: Span) -> Vec<Diagnostic> {
+        vec![
+            Diagnostic::error(format!("could not evaluate {} at compile time", what), span),
+            Diagnostic::note(self.message.clone(), self.span),
+        ]
+    }
+}
+
+/// Compile-time interpreter shared by Sema (const items, array lengths, tensor
+/// shapes, `staticAssert`) and Codegen (constant folding).
+pub struct ConstexprEval {
+    functions: HashMap<String, FunDecl>,
+    globals: HashMap<String, ConstValue>,
+    step_limit: usize,
+}
+
+impl Default for ConstexprEval {
+    fn default() -> Self {
+        Self::new()
+    }
+}
 
 impl ConstexprEval {
-    pub fn eval_expr(expr: &Expr) -> Option<Literal> {
+    pub fn new() -> Self {
+        Self {
+            functions: HashMap::new(),
+            globals: HashMap::new(),
+            step_limit: DEFAULT_STEP_LIMIT,
+        }
+    }
+
+    /// Collects the free functions that constant expressions may call. Names with
+    /// several implementations are left out, since picking one needs type information.
+    pub fn from_program(program: &Program) -> Self {
+        let mut eval = Self::new();
+        let mut overloaded = Vec::new();
+        for item in &program.items {
+            if let Item::Fun(f) = item {
+                if f.receiver.is_some() {
+                    continue;
+                }
+                if eval.functions.insert(f.name.clone(), f.clone()).is_some() {
+                    overloaded.push(f.name.clone());
+                }
+            }
+        }
+        for name in overloaded {
+            eval.functions.remove(&name);
+        }
+        eval
+    }
+
+    pub fn with_step_limit(mut self, limit: usize) -> Self {
+        self.step_limit = limit;
+        self
+    }
+
+    /// Evaluates every top-level `let` in declaration order. Each one must be a
+    /// compile-time constant; `var` globals are initialized at runtime.
+    pub fn eval_consts(&mut self, program: &Program) -> Vec<Diagnostic> {
+        let mut diags = Vec::new();
+        for item in &program.items {
+            if let Item::Const(v) = item {
+                if v.mutable {
+                    continue;
+                }
+                match self.eval(&v.value) {
+                    Ok(value) => match &v.pattern {
+                        Some(pat) => destructure(pat, value, &mut self.globals),
+                        None => {
+                            self.globals.insert(v.name.clone(), value);
+                        }
+                    },
+                    Err(e) if v.pattern.is_some() => diags.extend(e.report("destructuring initializer", v.span)),
+                    Err(e) => diags.extend(e.report(&format!("initializer of '{}'", v.name), v.span)),
+                }
+            }
+        }
+        diags
+    }
+
+    pub fn global(&self, name: &str) -> Option<&ConstValue> {
+        self.globals.get(name)
+    }
+
+    pub fn eval(&self, expr: &Expr) -> Result<ConstValue, ConstError> {
+        self.eval_with(expr, &HashMap::new())
+    }
+
+    /// Evaluates `expr` with extra immutable bindings visible in front of the globals.
+    pub fn eval_with(&self, expr: &Expr, locals: &HashMap<String, ConstValue>) -> Result<ConstValue, ConstError> {
+        let mut interp = Interp::new(self);
+        let scope = locals
+            .iter()
+            .map(|(name, value)| (name.clone(), Binding { value: value.clone(), mutable: false }))
+            .collect();
+        interp.scopes.push(scope);
+        match interp.expr(expr) {
+            Ok(v) => Ok(v),
+            Err(Unwind::Error(e)) => Err(e),
+            Err(_) => Err(ConstError::new("control flow escapes the constant expression", span_of(expr))),
+        }
+    }
+
+    /// Evaluates an array length, which must be a non-negative `Int`.
+    pub fn eval_length(&self, expr: &Expr) -> Result<i64, ConstError> {
+        self.eval_size(expr, "length")
+    }
+
+    fn eval_size(&self, expr: &Expr, what: &str) -> Result<i64, ConstError> {
+        match self.eval(expr)? {
+            ConstValue::Int(n) if n >= 0 => Ok(n),
+            ConstValue::Int(n) => Err(ConstError::new(format!("{} evaluates to negative value {}", what, n), span_of(expr))),
+            other => Err(ConstError::new(format!("{} must be an Int, found {}", what, other.type_name()), span_of(expr))),
+        }
+    }
+
+    /// Resolves a tensor dimension to a concrete size. Identifiers that do not
+    /// name a constant are symbolic and stay unresolved, as does `_`.
+    pub fn eval_dim(&self, dim: &ShapeDim, span: Span) -> Result<Option<i64>, ConstError> {
+        match dim {
+            ShapeDim::Int(v) if *v >= 0 => Ok(Some(*v)),
+            ShapeDim::Int(v) => Err(ConstError::new(format!("dimension evaluates to negative value {}", v), span)),
+            ShapeDim::Ident(name) => match self.globals.get(name) {
+                Some(ConstValue::Int(v)) if *v >= 0 => Ok(Some(*v)),
+                Some(ConstValue::Int(v)) => {
+                    Err(ConstError::new(format!("dimension '{}' evaluates to negative value {}", name, v), span))
+                }
+                Some(other) => Err(ConstError::new(
+                    format!("dimension '{}' must be an Int, found {}", name, other.type_name()),
+                    span,
+                )),
+                None => Ok(None),
+            },
+            ShapeDim::Expr(expr) => self.eval_size(expr, "dimension").map(Some),
+            ShapeDim::Unknown => Ok(None),
+        }
+    }
+}
+
+struct Binding {
+    value: ConstValue,
+    mutable: bool,
+}
+
+enum Unwind {
+    Error(ConstError),
+    Return(ConstValue),
+    Break(Option<String>, ConstValue),
+    Continue(Option<String>),
+}
+
+impl From<ConstError> for Unwind {
+    fn from(e: ConstError) -> Self {
+        Unwind::Error(e)
+    }
+}
+
+type Eval<T> = Result<T, Unwind>;
+
+fn fail<T>(message: impl Into<String>, span: Span) -> Eval<T> {
+    Err(Unwind::Error(ConstError::new(message, span)))
+}
+
+struct Interp<'e> {
+    eval: &'e ConstexprEval,
+    scopes: Vec<HashMap<String, Binding>>,
+    steps: usize,
+    depth: usize,
+}
+
+impl<'e> Interp<'e> {
+    fn new(eval: &'e ConstexprEval) -> Self {
+        Self { eval, scopes: Vec::new(), steps: 0, depth: 0 }
+    }
+
+    fn tick(&mut self, span: Span) -> Eval<()> {
+        self.steps += 1;
+        if self.steps > self.eval.step_limit {
+            return fail(
+                format!("evaluation exceeded the step limit of {} (infinite loop or recursion?)", self.eval.step_limit),
+                span,
+            );
+        }
+        Ok(())
+    }
+
+    fn lookup(&self, name: &str) -> Option<&ConstValue> {
+        self.scopes
+            .iter()
+            .rev()
+            .find_map(|s| s.get(name).map(|b| &b.value))
+            .or_else(|| self.eval.globals.get(name))
+    }
+
+    fn define(&mut self, name: &str, value: ConstValue, mutable: bool) {
+        if let Some(scope) = self.scopes.last_mut() {
+            scope.insert(name.to_string(), Binding { value, mutable });
+        }
+    }
+
+    fn expr(&mut self, expr: &Expr) -> Eval<ConstValue> {
+        let span = span_of(expr);
+        self.tick(span)?;
         match expr {
-            Expr::Literal(lit, _) => Some(lit.clone()),
-            _ => None, // TODO: Implement full expression evaluation
+            Expr::Literal(lit, _) => Ok(ConstValue::from_literal(lit)),
+            Expr::Ident(name, _) => match self.lookup(name) {
+                Some(v) => Ok(v.clone()),
+                None => fail(format!("'{}' is not a compile-time constant", name), span),
+            },
+            Expr::Instantiate { name, .. } => fail(format!("'{}' is not a compile-time constant", name), span),
+            Expr::StructLit { name, fields, .. } => {
+                let mut values = Vec::new();
+                for (field, value) in fields {
+                    values.push((field.clone(), self.expr(value)?));
+                }
+                Ok(ConstValue::Struct(name.clone(), values))
+            }
+            Expr::Unary { op, expr, .. } => {
+                let v = self.expr(expr)?;
+                match (op, v) {
+                    (UnaryOp::Neg, ConstValue::Int(v)) => match v.checked_neg() {
+                        Some(r) => Ok(ConstValue::Int(r)),
+                        None => fail("arithmetic overflow in constant expression", span),
+                    },
+                    (UnaryOp::Neg, ConstValue::Float(v)) => Ok(ConstValue::Float(-v)),
+                    (UnaryOp::Pos, v @ (ConstValue::Int(_) | ConstValue::Float(_))) => Ok(v),
+                    (UnaryOp::Not, ConstValue::Bool(v)) => Ok(ConstValue::Bool(!v)),
+                    (UnaryOp::BitNot, ConstValue::Int(v)) => Ok(ConstValue::Int(!v)),
+                    (op, v) => fail(format!("operator {:?} cannot be applied to {}", op, v.type_name()), span),
+                }
+            }
+            Expr::Range { start, end, inclusive, .. } => {
+                let bounds = [("start", self.expr(start)?), ("end", self.expr(end)?)];
+                if let Some((_, v)) = bounds.iter().find(|(_, v)| !matches!(v, ConstValue::Int(_))) {
+                    return fail(format!("range bounds must be integers, found {}", v.type_name()), span);
+                }
+                let mut fields: Vec<_> = bounds.into_iter().map(|(f, v)| (f.to_string(), v)).collect();
+                fields.push(("inclusive".to_string(), ConstValue::Bool(*inclusive)));
+                Ok(ConstValue::Struct("Range".to_string(), fields))
+            }
+            Expr::Cast { expr, ty, .. } => {
+                let v = self.expr(expr)?;
+                let to = crate::types::Type::from_type_ref(ty);
+                match (v, to.int_info()) {
+                    (ConstValue::Int(v), Some(_)) => Ok(ConstValue::Int(to.wrap_int(v as i128))),
+                    (ConstValue::Char(c), Some(_)) => Ok(ConstValue::Int(to.wrap_int(c as i128))),
+                    (ConstValue::Bool(b), Some(_)) => Ok(ConstValue::Int(b as i64)),
+                    // Float to integer conversions saturate, as in generated code.
+                    (ConstValue::Float(f), Some(_)) => Ok(ConstValue::Int(to.saturate_float(f))),
+                    (ConstValue::Int(v), None) if to.is_float() => Ok(ConstValue::Float(to.round_float(v as f64))),
+                    (ConstValue::Float(f), None) if to.is_float() => Ok(ConstValue::Float(to.round_float(f))),
+                    (v, _) => fail(format!("cannot cast {} to {:?}", v.type_name(), to), span),
+                }
+            }
+            Expr::Binary { left, op, right, .. } => match op {
+                BinaryOp::And | BinaryOp::Or => {
+                    let l = self.expect_bool(left)?;
+                    if l == matches!(op, BinaryOp::Or) {
+                        return Ok(ConstValue::Bool(l));
+                    }
+                    Ok(ConstValue::Bool(self.expect_bool(right)?))
+                }
+                // Constant values are never null, so the fallback is never taken.
+                BinaryOp::NullCoalesce => self.expr(left),
+                _ => {
+                    let l = self.expr(left)?;
+                    let r = self.expr(right)?;
+                    Ok(binary_op(&l, *op, &r, span)?)
+                }
+            },
+            Expr::Assign { left, op, right, .. } => {
+                let name = match &**left {
+                    Expr::Ident(name, _) => name,
+                    _ => return fail("only local variables can be assigned in a constant expression", span),
+                };
+                let rhs = self.expr(right)?;
+                let value = match op {
+                    AssignOp::Assign => rhs,
+                    _ => {
+                        let current = self.expr(left)?;
+                        let bin = match op {
+                            AssignOp::AddAssign => BinaryOp::Add,
+                            AssignOp::SubAssign => BinaryOp::Sub,
+                            AssignOp::MulAssign => BinaryOp::Mul,
+                            AssignOp::DivAssign => BinaryOp::Div,
+                            _ => BinaryOp::Mod,
+                        };
+                        binary_op(&current, bin, &rhs, span)?
+                    }
+                };
+                match self.scopes.iter_mut().rev().find_map(|s| s.get_mut(name)) {
+                    Some(binding) if binding.mutable => {
+                        binding.value = value;
+                        Ok(ConstValue::Unit)
+                    }
+                    Some(_) => fail(format!("cannot assign to immutable binding '{}'", name), span),
+                    None => fail(format!("cannot assign to '{}' in a constant expression", name), span),
+                }
+            }
+            Expr::Call { callee, args, .. } => self.call(callee, args, span),
+            Expr::Member { target, name, .. } => match self.expr(target)? {
+                ConstValue::Struct(s, fields) => match fields.into_iter().find(|(f, _)| f == name) {
+                    Some((_, v)) => Ok(v),
+                    None => fail(format!("struct '{}' has no field '{}'", s, name), span),
+                },
+                ConstValue::Tuple(mut items) => match name.parse::<usize>() {
+                    Ok(i) if i < items.len() => Ok(items.swap_remove(i)),
+                    _ => fail(format!("tuple has no field '{}'", name), span),
+                },
+                v => fail(format!("member '{}' of {} cannot be evaluated at compile time", name, v.type_name()), span),
+            },
+            Expr::Index { target, index, .. } => {
+                let target = self.expr(target)?;
+                let idx = match self.expr(index)? {
+                    ConstValue::Int(i) => i,
+                    v => return fail(format!("index must be an Int, found {}", v.type_name()), span),
+                };
+                let len = match &target {
+                    ConstValue::Array(items) => items.len(),
+                    ConstValue::String(s) => s.chars().count(),
+                    v => return fail(format!("cannot index into {}", v.type_name()), span),
+                };
+                if idx < 0 || idx as usize >= len {
+                    return fail(format!("index {} out of bounds for length {}", idx, len), span);
+                }
+                match target {
+                    ConstValue::Array(mut items) => Ok(items.swap_remove(idx as usize)),
+                    ConstValue::String(s) => Ok(ConstValue::Char(s.chars().nth(idx as usize).unwrap_or_default())),
+                    _ => unreachable!(),
+                }
+            }
+            Expr::If { cond, then_block, else_block, .. } => {
+                if self.expect_bool(cond)? {
+                    self.block(then_block)
+                } else {
+                    self.block(else_block)
+                }
+            }
+            Expr::Match { expr, arms, .. } => self.match_arms(expr, arms, span),
+            Expr::Block(b) => self.block(b),
+            Expr::Loop { label, body, .. } => loop {
+                self.tick(span)?;
+                if let Some(value) = after_pass(self.block(body), label)? {
+                    break Ok(value);
+                }
+            },
+            Expr::Array(items, _) => {
+                let mut values = Vec::new();
+                for it in items {
+                    values.push(self.expr(it)?);
+                }
+                Ok(ConstValue::Array(values))
+            }
+            Expr::Tuple(items, _) => Ok(ConstValue::Tuple(items.iter().map(|it| self.expr(it)).collect::<Eval<_>>()?)),
+            Expr::Tensor(_, _) => fail("tensor literals are not compile-time constants", span),
+            Expr::Interpolated { parts, .. } => {
+                let mut out = String::new();
+                for p in parts {
+                    out.push_str(&self.expr(p)?.to_string());
+                }
+                Ok(ConstValue::String(out))
+            }
+        }
+    }
+
+    fn expect_bool(&mut self, expr: &Expr) -> Eval<bool> {
+        match self.expr(expr)? {
+            ConstValue::Bool(b) => Ok(b),
+            v => fail(format!("expected Bool, found {}", v.type_name()), span_of(expr)),
+        }
+    }
+
+    fn call(&mut self, callee: &Expr, args: &[Expr], span: Span) -> Eval<ConstValue> {
+        let name = match callee {
+            Expr::Ident(name, _) | Expr::Instantiate { name, .. } => name,
+            Expr::Member { target, name, .. } if name == "len" && args.is_empty() => {
+                return match self.expr(target)? {
+                    ConstValue::String(s) => Ok(ConstValue::Int(s.chars().count() as i64)),
+                    ConstValue::Array(items) => Ok(ConstValue::Int(items.len() as i64)),
+                    v => fail(format!("method 'len' of {} cannot be evaluated at compile time", v.type_name()), span),
+                };
+            }
+            Expr::Member { name, .. } => {
+                return fail(format!("method '{}' cannot be evaluated at compile time", name), span);
+            }
+            _ => return fail("call cannot be evaluated at compile time", span),
+        };
+        let eval = self.eval;
+        let fun = match eval.functions.get(name) {
+            Some(f) if !f.is_async => f,
+            _ => return fail(format!("call to non-constant function '{}'", name), span),
+        };
+        if fun.params.len() != args.len() {
+            return fail(format!("'{}' expects {} arguments, found {}", name, fun.params.len(), args.len()), span);
+        }
+        if self.depth >= MAX_CALL_DEPTH {
+            return fail(format!("call depth exceeded {} while evaluating '{}'", MAX_CALL_DEPTH, name), span);
+        }
+
+        let mut frame = HashMap::new();
+        for (p, a) in fun.params.iter().zip(args) {
+            frame.insert(p.name.clone(), Binding { value: self.expr(a)?, mutable: false });
+        }
+        let saved = std::mem::replace(&mut self.scopes, vec![frame]);
+        self.depth += 1;
+        let result = self.block(&fun.body);
+        self.depth -= 1;
+        self.scopes = saved;
+        match result {
+            Ok(v) | Err(Unwind::Return(v)) => Ok(v),
+            Err(Unwind::Break(..) | Unwind::Continue(_)) => fail("break outside of a loop", fun.span),
+            Err(e) => Err(e),
+        }
+    }
+
+    fn block(&mut self, block: &Block) -> Eval<ConstValue> {
+        self.scopes.push(HashMap::new());
+        let mut deferred = Vec::new();
+        let mut result = self.block_inner(block, &mut deferred);
+        for d in deferred.into_iter().rev() {
+            if let Err(e) = self.block(d) {
+                result = Err(e);
+            }
+        }
+        self.scopes.pop();
+        result
+    }
+
+    fn block_inner<'b>(&mut self, block: &'b Block, deferred: &mut Vec<&'b Block>) -> Eval<ConstValue> {
+        let mut last = ConstValue::Unit;
+        for s in &block.stmts {
+            if let Stmt::Defer(body, _) = s {
+                deferred.push(body);
+                continue;
+            }
+            let value = self.stmt(s)?;
+            // A trailing `if`/`match` statement yields the block's value.
+            last = if matches!(s, Stmt::If(..) | Stmt::Match(..) | Stmt::Block(_)) { value } else { ConstValue::Unit };
+        }
+        match &block.tail {
+            Some(tail) => self.expr(tail),
+            None => Ok(last),
+        }
+    }
+
+    fn stmt(&mut self, stmt: &Stmt) -> Eval<ConstValue> {
+        match stmt {
+            Stmt::Var(v) => {
+                let value = self.expr(&v.value)?;
+                match &v.pattern {
+                    Some(pat) => {
+                        let mut bindings = HashMap::new();
+                        destructure(pat, value, &mut bindings);
+                        for (name, value) in bindings {
+                            self.define(&name, value, v.mutable);
+                        }
+                    }
+                    None => self.define(&v.name, value, v.mutable),
+                }
+                Ok(ConstValue::Unit)
+            }
+            Stmt::Expr(e, _) => self.expr(e),
+            Stmt::Return(e, _) => {
+                let value = match e {
+                    Some(e) => self.expr(e)?,
+                    None => ConstValue::Unit,
+                };
+                Err(Unwind::Return(value))
+            }
+            Stmt::Break(label, value, _) => {
+                let value = match value {
+                    Some(e) => self.expr(e)?,
+                    None => ConstValue::Unit,
+                };
+                Err(Unwind::Break(label.clone(), value))
+            }
+            Stmt::Continue(label, _) => Err(Unwind::Continue(label.clone())),
+            Stmt::If(cond, then_block, else_stmt, _) => {
+                if self.expect_bool(cond)? {
+                    self.block(then_block)
+                } else if let Some(s) = else_stmt {
+                    self.stmt(s)
+                } else {
+                    Ok(ConstValue::Unit)
+                }
+            }
+            Stmt::While(cond, body, label, span) => {
+                loop {
+                    self.tick(*span)?;
+                    if !self.expect_bool(cond)? {
+                        break;
+                    }
+                    if after_pass(self.block(body), label)?.is_some() {
+                        break;
+                    }
+                }
+                Ok(ConstValue::Unit)
+            }
+            Stmt::For(name, iter, body, label, span) => {
+                let items: Box<dyn Iterator<Item = ConstValue>> = match self.expr(iter)? {
+                    ConstValue::Array(items) => Box::new(items.into_iter()),
+                    ConstValue::String(s) => Box::new(s.chars().collect::<Vec<_>>().into_iter().map(ConstValue::Char)),
+                    ConstValue::Struct(name, fields) if name == "Range" => match fields.as_slice() {
+                        [(_, ConstValue::Int(a)), (_, ConstValue::Int(b)), (_, ConstValue::Bool(true))] => {
+                            Box::new((*a..=*b).map(ConstValue::Int))
+                        }
+                        [(_, ConstValue::Int(a)), (_, ConstValue::Int(b)), _] => Box::new((*a..*b).map(ConstValue::Int)),
+                        _ => return fail("malformed range", *span),
+                    },
+                    v => return fail(format!("cannot iterate over {} at compile time", v.type_name()), *span),
+                };
+                for item in items {
+                    self.tick(*span)?;
+                    self.scopes.push(HashMap::new());
+                    self.define(name, item, false);
+                    let result = self.block(body);
+                    self.scopes.pop();
+                    if after_pass(result, label)?.is_some() {
+                        break;
+                    }
+                }
+                Ok(ConstValue::Unit)
+            }
+            Stmt::Match(expr, arms, span) => self.match_arms(expr, arms, *span),
+            // Collected and run by `block`.
+            Stmt::Defer(..) => Ok(ConstValue::Unit),
+            Stmt::Block(b) => self.block(b),
+        }
+    }
+
+    fn match_arms(&mut self, expr: &Expr, arms: &[MatchArm], span: Span) -> Eval<ConstValue> {
+        let value = self.expr(expr)?;
+        for arm in arms {
+            let mut bindings = HashMap::new();
+            if self.match_pattern(&arm.pat, &value, &mut bindings)? {
+                self.scopes.push(bindings);
+                let result = self.expr(&arm.body);
+                self.scopes.pop();
+                return result;
+            }
+        }
+        fail(format!("no match arm matches value {}", value), span)
+    }
+
+    fn match_pattern(&mut self, pat: &Pattern, value: &ConstValue, bindings: &mut HashMap<String, Binding>) -> Eval<bool> {
+        match pat {
+            Pattern::Wildcard(_) => Ok(true),
+            Pattern::Ident(name, _) => {
+                bindings.insert(name.clone(), Binding { value: value.clone(), mutable: false });
+                Ok(true)
+            }
+            Pattern::Literal(lit, _) => Ok(&ConstValue::from_literal(lit) == value),
+            Pattern::Struct { name, fields, .. } => {
+                let ConstValue::Struct(s, values) = value else {
+                    return Ok(false);
+                };
+                if s != name {
+                    return Ok(false);
+                }
+                for (field, sub) in fields {
+                    match values.iter().find(|(f, _)| f == field) {
+                        Some((_, v)) => {
+                            if !self.match_pattern(sub, v, bindings)? {
+                                return Ok(false);
+                            }
+                        }
+                        None => return Ok(false),
+                    }
+                }
+                Ok(true)
+            }
+            Pattern::Is(ty, inner, _) => {
+                let matches = match ty {
+                    TypeRef::Named(n, _, _) => n == value.type_name(),
+                    TypeRef::Array(..) | TypeRef::FixedArray { .. } => matches!(value, ConstValue::Array(_)),
+                    _ => false,
+                };
+                Ok(matches && self.match_pattern(inner, value, bindings)?)
+            }
+            Pattern::Tuple(parts, _) => {
+                let ConstValue::Tuple(values) = value else {
+                    return Ok(false);
+                };
+                if parts.len() != values.len() {
+                    return Ok(false);
+                }
+                for (sub, v) in parts.iter().zip(values) {
+                    if !self.match_pattern(sub, v, bindings)? {
+                        return Ok(false);
+                    }
+                }
+                Ok(true)
+            }
+            Pattern::Variant { span, .. } => fail("pattern cannot be matched at compile time", *span),
+        }
+    }
+}
+
+/// Binds the names of an irrefutable `let` pattern; Sema has already
+/// checked that the pattern fits the value's type.
+fn destructure(pat: &Pattern, value: ConstValue, out: &mut HashMap<String, ConstValue>) {
+    match (pat, value) {
+        (Pattern::Ident(name, _), value) => {
+            out.insert(name.clone(), value);
+        }
+        (Pattern::Tuple(parts, _), ConstValue::Tuple(values)) => {
+            for (part, value) in parts.iter().zip(values) {
+                destructure(part, value, out);
+            }
+        }
+        _ => {}
+    }
+}
+
+fn binary_op(l: &ConstValue, op: BinaryOp, r: &ConstValue, span: Span) -> Result<ConstValue, ConstError> {
+    use ConstValue::*;
+    let overflow = || ConstError::new("arithmetic overflow in constant expression", span);
+    let result = match (l, r) {
+        (Int(a), Int(b)) => match op {
+            BinaryOp::Add => Some(Int(a.checked_add(*b).ok_or_else(overflow)?)),
+            BinaryOp::Sub => Some(Int(a.checked_sub(*b).ok_or_else(overflow)?)),
+            BinaryOp::Mul => Some(Int(a.checked_mul(*b).ok_or_else(overflow)?)),
+            BinaryOp::Div | BinaryOp::Mod if *b == 0 => {
+                return Err(ConstError::new("division by zero in constant expression", span));
+            }
+            BinaryOp::Div => Some(Int(a.checked_div(*b).ok_or_else(overflow)?)),
+            BinaryOp::Mod => Some(Int(a.checked_rem(*b).ok_or_else(overflow)?)),
+            _ => compare(a, op, b),
+        },
+        (Int(_) | Float(_), Int(_) | Float(_)) => {
+            let (a, b) = (as_float(l), as_float(r));
+            match op {
+                BinaryOp::Add => Some(Float(a + b)),
+                BinaryOp::Sub => Some(Float(a - b)),
+                BinaryOp::Mul => Some(Float(a * b)),
+                BinaryOp::Div => Some(Float(a / b)),
+                BinaryOp::Mod => Some(Float(a % b)),
+                _ => compare(&a, op, &b),
+            }
         }
+        (String(a), String(b)) => match op {
+            BinaryOp::Add => Some(String(format!("{}{}", a, b))),
+            _ => compare(a, op, b),
+        },
+        (Char(a), Char(b)) => compare(a, op, b),
+        (Bool(a), Bool(b)) => compare(a, op, b),
+        _ => match op {
+            BinaryOp::Eq => Some(Bool(l == r)),
+            BinaryOp::NotEq => Some(Bool(l != r)),
+            _ => None,
+        },
+    };
+    result.ok_or_else(|| {
+        ConstError::new(
+            format!("operator {:?} cannot be applied to {} and {} at compile time", op, l.type_name(), r.type_name()),
+            span,
+        )
+    })
+}
+
+fn compare<T: PartialOrd>(a: &T, op: BinaryOp, b: &T) -> Option<ConstValue> {
+    let result = match op {
+        BinaryOp::Eq => a == b,
+        BinaryOp::NotEq => a != b,
+        BinaryOp::Lt => a < b,
+        BinaryOp::LtEq => a <= b,
+        BinaryOp::Gt => a > b,
+        BinaryOp::GtEq => a >= b,
+        _ => return None,
+    };
+    Some(ConstValue::Bool(result))
+}
+
+/// How a loop continues after one pass over its body: `Some` ends it with
+/// the value of a `break`, and jumps to enclosing loops propagate.
+fn after_pass(result: Eval<ConstValue>, label: &Option<String>) -> Eval<Option<ConstValue>> {
+    match result {
+        Ok(_) => Ok(None),
+        Err(Unwind::Continue(l)) if l.is_none() || l == *label => Ok(None),
+        Err(Unwind::Break(l, v)) if l.is_none() || l == *label => Ok(Some(v)),
+        Err(e) => Err(e),
+    }
+}
+
+fn as_float(v: &ConstValue) -> f64 {
+    match v {
+        ConstValue::Int(i) => *i as f64,
+        ConstValue::Float(f) => *f,
+        _ => f64::NAN,
+    }
+}
+
+fn span_of(expr: &Expr) -> Span {
+    match expr {
+        Expr::Literal(_, s) | Expr::Ident(_, s) | Expr::Array(_, s) | Expr::Tuple(_, s) | Expr::Tensor(_, s) => *s,
+        Expr::Instantiate { span, .. }
+        | Expr::StructLit { span, .. }
+        | Expr::Unary { span, .. }
+        | Expr::Binary { span, .. }
+        | Expr::Assign { span, .. }
+        | Expr::Call { span, .. }
+        | Expr::Member { span, .. }
+        | Expr::Index { span, .. }
+        | Expr::Range { span, .. }
+        | Expr::Cast { span, .. }
+        | Expr::If { span, .. }
+        | Expr::Match { span, .. }
+        | Expr::Loop { span, .. }
+        | Expr::Interpolated { span, .. } => *span,
+        Expr::Block(b) => b.span,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::lexer::Lexer;
+    use crate::parser::Parser;
+
+    fn program(src: &str) -> Program {
+        let tokens = Lexer::new(src).tokenize().expect("lex");
+        Parser::new(tokens).parse_program().expect("parse")
+    }
+
+    fn eval_global(src: &str, name: &str) -> ConstValue {
+        let program = program(src);
+        let mut eval = ConstexprEval::from_program(&program);
+        let diags = eval.eval_consts(&program);
+        assert!(diags.is_empty(), "{:?}", diags);
+        eval.global(name).cloned().expect("global")
+    }
+
+    #[test]
+    fn evaluates_pure_function_calls() {
+        let src = "fun fact(n: Int) -> Int { if (n <= 1) { 1 } else { n * fact(n - 1) } }\nlet F = fact(10);";
+        assert_eq!(eval_global(src, "F"), ConstValue::Int(3628800));
+    }
+
+    #[test]
+    fn evaluates_loops_match_and_strings() {
+        let src = r#"
+fun label(n: Int) -> String {
+    var total = 0;
+    var i = 0;
+    while (i < n) { total += i; i += 1; }
+    match (total) { 10 => "ten"; _ => "other"; }
+}
+let L = label(5) + "!";
+"#;
+        assert_eq!(eval_global(src, "L"), ConstValue::String("ten!".to_string()));
+    }
+
+    #[test]
+    fn evaluates_labeled_jumps_loop_values_and_defers() {
+        let src = r#"
+fun search(n: Int) -> Int {
+    var log = 0;
+    let hit = loop {
+        var i = 0;
+        outer: while (i < n) {
+            defer { log += 1; }
+            i += 1;
+            for j in [1, 2, 3] {
+                if (i * j == 6) { break outer; }
+                if (j == i) { continue outer; }
+            }
+        }
+        break i;
+    };
+    hit * 100 + log
+}
+let S = search(10);
+"#;
+        assert_eq!(eval_global(src, "S"), ConstValue::Int(303));
+    }
+
+    #[test]
+    fn evaluates_structs_and_arrays() {
+        let src = "struct P { x: Int; y: Int; }\nlet A = [P { x: 1, y: 2 }, P { x: 3, y: 4 }];\nlet S = A[1].y + A.len();";
+        assert_eq!(eval_global(src, "S"), ConstValue::Int(6));
+    }
+
+    #[test]
+    fn evaluates_for_loops_over_ranges() {
+        let src = "fun sum(n: Int) -> Int { var t = 0; for i in 1..=n { t += i; } for i in 0..0 { t += 100; } t }\nlet S = sum(10);";
+        assert_eq!(eval_global(src, "S"), ConstValue::Int(55));
+    }
+
+    #[test]
+    fn evaluates_tuples_and_destructuring() {
+        let src = "fun divmod(a: Int, b: Int) -> (Int, Int) { (a / b, a % b) }\n\
+                   let (Q, R) = divmod(17, 5);\n\
+                   fun f() -> Int { let (a, (b, _)) = (1, (2, 3)); let t = (a, b); t.0 * 10 + t.1 }\n\
+                   let S = Q * 100 + R * 10 + f();";
+        assert_eq!(eval_global(src, "S"), ConstValue::Int(332));
+    }
+
+    #[test]
+    fn rejects_impure_calls_and_runaway_loops() {
+        let program = program("fun spin() -> Int { while (true) { } 0 }\nlet A = readLine();\nlet B = spin();");
+        let mut eval = ConstexprEval::from_program(&program).with_step_limit(1_000);
+        let diags = eval.eval_consts(&program);
+        let messages: Vec<_> = diags.iter().map(|d| d.message.as_str()).collect();
+        assert!(messages.contains(&"call to non-constant function 'readLine'"), "{:?}", messages);
+        assert!(messages.iter().any(|m| m.contains("step limit")), "{:?}", messages);
+    }
+
+    #[test]
+    fn reports_overflow_and_division_by_zero() {
+        let eval = ConstexprEval::new();
+        let program = program("let A = 9223372036854775807 + 1;\nlet B = 1 / 0;");
+        let errors: Vec<_> = program
+            .items
+            .iter()
+            .filter_map(|i| match i {
+                Item::Const(v) => eval.eval(&v.value).err().map(|e| e.message),
+                _ => None,
+            })
+            .collect();
+        assert_eq!(errors, ["arithmetic overflow in constant expression", "division by zero in constant expression"]);
     }
 }
@@ -0,0 +1,373 @@
+use std::collections::HashMap;
+
+use crate::ast::{BinaryOp, Block, Expr, MatchArm, Stmt};
+use crate::diag::{Position, Span};
+use crate::exhaustive::pattern_span;
+
+/// Where each line of a program came from. Imports are inlined into one
+/// source, so line `n` of it is `lines[n - 1]`: an index into `files` and
+/// the line there, or `None` for lines no file holds (built-in modules).
+#[derive(Debug, Clone, Default)]
+pub struct SourceMap {
+    pub files: Vec<String>,
+    lines: Vec<Option<(usize, usize)>>,
+}
+
+impl SourceMap {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Records the next line of the program as `line` of `file`.
+    pub fn push_line(&mut self, file: &str, line: usize) {
+        let index = match self.files.iter().position(|f| f == file) {
+            Some(i) => i,
+            None => {
+                self.files.push(file.to_string());
+                self.files.len() - 1
+            }
+        };
+        self.lines.push(Some((index, line)));
+    }
+
+    pub fn push_generated(&mut self) {
+        self.lines.push(None);
+    }
+
+    pub fn locate(&self, line: usize) -> Option<(usize, usize)> {
+        self.lines.get(line.checked_sub(1)?).copied().flatten()
+    }
+}
+
+/// A point where codegen increments a counter, keyed by source offsets.
+/// A block counts on entry; a split counts the rest of a block after a
+/// statement that may leave it early.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum Anchor {
+    Block(usize),
+    Split(usize, usize),
+    /// The `if` of an `else if`.
+    Else(usize),
+    /// An arm whose body is not a block.
+    Arm(usize),
+    /// The right operand of `&&` or `||`.
+    Rhs(usize),
+}
+
+impl Anchor {
+    pub fn block(block: &Block) -> Self {
+        Anchor::Block(block.span.start.offset)
+    }
+
+    /// The statements of `block` from `index` on, or its tail when `index`
+    /// is past them.
+    pub fn split(block: &Block, index: usize) -> Self {
+        Anchor::Split(block.span.start.offset, index)
+    }
+
+    pub fn arm(arm: &MatchArm) -> Self {
+        Anchor::Arm(arm.span.start.offset)
+    }
+
+    pub fn rhs(right: &Expr) -> Self {
+        Anchor::Rhs(span_of(right).start.offset)
+    }
+}
+
+/// A source range executed as often as its counter says. Regions nest; the
+/// innermost one covering a position wins. A gap only sets the count of
+/// the lines that start inside it, such as the line of a match arm.
+#[derive(Debug, Clone, Copy)]
+pub struct Region {
+    pub counter: u32,
+    pub span: Span,
+    pub gap: bool,
+}
+
+/// The counters of one function body and the regions they count.
+#[derive(Debug, Default)]
+pub struct FunctionCoverage {
+    pub regions: Vec<Region>,
+    pub anchors: HashMap<Anchor, u32>,
+}
+
+impl FunctionCoverage {
+    pub fn of(body: &Block) -> Self {
+        let mut cov = FunctionCoverage::default();
+        cov.block(body);
+        cov
+    }
+
+    pub fn counters(&self) -> u32 {
+        self.anchors.len() as u32
+    }
+
+    /// Identifies the region layout, so counts recorded by a binary built
+    /// from other source are not matched to these regions.
+    pub fn hash(&self) -> u64 {
+        let mut h: u64 = 0xcbf2_9ce4_8422_2325;
+        for r in &self.regions {
+            let s = &r.span;
+            for v in [r.counter as usize, s.start.line, s.start.column, s.end.line, s.end.column, r.gap as usize] {
+                h = (h ^ v as u64).wrapping_mul(0x0100_0000_01b3);
+            }
+        }
+        h.max(1)
+    }
+
+    fn counter(&mut self, anchor: Anchor, start: Position, end: Position) -> u32 {
+        if let Some(&counter) = self.anchors.get(&anchor) {
+            return counter;
+        }
+        let counter = self.anchors.len() as u32;
+        self.anchors.insert(anchor, counter);
+        self.regions.push(Region { counter, span: Span::new(start, end), gap: false });
+        counter
+    }
+
+    fn gap(&mut self, counter: u32, start: Position, end: Position) {
+        if (start.line, start.column) < (end.line, end.column) {
+            self.regions.push(Region { counter, span: Span::new(start, end), gap: true });
+        }
+    }
+
+    fn block(&mut self, block: &Block) -> u32 {
+        let counter = self.counter(Anchor::block(block), block.span.start, block.span.end);
+        for (i, stmt) in block.stmts.iter().enumerate() {
+            self.stmt(stmt);
+            // The rest starts right after the statement, so that the lines
+            // up to the next one get its count too.
+            if leaves_stmt(stmt) && (i + 1 < block.stmts.len() || block.tail.is_some()) {
+                self.counter(Anchor::split(block, i + 1), stmt_span(stmt).end, block.span.end);
+            }
+        }
+        if let Some(tail) = &block.tail {
+            self.expr(tail);
+        }
+        counter
+    }
+
+    fn stmt(&mut self, stmt: &Stmt) {
+        match stmt {
+            Stmt::Var(v) => self.expr(&v.value),
+            Stmt::Expr(e, _) => self.expr(e),
+            Stmt::Return(e, _) | Stmt::Break(_, e, _) => {
+                if let Some(e) = e {
+                    self.expr(e);
+                }
+            }
+            Stmt::Continue(..) => {}
+            Stmt::If(cond, then_block, else_stmt, _) => {
+                self.expr(cond);
+                self.block(then_block);
+                match else_stmt.as_deref() {
+                    Some(s @ Stmt::If(.., span)) => {
+                        self.counter(Anchor::Else(span.start.offset), span.start, span.end);
+                        self.stmt(s);
+                    }
+                    Some(s) => self.stmt(s),
+                    None => {}
+                }
+            }
+            Stmt::While(cond, body, ..) => {
+                self.expr(cond);
+                self.block(body);
+            }
+            Stmt::For(_, iter, body, ..) => {
+                self.expr(iter);
+                self.block(body);
+            }
+            Stmt::Match(e, arms, _) => {
+                self.expr(e);
+                self.arms(span_of(e).end, arms);
+            }
+            Stmt::Defer(b, _) | Stmt::Block(b) => {
+                self.block(b);
+            }
+        }
+    }
+
+    /// Each arm counts from its pattern on, and a gap before it carries that
+    /// count to the start of its line.
+    fn arms(&mut self, scrutinee_end: Position, arms: &[MatchArm]) {
+        let mut prev = scrutinee_end;
+        for arm in arms {
+            let start = pattern_span(&arm.pat).start;
+            let body = span_of(&arm.body);
+            let counter = if let Expr::Block(b) = &arm.body {
+                self.block(b)
+            } else {
+                let counter = self.counter(Anchor::arm(arm), start, body.end);
+                self.expr(&arm.body);
+                counter
+            };
+            self.gap(counter, prev, start);
+            prev = body.end;
+        }
+    }
+
+    fn expr(&mut self, expr: &Expr) {
+        match expr {
+            Expr::Binary { left, op: BinaryOp::And | BinaryOp::Or, right, .. } => {
+                self.expr(left);
+                let span = span_of(right);
+                self.counter(Anchor::rhs(right), span.start, span.end);
+                self.expr(right);
+            }
+            Expr::If { cond, then_block, else_block, .. } => {
+                self.expr(cond);
+                self.block(then_block);
+                self.block(else_block);
+            }
+            Expr::Match { expr, arms, .. } => {
+                self.expr(expr);
+                self.arms(span_of(expr).end, arms);
+            }
+            Expr::Loop { body, .. } | Expr::Block(body) => {
+                self.block(body);
+            }
+            _ => {
+                for child in children(expr) {
+                    self.expr(child);
+                }
+            }
+        }
+    }
+}
+
+/// Whether a `return`, `break` or `continue` inside `stmt` may skip the
+/// statements after it.
+fn leaves_stmt(stmt: &Stmt) -> bool {
+    match stmt {
+        Stmt::Return(..) | Stmt::Break(..) | Stmt::Continue(..) => true,
+        Stmt::Var(v) => leaves_expr(&v.value),
+        Stmt::Expr(e, _) => leaves_expr(e),
+        Stmt::If(cond, then_block, else_stmt, _) => {
+            leaves_expr(cond) || leaves_block(then_block) || else_stmt.as_deref().is_some_and(leaves_stmt)
+        }
+        Stmt::While(cond, body, ..) => leaves_expr(cond) || leaves_block(body),
+        Stmt::For(_, iter, body, ..) => leaves_expr(iter) || leaves_block(body),
+        Stmt::Match(e, arms, _) => leaves_expr(e) || arms.iter().any(|a| leaves_expr(&a.body)),
+        Stmt::Defer(..) => false,
+        Stmt::Block(b) => leaves_block(b),
+    }
+}
+
+fn leaves_block(block: &Block) -> bool {
+    block.stmts.iter().any(leaves_stmt) || block.tail.as_deref().is_some_and(leaves_expr)
+}
+
+fn leaves_expr(expr: &Expr) -> bool {
+    match expr {
+        Expr::If { cond, then_block, else_block, .. } => {
+            leaves_expr(cond) || leaves_block(then_block) || leaves_block(else_block)
+        }
+        Expr::Match { expr, arms, .. } => leaves_expr(expr) || arms.iter().any(|a| leaves_expr(&a.body)),
+        Expr::Loop { body, .. } | Expr::Block(body) => leaves_block(body),
+        _ => children(expr).into_iter().any(leaves_expr),
+    }
+}
+
+/// The operands of expressions without blocks of their own.
+fn children(expr: &Expr) -> Vec<&Expr> {
+    match expr {
+        Expr::Literal(..) | Expr::Ident(..) | Expr::Instantiate { .. } => Vec::new(),
+        Expr::StructLit { fields, .. } => fields.iter().map(|(_, e)| e).collect(),
+        Expr::Unary { expr, .. } | Expr::Member { target: expr, .. } | Expr::Cast { expr, .. } => vec![expr],
+        Expr::Binary { left, right, .. } | Expr::Assign { left, right, .. } => vec![left, right],
+        Expr::Index { target, index, .. } => vec![target, index],
+        Expr::Range { start, end, .. } => vec![start, end],
+        Expr::Call { callee, args, .. } => std::iter::once(&**callee).chain(args).collect(),
+        Expr::Array(items, _) | Expr::Tuple(items, _) | Expr::Interpolated { parts: items, .. } => items.iter().collect(),
+        Expr::Tensor(rows, _) => rows.iter().flatten().collect(),
+        Expr::If { .. } | Expr::Match { .. } | Expr::Loop { .. } | Expr::Block(_) => Vec::new(),
+    }
+}
+
+fn stmt_span(stmt: &Stmt) -> Span {
+    match stmt {
+        Stmt::Var(v) => v.span,
+        // The span of an expression statement may be just its `;`.
+        Stmt::Expr(e, s) => Span::new(span_of(e).start, s.end),
+        Stmt::Return(_, s)
+        | Stmt::Break(_, _, s)
+        | Stmt::Continue(_, s)
+        | Stmt::If(.., s)
+        | Stmt::While(.., s)
+        | Stmt::For(.., s)
+        | Stmt::Match(.., s)
+        | Stmt::Defer(_, s) => *s,
+        Stmt::Block(b) => b.span,
+    }
+}
+
+fn span_of(expr: &Expr) -> Span {
+    match expr {
+        Expr::Literal(_, s) | Expr::Ident(_, s) | Expr::Array(_, s) | Expr::Tuple(_, s) | Expr::Tensor(_, s) => *s,
+        Expr::Instantiate { span, .. }
+        | Expr::StructLit { span, .. }
+        | Expr::Unary { span, .. }
+        | Expr::Binary { span, .. }
+        | Expr::Assign { span, .. }
+        | Expr::Call { span, .. }
+        | Expr::Member { span, .. }
+        | Expr::Index { span, .. }
+        | Expr::Range { span, .. }
+        | Expr::Cast { span, .. }
+        | Expr::If { span, .. }
+        | Expr::Match { span, .. }
+        | Expr::Loop { span, .. }
+        | Expr::Interpolated { span, .. } => *span,
+        Expr::Block(b) => b.span,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{FunctionCoverage, SourceMap};
+    use crate::ast::Item;
+    use crate::lexer::Lexer;
+    use crate::parser::Parser;
+
+    /// The non-gap regions of the first function, as `counter: start-end` lines.
+    fn regions(src: &str) -> Vec<String> {
+        let tokens = Lexer::new(src).tokenize().expect("lex");
+        let program = Parser::new(tokens).parse_program().expect("parse");
+        let Some(Item::Fun(f)) = program.items.first() else { panic!("no function") };
+        let cov = FunctionCoverage::of(&f.body);
+        assert_eq!(cov.counters() as usize, cov.regions.iter().filter(|r| !r.gap).count());
+        cov.regions
+            .iter()
+            .filter(|r| !r.gap)
+            .map(|r| format!("{}: {}:{}-{}:{}", r.counter, r.span.start.line, r.span.start.column, r.span.end.line, r.span.end.column))
+            .collect()
+    }
+
+    #[test]
+    fn early_exits_and_branches_get_counters() {
+        let src = "fun f(n: Int) -> Int {\n    if (n < 0) {\n        return 0;\n    } else if (n == 1) {\n        return 1;\n    }\n    n > 2 && n < 9;\n    match (n) {\n        3 => 4;\n        _ => { 5 }\n    }\n}\n";
+        assert_eq!(
+            regions(src),
+            [
+                "0: 1:22-12:2",
+                "1: 2:16-4:6",
+                "2: 4:12-6:6",
+                "3: 4:24-6:6",
+                "4: 6:6-12:2",
+                "5: 7:14-7:19",
+                "6: 9:9-9:15",
+                "7: 10:14-10:19",
+            ]
+        );
+    }
+
+    #[test]
+    fn source_map_skips_generated_lines() {
+        let mut map = SourceMap::new();
+        map.push_line("/a.kor", 1);
+        map.push_generated();
+        map.push_line("/b.kor", 7);
+        assert_eq!(map.files, ["/a.kor", "/b.kor"]);
+        assert_eq!((map.locate(1), map.locate(2), map.locate(3), map.locate(4)), (Some((0, 1)), None, Some((1, 7)), None));
+    }
+}
@@ -24,10 +24,29 @@ fn analyze_fun(fun: &FunDecl) -> EscapeResult {
     res
 }
 
+fn collect_pattern_locals(pat: &Pattern, locals: &mut HashSet<String>) {
+    match pat {
+        Pattern::Ident(name, _) => {
+            locals.insert(name.clone());
+        }
+        Pattern::Tuple(items, _) | Pattern::Variant { args: items, .. } => {
+            items.iter().for_each(|p| collect_pattern_locals(p, locals));
+        }
+        Pattern::Struct { fields, .. } => fields.iter().for_each(|(_, p)| collect_pattern_locals(p, locals)),
+        Pattern::Is(_, inner, _) => collect_pattern_locals(inner, locals),
+        Pattern::Literal(..) | Pattern::Wildcard(_) => {}
+    }
+}
+
 fn collect_locals(block: &Block, locals: &mut HashSet<String>) {
     for stmt in &block.stmts {
         if let Stmt::Var(v) = stmt {
-            locals.insert(v.name.clone());
+            match &v.pattern {
+                Some(pat) => collect_pattern_locals(pat, locals),
+                None => {
+                    locals.insert(v.name.clone());
+                }
+            }
         }
         if let Stmt::Block(b) = stmt {
             collect_locals(b, locals);
@@ -40,10 +59,7 @@ fn collect_locals(block: &Block, locals: &mut HashSet<String>) {
                 }
             }
         }
-        if let Stmt::While(_, b, _) = stmt {
-            collect_locals(b, locals);
-        }
-        if let Stmt::For(_, _, b, _) = stmt {
+        if let Stmt::While(_, b, ..) | Stmt::For(_, _, b, ..) | Stmt::Defer(b, _) = stmt {
             collect_locals(b, locals);
         }
         if let Stmt::Match(_, arms, _) = stmt {
@@ -71,14 +87,17 @@ fn mark_escapes_in_block(block: &Block, res: &mut EscapeResult, locals: &HashSet
                     }
                 }
             }
-            Stmt::While(cond, b, _) => {
+            Stmt::While(cond, b, ..) => {
                 mark_escapes(cond, res, locals, false);
                 mark_escapes_in_block(b, res, locals);
             }
-            Stmt::For(_, iter, b, _) => {
+            Stmt::For(_, iter, b, ..) => {
                 mark_escapes(iter, res, locals, false);
                 mark_escapes_in_block(b, res, locals);
             }
+            // The value of a `loop` may leave the function, like a return value.
+            Stmt::Break(_, Some(expr), _) => mark_escapes(expr, res, locals, true),
+            Stmt::Defer(b, _) => mark_escapes_in_block(b, res, locals),
             Stmt::Match(e, arms, _) => {
                 mark_escapes(e, res, locals, false);
                 for arm in arms {
@@ -137,12 +156,17 @@ fn mark_escapes(expr: &Expr, res: &mut EscapeResult, locals: &HashSet<String>, s
                 mark_escapes(&arm.body, res, locals, should_mark);
             }
         }
-        Expr::Block(b) => mark_escapes_in_block(b, res, locals),
+        Expr::Block(b) | Expr::Loop { body: b, .. } => mark_escapes_in_block(b, res, locals),
         Expr::Array(items, _) => {
             for it in items {
                 mark_escapes(it, res, locals, true);
             }
         }
+        Expr::Tuple(items, _) => {
+            for it in items {
+                mark_escapes(it, res, locals, should_mark);
+            }
+        }
         Expr::Tensor(rows, _) => {
             for row in rows {
                 for it in row {
@@ -0,0 +1,516 @@
+use crate::ast::{Literal, MatchArm, Pattern, TypeRef};
+use crate::diag::{Diagnostic, Span};
+use crate::sema::{Sema, Type};
+
+/// Missing patterns listed in a non-exhaustive diagnostic.
+const MAX_WITNESSES: usize = 3;
+
+#[derive(Debug, Clone, PartialEq)]
+enum Ctor {
+    /// `(enum, variant)`.
+    Variant(String, String),
+    /// A direct child of a sealed hierarchy; its single field is the child value.
+    Child(String),
+    Struct(String),
+    Tuple(usize),
+    Bool(bool),
+    /// A literal of an open domain (ints, strings, ...) or an unresolved name.
+    /// These never form a complete signature.
+    Opaque(String),
+}
+
+#[derive(Debug, Clone)]
+enum Pat {
+    Wild,
+    Ctor(Ctor, Vec<Pat>),
+}
+
+impl Pat {
+    fn ctor(&self) -> Option<&Ctor> {
+        match self {
+            Pat::Wild => None,
+            Pat::Ctor(c, _) => Some(c),
+        }
+    }
+}
+
+type Row = Vec<Pat>;
+
+/// Exhaustiveness and reachability for one `match`, using the pattern-matrix
+/// usefulness algorithm: a row is useful against a matrix if some value is
+/// matched by the row and by no row of the matrix. An arm that is not useful
+/// against the arms above it is unreachable, and the match is exhaustive when a
+/// wildcard is not useful against all of its arms.
+pub fn check_match(sema: &Sema, scrutinee: &Type, arms: &[MatchArm], span: Span) -> Vec<Diagnostic> {
+    let checker = MatchChecker { sema };
+    let mut diags = Vec::new();
+    let mut rows: Vec<Row> = Vec::new();
+    for arm in arms {
+        let row = vec![checker.lower(&arm.pat, scrutinee)];
+        if !checker.useful(&rows, &row, std::slice::from_ref(scrutinee)) {
+            diags.push(Diagnostic::warning("unreachable match arm", pattern_span(&arm.pat)));
+        }
+        rows.push(row);
+    }
+
+    let missing = checker.missing(&rows, std::slice::from_ref(scrutinee), MAX_WITNESSES + 1);
+    if !missing.is_empty() {
+        let mut shown: Vec<String> = missing
+            .iter()
+            .take(MAX_WITNESSES)
+            .map(|w| format!("`{}`", checker.show(&w[0])))
+            .collect();
+        let msg = if missing.len() > MAX_WITNESSES {
+            format!("non-exhaustive match: patterns {} and more not covered", shown.join(", "))
+        } else if shown.len() == 1 {
+            format!("non-exhaustive match: pattern {} not covered", shown[0])
+        } else {
+            let last = shown.pop().unwrap_or_default();
+            format!("non-exhaustive match: patterns {} and {} not covered", shown.join(", "), last)
+        };
+        diags.push(Diagnostic::error(msg, span));
+    }
+    diags
+}
+
+/// A `let` pattern must match every value of its type, like a one-arm match.
+pub fn check_irrefutable(sema: &Sema, ty: &Type, pat: &Pattern) -> Option<Diagnostic> {
+    let checker = MatchChecker { sema };
+    let rows = vec![vec![checker.lower(pat, ty)]];
+    let missing = checker.missing(&rows, std::slice::from_ref(ty), 1);
+    let witness = missing.first()?;
+    Some(Diagnostic::error(
+        format!("refutable pattern in 'let': `{}` not covered", checker.show(&witness[0])),
+        pattern_span(pat),
+    ))
+}
+
+struct MatchChecker<'s> {
+    sema: &'s Sema,
+}
+
+impl<'s> MatchChecker<'s> {
+    // ---------- lowering ----------
+
+    fn lower(&self, pat: &Pattern, ty: &Type) -> Pat {
+        if let Some(sealed) = self.sealed_name(ty) {
+            return self.lower_sealed(&sealed, pat);
+        }
+        match pat {
+            Pattern::Wildcard(_) => Pat::Wild,
+            Pattern::Ident(name, _) => match self.sema.lookup_variant(name, ty) {
+                Some((e, v)) => self.variant(&e, &v, &[], ty),
+                None => Pat::Wild,
+            },
+            Pattern::Literal(Literal::Bool(b), _) => Pat::Ctor(Ctor::Bool(*b), Vec::new()),
+            Pattern::Literal(lit, _) => Pat::Ctor(Ctor::Opaque(format!("{:?}", lit)), Vec::new()),
+            Pattern::Tuple(parts, _) => {
+                let tys = match ty {
+                    Type::Tuple(ts) if ts.len() == parts.len() => ts.clone(),
+                    _ => vec![Type::Unknown; parts.len()],
+                };
+                let args = parts.iter().zip(&tys).map(|(p, t)| self.lower(p, t)).collect();
+                Pat::Ctor(Ctor::Tuple(parts.len()), args)
+            }
+            Pattern::Variant { name, args, .. } => {
+                if let Some((e, v)) = self.sema.lookup_variant(name, ty) {
+                    self.variant(&e, &v, args, ty)
+                } else if self.sema.structs.contains_key(name) {
+                    self.positional_struct(name, args, ty)
+                } else {
+                    opaque(name)
+                }
+            }
+            Pattern::Struct { name, fields, .. } => {
+                if !self.sema.structs.contains_key(name) {
+                    return opaque(name);
+                }
+                let args = self
+                    .sema
+                    .struct_field_types(name, ty)
+                    .iter()
+                    .map(|(field, fty)| match fields.iter().find(|(f, _)| f == field) {
+                        Some((_, p)) => self.lower(p, fty),
+                        None => Pat::Wild,
+                    })
+                    .collect();
+                Pat::Ctor(Ctor::Struct(name.clone()), args)
+            }
+            Pattern::Is(tr, inner, _) => self.lower_is(tr, inner, ty),
+        }
+    }
+
+    fn lower_is(&self, tr: &TypeRef, inner: &Pattern, ty: &Type) -> Pat {
+        let target = self.sema.type_from_ref(tr);
+        let name = match &target {
+            Type::Named(n) | Type::Generic(n, _) => n.clone(),
+            _ => return if &target == ty { self.lower(inner, ty) } else { opaque(&format!("{:?}", target)) },
+        };
+        let tuple_args = match inner {
+            Pattern::Tuple(parts, _) => parts.as_slice(),
+            _ => &[],
+        };
+        if self.sema.structs.contains_key(&name) {
+            return match inner {
+                Pattern::Tuple(parts, _) => self.positional_struct(&name, parts, &target),
+                Pattern::Wildcard(_) | Pattern::Ident(..) => self.positional_struct(&name, &[], &target),
+                _ => self.lower(inner, &target),
+            };
+        }
+        if self.sema.enums.contains_key(&name) || self.sema.sealed_types.contains_key(&name) {
+            return self.lower(inner, &target);
+        }
+        match self.sema.lookup_variant(&name, ty) {
+            Some((e, v)) => self.variant(&e, &v, tuple_args, ty),
+            None => opaque(&name),
+        }
+    }
+
+    fn lower_sealed(&self, sealed: &str, pat: &Pattern) -> Pat {
+        let sealed_ty = Type::Named(sealed.to_string());
+        let child = |name: &str| self.sema.sealed_child(sealed, name);
+        match pat {
+            Pattern::Wildcard(_) => Pat::Wild,
+            Pattern::Is(tr @ TypeRef::Named(n, _, _), inner, _) if child(n) => {
+                let sub = self.lower_is(tr, inner, &Type::Named(n.clone()));
+                Pat::Ctor(Ctor::Child(n.clone()), vec![sub])
+            }
+            Pattern::Struct { name, .. } | Pattern::Variant { name, .. } if child(name) => {
+                Pat::Ctor(Ctor::Child(name.clone()), vec![self.lower(pat, &Type::Named(name.clone()))])
+            }
+            Pattern::Ident(name, _) | Pattern::Variant { name, .. } => match self.sema.lookup_variant(name, &sealed_ty) {
+                Some((e, _)) if child(&e) => {
+                    Pat::Ctor(Ctor::Child(e.clone()), vec![self.lower(pat, &Type::Named(e))])
+                }
+                _ if matches!(pat, Pattern::Ident(..)) => Pat::Wild,
+                _ => opaque(name),
+            },
+            _ => self.lower(pat, &Type::Unknown),
+        }
+    }
+
+    fn variant(&self, enum_name: &str, variant: &str, args: &[Pattern], ty: &Type) -> Pat {
+        let payload = self.sema.variant_payload(enum_name, variant, ty);
+        let sub = payload
+            .iter()
+            .enumerate()
+            .map(|(i, t)| args.get(i).map(|p| self.lower(p, t)).unwrap_or(Pat::Wild))
+            .collect();
+        Pat::Ctor(Ctor::Variant(enum_name.to_string(), variant.to_string()), sub)
+    }
+
+    fn positional_struct(&self, name: &str, args: &[Pattern], ty: &Type) -> Pat {
+        let sub = self
+            .sema
+            .struct_field_types(name, ty)
+            .iter()
+            .enumerate()
+            .map(|(i, (_, t))| args.get(i).map(|p| self.lower(p, t)).unwrap_or(Pat::Wild))
+            .collect();
+        Pat::Ctor(Ctor::Struct(name.to_string()), sub)
+    }
+
+    fn sealed_name(&self, ty: &Type) -> Option<String> {
+        match ty {
+            Type::Named(n) if self.sema.sealed_types.contains_key(n) => Some(n.clone()),
+            _ => None,
+        }
+    }
+
+    // ---------- constructor signatures ----------
+
+    /// All constructors of `ty`, or `None` for open domains. An unknown type is
+    /// inferred from the constructors the column actually uses.
+    fn constructors(&self, ty: &Type, heads: &[&Ctor]) -> Option<Vec<Ctor>> {
+        match ty {
+            Type::Bool => Some(vec![Ctor::Bool(true), Ctor::Bool(false)]),
+            Type::Tuple(ts) => Some(vec![Ctor::Tuple(ts.len())]),
+            Type::Named(n) | Type::Generic(n, _) => {
+                if let Some(e) = self.sema.enums.get(n) {
+                    Some(e.variants.iter().map(|v| Ctor::Variant(n.clone(), v.name.clone())).collect())
+                } else if let Some(s) = self.sema.sealed_types.get(n) {
+                    Some(
+                        s.items
+                            .iter()
+                            .filter_map(|item| match item {
+                                crate::ast::Item::Struct(st) => Some(Ctor::Child(st.name.clone())),
+                                crate::ast::Item::Enum(e) => Some(Ctor::Child(e.name.clone())),
+                                _ => None,
+                            })
+                            .collect(),
+                    )
+                } else if self.sema.structs.contains_key(n) {
+                    Some(vec![Ctor::Struct(n.clone())])
+                } else {
+                    None
+                }
+            }
+            Type::Unknown | Type::Any => match heads.first()? {
+                Ctor::Variant(e, _) => self.constructors(&Type::Named(e.clone()), &[]),
+                Ctor::Struct(s) => Some(vec![Ctor::Struct(s.clone())]),
+                Ctor::Tuple(n) => Some(vec![Ctor::Tuple(*n)]),
+                Ctor::Bool(_) => self.constructors(&Type::Bool, &[]),
+                Ctor::Child(_) | Ctor::Opaque(_) => None,
+            },
+            _ => None,
+        }
+    }
+
+    /// Types of the sub-patterns of `ctor` when matching a value of type `ty`.
+    fn fields(&self, ctor: &Ctor, ty: &Type) -> Vec<Type> {
+        match ctor {
+            Ctor::Variant(e, v) => self.sema.variant_payload(e, v, ty),
+            Ctor::Child(c) => vec![Type::Named(c.clone())],
+            Ctor::Struct(s) => self.sema.struct_field_types(s, ty).into_iter().map(|(_, t)| t).collect(),
+            Ctor::Tuple(n) => match ty {
+                Type::Tuple(ts) if ts.len() == *n => ts.clone(),
+                _ => vec![Type::Unknown; *n],
+            },
+            Ctor::Bool(_) | Ctor::Opaque(_) => Vec::new(),
+        }
+    }
+
+    fn arity(&self, ctor: &Ctor, ty: &Type) -> usize {
+        self.fields(ctor, ty).len()
+    }
+
+    // ---------- the matrix algorithm ----------
+
+    fn useful(&self, rows: &[Row], v: &[Pat], tys: &[Type]) -> bool {
+        let Some((head, rest)) = v.split_first() else {
+            return rows.is_empty();
+        };
+        let ty = &tys[0];
+        match head {
+            Pat::Ctor(c, args) => {
+                let sub = specialize(rows, c, args.len());
+                let mut next: Row = args.clone();
+                next.extend_from_slice(rest);
+                let mut next_tys = self.fields(c, ty);
+                next_tys.extend_from_slice(&tys[1..]);
+                self.useful(&sub, &next, &next_tys)
+            }
+            Pat::Wild => {
+                let heads = column_heads(rows);
+                match self.constructors(ty, &heads) {
+                    Some(all) if is_complete(&all, &heads) => all.iter().any(|c| {
+                        let arity = self.arity(c, ty);
+                        let mut next = vec![Pat::Wild; arity];
+                        next.extend_from_slice(rest);
+                        let mut next_tys = self.fields(c, ty);
+                        next_tys.extend_from_slice(&tys[1..]);
+                        self.useful(&specialize(rows, c, arity), &next, &next_tys)
+                    }),
+                    _ => self.useful(&default_rows(rows), rest, &tys[1..]),
+                }
+            }
+        }
+    }
+
+    /// Value vectors, as patterns, that no row matches; at most `limit` of them.
+    fn missing(&self, rows: &[Row], tys: &[Type], limit: usize) -> Vec<Row> {
+        let Some((ty, rest_tys)) = tys.split_first() else {
+            return if rows.is_empty() { vec![Vec::new()] } else { Vec::new() };
+        };
+        let heads = column_heads(rows);
+        let all = self.constructors(ty, &heads);
+        let mut out = Vec::new();
+        match all {
+            Some(all) if is_complete(&all, &heads) => {
+                for c in &all {
+                    let arity = self.arity(c, ty);
+                    let mut next_tys = self.fields(c, ty);
+                    next_tys.extend_from_slice(rest_tys);
+                    for w in self.missing(&specialize(rows, c, arity), &next_tys, limit - out.len()) {
+                        let (args, rest) = w.split_at(arity);
+                        let mut row = vec![Pat::Ctor(c.clone(), args.to_vec())];
+                        row.extend_from_slice(rest);
+                        out.push(row);
+                    }
+                    if out.len() >= limit {
+                        break;
+                    }
+                }
+            }
+            all => {
+                let rest = self.missing(&default_rows(rows), rest_tys, limit);
+                if rest.is_empty() {
+                    return out;
+                }
+                let fill: Vec<Pat> = match all {
+                    Some(all) if !heads.is_empty() => all
+                        .iter()
+                        .filter(|c| !heads.contains(c))
+                        .map(|c| Pat::Ctor(c.clone(), vec![Pat::Wild; self.arity(c, ty)]))
+                        .collect(),
+                    _ => vec![Pat::Wild],
+                };
+                'outer: for f in &fill {
+                    for w in &rest {
+                        let mut row = vec![f.clone()];
+                        row.extend_from_slice(w);
+                        out.push(row);
+                        if out.len() >= limit {
+                            break 'outer;
+                        }
+                    }
+                }
+            }
+        }
+        out
+    }
+
+    // ---------- witnesses ----------
+
+    fn show(&self, pat: &Pat) -> String {
+        match pat {
+            Pat::Wild => "_".to_string(),
+            Pat::Ctor(Ctor::Variant(e, v), args) if args.is_empty() => format!("{}.{}", e, v),
+            Pat::Ctor(Ctor::Variant(e, v), args) => format!("{}.{}({})", e, v, self.show_list(args)),
+            Pat::Ctor(Ctor::Child(c), args) => match args.first() {
+                Some(Pat::Wild) | None => format!("is {}", c),
+                Some(sub) => self.show(sub),
+            },
+            Pat::Ctor(Ctor::Struct(s), args) => {
+                let names = self.sema.struct_field_types(s, &Type::Unknown);
+                let fields: Vec<String> = names
+                    .iter()
+                    .zip(args)
+                    .map(|((n, _), p)| format!("{}: {}", n, self.show(p)))
+                    .collect();
+                if fields.is_empty() {
+                    format!("{} {{}}", s)
+                } else {
+                    format!("{} {{ {} }}", s, fields.join(", "))
+                }
+            }
+            Pat::Ctor(Ctor::Tuple(_), args) => format!("({})", self.show_list(args)),
+            Pat::Ctor(Ctor::Bool(b), _) => b.to_string(),
+            Pat::Ctor(Ctor::Opaque(_), _) => "_".to_string(),
+        }
+    }
+
+    fn show_list(&self, pats: &[Pat]) -> String {
+        pats.iter().map(|p| self.show(p)).collect::<Vec<_>>().join(", ")
+    }
+}
+
+fn opaque(name: &str) -> Pat {
+    Pat::Ctor(Ctor::Opaque(name.to_string()), Vec::new())
+}
+
+fn column_heads(rows: &[Row]) -> Vec<&Ctor> {
+    let mut heads: Vec<&Ctor> = Vec::new();
+    for c in rows.iter().filter_map(|r| r[0].ctor()) {
+        if !heads.contains(&c) {
+            heads.push(c);
+        }
+    }
+    heads
+}
+
+fn is_complete(all: &[Ctor], heads: &[&Ctor]) -> bool {
+    !all.is_empty() && all.iter().all(|c| heads.contains(&c))
+}
+
+fn specialize(rows: &[Row], ctor: &Ctor, arity: usize) -> Vec<Row> {
+    rows.iter()
+        .filter_map(|row| {
+            let mut out = match &row[0] {
+                Pat::Wild => vec![Pat::Wild; arity],
+                Pat::Ctor(c, args) if c == ctor => args.clone(),
+                Pat::Ctor(..) => return None,
+            };
+            out.extend_from_slice(&row[1..]);
+            Some(out)
+        })
+        .collect()
+}
+
+fn default_rows(rows: &[Row]) -> Vec<Row> {
+    rows.iter().filter(|r| matches!(r[0], Pat::Wild)).map(|r| r[1..].to_vec()).collect()
+}
+
+pub(crate) fn pattern_span(pat: &Pattern) -> Span {
+    match pat {
+        Pattern::Ident(_, s) | Pattern::Wildcard(s) | Pattern::Literal(_, s) | Pattern::Tuple(_, s) => *s,
+        Pattern::Variant { span, .. } | Pattern::Struct { span, .. } | Pattern::Is(_, _, span) => *span,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::diag::DiagnosticLevel;
+    use crate::test_support::{check, errors};
+
+    const COLOR: &str = "enum Color { Red; Green; Blue(Int); }\n";
+
+    #[test]
+    fn missing_enum_variants_are_listed() {
+        let src = format!("{}fun f(c: Color) -> Int {{ match (c) {{ Color.Red => 1; }} }}", COLOR);
+        assert_eq!(
+            errors(&src),
+            ["non-exhaustive match: patterns `Color.Green` and `Color.Blue(_)` not covered"]
+        );
+    }
+
+    #[test]
+    fn nested_bool_payload_patterns() {
+        let src = "enum Pair { P(Bool, Bool); }\n\
+                   fun f(p: Pair) -> Int { match (p) { P(true, _) => 1; P(false, true) => 2; } }";
+        assert_eq!(errors(src), ["non-exhaustive match: pattern `Pair.P(false, false)` not covered"]);
+    }
+
+    #[test]
+    fn let_patterns_must_be_irrefutable() {
+        let src = "fun pair() -> (Int, Bool) { (1, true) }\n\
+                   fun f() -> Int { let (a, _) = pair(); let (b, true) = pair(); let (c, d, e) = pair(); a }";
+        assert_eq!(
+            errors(src),
+            ["refutable pattern in 'let': `(_, false)` not covered", "tuple pattern length mismatch: expected 2, got 3"]
+        );
+    }
+
+    #[test]
+    fn unreachable_arm_is_a_warning() {
+        let src = format!(
+            "{}fun f(c: Color) -> Int {{ match (c) {{ Color.Blue(_) => 1; _ => 2; Color.Red => 3; }} }}",
+            COLOR
+        );
+        let warnings = check(&src).expect("an unreachable arm alone does not fail checking");
+        assert_eq!(warnings.len(), 1);
+        assert_eq!(warnings[0].level, DiagnosticLevel::Warning);
+        assert_eq!(warnings[0].message, "unreachable match arm");
+    }
+
+    #[test]
+    fn warnings_alone_do_not_fail_checking() {
+        let redundant = format!(
+            "{}fun f(c: Color) -> Int {{ match (c) {{ _ => 1; Color.Red => 2; }} }}",
+            COLOR
+        );
+        assert!(check(&redundant).is_ok());
+        let mixed = format!("{}fun g() -> Int {{ missing() }}", redundant);
+        let diags = check(&mixed).expect_err("errors still fail checking");
+        assert!(diags.iter().any(|d| d.level == DiagnosticLevel::Warning), "{:?}", diags);
+        assert!(diags.iter().any(|d| d.level == DiagnosticLevel::Error), "{:?}", diags);
+    }
+
+    #[test]
+    fn sealed_hierarchy_children_must_be_covered() {
+        let src = "sealed Shape { struct Circle { r: Float } struct Square { s: Float } }\n\
+                   fun f(x: Shape) -> Int { match (x) { Circle { r } => 1; } }";
+        assert_eq!(errors(src), ["non-exhaustive match: pattern `is Square` not covered"]);
+    }
+
+    #[test]
+    fn payload_patterns_and_open_literals() {
+        let ok = format!(
+            "{}fun f(c: Color) -> Int {{ match (c) {{ Color.Blue(0) => 0; Color.Blue(n) => n; Red => 1; Green => 2; }} }}",
+            COLOR
+        );
+        assert!(errors(&ok).is_empty(), "{:?}", errors(&ok));
+        let src = "fun f(n: Int) -> Int { match (n) { 0 => 1; 1 => 2; } }";
+        assert_eq!(errors(src), ["non-exhaustive match: pattern `_` not covered"]);
+    }
+}
@@ -1,15 +1,13 @@
 use crate::sema::{Sema, Type};
 use crate::diag::Span;
+use crate::specialization::{Resolution, SpecQuery};
 
 pub fn resolve_extension_method(sema: &Sema, receiver_ty: &Type, method_name: &str) -> Option<Type> {
-    if let Some(methods) = sema.extensions.get(method_name) {
-        for (recv, sig) in methods {
-            if recv == receiver_ty {
-                return Some(sig.clone());
-            }
-        }
+    let query = SpecQuery { generic_args: &[], receiver: Some(receiver_ty), args: None };
+    match sema.specializations.find_best_match(method_name, &query) {
+        Resolution::Selected(spec, subst) => Some(spec.instantiated_sig(&subst)),
+        _ => None,
     }
-    None
 }
 
 pub fn check_extension_dispatch(sema: &mut Sema, receiver_ty: &Type, method_name: &str, span: Span) -> Type {
@@ -0,0 +1,1266 @@
+//! `korlang bindgen`: reads a C header and writes a Korlang module of
+//! `extern` declarations, layout-matching structs and integer constants.
+//!
+//! Only a practical subset of C is understood: function prototypes, structs,
+//! enums, typedefs and `#define`d integer constants. A small preprocessor
+//! evaluates conditionals against the host's predefined macros, follows
+//! `#include "..."` and expands macros, which strips the usual
+//! `API`/`EXPORT` decorations. Whatever cannot be bound is listed at the end
+//! of the generated module.
+
+use std::collections::{HashMap, HashSet};
+
+/// C names that are Korlang keywords; fields and parameters called so get a
+/// trailing `_`.
+const KEYWORDS: &[&str] = &[
+    "fun", "gpu", "let", "var", "if", "else", "match", "for", "while", "loop", "defer", "break", "continue",
+    "return", "view", "resource", "state", "spawn", "import", "as", "struct", "enum", "type", "in", "mut",
+    "interface", "sealed", "implements", "impl", "extern", "class", "true", "false", "null", "self", "async",
+];
+
+/// Words that may precede or follow a type without changing it.
+const QUALIFIERS: &[&str] = &[
+    "const", "volatile", "static", "inline", "extern", "register", "auto", "restrict", "__restrict",
+    "__restrict__", "__inline", "__inline__", "__extension__", "_Noreturn", "__const", "_Nullable", "_Nonnull",
+    "_Null_unspecified", "__nullable", "__nonnull",
+];
+
+/// Parenthesized decorations that are skipped whole.
+const ATTRIBUTES: &[&str] = &["__attribute__", "__attribute", "__declspec", "__asm__", "__asm", "asm", "_Alignas"];
+
+/// Generates the Korlang bindings for a C header. `source_name` is only
+/// used in the leading comment.
+pub fn generate_bindings(header: &str, source_name: &str) -> String {
+    generate_bindings_with(header, source_name, &mut |_| None)
+}
+
+/// Like `generate_bindings`, reading `#include "file"` through `include`,
+/// which returns the contents of the named file. `<file>` includes are
+/// system headers and are skipped.
+pub fn generate_bindings_with(
+    header: &str,
+    source_name: &str,
+    include: &mut dyn FnMut(&str) -> Option<String>,
+) -> String {
+    let mut gen = Bindgen::default();
+    for (name, value) in predefined() {
+        gen.macros.insert(name.to_string(), Macro { params: None, body: lex(value) });
+    }
+    let mut body = String::new();
+    gen.preprocess(header, include, 0, &mut body);
+    gen.read(&body);
+    gen.render(source_name)
+}
+
+/// What a C compiler for the host predefines; headers test these to pick
+/// their declarations.
+fn predefined() -> Vec<(&'static str, &'static str)> {
+    let mut defs = vec![
+        ("__STDC__", "1"),
+        ("__STDC_VERSION__", "201710L"),
+        ("__STDC_HOSTED__", "1"),
+        ("__GNUC__", "4"),
+        ("__CHAR_BIT__", "8"),
+        ("__SIZEOF_INT__", "4"),
+        ("__SIZEOF_LONG__", "8"),
+        ("__SIZEOF_POINTER__", "8"),
+        ("__LP64__", "1"),
+    ];
+    if cfg!(target_os = "linux") {
+        defs.extend([("__linux__", "1"), ("__unix__", "1")]);
+    } else if cfg!(target_os = "macos") {
+        defs.extend([("__APPLE__", "1"), ("__MACH__", "1")]);
+    }
+    if cfg!(target_arch = "x86_64") {
+        defs.push(("__x86_64__", "1"));
+    } else if cfg!(target_arch = "aarch64") {
+        defs.push(("__aarch64__", "1"));
+    }
+    defs
+}
+
+#[derive(Debug, Clone, PartialEq)]
+enum Tok {
+    Ident(String),
+    Int(i128),
+    Str(String),
+    Punct(String),
+    /// Float literals and anything else bindgen has no use for.
+    Other(String),
+}
+
+#[derive(Debug, Clone, PartialEq)]
+enum CType {
+    Void,
+    Int { bits: u32, signed: bool },
+    Float { bits: u32 },
+    Bool,
+    Enum,
+    /// Key into `Bindgen::structs`: the tag, or a name made up for an
+    /// anonymous struct until a typedef names it.
+    Struct(String),
+    Union,
+    /// A type name whose definition is not in the header, such as `FILE`.
+    Opaque(String),
+    Pointer(Box<CType>),
+    Array(Box<CType>),
+    Function,
+    Unsupported(&'static str),
+}
+
+/// Parameters of a function declarator; unnamed ones have no name.
+type Params = Vec<(Option<String>, CType)>;
+
+/// How a declarator derives its type from the base type, read from the
+/// name outwards.
+enum Deriv {
+    Pointer,
+    Array,
+    Function(Params, bool),
+}
+
+/// A `#define`; `params` is set for function-like macros, with `...` named
+/// `__VA_ARGS__`.
+struct Macro {
+    params: Option<Vec<String>>,
+    body: Vec<Tok>,
+}
+
+struct StructDef {
+    key: String,
+    fields: Vec<(String, CType)>,
+    /// Why the struct has no Korlang layout, if it has none.
+    problem: Option<String>,
+}
+
+struct FunctionDecl {
+    name: String,
+    params: Params,
+    variadic: bool,
+    ret: CType,
+}
+
+#[derive(Default)]
+struct Bindgen {
+    macros: HashMap<String, Macro>,
+    /// Object-like macros in definition order, candidates for constants.
+    defines: Vec<String>,
+    /// Integer constants in source order, with their Korlang type.
+    consts: Vec<(String, i128, &'static str)>,
+    values: HashMap<String, i128>,
+    typedefs: HashMap<String, CType>,
+    structs: Vec<StructDef>,
+    /// Typedef names given to struct tags, e.g. `z_stream` for `z_stream_s`.
+    aliases: HashMap<String, String>,
+    functions: Vec<FunctionDecl>,
+    notes: Vec<String>,
+    anonymous: usize,
+    toks: Vec<Tok>,
+    pos: usize,
+}
+
+type Parsed<T> = Result<T, String>;
+
+impl Bindgen {
+    /// Runs the preprocessor over `src`, appending the lines that survive
+    /// its conditionals to `out`.
+    fn preprocess(&mut self, src: &str, include: &mut dyn FnMut(&str) -> Option<String>, depth: usize, out: &mut String) {
+        // One entry per open `#if`: whether its lines are read, and whether
+        // one of its branches was.
+        let mut conds: Vec<(bool, bool)> = Vec::new();
+        for line in strip_comments(src).replace("\\\n", " ").lines() {
+            let active = conds.iter().all(|c| c.0);
+            let Some(directive) = line.trim_start().strip_prefix('#') else {
+                if active {
+                    out.push_str(line);
+                }
+                out.push('\n');
+                continue;
+            };
+            let directive = directive.trim_start();
+            let end = directive.find(|c: char| !c.is_ascii_alphabetic()).unwrap_or(directive.len());
+            let (word, rest) = directive.split_at(end);
+            match word {
+                "if" | "ifdef" | "ifndef" => {
+                    let taken = active
+                        && match word {
+                            "if" => self.condition(rest),
+                            "ifdef" => self.macros.contains_key(rest.trim()),
+                            _ => !self.macros.contains_key(rest.trim()),
+                        };
+                    conds.push((taken, taken));
+                }
+                "elif" => {
+                    let outer = conds.len().saturating_sub(1);
+                    let parent = conds[..outer].iter().all(|c| c.0);
+                    let taken = parent && conds.last().is_some_and(|c| !c.1) && self.condition(rest);
+                    if let Some(c) = conds.last_mut() {
+                        c.0 = taken;
+                        c.1 |= taken;
+                    }
+                }
+                "else" => {
+                    if let Some(c) = conds.last_mut() {
+                        c.0 = !c.1;
+                        c.1 = true;
+                    }
+                }
+                "endif" => {
+                    conds.pop();
+                }
+                "define" if active => {
+                    if let Some((name, m)) = parse_define(rest) {
+                        if m.params.is_none() {
+                            self.defines.retain(|d| *d != name);
+                            self.defines.push(name.clone());
+                        }
+                        self.macros.insert(name, m);
+                    }
+                }
+                "undef" if active => {
+                    self.macros.remove(rest.trim());
+                }
+                "include" if active && depth < 16 => {
+                    let file = rest.trim().strip_prefix('"').and_then(|r| r.split('"').next());
+                    if let Some(text) = file.and_then(&mut *include) {
+                        self.preprocess(&text, include, depth + 1, out);
+                    }
+                }
+                _ => {}
+            }
+            out.push('\n');
+        }
+    }
+
+    /// Evaluates an `#if` condition. As in C, `defined` is decided first
+    /// and identifiers left after expansion count as 0.
+    fn condition(&self, expr: &str) -> bool {
+        let toks = lex(expr);
+        let mut resolved = Vec::new();
+        let mut i = 0;
+        while i < toks.len() {
+            if toks[i] == Tok::Ident("defined".to_string()) {
+                let (name, len) = match (toks.get(i + 1), toks.get(i + 2)) {
+                    (Some(Tok::Punct(p)), Some(Tok::Ident(n))) if p == "(" => (n, 4),
+                    (Some(Tok::Ident(n)), _) => (n, 2),
+                    _ => return false,
+                };
+                resolved.push(Tok::Int(self.macros.contains_key(name) as i128));
+                i += len;
+            } else {
+                resolved.push(toks[i].clone());
+                i += 1;
+            }
+        }
+        let expanded: Vec<Tok> = self
+            .expand(&resolved, &mut HashSet::new())
+            .into_iter()
+            .map(|t| if matches!(t, Tok::Ident(_)) { Tok::Int(0) } else { t })
+            .collect();
+        eval_const(&expanded, &HashMap::new()).is_some_and(|v| v != 0)
+    }
+
+    fn read(&mut self, body: &str) {
+        for name in self.defines.clone() {
+            if name.starts_with("__") || self.values.contains_key(&name) {
+                continue;
+            }
+            let Some(m) = self.macros.get(&name) else { continue };
+            let value = self.expand(&m.body, &mut HashSet::from([name.clone()]));
+            if let Some(v) = eval_const(&value, &self.values) {
+                self.add_const(&name, v, "Int");
+            }
+        }
+        let toks = lex(body);
+        self.toks = self.expand(&toks, &mut HashSet::new());
+        self.pos = 0;
+        while self.pos < self.toks.len() {
+            let start = self.pos;
+            if let Err(problem) = self.top_level() {
+                let text = describe(&self.toks[start..self.pos.min(self.toks.len())]);
+                self.notes.push(format!("`{}`: {}", text, problem));
+                self.recover();
+            }
+        }
+    }
+
+    /// Expands macros, never expanding one inside its own expansion. A
+    /// function-like macro not followed by arguments is left alone.
+    fn expand(&self, toks: &[Tok], active: &mut HashSet<String>) -> Vec<Tok> {
+        let mut out = Vec::new();
+        let mut i = 0;
+        while i < toks.len() {
+            let tok = &toks[i];
+            i += 1;
+            let found = match tok {
+                Tok::Ident(name) if !active.contains(name) => self.macros.get(name).map(|m| (name, m)),
+                _ => None,
+            };
+            let Some((name, m)) = found else {
+                out.push(tok.clone());
+                continue;
+            };
+            let body = match &m.params {
+                None => m.body.clone(),
+                Some(params) => match macro_args(toks, i) {
+                    Some((args, next)) => {
+                        i = next;
+                        substitute(&m.body, params, &args)
+                    }
+                    None => {
+                        out.push(tok.clone());
+                        continue;
+                    }
+                },
+            };
+            active.insert(name.clone());
+            out.extend(self.expand(&body, active));
+            active.remove(name);
+        }
+        out
+    }
+
+    fn add_const(&mut self, name: &str, value: i128, ty: &'static str) {
+        self.values.insert(name.to_string(), value);
+        if i64::try_from(value).is_err() {
+            self.notes.push(format!("{}: {} does not fit in an Int", name, value));
+        } else if is_keyword(name) {
+            self.notes.push(format!("{}: the name is a Korlang keyword", name));
+        } else {
+            self.consts.push((name.to_string(), value, ty));
+        }
+    }
+
+    fn top_level(&mut self) -> Parsed<()> {
+        if self.eat_punct(";") || self.eat_punct("}") {
+            return Ok(());
+        }
+        // `extern "C" {` from a C++ guard; its `}` is skipped above.
+        if self.peek_ident("extern") && matches!(self.toks.get(self.pos + 1), Some(Tok::Str(_))) {
+            self.pos += 2;
+            self.eat_punct("{");
+            return Ok(());
+        }
+        if self.eat_ident("typedef") {
+            return self.typedef();
+        }
+        let (base, local) = self.specifiers()?;
+        let base = base.ok_or("expected a declaration")?;
+        if self.eat_punct(";") {
+            return Ok(());
+        }
+        loop {
+            let (name, derivs) = self.declarator()?;
+            self.skip_attributes();
+            let body = self.peek_punct("{");
+            if body {
+                self.skip_balanced()?;
+            }
+            let mut derivs = derivs.into_iter();
+            if let (Some(name), Some(Deriv::Function(params, variadic))) = (name, derivs.next()) {
+                let ret = apply(base.clone(), derivs.collect());
+                // Inline definitions have no symbol to link against.
+                if !body && !local && !self.functions.iter().any(|f| f.name == name) {
+                    self.functions.push(FunctionDecl { name, params, variadic, ret });
+                }
+            }
+            if body {
+                return Ok(());
+            }
+            if self.eat_punct(",") {
+                continue;
+            }
+            self.skip_initializer();
+            return self.expect_punct(";");
+        }
+    }
+
+    fn typedef(&mut self) -> Parsed<()> {
+        let (base, _) = self.specifiers()?;
+        let base = base.ok_or("expected a type after 'typedef'")?;
+        loop {
+            let (name, derivs) = self.declarator()?;
+            let name = name.ok_or("a typedef needs a name")?;
+            let ty = apply(base.clone(), derivs);
+            if let CType::Struct(key) = &ty {
+                self.aliases.entry(key.clone()).or_insert_with(|| name.clone());
+            }
+            self.typedefs.insert(name, ty);
+            self.skip_attributes();
+            if !self.eat_punct(",") {
+                return self.expect_punct(";");
+            }
+        }
+    }
+
+    /// Reads declaration specifiers into a base type. The flag is set when
+    /// they include `static` or `inline`, whose functions are not linkable.
+    fn specifiers(&mut self) -> Parsed<(Option<CType>, bool)> {
+        let mut base = None;
+        let mut words: Vec<String> = Vec::new();
+        let mut local = false;
+        while let Some(Tok::Ident(word)) = self.toks.get(self.pos).cloned() {
+            let w = word.as_str();
+            if QUALIFIERS.contains(&w) {
+                local |= matches!(w, "static" | "inline" | "__inline" | "__inline__");
+                self.pos += 1;
+            } else if ATTRIBUTES.contains(&w) {
+                self.skip_attributes();
+            } else if matches!(
+                w,
+                "void" | "char" | "short" | "int" | "long" | "float" | "double" | "signed" | "unsigned" | "_Bool"
+                    | "bool" | "_Complex"
+            ) {
+                words.push(word);
+                self.pos += 1;
+            } else if matches!(w, "struct" | "union" | "enum") && base.is_none() && words.is_empty() {
+                self.pos += 1;
+                base = Some(self.tagged(w)?);
+            } else if base.is_none() && words.is_empty() {
+                self.pos += 1;
+                base = Some(self.type_name(&word));
+            } else {
+                break;
+            }
+        }
+        if !words.is_empty() {
+            base = Some(arithmetic(&words));
+        }
+        Ok((base, local))
+    }
+
+    fn type_name(&self, name: &str) -> CType {
+        if let Some(ty) = self.typedefs.get(name) {
+            return ty.clone();
+        }
+        let int = |bits, signed| CType::Int { bits, signed };
+        match name {
+            "int8_t" => int(8, true),
+            "int16_t" => int(16, true),
+            "int32_t" => int(32, true),
+            "int64_t" | "ssize_t" | "ptrdiff_t" | "intptr_t" | "off_t" | "intmax_t" => int(64, true),
+            "uint8_t" => int(8, false),
+            "uint16_t" => int(16, false),
+            "uint32_t" => int(32, false),
+            "uint64_t" | "size_t" | "uintptr_t" | "uintmax_t" => int(64, false),
+            _ => CType::Opaque(name.to_string()),
+        }
+    }
+
+    /// `struct`, `union` or `enum`, with an optional tag and body.
+    fn tagged(&mut self, kind: &str) -> Parsed<CType> {
+        self.skip_attributes();
+        let tag = match self.toks.get(self.pos) {
+            Some(Tok::Ident(t)) => {
+                self.pos += 1;
+                Some(t.clone())
+            }
+            _ => None,
+        };
+        self.skip_attributes();
+        let has_body = self.peek_punct("{");
+        match kind {
+            "union" => {
+                if has_body {
+                    self.skip_balanced()?;
+                }
+                Ok(CType::Union)
+            }
+            "enum" => {
+                if has_body {
+                    self.enumerators()?;
+                }
+                Ok(CType::Enum)
+            }
+            _ => {
+                let key = match tag {
+                    Some(t) => t,
+                    None if has_body => {
+                        self.anonymous += 1;
+                        format!("<anonymous {}>", self.anonymous)
+                    }
+                    None => return Err("expected a struct tag".to_string()),
+                };
+                if has_body {
+                    self.pos += 1;
+                    let def = self.struct_body(key.clone())?;
+                    self.structs.retain(|s| s.key != key);
+                    self.structs.push(def);
+                }
+                Ok(CType::Struct(key))
+            }
+        }
+    }
+
+    fn struct_body(&mut self, key: String) -> Parsed<StructDef> {
+        let mut def = StructDef { key, fields: Vec::new(), problem: None };
+        while !self.eat_punct("}") {
+            if self.pos >= self.toks.len() {
+                return Err("unterminated struct".to_string());
+            }
+            if self.eat_punct(";") {
+                continue;
+            }
+            let (base, _) = self.specifiers()?;
+            let base = base.ok_or("expected a field")?;
+            if self.eat_punct(";") {
+                def.problem.get_or_insert_with(|| "it has an anonymous member".to_string());
+                continue;
+            }
+            loop {
+                let (name, derivs) = self.declarator()?;
+                let name = name.ok_or("expected a field name")?;
+                let ty = apply(base.clone(), derivs);
+                if self.eat_punct(":") {
+                    self.skip_until(&[",", ";"]);
+                    def.problem.get_or_insert_with(|| format!("field '{}' is a bit-field", name));
+                } else if let Some(problem) = field_problem(&name, &ty) {
+                    def.problem.get_or_insert(problem);
+                }
+                def.fields.push((name, ty));
+                self.skip_attributes();
+                if !self.eat_punct(",") {
+                    break;
+                }
+            }
+            self.expect_punct(";")?;
+        }
+        Ok(def)
+    }
+
+    /// Enumerators become `Int32` constants; a missing value is the previous
+    /// one plus one.
+    fn enumerators(&mut self) -> Parsed<()> {
+        self.expect_punct("{")?;
+        let mut next = 0i128;
+        while !self.eat_punct("}") {
+            let name = match self.toks.get(self.pos) {
+                Some(Tok::Ident(n)) => n.clone(),
+                _ => return Err("expected an enumerator".to_string()),
+            };
+            self.pos += 1;
+            self.skip_attributes();
+            if self.eat_punct("=") {
+                let start = self.pos;
+                self.skip_until(&[",", "}"]);
+                next = eval_const(&self.toks[start..self.pos], &self.values)
+                    .ok_or_else(|| format!("the value of '{}' is not an integer constant", name))?;
+            }
+            let ty = if i32::try_from(next).is_ok() { "Int32" } else { "Int" };
+            self.add_const(&name, next, ty);
+            next += 1;
+            self.eat_punct(",");
+        }
+        Ok(())
+    }
+
+    /// Reads a possibly abstract declarator: `*name`, `(*name)(int)`,
+    /// `name[4]`, or none of it.
+    fn declarator(&mut self) -> Parsed<(Option<String>, Vec<Deriv>)> {
+        let mut pointers = 0;
+        loop {
+            if self.eat_punct("*") {
+                pointers += 1;
+            } else if matches!(self.toks.get(self.pos), Some(Tok::Ident(q)) if QUALIFIERS.contains(&q.as_str())) {
+                self.pos += 1;
+            } else if matches!(self.toks.get(self.pos), Some(Tok::Ident(a)) if ATTRIBUTES.contains(&a.as_str())) {
+                self.skip_attributes();
+            } else {
+                break;
+            }
+        }
+        let grouped = self.peek_punct("(")
+            && matches!(self.toks.get(self.pos + 1), Some(Tok::Punct(p)) if p == "*" || p == "^");
+        let (name, mut derivs) = if grouped {
+            self.pos += 1;
+            let inner = self.declarator()?;
+            self.expect_punct(")")?;
+            inner
+        } else if let Some(Tok::Ident(n)) = self.toks.get(self.pos).cloned() {
+            self.pos += 1;
+            (Some(n), Vec::new())
+        } else {
+            (None, Vec::new())
+        };
+        loop {
+            if self.eat_punct("[") {
+                self.skip_until(&["]"]);
+                self.expect_punct("]")?;
+                derivs.push(Deriv::Array);
+            } else if self.eat_punct("(") {
+                let (params, variadic) = self.params()?;
+                derivs.push(Deriv::Function(params, variadic));
+            } else {
+                break;
+            }
+        }
+        derivs.extend((0..pointers).map(|_| Deriv::Pointer));
+        Ok((name, derivs))
+    }
+
+    fn params(&mut self) -> Parsed<(Params, bool)> {
+        let mut params = Vec::new();
+        if self.eat_punct(")") {
+            return Ok((params, false));
+        }
+        if self.peek_ident("void") && matches!(self.toks.get(self.pos + 1), Some(Tok::Punct(p)) if p == ")") {
+            self.pos += 2;
+            return Ok((params, false));
+        }
+        loop {
+            if self.eat_punct("...") {
+                self.expect_punct(")")?;
+                return Ok((params, true));
+            }
+            let (base, _) = self.specifiers()?;
+            let base = base.ok_or("expected a parameter type")?;
+            let (name, derivs) = self.declarator()?;
+            // Arrays and functions are passed as pointers.
+            let ty = match apply(base, derivs) {
+                CType::Array(elem) => CType::Pointer(elem),
+                CType::Function => CType::Pointer(Box::new(CType::Function)),
+                other => other,
+            };
+            params.push((name, ty));
+            self.skip_attributes();
+            if self.eat_punct(")") {
+                return Ok((params, false));
+            }
+            self.expect_punct(",")?;
+        }
+    }
+
+    fn render(&self, source_name: &str) -> String {
+        let mut out = format!("// Generated by `korlang bindgen` from {}.\n", source_name);
+        if !self.consts.is_empty() {
+            out.push('\n');
+        }
+        for (name, value, ty) in &self.consts {
+            out.push_str(&format!("let {}: {} = {};\n", name, ty, value));
+        }
+        let mut notes = self.notes.clone();
+        for def in &self.structs {
+            let name = match self.struct_name(&def.key) {
+                Some(n) => n,
+                None => continue,
+            };
+            if let Some(problem) = &def.problem {
+                notes.push(format!("struct {}: {}; pointers to it are *Void", name, problem));
+                continue;
+            }
+            out.push_str(&format!("\nstruct {} {{\n", name));
+            for (field, ty) in &def.fields {
+                // Checked by `field_problem` already.
+                let ty = self.korlang_type(ty).unwrap_or_default();
+                out.push_str(&format!("    {}: {};\n", safe_name(field), ty));
+            }
+            out.push_str("}\n");
+        }
+        let mut externs = Vec::new();
+        for f in &self.functions {
+            match self.extern_fun(f) {
+                Ok(line) => externs.push(line),
+                Err(problem) => notes.push(format!("{}: {}", f.name, problem)),
+            }
+        }
+        if !externs.is_empty() {
+            out.push_str("\nextern \"C\" {\n");
+            for line in externs {
+                out.push_str(&format!("    {}\n", line));
+            }
+            out.push_str("}\n");
+        }
+        if !notes.is_empty() {
+            out.push_str("\n// Not bound:\n");
+            for note in notes {
+                out.push_str(&format!("// - {}\n", note));
+            }
+        }
+        out
+    }
+
+    fn extern_fun(&self, f: &FunctionDecl) -> Parsed<String> {
+        if is_keyword(&f.name) {
+            return Err("the name is a Korlang keyword".to_string());
+        }
+        let mut params = Vec::new();
+        for (i, (name, ty)) in f.params.iter().enumerate() {
+            let name = name.as_deref().map(safe_name).unwrap_or_else(|| format!("arg{}", i));
+            params.push(format!("{}: {}", name, self.korlang_type(ty)?));
+        }
+        if f.variadic {
+            params.push("...".to_string());
+        }
+        let ret = match &f.ret {
+            CType::Void => String::new(),
+            ty => format!(" -> {}", self.korlang_type(ty)?),
+        };
+        Ok(format!("fun {}({}){};", f.name, params.join(", "), ret))
+    }
+
+    /// The Korlang spelling of a C type. Pointers may be `NULL`, so they are
+    /// all optional; a pointer to a bound struct is the struct itself.
+    fn korlang_type(&self, ty: &CType) -> Parsed<String> {
+        Ok(match ty {
+            CType::Int { bits: 64, signed: true } => "Int".to_string(),
+            CType::Int { bits: 64, signed: false } => "UInt".to_string(),
+            CType::Int { bits, signed } => format!("{}Int{}", if *signed { "" } else { "U" }, bits),
+            CType::Float { bits: 32 } => "Float32".to_string(),
+            CType::Float { .. } => "Float".to_string(),
+            CType::Bool => "Bool".to_string(),
+            CType::Enum => "Int32".to_string(),
+            CType::Pointer(inner) => match &**inner {
+                CType::Int { bits: 8, signed: true } => "CString?".to_string(),
+                CType::Struct(key) if self.is_bound(key) => format!("{}?", self.struct_name(key).unwrap_or_default()),
+                CType::Void | CType::Function | CType::Union | CType::Opaque(_) | CType::Struct(_) | CType::Array(_)
+                | CType::Unsupported(_) => "*Void?".to_string(),
+                other => {
+                    let inner = self.korlang_type(other)?;
+                    if inner.ends_with('?') { format!("*{}", inner) } else { format!("*{}?", inner) }
+                }
+            },
+            CType::Struct(key) => {
+                let name = self.struct_name(key).unwrap_or_else(|| key.clone());
+                return Err(format!("passes struct '{}' by value", name));
+            }
+            CType::Union => return Err("passes a union by value".to_string()),
+            CType::Opaque(name) => return Err(format!("uses '{}', which the header does not define", name)),
+            CType::Array(_) => return Err("uses an array type".to_string()),
+            CType::Function => return Err("uses a function type".to_string()),
+            CType::Void => return Err("uses 'void' as a value".to_string()),
+            CType::Unsupported(what) => return Err(format!("uses {}", what)),
+        })
+    }
+
+    fn struct_name(&self, key: &str) -> Option<String> {
+        let name = self.aliases.get(key).cloned().or_else(|| (!key.starts_with('<')).then(|| key.to_string()))?;
+        (!is_keyword(&name)).then_some(name)
+    }
+
+    fn is_bound(&self, key: &str) -> bool {
+        self.struct_name(key).is_some() && self.structs.iter().any(|s| s.key == key && s.problem.is_none())
+    }
+
+    fn recover(&mut self) {
+        let mut depth = 0usize;
+        while let Some(tok) = self.toks.get(self.pos) {
+            self.pos += 1;
+            match tok {
+                Tok::Punct(p) if p == "{" || p == "(" => depth += 1,
+                Tok::Punct(p) if (p == "}" || p == ")") && depth > 0 => depth -= 1,
+                Tok::Punct(p) if p == ";" && depth == 0 => return,
+                _ => {}
+            }
+        }
+    }
+
+    /// Skips to the next of `stops` outside brackets, without consuming it.
+    fn skip_until(&mut self, stops: &[&str]) {
+        let mut depth = 0usize;
+        while let Some(tok) = self.toks.get(self.pos) {
+            if let Tok::Punct(p) = tok {
+                if depth == 0 && stops.contains(&p.as_str()) {
+                    return;
+                }
+                match p.as_str() {
+                    "(" | "[" | "{" => depth += 1,
+                    ")" | "]" | "}" if depth > 0 => depth -= 1,
+                    ")" | "]" | "}" => return,
+                    _ => {}
+                }
+            }
+            self.pos += 1;
+        }
+    }
+
+    fn skip_initializer(&mut self) {
+        if self.eat_punct("=") {
+            self.skip_until(&[";"]);
+        }
+    }
+
+    /// Skips a bracketed group starting at the current token.
+    fn skip_balanced(&mut self) -> Parsed<()> {
+        let mut depth = 0usize;
+        while let Some(tok) = self.toks.get(self.pos) {
+            self.pos += 1;
+            if let Tok::Punct(p) = tok {
+                match p.as_str() {
+                    "(" | "[" | "{" => depth += 1,
+                    ")" | "]" | "}" => {
+                        depth -= 1;
+                        if depth == 0 {
+                            return Ok(());
+                        }
+                    }
+                    _ => {}
+                }
+            }
+        }
+        Err("unbalanced brackets".to_string())
+    }
+
+    fn skip_attributes(&mut self) {
+        while matches!(self.toks.get(self.pos), Some(Tok::Ident(a)) if ATTRIBUTES.contains(&a.as_str())) {
+            self.pos += 1;
+            if self.peek_punct("(") {
+                let _ = self.skip_balanced();
+            }
+        }
+    }
+
+    fn peek_punct(&self, p: &str) -> bool {
+        matches!(self.toks.get(self.pos), Some(Tok::Punct(t)) if t == p)
+    }
+
+    fn peek_ident(&self, word: &str) -> bool {
+        matches!(self.toks.get(self.pos), Some(Tok::Ident(t)) if t == word)
+    }
+
+    fn eat_punct(&mut self, p: &str) -> bool {
+        let found = self.peek_punct(p);
+        if found {
+            self.pos += 1;
+        }
+        found
+    }
+
+    fn eat_ident(&mut self, word: &str) -> bool {
+        let found = self.peek_ident(word);
+        if found {
+            self.pos += 1;
+        }
+        found
+    }
+
+    fn expect_punct(&mut self, p: &str) -> Parsed<()> {
+        if self.eat_punct(p) {
+            Ok(())
+        } else {
+            Err(format!("expected '{}'", p))
+        }
+    }
+}
+
+/// Builds the type a declarator describes, innermost derivation last.
+fn apply(base: CType, derivs: Vec<Deriv>) -> CType {
+    derivs.into_iter().rev().fold(base, |ty, d| match d {
+        Deriv::Pointer => CType::Pointer(Box::new(ty)),
+        Deriv::Array => CType::Array(Box::new(ty)),
+        Deriv::Function(..) => CType::Function,
+    })
+}
+
+/// Fields are scalars or pointers; anything stored inline has no Korlang
+/// counterpart.
+fn field_problem(name: &str, ty: &CType) -> Option<String> {
+    match ty {
+        CType::Array(_) => Some(format!("field '{}' is an array", name)),
+        CType::Struct(_) => Some(format!("field '{}' holds a struct by value", name)),
+        CType::Union => Some(format!("field '{}' is a union", name)),
+        CType::Opaque(t) => Some(format!("field '{}' has type '{}', which the header does not define", name, t)),
+        CType::Unsupported(what) => Some(format!("field '{}' is {}", name, what)),
+        CType::Void | CType::Function => Some(format!("field '{}' has no storage type", name)),
+        _ => None,
+    }
+}
+
+/// `unsigned long` and the like, for an LP64 target.
+fn arithmetic(words: &[String]) -> CType {
+    let has = |w: &str| words.iter().any(|x| x == w);
+    let longs = words.iter().filter(|w| *w == "long").count();
+    let signed = !has("unsigned");
+    if has("_Complex") {
+        CType::Unsupported("a complex type")
+    } else if has("void") {
+        CType::Void
+    } else if has("_Bool") || has("bool") {
+        CType::Bool
+    } else if has("float") {
+        CType::Float { bits: 32 }
+    } else if has("double") && longs > 0 {
+        CType::Unsupported("long double")
+    } else if has("double") {
+        CType::Float { bits: 64 }
+    } else if has("char") {
+        CType::Int { bits: 8, signed }
+    } else if has("short") {
+        CType::Int { bits: 16, signed }
+    } else if longs > 0 {
+        CType::Int { bits: 64, signed }
+    } else {
+        CType::Int { bits: 32, signed }
+    }
+}
+
+fn is_keyword(name: &str) -> bool {
+    KEYWORDS.contains(&name)
+}
+
+fn safe_name(name: &str) -> String {
+    if is_keyword(name) { format!("{}_", name) } else { name.to_string() }
+}
+
+/// The first few tokens of a declaration, for notes about it.
+fn describe(toks: &[Tok]) -> String {
+    let words: Vec<String> = toks
+        .iter()
+        .take(6)
+        .map(|t| match t {
+            Tok::Ident(s) | Tok::Punct(s) | Tok::Other(s) => s.clone(),
+            Tok::Int(v) => v.to_string(),
+            Tok::Str(s) => format!("\"{}\"", s),
+        })
+        .collect();
+    let mut text = words.join(" ");
+    if toks.len() > 6 {
+        text.push_str(" ...");
+    }
+    text
+}
+
+/// `NAME body` or `NAME(params) body` of a `#define`.
+fn parse_define(rest: &str) -> Option<(String, Macro)> {
+    let rest = rest.trim_start();
+    let end = rest.find(|c: char| !(c.is_ascii_alphanumeric() || c == '_')).unwrap_or(rest.len());
+    let name = &rest[..end];
+    if name.is_empty() {
+        return None;
+    }
+    let Some(list) = rest[end..].strip_prefix('(') else {
+        return Some((name.to_string(), Macro { params: None, body: lex(&rest[end..]) }));
+    };
+    let close = list.find(')')?;
+    let params = list[..close]
+        .split(',')
+        .map(|p| p.trim())
+        .filter(|p| !p.is_empty())
+        .map(|p| if p == "..." { "__VA_ARGS__".to_string() } else { p.to_string() })
+        .collect();
+    Some((name.to_string(), Macro { params: Some(params), body: lex(&list[close + 1..]) }))
+}
+
+/// The arguments of a macro call whose `(` is at `open`, and the position
+/// after its `)`.
+fn macro_args(toks: &[Tok], open: usize) -> Option<(Vec<Vec<Tok>>, usize)> {
+    if !matches!(toks.get(open), Some(Tok::Punct(p)) if p == "(") {
+        return None;
+    }
+    let mut args = vec![Vec::new()];
+    let mut depth = 0usize;
+    for (i, tok) in toks.iter().enumerate().skip(open + 1) {
+        match tok {
+            Tok::Punct(p) if p == ")" && depth == 0 => {
+                if args.len() == 1 && args[0].is_empty() {
+                    args.clear();
+                }
+                return Some((args, i + 1));
+            }
+            Tok::Punct(p) if p == "," && depth == 0 => args.push(Vec::new()),
+            Tok::Punct(p) if p == "(" => {
+                depth += 1;
+                args.last_mut()?.push(tok.clone());
+            }
+            Tok::Punct(p) if p == ")" => {
+                depth -= 1;
+                args.last_mut()?.push(tok.clone());
+            }
+            _ => args.last_mut()?.push(tok.clone()),
+        }
+    }
+    None
+}
+
+/// Puts a function-like macro's arguments into its body. `#` and `##` are
+/// dropped rather than applied.
+fn substitute(body: &[Tok], params: &[String], args: &[Vec<Tok>]) -> Vec<Tok> {
+    let mut out = Vec::new();
+    for tok in body {
+        match tok {
+            Tok::Ident(name) if name == "__VA_ARGS__" => {
+                let fixed = params.len().saturating_sub(1);
+                for (i, arg) in args.iter().enumerate().skip(fixed) {
+                    if i > fixed {
+                        out.push(Tok::Punct(",".to_string()));
+                    }
+                    out.extend(arg.iter().cloned());
+                }
+            }
+            Tok::Ident(name) => match params.iter().position(|p| p == name) {
+                Some(i) => out.extend(args.get(i).into_iter().flatten().cloned()),
+                None => out.push(tok.clone()),
+            },
+            Tok::Punct(p) if p == "#" || p == "##" => {}
+            _ => out.push(tok.clone()),
+        }
+    }
+    out
+}
+
+/// Blanks out comments, keeping line breaks and string literals intact.
+fn strip_comments(src: &str) -> String {
+    let chars: Vec<char> = src.chars().collect();
+    let mut out = String::with_capacity(src.len());
+    let mut i = 0;
+    while i < chars.len() {
+        match (chars[i], chars.get(i + 1)) {
+            ('/', Some('/')) => {
+                while i < chars.len() && chars[i] != '\n' {
+                    i += 1;
+                }
+            }
+            ('/', Some('*')) => {
+                i += 2;
+                while i < chars.len() && !(chars[i] == '*' && chars.get(i + 1) == Some(&'/')) {
+                    if chars[i] == '\n' {
+                        out.push('\n');
+                    }
+                    i += 1;
+                }
+                i += 2;
+                out.push(' ');
+            }
+            (q @ ('"' | '\''), _) => {
+                out.push(q);
+                i += 1;
+                while i < chars.len() && chars[i] != q && chars[i] != '\n' {
+                    if chars[i] == '\\' && i + 1 < chars.len() {
+                        out.push(chars[i]);
+                        i += 1;
+                    }
+                    out.push(chars[i]);
+                    i += 1;
+                }
+                if i < chars.len() && chars[i] == q {
+                    out.push(q);
+                    i += 1;
+                }
+            }
+            (c, _) => {
+                out.push(c);
+                i += 1;
+            }
+        }
+    }
+    out
+}
+
+fn lex(src: &str) -> Vec<Tok> {
+    const PUNCTS: &[&str] = &["...", "<<", ">>", "->", "&&", "||", "==", "!=", "<=", ">=", "##"];
+    let chars: Vec<char> = src.chars().collect();
+    let mut toks = Vec::new();
+    let mut i = 0;
+    while i < chars.len() {
+        let c = chars[i];
+        if c.is_whitespace() {
+            i += 1;
+        } else if c.is_ascii_alphabetic() || c == '_' {
+            let start = i;
+            while i < chars.len() && (chars[i].is_ascii_alphanumeric() || chars[i] == '_') {
+                i += 1;
+            }
+            toks.push(Tok::Ident(chars[start..i].iter().collect()));
+        } else if c.is_ascii_digit() || (c == '.' && chars.get(i + 1).is_some_and(|d| d.is_ascii_digit())) {
+            let start = i;
+            while i < chars.len()
+                && (chars[i].is_ascii_alphanumeric()
+                    || chars[i] == '.'
+                    || ((chars[i] == '+' || chars[i] == '-') && matches!(chars[i - 1], 'e' | 'E' | 'p' | 'P')))
+            {
+                i += 1;
+            }
+            let text: String = chars[start..i].iter().collect();
+            toks.push(parse_int(&text).map(Tok::Int).unwrap_or(Tok::Other(text)));
+        } else if c == '"' {
+            let start = i + 1;
+            i += 1;
+            while i < chars.len() && chars[i] != '"' {
+                i += if chars[i] == '\\' { 2 } else { 1 };
+            }
+            toks.push(Tok::Str(chars[start..i.min(chars.len())].iter().collect()));
+            i += 1;
+        } else if c == '\'' {
+            let start = i;
+            i += 1;
+            while i < chars.len() && chars[i] != '\'' {
+                i += if chars[i] == '\\' { 2 } else { 1 };
+            }
+            i += 1;
+            let text: String = chars[start..i.min(chars.len())].iter().collect();
+            toks.push(char_value(&text).map(Tok::Int).unwrap_or(Tok::Other(text)));
+        } else {
+            let rest: String = chars[i..(i + 3).min(chars.len())].iter().collect();
+            let punct = PUNCTS.iter().find(|p| rest.starts_with(**p)).map(|p| p.to_string()).unwrap_or_else(|| c.to_string());
+            i += punct.chars().count();
+            toks.push(Tok::Punct(punct));
+        }
+    }
+    toks
+}
+
+/// Decimal, hex, octal or binary, with any `u`/`l` suffix.
+fn parse_int(text: &str) -> Option<i128> {
+    let digits = text.trim_end_matches(['u', 'U', 'l', 'L']);
+    let (digits, radix) = if let Some(h) = digits.strip_prefix("0x").or_else(|| digits.strip_prefix("0X")) {
+        (h, 16)
+    } else if let Some(b) = digits.strip_prefix("0b").or_else(|| digits.strip_prefix("0B")) {
+        (b, 2)
+    } else if digits.len() > 1 && digits.starts_with('0') {
+        (&digits[1..], 8)
+    } else {
+        (digits, 10)
+    };
+    i128::from_str_radix(digits, radix).ok()
+}
+
+fn char_value(text: &str) -> Option<i128> {
+    let inner = text.strip_prefix('\'')?.strip_suffix('\'')?;
+    let mut chars = inner.chars();
+    let value = match (chars.next()?, chars.next()) {
+        ('\\', Some('n')) => '\n',
+        ('\\', Some('t')) => '\t',
+        ('\\', Some('r')) => '\r',
+        ('\\', Some('0')) => '\0',
+        ('\\', Some(c)) => c,
+        (c, None) => c,
+        _ => return None,
+    };
+    Some(value as i128)
+}
+
+/// Evaluates an integer constant expression over earlier constants.
+fn eval_const(toks: &[Tok], values: &HashMap<String, i128>) -> Option<i128> {
+    let mut eval = ConstEval { toks, pos: 0, values };
+    let v = eval.conditional()?;
+    (eval.pos == toks.len()).then_some(v)
+}
+
+struct ConstEval<'a> {
+    toks: &'a [Tok],
+    pos: usize,
+    values: &'a HashMap<String, i128>,
+}
+
+impl ConstEval<'_> {
+    fn conditional(&mut self) -> Option<i128> {
+        let cond = self.binary(0)?;
+        if !self.eat("?") {
+            return Some(cond);
+        }
+        let then = self.conditional()?;
+        if !self.eat(":") {
+            return None;
+        }
+        let otherwise = self.conditional()?;
+        Some(if cond != 0 { then } else { otherwise })
+    }
+
+    fn binary(&mut self, min_prec: u8) -> Option<i128> {
+        let mut left = self.unary()?;
+        loop {
+            let op = match self.toks.get(self.pos) {
+                Some(Tok::Punct(p)) => p.clone(),
+                _ => return Some(left),
+            };
+            let prec = match op.as_str() {
+                "||" => 1,
+                "&&" => 2,
+                "|" => 3,
+                "^" => 4,
+                "&" => 5,
+                "==" | "!=" => 6,
+                "<" | ">" | "<=" | ">=" => 7,
+                "<<" | ">>" => 8,
+                "+" | "-" => 9,
+                "*" | "/" | "%" => 10,
+                _ => return Some(left),
+            };
+            if prec < min_prec {
+                return Some(left);
+            }
+            self.pos += 1;
+            let right = self.binary(prec + 1)?;
+            left = match op.as_str() {
+                "||" => (left != 0 || right != 0) as i128,
+                "&&" => (left != 0 && right != 0) as i128,
+                "|" => left | right,
+                "^" => left ^ right,
+                "&" => left & right,
+                "==" => (left == right) as i128,
+                "!=" => (left != right) as i128,
+                "<" => (left < right) as i128,
+                ">" => (left > right) as i128,
+                "<=" => (left <= right) as i128,
+                ">=" => (left >= right) as i128,
+                "<<" => left.checked_shl(u32::try_from(right).ok()?)?,
+                ">>" => left.checked_shr(u32::try_from(right).ok()?)?,
+                "+" => left.checked_add(right)?,
+                "-" => left.checked_sub(right)?,
+                "*" => left.checked_mul(right)?,
+                "/" => left.checked_div(right)?,
+                _ => left.checked_rem(right)?,
+            };
+        }
+    }
+
+    fn eat(&mut self, p: &str) -> bool {
+        let found = matches!(self.toks.get(self.pos), Some(Tok::Punct(t)) if t == p);
+        if found {
+            self.pos += 1;
+        }
+        found
+    }
+
+    fn unary(&mut self) -> Option<i128> {
+        let tok = self.toks.get(self.pos)?.clone();
+        self.pos += 1;
+        match tok {
+            Tok::Int(v) => Some(v),
+            Tok::Ident(name) => self.values.get(&name).copied(),
+            Tok::Punct(p) if p == "-" => self.unary().map(|v| -v),
+            Tok::Punct(p) if p == "+" => self.unary(),
+            Tok::Punct(p) if p == "~" => self.unary().map(|v| !v),
+            Tok::Punct(p) if p == "!" => self.unary().map(|v| (v == 0) as i128),
+            Tok::Punct(p) if p == "(" => {
+                let v = self.conditional()?;
+                self.eat(")").then_some(v)
+            }
+            _ => None,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::generate_bindings_with;
+    use crate::lexer::Lexer;
+    use crate::parser::Parser;
+    use crate::sema::Sema;
+
+    const HEADER: &str = include_str!("../../../../tests/bindgen/geometry.h");
+    const TYPES: &str = include_str!("../../../../tests/bindgen/geometry_types.h");
+    const BINDINGS: &str = include_str!("../../../../tests/bindgen/geometry.kor");
+
+    #[test]
+    fn binds_the_sample_header() {
+        let mut include = |file: &str| (file == "geometry_types.h").then(|| TYPES.to_string());
+        assert_eq!(generate_bindings_with(HEADER, "geometry.h", &mut include), BINDINGS);
+    }
+
+    #[test]
+    fn generated_bindings_type_check() {
+        let src = format!(
+            "{}\nfun main() {{\n\
+                 let p = geom_point_new(1.0, 2.0);\n\
+                 let moved = geom_translate(p, GEOM_ORIGIN_X, 0.5);\n\
+                 geom_log(GEOM_LOG_INFO, \"%s\", \"moved\");\n\
+                 let ok: Bool = geom_point_free(moved) == GEOM_OK;\n\
+             }}",
+            BINDINGS
+        );
+        let tokens = Lexer::new(&src).tokenize().expect("lex");
+        let program = Parser::new(tokens).parse_program().expect("parse");
+        let result = Sema::new().check_program(&program);
+        assert!(result.is_ok(), "{:?}", result.err());
+    }
+}
@@ -1,4 +1,6 @@
-use crate::ast::GenericParam;
+use std::collections::HashMap;
+
+use crate::ast::{FunDecl, StructDecl};
 use crate::sema::Type;
 
 pub struct CppTemplateInterop {
@@ -24,39 +26,67 @@ impl CppTemplateInterop {
         mangled
     }
 
-    pub fn generate_header(&self, structs: &[crate::ast::StructDecl], interfaces: &[crate::ast::InterfaceDecl]) -> String {
-        let mut header = format!("#pragma once\n#include <string>\n#include <vector>\n#include <cstdint>\n\nnamespace {} {{\n\n", self.namespace_prefix);
-        
-        for s in structs {
-            header.push_str(&format!("struct {} {{\n", s.name));
-            for field in &s.fields {
-                header.push_str(&format!("    {} {};\n", self.type_ref_to_cpp(&field.ty), field.name));
+    /// Generates the header for a library's `@export` functions. It declares
+    /// the structs they reach, with the fields in declaration order, and
+    /// wraps the prototypes in `extern "C"` so C++ can include it too.
+    pub fn generate_header(&self, guard: &str, structs: &[StructDecl], exports: &[FunDecl]) -> String {
+        let by_name: HashMap<&str, &StructDecl> = structs.iter().map(|s| (s.name.as_str(), s)).collect();
+        let mut used = Vec::new();
+        for f in exports {
+            let types = f.params.iter().map(|p| &p.ty).chain(f.ret.iter());
+            for tr in types {
+                collect_structs(&Type::from_type_ref(tr), &by_name, &mut used);
             }
-            header.push_str("};\n\n");
         }
 
-        for i in interfaces {
-            header.push_str(&format!("class {} {{\npublic:\n    virtual ~{}() = default;\n", i.name, i.name));
-            for method in &i.methods {
-                header.push_str(&format!("    virtual void {}() = 0;\n", method.name));
+        let mut header = format!(
+            "#ifndef {guard}\n#define {guard}\n\n#include <stdbool.h>\n#include <stdint.h>\n\n#ifdef __cplusplus\nextern \"C\" {{\n#endif\n\n"
+        );
+        for s in &used {
+            header.push_str(&format!("typedef struct {0} {0};\n", s.name));
+        }
+        if !used.is_empty() {
+            header.push('\n');
+        }
+        for s in &used {
+            header.push_str(&format!("struct {} {{\n", s.name));
+            for field in &s.fields {
+                let ty = Type::from_type_ref(&field.ty);
+                header.push_str(&format!("    {} {};\n", self.type_to_c(&ty, &by_name), field.name));
             }
             header.push_str("};\n\n");
         }
-
-        header.push_str(&format!("}} // namespace {}\n", self.namespace_prefix));
+        for f in exports {
+            let ret = f.ret.as_ref().map(Type::from_type_ref).unwrap_or(Type::Unit);
+            let params: Vec<String> = f
+                .params
+                .iter()
+                .map(|p| format!("{} {}", self.type_to_c(&Type::from_type_ref(&p.ty), &by_name), p.name))
+                .collect();
+            let params = if params.is_empty() { "void".to_string() } else { params.join(", ") };
+            header.push_str(&format!("{} {}({});\n", self.type_to_c(&ret, &by_name), f.name, params));
+        }
+        header.push_str(&format!("\n#ifdef __cplusplus\n}}\n#endif\n\n#endif /* {} */\n", guard));
         header
     }
 
-    fn type_ref_to_cpp(&self, tr: &crate::ast::TypeRef) -> String {
-        match tr {
-            crate::ast::TypeRef::Named(n, _, _) => {
-                match n.as_str() {
-                    "Int" => "int64_t".to_string(),
-                    "Float" => "double".to_string(),
-                    "String" => "std::string".to_string(),
-                    _ => n.clone(),
-                }
-            }
+    /// The C spelling of a type accepted by `@export`; structs are passed by
+    /// pointer.
+    fn type_to_c(&self, ty: &Type, structs: &HashMap<&str, &StructDecl>) -> String {
+        match ty {
+            Type::Int => "int64_t".to_string(),
+            Type::UInt => "uint64_t".to_string(),
+            Type::SizedInt { bits, signed } => format!("{}int{}_t", if *signed { "" } else { "u" }, bits),
+            Type::Float => "double".to_string(),
+            Type::Float32 => "float".to_string(),
+            Type::Bool => "bool".to_string(),
+            Type::Char => "uint32_t".to_string(),
+            Type::Unit => "void".to_string(),
+            Type::Named(n) if n == "CString" => "const char*".to_string(),
+            Type::Named(n) if n == "Void" => "void".to_string(),
+            Type::Named(n) if structs.contains_key(n.as_str()) => format!("{}*", n),
+            Type::Generic(n, args) if n == "Ptr" && args.len() == 1 => format!("{}*", self.type_to_c(&args[0], structs)),
+            Type::Optional(inner) => self.type_to_c(inner, structs),
             _ => "void*".to_string(),
         }
     }
@@ -71,3 +101,73 @@ impl CppTemplateInterop {
         }
     }
 }
+
+/// Adds the structs `ty` reaches, each once, in the order they are met.
+/// The header forward-declares them all, so the order needs no sorting.
+fn collect_structs<'a>(ty: &Type, structs: &HashMap<&str, &'a StructDecl>, out: &mut Vec<&'a StructDecl>) {
+    let name = match ty {
+        Type::Named(n) => n,
+        Type::Optional(inner) => return collect_structs(inner, structs, out),
+        _ => return,
+    };
+    let decl = match structs.get(name.as_str()) {
+        Some(d) => *d,
+        None => return,
+    };
+    if out.iter().any(|s| s.name == decl.name) {
+        return;
+    }
+    out.push(decl);
+    for field in &decl.fields {
+        collect_structs(&Type::from_type_ref(&field.ty), structs, out);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::CppTemplateInterop;
+    use crate::ast::Item;
+    use crate::lexer::Lexer;
+    use crate::parser::Parser;
+
+    #[test]
+    fn header_declares_exports_and_the_structs_they_reach() {
+        let src = "struct Point { x: Float; y: Float; }\n\
+                   struct Segment { from: Point; to: Point; closed: Bool; }\n\
+                   struct Unused { n: Int; }\n\
+                   @export fun seg_len(s: Segment) -> Float { 0.0 }\n\
+                   @export fun fill(buf: *UInt8, len: UInt32, name: CString?) -> Int32 { 0 }\n\
+                   @export fun reset() {}";
+        let tokens = Lexer::new(src).tokenize().expect("lex");
+        let program = Parser::new(tokens).parse_program().expect("parse");
+        let mut structs = Vec::new();
+        let mut exports = Vec::new();
+        for item in program.items {
+            match item {
+                Item::Struct(s) => structs.push(s),
+                Item::Fun(f) if f.export => exports.push(f),
+                _ => {}
+            }
+        }
+        let header = CppTemplateInterop::new().generate_header("GEOM_H", &structs, &exports);
+        let body = header
+            .split("extern \"C\" {\n#endif\n\n")
+            .nth(1)
+            .and_then(|rest| rest.split("\n#ifdef __cplusplus\n}").next())
+            .expect("extern \"C\" block");
+        assert!(header.starts_with("#ifndef GEOM_H\n#define GEOM_H\n"));
+        assert_eq!(
+            body,
+            "typedef struct Segment Segment;\n\
+             typedef struct Point Point;\n\
+             \n\
+             struct Segment {\n    Point* from;\n    Point* to;\n    bool closed;\n};\n\
+             \n\
+             struct Point {\n    double x;\n    double y;\n};\n\
+             \n\
+             double seg_len(Segment* s);\n\
+             int32_t fill(uint8_t* buf, uint32_t len, const char* name);\n\
+             void reset(void);\n"
+        );
+    }
+}
@@ -0,0 +1,124 @@
+use std::collections::HashSet;
+
+use crate::ast::FunDecl;
+use crate::diag::{Diagnostic, Span};
+use crate::ffi::safety::FfiSafetyChecker;
+use crate::sema::Sema;
+use crate::types::Type;
+
+impl Sema {
+    /// An `@export` function becomes a plain C symbol: one implementation
+    /// under its own name, with a signature C can spell. Structs cross as
+    /// pointers to their fields.
+    pub(crate) fn check_export(&mut self, fun: &FunDecl) {
+        if fun.receiver.is_some() {
+            self.diags.push(Diagnostic::error(
+                format!("method '{}' cannot be exported; export a function that calls it", fun.name),
+                fun.span,
+            ));
+            return;
+        }
+        if !fun.generic_params.is_empty() {
+            self.diags.push(Diagnostic::error(
+                format!("generic function '{}' cannot be exported", fun.name),
+                fun.span,
+            ));
+            return;
+        }
+        if self.specializations.candidates(&fun.name, false).len() > 1 {
+            self.diags.push(Diagnostic::error(
+                format!("exported function '{}' cannot be overloaded", fun.name),
+                fun.span,
+            ));
+        }
+        for p in &fun.params {
+            let ty = self.type_from_ref(&p.ty);
+            self.check_boundary_type(&ty, p.span);
+        }
+        if let Some(ret) = &fun.ret {
+            let ty = self.type_from_ref(ret);
+            self.check_boundary_type(&ty, fun.span);
+        }
+    }
+
+    /// A C type, or a struct with a C layout passed as a pointer (`T?` for
+    /// one that may be `NULL`). Used by `extern` declarations too.
+    pub(crate) fn check_boundary_type(&mut self, ty: &Type, span: Span) {
+        match ty {
+            Type::Named(n) if self.structs.contains_key(n) => {
+                let mut seen = HashSet::new();
+                self.check_export_struct(n, span, &mut seen);
+            }
+            Type::Optional(inner) if matches!(&**inner, Type::Named(n) if self.structs.contains_key(n)) => {
+                self.check_boundary_type(inner, span)
+            }
+            _ => self.diags.extend(FfiSafetyChecker::check_type_safety(ty, span)),
+        }
+    }
+
+    /// A struct has a C layout when every field does; fields holding
+    /// structs, optional or not, are pointers.
+    fn check_export_struct(&mut self, name: &str, span: Span, seen: &mut HashSet<String>) {
+        if !seen.insert(name.to_string()) {
+            return;
+        }
+        let decl = self.structs[name].clone();
+        if !decl.generic_params.is_empty() {
+            self.diags.push(Diagnostic::error(
+                format!("generic struct '{}' has no C layout", name),
+                span,
+            ));
+            return;
+        }
+        for field in &decl.fields {
+            let ty = self.type_from_ref(&field.ty);
+            let pointee = match &ty {
+                Type::Optional(inner) => &**inner,
+                other => other,
+            };
+            match pointee {
+                Type::Named(n) if self.structs.contains_key(n) => self.check_export_struct(n, span, seen),
+                _ if FfiSafetyChecker::is_c_type(&ty) && ty != Type::Unit => {}
+                _ => self.diags.push(Diagnostic::error(
+                    format!("struct '{}' has no C layout: field '{}' has type {:?}", name, field.name, ty),
+                    field.span,
+                )),
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::test_support::errors;
+
+    #[test]
+    fn exports_take_c_types_and_layout_compatible_structs() {
+        let src = "struct Point { x: Float; y: Float; }\n\
+                   struct Segment { from: Point; to: Point; closed: Bool; }\n\
+                   @export fun seg_len(s: Segment) -> Float { s.to.x - s.from.x }\n\
+                   @export fun origin() -> Point { Point { x: 0.0, y: 0.0 } }\n\
+                   @export @nogc fun add(a: Int32, b: Int32) -> Int32 { a + b }\n\
+                   @export fun greet(name: CString) { print(name); }";
+        assert!(errors(src).is_empty(), "{:?}", errors(src));
+    }
+
+    #[test]
+    fn rejects_exports_without_a_c_signature() {
+        let src = "struct Named { name: String; }\n\
+                   @export fun label(n: Named) {}\n\
+                   @export fun text() -> String { \"x\" }\n\
+                   @export fun id<T>(x: T) -> T { x }\n\
+                   @export fun twice(x: Int) -> Int { x * 2 }\n\
+                   fun twice(x: Float) -> Float { x * 2.0 }";
+        assert_eq!(
+            errors(src),
+            [
+                "struct 'Named' has no C layout: field 'name' has type String",
+                "Korlang String is not a C type; declare it as 'CString' (Korlang strings convert implicitly)",
+                "generic function 'id' cannot be exported",
+                "exported function 'twice' cannot be overloaded",
+            ]
+        );
+    }
+}
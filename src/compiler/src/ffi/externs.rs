@@ -0,0 +1,126 @@
+use crate::ast::{Expr, ExternDecl};
+use crate::diag::{Diagnostic, Span};
+use crate::ffi::safety::FfiSafetyChecker;
+use crate::sema::Sema;
+use crate::types::Type;
+
+impl Sema {
+    /// Makes an `extern` function callable by name. C has one namespace, so
+    /// the name may not also belong to a Korlang function.
+    pub(crate) fn declare_extern(&mut self, decl: &ExternDecl) {
+        if self.functions.contains_key(&decl.name) {
+            self.diags.push(Diagnostic::error(format!("'{}' is already declared", decl.name), decl.span));
+            return;
+        }
+        let params = decl.params.iter().map(|p| self.type_from_ref(&p.ty)).collect();
+        let ret = decl.ret.as_ref().map(|t| self.type_from_ref(t)).unwrap_or(Type::Unit);
+        self.functions.insert(decl.name.clone(), Type::Func(params, Box::new(ret)));
+        self.externs.insert(decl.name.clone(), decl.clone());
+    }
+
+    /// Only the C ABI is supported, and every type in the signature must
+    /// exist in C; structs with a C layout are passed as pointers.
+    pub(crate) fn check_extern(&mut self, decl: &ExternDecl) {
+        if decl.abi != "C" {
+            self.diags.push(Diagnostic::error(
+                format!("unsupported ABI \"{}\"; only \"C\" is supported", decl.abi),
+                decl.span,
+            ));
+        }
+        for p in &decl.params {
+            self.check_type_ref(&p.ty);
+            let ty = self.type_from_ref(&p.ty);
+            self.check_boundary_type(&ty, p.span);
+        }
+        if let Some(ret) = &decl.ret {
+            self.check_type_ref(ret);
+            let ty = self.type_from_ref(ret);
+            self.check_boundary_type(&ty, decl.span);
+        }
+    }
+
+    /// Declared parameters are checked like any call; arguments passed
+    /// through `...` only need a C representation.
+    pub(crate) fn check_extern_call(&mut self, decl: &ExternDecl, args: &[Expr], span: Span, nogc: bool) -> Type {
+        let (params, ret) = match self.functions.get(&decl.name) {
+            Some(Type::Func(params, ret)) => (params.clone(), (**ret).clone()),
+            _ => return Type::Unknown,
+        };
+        if args.len() < params.len() && decl.variadic {
+            self.diags.push(Diagnostic::error(
+                format!(
+                    "'{}' takes at least {} argument{}",
+                    decl.name,
+                    params.len(),
+                    if params.len() == 1 { "" } else { "s" }
+                ),
+                span,
+            ));
+        } else if args.len() != params.len() && !decl.variadic {
+            self.diags.push(Diagnostic::error("argument count mismatch", span));
+        }
+        for (arg, p) in args.iter().zip(params.iter()) {
+            let at = self.check_expr_with(arg, nogc);
+            let at = self.adapt_literal(p, arg, at);
+            self.unify(p, &at, self.span_of(arg));
+        }
+        for arg in args.iter().skip(params.len()) {
+            let at = self.check_expr_with(arg, nogc);
+            let passable = match &at {
+                Type::Unit => false,
+                Type::Unknown | Type::Any | Type::String => true,
+                other => FfiSafetyChecker::is_c_type(other),
+            };
+            if !passable {
+                self.diags.push(Diagnostic::error(
+                    format!("cannot pass {:?} through the variadic arguments of '{}'", at, decl.name),
+                    self.span_of(arg),
+                ));
+            }
+        }
+        ret
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::test_support::errors;
+
+    #[test]
+    fn externs_are_called_with_c_types() {
+        let src = "extern \"C\" fun strlen(s: CString) -> UInt;\n\
+                   extern \"C\" {\n\
+                       fun printf(format: CString, ...) -> Int32;\n\
+                       fun malloc(size: UInt) -> *Void?;\n\
+                       fun free(p: *Void?);\n\
+                   }\n\
+                   fun f(name: String) -> UInt {\n\
+                       let small: Int8 = 3;\n\
+                       printf(\"%s %d %f\\n\", name, small, 1.5);\n\
+                       let p = malloc(8);\n\
+                       free(p);\n\
+                       strlen(name)\n\
+                   }";
+        assert!(errors(src).is_empty(), "{:?}", errors(src));
+    }
+
+    #[test]
+    fn rejects_non_c_signatures_and_arguments() {
+        let src = "struct Point { x: Int; }\n\
+                   struct Named { name: String; }\n\
+                   extern \"C\" fun takes(p: Point, n: Named?, s: String);\n\
+                   extern \"Rust\" fun r();\n\
+                   extern \"C\" fun printf(format: CString, ...) -> Int32;\n\
+                   fun f() { printf(); printf(\"%d\", Point { x: 1 }); }";
+        assert_eq!(
+            errors(src),
+            [
+                "struct 'Named' has no C layout: field 'name' has type String",
+                "Korlang String is not a C type; declare it as 'CString' (Korlang strings convert implicitly)",
+                "unsupported ABI \"Rust\"; only \"C\" is supported",
+                "'printf' takes at least 1 argument",
+                "cannot pass Named(\"Point\") through the variadic arguments of 'printf'",
+            ]
+        );
+    }
+}
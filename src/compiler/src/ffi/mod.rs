@@ -1,4 +1,7 @@
+pub mod bindgen;
 pub mod cpp;
+pub mod exports;
+pub mod externs;
 pub mod rust;
 pub mod python;
 pub mod safety;
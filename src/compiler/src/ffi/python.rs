@@ -1,43 +1,33 @@
-use std::collections::HashMap;
-use std::time::Instant;
+/// The `python` module: `extern "C"` declarations of the runtime's CPython
+/// bridge and the `py_*` functions wrapping them. It is built in, so
+/// `import python` resolves without a standard library on disk.
+pub const PYTHON_MODULE: &str = include_str!("../../../stdlib/core/python.kor");
 
-pub struct PythonBridge {
-    pub call_metrics: HashMap<String, PythonCallMetric>,
-}
-
-pub struct PythonCallMetric {
-    pub count: usize,
-    pub total_duration_ms: u128,
-}
-
-impl PythonBridge {
-    pub fn new() -> Self {
-        Self {
-            call_metrics: HashMap::new(),
-        }
+/// The source of a module shipped inside the compiler.
+pub fn builtin_module(name: &str) -> Option<&'static str> {
+    match name {
+        "python" | "std.python" => Some(PYTHON_MODULE),
+        _ => None,
     }
+}
 
-    pub fn execute_python_call(&mut self, func_name: &str, args: Vec<String>) -> String {
-        let start = Instant::now();
-        // Simulate python call
-        println!("[PYTHON-FFI] Calling {} with args {:?}", func_name, args);
-        let duration = start.elapsed().as_millis();
-        
-        let metric = self.call_metrics.entry(func_name.to_string()).or_insert(PythonCallMetric {
-            count: 0,
-            total_duration_ms: 0,
-        });
-        metric.count += 1;
-        metric.total_duration_ms += duration;
-        
-        format!("Result from Python {}", func_name)
-    }
+#[cfg(test)]
+mod tests {
+    use super::PYTHON_MODULE;
+    use crate::lexer::Lexer;
+    use crate::parser::Parser;
+    use crate::sema::Sema;
 
-    pub fn get_report(&self) {
-        println!("--- Python Bridge Performance Report ---");
-        for (name, metric) in &self.call_metrics {
-            println!("Function: {}, Calls: {}, Avg Duration: {}ms", 
-                name, metric.count, metric.total_duration_ms / metric.count as u128);
+    #[test]
+    fn python_module_type_checks() {
+        let src = format!(
+            "{}\nfun main() -> Int {{\n    let xs = py_list();\n    let one = py_int(1);\n    if (xs != null && one != null) {{\n        py_append(xs, one);\n        println(py_len(xs));\n    }}\n    let e = py_error();\n    if (e != null) {{ println(e); }}\n    0\n}}\n",
+            PYTHON_MODULE.replace("module python", "")
+        );
+        let tokens = Lexer::new(&src).tokenize().expect("lex");
+        let program = Parser::new(tokens).parse_program().expect("parse");
+        if let Err(diags) = Sema::new().check_program(&program) {
+            panic!("{:?}", diags.iter().map(|d| &d.message).collect::<Vec<_>>());
         }
     }
 }
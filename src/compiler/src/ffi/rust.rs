@@ -1,46 +1,546 @@
+use std::collections::HashSet;
+
+use crate::ast::{FunDecl, InterfaceDecl, Item, Program, StructDecl};
+use crate::ffi::safety::FfiSafetyChecker;
 use crate::sema::Type;
-use crate::diag::Diagnostic;
 
-pub struct RustTraitMapper;
+/// Generates a Rust crate for a Korlang library: the `#[repr(C)]` structs,
+/// `extern "C"` declarations of its `@export` functions, safe wrappers
+/// around them and a trait for each interface.
+pub struct RustTraitMapper {
+    /// Structs with a C layout, in declaration order.
+    structs: Vec<StructDecl>,
+    interfaces: HashSet<String>,
+    exports: Vec<FunDecl>,
+    traits: Vec<InterfaceDecl>,
+}
+
+/// How a value crosses between the safe wrapper and the raw declaration.
+enum Conv {
+    Plain,
+    Str,
+    OptStr,
+    Struct,
+    OptStruct,
+    /// A raw pointer, which makes the wrapper `unsafe`.
+    Raw,
+}
 
 impl RustTraitMapper {
-    /// Maps a Korlang interface to a Rust trait representation
-    pub fn map_interface_to_trait(interface_name: &str, methods: &[String]) -> String {
-        let mut rust_code = format!("pub trait {} {{\n", interface_name);
-        for method in methods {
-            rust_code.push_str(&format!("    fn {}(&self);\n", method));
-        }
-        rust_code.push_str("}\n");
-        rust_code
-    }
-
-    pub fn generate_extern_c_wrappers(func_name: &str, params: &[Type], ret: &Type) -> String {
-        let mut rust_code = format!("#[no_mangle]\npub extern \"C\" fn korlang_{}(", func_name);
-        for (i, param) in params.iter().enumerate() {
-            if i > 0 { rust_code.push_str(", "); }
-            let rust_ty = match param {
-                Type::Int => "i64",
-                Type::Float => "f64",
-                Type::String => "*const libc::c_char",
-                _ => "*mut libc::c_void",
-            };
-            rust_code.push_str(&format!("arg{}: {}", i, rust_ty));
+    pub fn new(program: &Program) -> Self {
+        let mut structs = Vec::new();
+        let mut interfaces = HashSet::new();
+        let mut exports = Vec::new();
+        let mut traits = Vec::new();
+        for item in &program.items {
+            match item {
+                Item::Struct(s) if s.generic_params.is_empty() => structs.push(s.clone()),
+                Item::Interface(i) => {
+                    interfaces.insert(i.name.clone());
+                    traits.push(i.clone());
+                }
+                Item::Fun(f) if f.export => exports.push(f.clone()),
+                _ => {}
+            }
+        }
+        // Drop structs without a C layout until the rest only reach each other.
+        loop {
+            let names: HashSet<String> = structs.iter().map(|s| s.name.clone()).collect();
+            let before = structs.len();
+            structs.retain(|s| s.fields.iter().all(|f| is_layout_field(&Type::from_type_ref(&f.ty), &names)));
+            if structs.len() == before {
+                break;
+            }
+        }
+        Self { structs, interfaces, exports, traits }
+    }
+
+    /// The files of the crate, relative to its root. The crate links the
+    /// static library `lib<lib_name>.a`, looked up in `KORLANG_LIB_DIR`.
+    pub fn generate_crate(&self, crate_name: &str, lib_name: &str) -> Vec<(&'static str, String)> {
+        let manifest = format!(
+            "[package]\nname = \"{}\"\nversion = \"0.1.0\"\nedition = \"2021\"\nbuild = \"build.rs\"\n\n[lib]\npath = \"src/lib.rs\"\n\n[workspace]\n",
+            crate_name
+        );
+        let build = format!(
+            "fn main() {{\n    println!(\"cargo:rerun-if-env-changed=KORLANG_LIB_DIR\");\n    if let Ok(dir) = std::env::var(\"KORLANG_LIB_DIR\") {{\n        println!(\"cargo:rustc-link-search=native={{}}\", dir);\n    }}\n    println!(\"cargo:rustc-link-lib=static={}\");\n}}\n",
+            lib_name
+        );
+        vec![("Cargo.toml", manifest), ("build.rs", build), ("src/lib.rs", self.generate_lib(lib_name))]
+    }
+
+    /// `src/lib.rs`: the raw declarations live in `sys`, the safe wrappers
+    /// and traits at the crate root.
+    pub fn generate_lib(&self, lib_name: &str) -> String {
+        let mut wrappers = String::new();
+        let mut externs = String::new();
+        let (mut to_c, mut from_c) = (false, false);
+        for f in &self.exports {
+            let (decl, wrapper) = self.generate_extern_c_wrappers(f);
+            externs.push_str(&format!("        {}\n", decl));
+            wrappers.push_str(&wrapper);
+            wrappers.push('\n');
+            to_c |= wrapper.contains("c_string(");
+            from_c |= wrapper.contains("rust_string(");
+        }
+
+        let mut lib = format!(
+            "//! Rust bindings for the Korlang library `{}`, generated by `korlang ffi rust`.\n\n#![allow(dead_code, non_camel_case_types, non_snake_case, unused_imports)]\n\nuse std::ffi::{{c_char, c_void, CStr, CString}};\nuse std::ptr;\n\n",
+            lib_name
+        );
+        for s in &self.structs {
+            lib.push_str("#[repr(C)]\n#[derive(Debug, Clone, Copy)]\n");
+            lib.push_str(&format!("pub struct {} {{\n", ident(&s.name)));
+            for field in &s.fields {
+                let ty = Type::from_type_ref(&field.ty);
+                lib.push_str(&format!("    pub {}: {},\n", ident(&field.name), self.raw_type(&ty)));
+            }
+            lib.push_str("}\n\n");
+        }
+        lib.push_str("/// The exported functions as the library declares them.\npub mod sys {\n    use super::*;\n\n    extern \"C\" {\n");
+        lib.push_str(&externs);
+        lib.push_str("    }\n}\n\n");
+        if to_c {
+            lib.push_str("fn c_string(s: &str) -> CString {\n    CString::new(s).expect(\"string passed to Korlang contains a NUL byte\")\n}\n\n");
+        }
+        if from_c {
+            lib.push_str("unsafe fn rust_string(p: *const c_char) -> Option<String> {\n    if p.is_null() {\n        None\n    } else {\n        Some(CStr::from_ptr(p).to_string_lossy().into_owned())\n    }\n}\n\n");
+        }
+        lib.push_str(&wrappers);
+        for s in &self.structs {
+            if let Some(code) = self.struct_trait(s) {
+                lib.push_str(&code);
+                lib.push('\n');
+            }
+        }
+
+        let mut skipped = Vec::new();
+        for iface in &self.traits {
+            match self.map_interface_to_trait(iface) {
+                Ok(code) => {
+                    lib.push_str(&code);
+                    lib.push('\n');
+                }
+                Err(reason) => skipped.push(format!("// interface {}: {}", iface.name, reason)),
+            }
+        }
+        if !skipped.is_empty() {
+            lib.push_str("// Not mapped:\n");
+            lib.push_str(&skipped.join("\n"));
+            lib.push('\n');
+        }
+        while lib.ends_with("\n\n") {
+            lib.pop();
         }
-        let rust_ret = match ret {
-            Type::Unit => "",
-            Type::Int => " -> i64",
-            _ => " -> *mut libc::c_void",
+        lib
+    }
+
+    /// Maps a Korlang interface to a Rust trait with the same methods.
+    /// Strings and arrays are borrowed as parameters and owned as results,
+    /// and interface-typed values are trait objects. Default bodies are
+    /// Korlang code, so every method is required.
+    pub fn map_interface_to_trait(&self, iface: &InterfaceDecl) -> Result<String, String> {
+        let params: Vec<String> = iface.generic_params.iter().map(|g| g.name.clone()).collect();
+        let mut generics = Vec::new();
+        for g in &iface.generic_params {
+            let mut bounds = Vec::new();
+            for c in &g.constraints {
+                match Type::from_type_ref(c) {
+                    Type::Named(n) if self.interfaces.contains(&n) => bounds.push(n),
+                    other => return Err(format!("constraint {:?} on '{}' has no Rust bound", other, g.name)),
+                }
+            }
+            if bounds.is_empty() {
+                generics.push(g.name.clone());
+            } else {
+                generics.push(format!("{}: {}", g.name, bounds.join(" + ")));
+            }
+        }
+        let generics = if generics.is_empty() { String::new() } else { format!("<{}>", generics.join(", ")) };
+
+        let mut code = format!("/// Mirrors the Korlang interface `{}`.\npub trait {}{} {{\n", iface.name, ident(&iface.name), generics);
+        for m in &iface.methods {
+            let mut sig = format!("    fn {}(&self", ident(&m.name));
+            for p in &m.params {
+                let ty = Type::from_type_ref(&p.ty);
+                let rust = self
+                    .param_type(&ty, &params)
+                    .ok_or_else(|| format!("parameter '{}' of '{}' has type {:?}", p.name, m.name, ty))?;
+                sig.push_str(&format!(", {}: {}", ident(&p.name), rust));
+            }
+            sig.push(')');
+            let ret = m.ret.as_ref().map(Type::from_type_ref).unwrap_or(Type::Unit);
+            if ret != Type::Unit {
+                let rust = self
+                    .owned_type(&ret, &params)
+                    .ok_or_else(|| format!("'{}' returns {:?}", m.name, ret))?;
+                sig.push_str(&format!(" -> {}", rust));
+            }
+            code.push_str(&sig);
+            code.push_str(";\n");
+        }
+        code.push_str("}\n");
+        Ok(code)
+    }
+
+    /// The exports taking `s` first, as methods of a trait implemented for
+    /// it, so `seg.seg_len2()` can be called on a value.
+    fn struct_trait(&self, s: &StructDecl) -> Option<String> {
+        let mut decls = String::new();
+        let mut impls = String::new();
+        for f in &self.exports {
+            let Some(first) = f.params.first() else { continue };
+            let ty = Type::from_type_ref(&first.ty);
+            if !matches!(self.conv(&ty), Conv::Struct) || struct_name(&ty) != ident(&s.name) {
+                continue;
+            }
+            let (_, wrapper) = self.generate_extern_c_wrappers(f);
+            let sig = wrapper.lines().find(|l| l.starts_with("pub ")).unwrap_or_default();
+            let sig = sig.trim_start_matches("pub ").trim_end_matches(" {");
+            let receiver = format!("({}: &mut {}", ident(&first.name), ident(&s.name));
+            let method_sig = sig.replacen(&receiver, "(&mut self", 1);
+            let args: Vec<String> =
+                std::iter::once("self".to_string()).chain(f.params[1..].iter().map(|p| ident(&p.name))).collect();
+            let call = format!("{}({})", ident(&f.name), args.join(", "));
+            let call = if sig.starts_with("unsafe ") { format!("unsafe {{ {} }}", call) } else { call };
+            decls.push_str(&format!("    {};\n", method_sig));
+            impls.push_str(&format!("    {} {{\n        {}\n    }}\n", method_sig, call));
+        }
+        if decls.is_empty() {
+            return None;
+        }
+        let name = ident(&s.name);
+        Some(format!(
+            "/// The exported functions that take a `{0}` first, as methods.\npub trait {0}Exports {{\n{1}}}\n\nimpl {0}Exports for {0} {{\n{2}}}\n",
+            name, decls, impls
+        ))
+    }
+
+    /// The raw declaration of an `@export` function and a safe wrapper for
+    /// it. The wrapper converts `&str` to a C string for the call and copies
+    /// strings and structs returned from the Korlang heap into Rust values,
+    /// `None` for a null result. Structs are passed as `&mut`; Korlang must
+    /// not keep the pointer. Raw pointers, also as struct fields, cannot be
+    /// checked, so wrappers taking them are `unsafe`.
+    pub fn generate_extern_c_wrappers(&self, fun: &FunDecl) -> (String, String) {
+        let name = ident(&fun.name);
+        let mut raw_params = Vec::new();
+        let mut params = Vec::new();
+        let mut prelude = String::new();
+        let mut args = Vec::new();
+        let mut unsafe_fn = false;
+        for p in &fun.params {
+            let ty = Type::from_type_ref(&p.ty);
+            let pname = ident(&p.name);
+            raw_params.push(format!("{}: {}", pname, self.raw_type(&ty)));
+            match self.conv(&ty) {
+                Conv::Plain => {
+                    params.push(format!("{}: {}", pname, self.raw_type(&ty)));
+                    args.push(pname);
+                }
+                Conv::Raw => {
+                    unsafe_fn = true;
+                    params.push(format!("{}: {}", pname, self.raw_type(&ty)));
+                    args.push(pname);
+                }
+                Conv::Str => {
+                    params.push(format!("{}: &str", pname));
+                    prelude.push_str(&format!("    let {0} = c_string({0});\n", pname));
+                    args.push(format!("{}.as_ptr()", pname));
+                }
+                Conv::OptStr => {
+                    params.push(format!("{}: Option<&str>", pname));
+                    prelude.push_str(&format!("    let {0} = {0}.map(c_string);\n", pname));
+                    args.push(format!("{}.as_ref().map_or(ptr::null(), |s| s.as_ptr())", pname));
+                }
+                Conv::Struct => {
+                    unsafe_fn |= self.has_pointers(&ty);
+                    params.push(format!("{}: &mut {}", pname, struct_name(&ty)));
+                    args.push(pname);
+                }
+                Conv::OptStruct => {
+                    unsafe_fn |= self.has_pointers(&ty);
+                    params.push(format!("{}: Option<&mut {}>", pname, struct_name(&ty)));
+                    args.push(format!("{}.map_or(ptr::null_mut(), |s| s as *mut _)", pname));
+                }
+            }
+        }
+
+        let ret = fun.ret.as_ref().map(Type::from_type_ref).unwrap_or(Type::Unit);
+        let call = format!("sys::{}({})", name, args.join(", "));
+        let (raw_ret, safe_ret, body) = if ret == Type::Unit {
+            (String::new(), String::new(), format!("unsafe {{ {} }}", call))
+        } else {
+            let raw = self.raw_type(&ret);
+            let (safe, body) = match self.conv(&ret) {
+                Conv::Plain => (raw.clone(), format!("unsafe {{ {} }}", call)),
+                Conv::Raw => {
+                    unsafe_fn = true;
+                    (raw.clone(), format!("unsafe {{ {} }}", call))
+                }
+                Conv::Str => ("String".to_string(), format!("unsafe {{ rust_string({}) }}.unwrap_or_default()", call)),
+                Conv::OptStr => ("Option<String>".to_string(), format!("unsafe {{ rust_string({}) }}", call)),
+                Conv::Struct | Conv::OptStruct => {
+                    (format!("Option<{}>", struct_name(&ret)), format!("unsafe {{ {}.as_ref().copied() }}", call))
+                }
+            };
+            (format!(" -> {}", raw), format!(" -> {}", safe), body)
         };
-        rust_code.push_str(&format!("){} {{\n    // Implementation generated by Korlang FFI\n}}\n", rust_ret));
-        rust_code
+
+        let decl = format!("pub fn {}({}){};", name, raw_params.join(", "), raw_ret);
+        let wrapper = format!(
+            "{}pub {}fn {}({}){} {{\n{}    {}\n}}\n",
+            if unsafe_fn { SAFETY_DOC } else { "" },
+            if unsafe_fn { "unsafe " } else { "" },
+            name,
+            params.join(", "),
+            safe_ret,
+            prelude,
+            body
+        );
+        (decl, wrapper)
+    }
+
+    fn is_struct(&self, name: &str) -> bool {
+        self.structs.iter().any(|s| s.name == name)
+    }
+
+    /// Whether the struct `ty` holds raw pointers, which safe code could
+    /// set to null or leave dangling.
+    fn has_pointers(&self, ty: &Type) -> bool {
+        let name = struct_name(ty);
+        self.structs
+            .iter()
+            .filter(|s| ident(&s.name) == name)
+            .flat_map(|s| &s.fields)
+            .any(|f| self.raw_type(&Type::from_type_ref(&f.ty)).starts_with('*'))
+    }
+
+    fn conv(&self, ty: &Type) -> Conv {
+        match ty {
+            Type::Named(n) if n == "CString" => Conv::Str,
+            Type::Named(n) if self.is_struct(n) => Conv::Struct,
+            Type::Optional(inner) => match &**inner {
+                Type::Named(n) if n == "CString" => Conv::OptStr,
+                Type::Named(n) if self.is_struct(n) => Conv::OptStruct,
+                _ => Conv::Raw,
+            },
+            Type::Generic(n, _) if n == "Ptr" => Conv::Raw,
+            _ => Conv::Plain,
+        }
+    }
+
+    /// The C ABI spelling of a type accepted by `@export`, matching the
+    /// header `korlang build` writes for the library.
+    fn raw_type(&self, ty: &Type) -> String {
+        match ty {
+            Type::Int => "i64".to_string(),
+            Type::UInt => "u64".to_string(),
+            Type::SizedInt { bits, signed } => format!("{}{}", if *signed { "i" } else { "u" }, bits),
+            Type::Float => "f64".to_string(),
+            Type::Float32 => "f32".to_string(),
+            Type::Bool => "bool".to_string(),
+            Type::Char => "u32".to_string(),
+            Type::Unit => "c_void".to_string(),
+            Type::Named(n) if n == "CString" => "*const c_char".to_string(),
+            Type::Named(n) if self.is_struct(n) => format!("*mut {}", ident(n)),
+            Type::Generic(n, args) if n == "Ptr" && args.len() == 1 => format!("*mut {}", self.raw_type(&args[0])),
+            Type::Optional(inner) => self.raw_type(inner),
+            _ => "*mut c_void".to_string(),
+        }
     }
 
-    pub fn validate_rust_type(ty: &Type) -> Result<(), String> {
+    /// A trait method parameter: strings and arrays are borrowed, interface
+    /// values are `&dyn` references.
+    fn param_type(&self, ty: &Type, generics: &[String]) -> Option<String> {
         match ty {
-            Type::Func(_, _) => Ok(()),
-            Type::Named(_) => Ok(()),
-            Type::Generic(_, _) => Ok(()),
-            _ => Err(format!("Type {:?} is not directly representable in Rust FFI", ty)),
+            Type::String => Some("&str".to_string()),
+            Type::Array(inner) => Some(format!("&[{}]", self.owned_type(inner, generics)?)),
+            Type::Optional(inner) if **inner == Type::String => Some("Option<&str>".to_string()),
+            Type::Named(n) if self.interfaces.contains(n) => Some(format!("&dyn {}", ident(n))),
+            Type::Generic(n, args) if self.interfaces.contains(n) => {
+                Some(format!("&dyn {}<{}>", ident(n), self.owned_list(args, generics)?))
+            }
+            Type::Func(ps, r) => Some(format!("&dyn {}", self.fn_trait(ps, r, generics)?)),
+            _ => self.owned_type(ty, generics),
+        }
+    }
+
+    fn owned_type(&self, ty: &Type, generics: &[String]) -> Option<String> {
+        Some(match ty {
+            Type::Int | Type::UInt | Type::SizedInt { .. } | Type::Float | Type::Float32 | Type::Bool => {
+                self.raw_type(ty)
+            }
+            Type::Char => "char".to_string(),
+            Type::Unit => "()".to_string(),
+            Type::String => "String".to_string(),
+            Type::Any => "Box<dyn std::any::Any>".to_string(),
+            Type::Array(inner) => format!("Vec<{}>", self.owned_type(inner, generics)?),
+            Type::Optional(inner) => format!("Option<{}>", self.owned_type(inner, generics)?),
+            Type::Tuple(elems) if elems.len() == 1 => format!("({},)", self.owned_type(&elems[0], generics)?),
+            Type::Tuple(elems) => format!("({})", self.owned_list(elems, generics)?),
+            Type::Func(ps, r) => format!("Box<dyn {}>", self.fn_trait(ps, r, generics)?),
+            Type::Named(n) | Type::Parameter(n) if generics.contains(n) => n.clone(),
+            Type::Named(n) if n == "CString" => "CString".to_string(),
+            Type::Named(n) if self.is_struct(n) => ident(n),
+            Type::Named(n) if self.interfaces.contains(n) => format!("Box<dyn {}>", ident(n)),
+            Type::Generic(n, args) if self.interfaces.contains(n) => {
+                format!("Box<dyn {}<{}>>", ident(n), self.owned_list(args, generics)?)
+            }
+            Type::Generic(n, args) if n == "Ptr" && args.len() == 1 => self.raw_type(ty),
+            _ => return None,
+        })
+    }
+
+    fn owned_list(&self, tys: &[Type], generics: &[String]) -> Option<String> {
+        let parts: Option<Vec<String>> = tys.iter().map(|t| self.owned_type(t, generics)).collect();
+        Some(parts?.join(", "))
+    }
+
+    fn fn_trait(&self, params: &[Type], ret: &Type, generics: &[String]) -> Option<String> {
+        let params = self.owned_list(params, generics)?;
+        if *ret == Type::Unit {
+            Some(format!("Fn({})", params))
+        } else {
+            Some(format!("Fn({}) -> {}", params, self.owned_type(ret, generics)?))
+        }
+    }
+}
+
+const SAFETY_DOC: &str = "/// # Safety\n///\n/// Raw pointers passed in, also inside structs, must be null or valid for\n/// reads and writes for the duration of the call.\n";
+
+/// A field a `#[repr(C)]` struct can hold: a C scalar or pointer, or a
+/// pointer to another such struct.
+fn is_layout_field(ty: &Type, structs: &HashSet<String>) -> bool {
+    let pointee = match ty {
+        Type::Optional(inner) => &**inner,
+        other => other,
+    };
+    match pointee {
+        Type::Named(n) if structs.contains(n) => true,
+        _ => FfiSafetyChecker::is_c_type(ty) && *ty != Type::Unit,
+    }
+}
+
+fn struct_name(ty: &Type) -> String {
+    match ty {
+        Type::Named(n) => ident(n),
+        Type::Optional(inner) => struct_name(inner),
+        _ => unreachable!("struct conversion on {:?}", ty),
+    }
+}
+
+/// Korlang names that are Rust keywords are written as raw identifiers.
+fn ident(name: &str) -> String {
+    const KEYWORDS: &[&str] = &[
+        "as", "async", "await", "box", "break", "const", "continue", "dyn", "else", "enum", "extern", "false", "fn",
+        "for", "if", "impl", "in", "let", "loop", "match", "mod", "move", "mut", "pub", "ref", "return", "static",
+        "struct", "trait", "true", "try", "type", "unsafe", "use", "where", "while", "yield",
+    ];
+    if KEYWORDS.contains(&name) {
+        format!("r#{}", name)
+    } else {
+        name.to_string()
+    }
+}
+
+/// A crate name derived from a file stem.
+pub fn crate_name(stem: &str) -> String {
+    let name: String = stem
+        .chars()
+        .map(|c| if c.is_ascii_alphanumeric() { c.to_ascii_lowercase() } else { '_' })
+        .collect();
+    match name.chars().next() {
+        Some(c) if c.is_ascii_alphabetic() => name,
+        _ => format!("korlang_{}", name),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::RustTraitMapper;
+    use crate::lexer::Lexer;
+    use crate::parser::Parser;
+    use crate::sema::Sema;
+    use std::process::Command;
+
+    const SRC: &str = "struct Point { x: Float; y: Float; }\n\
+                       struct Segment { from: Point; to: Point?; closed: Bool; }\n\
+                       struct Named { name: String; }\n\
+                       interface Shape { fun area() -> Float; fun name() -> String; fun scale(by: Float) -> Shape; }\n\
+                       interface Visitor<T> { fun visit(items: [T], label: String?) -> (Int, Bool); }\n\
+                       @export fun seg_len2(s: Segment) -> Float { 0.0 }\n\
+                       @export fun make_point(x: Float, y: Float) -> Point { Point { x: x, y: y } }\n\
+                       @export fun norm(p: Point) -> Float { p.x }\n\
+                       @export fun moved(p: Point, dx: Float) -> Point { Point { x: p.x + dx, y: p.y } }\n\
+                       @export fun find(name: CString?) -> Point? { null }\n\
+                       @export fun greet(name: CString) -> CString { name }\n\
+                       @export fun fill(buf: *UInt8, len: UInt32) -> Int32 { 0 }\n\
+                       @export fun reset() {}";
+
+    fn mapper() -> RustTraitMapper {
+        let tokens = Lexer::new(SRC).tokenize().expect("lex");
+        let program = Parser::new(tokens).parse_program().expect("parse");
+        Sema::new().check_program(&program).expect("sema");
+        RustTraitMapper::new(&program)
+    }
+
+    #[test]
+    fn wrappers_convert_strings_and_structs() {
+        let lib = mapper().generate_lib("geom");
+        for expected in [
+            "#[repr(C)]\n#[derive(Debug, Clone, Copy)]\npub struct Segment {\n    pub from: *mut Point,\n    pub to: *mut Point,\n    pub closed: bool,\n}",
+            "        pub fn seg_len2(s: *mut Segment) -> f64;\n",
+            "        pub fn greet(name: *const c_char) -> *const c_char;\n",
+            "        pub fn reset();\n",
+            "pub fn make_point(x: f64, y: f64) -> Option<Point> {\n    unsafe { sys::make_point(x, y).as_ref().copied() }\n}",
+            "/// # Safety\n///\n/// Raw pointers passed in, also inside structs, must be null or valid for\n/// reads and writes for the duration of the call.\npub unsafe fn seg_len2(s: &mut Segment) -> f64 {",
+            "pub fn norm(p: &mut Point) -> f64 {\n    unsafe { sys::norm(p) }\n}",
+            "pub trait PointExports {\n    fn norm(&mut self) -> f64;\n    fn moved(&mut self, dx: f64) -> Option<Point>;\n}\n\n\
+             impl PointExports for Point {\n    fn norm(&mut self) -> f64 {\n        norm(self)\n    }\n    \
+             fn moved(&mut self, dx: f64) -> Option<Point> {\n        moved(self, dx)\n    }\n}",
+            "impl SegmentExports for Segment {\n    unsafe fn seg_len2(&mut self) -> f64 {\n        unsafe { seg_len2(self) }\n    }\n}",
+            "pub fn find(name: Option<&str>) -> Option<Point> {\n    let name = name.map(c_string);\n    unsafe { sys::find(name.as_ref().map_or(ptr::null(), |s| s.as_ptr())).as_ref().copied() }\n}",
+            "pub fn greet(name: &str) -> String {\n    let name = c_string(name);\n    unsafe { rust_string(sys::greet(name.as_ptr())) }.unwrap_or_default()\n}",
+            "pub unsafe fn fill(buf: *mut u8, len: u32) -> i32 {",
+            "pub trait Shape {\n    fn area(&self) -> f64;\n    fn name(&self) -> String;\n    fn scale(&self, by: f64) -> Box<dyn Shape>;\n}",
+            "pub trait Visitor<T> {\n    fn visit(&self, items: &[T], label: Option<&str>) -> (i64, bool);\n}",
+        ] {
+            assert!(lib.contains(expected), "missing\n{}\nin\n{}", expected, lib);
+        }
+        assert!(!lib.contains("struct Named"));
+    }
+
+    #[test]
+    fn crate_builds_against_the_static_library() {
+        let files = mapper().generate_crate("geom-rs", "geom");
+        let paths: Vec<&str> = files.iter().map(|(path, _)| *path).collect();
+        assert_eq!(paths, ["Cargo.toml", "build.rs", "src/lib.rs"]);
+        assert!(files[0].1.starts_with("[package]\nname = \"geom-rs\"\n"), "{}", files[0].1);
+        assert!(files[0].1.ends_with("\n[workspace]\n"), "{}", files[0].1);
+        assert!(files[1].1.contains("println!(\"cargo:rustc-link-lib=static=geom\");"), "{}", files[1].1);
+        assert_eq!(files[2].1, mapper().generate_lib("geom"));
+    }
+
+    #[test]
+    #[ignore = "builds the generated crate with cargo; run with --ignored"]
+    fn generated_crate_passes_cargo_check() {
+        let dir = std::env::temp_dir().join(format!("korlang-ffi-rust-{}", std::process::id()));
+        let _ = std::fs::remove_dir_all(&dir);
+        for (path, contents) in mapper().generate_crate("geom", "geom") {
+            let path = dir.join(path);
+            std::fs::create_dir_all(path.parent().unwrap()).unwrap();
+            std::fs::write(path, contents).unwrap();
         }
+        let cargo = std::env::var_os("CARGO").unwrap_or_else(|| "cargo".into());
+        let output = Command::new(cargo)
+            .args(["check", "--offline", "--quiet"])
+            .current_dir(&dir)
+            .env("CARGO_TARGET_DIR", dir.join("target"))
+            .env("RUSTFLAGS", "-D warnings")
+            .output()
+            .expect("run cargo");
+        let _ = std::fs::remove_dir_all(&dir);
+        assert!(output.status.success(), "{}", String::from_utf8_lossy(&output.stderr));
     }
 }
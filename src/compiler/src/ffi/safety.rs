@@ -8,23 +8,46 @@ impl FfiSafetyChecker {
     pub fn check_type_safety(ty: &Type, span: Span) -> Vec<Diagnostic> {
         let mut diags = Vec::new();
         match ty {
-            Type::Named(n) if n == "String" => {
-                diags.push(Diagnostic::warning(
-                    "Passing Korlang String to FFI might require manual memory management on the other side.",
-                    span
+            _ if Self::is_c_type(ty) => {}
+            Type::String => {
+                diags.push(Diagnostic::error(
+                    "Korlang String is not a C type; declare it as 'CString' (Korlang strings convert implicitly)",
+                    span,
                 ));
             }
             Type::Func(_, _) => {
-                diags.push(Diagnostic::warning(
+                diags.push(Diagnostic::error(
                     "Passing closures across FFI boundary requires C-compatible function pointers.",
-                    span
+                    span,
+                ));
+            }
+            _ => {
+                diags.push(Diagnostic::error(
+                    format!("type {:?} cannot cross a C boundary; use sized integers, floats, Bool, CString or pointers", ty),
+                    span,
                 ));
             }
-            _ => {}
         }
         diags
     }
 
+    /// Integers, floats, `Bool`, `Unit` as `void`, `CString` (`char*`) and
+    /// pointers, which may be nullable.
+    pub fn is_c_type(ty: &Type) -> bool {
+        match ty {
+            Type::Optional(inner) => Self::is_pointer(inner),
+            _ => ty.is_numeric() || matches!(ty, Type::Bool | Type::Unit) || Self::is_pointer(ty),
+        }
+    }
+
+    pub fn is_pointer(ty: &Type) -> bool {
+        match ty {
+            Type::Named(n) => n == "CString",
+            Type::Generic(n, args) => n == "Ptr" && args.len() == 1,
+            _ => false,
+        }
+    }
+
     /// Injects safety checks for pointer arithmetic or out-of-bounds access if necessary
     pub fn verify_pointer_access(ptr_name: &str) -> String {
         format!("if ({} == nullptr) {{ throw std::runtime_error(\"FFI Safety Violation: Null pointer access\"); }}", ptr_name)
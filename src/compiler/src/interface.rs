@@ -1,5 +1,7 @@
 use crate::types::Type;
-use crate::ast::InterfaceDecl;
+use crate::ast::{Block, FunSig, InterfaceDecl, Item, StructDecl, TypeRef};
+use crate::diag::Diagnostic;
+use crate::sema::Sema;
 use std::collections::HashMap;
 
 pub struct InterfaceSystem {
@@ -21,6 +23,280 @@ impl InterfaceSystem {
 
     pub fn register_implementation(&mut self, interface_name: &str, ty: Type) {
         let entry = self.implementations.entry(interface_name.to_string()).or_insert_with(Vec::new);
-        entry.push(ty);
+        if !entry.contains(&ty) {
+            entry.push(ty);
+        }
+    }
+
+    /// Types that passed the conformance check for `interface_name`, in declaration order.
+    pub fn implementors(&self, interface_name: &str) -> &[Type] {
+        self.implementations.get(interface_name).map(|v| v.as_slice()).unwrap_or(&[])
+    }
+
+    pub fn implements(&self, ty: &Type, interface_name: &str) -> bool {
+        self.implementors(interface_name).contains(ty)
+    }
+}
+
+impl Sema {
+    pub(crate) fn check_implementations(&mut self, items: &[Item]) {
+        for item in items {
+            match item {
+                Item::Struct(s) => {
+                    for interface_ref in &s.implements {
+                        self.check_conformance(s, interface_ref);
+                    }
+                }
+                Item::Sealed(sealed) => self.check_implementations(&sealed.items),
+                _ => {}
+            }
+        }
+    }
+
+    /// Checks that `s` provides every method of the interface named by
+    /// `interface_ref` with a matching signature, after substituting the
+    /// interface's type arguments. Methods with a default body may be omitted.
+    pub(crate) fn check_conformance(&mut self, s: &StructDecl, interface_ref: &TypeRef) {
+        let (name, args) = match self.type_from_ref(interface_ref) {
+            Type::Named(name) => (name, Vec::new()),
+            Type::Generic(name, args) => (name, args),
+            other => {
+                self.diags.push(Diagnostic::error(format!("{:?} is not an interface", other), s.span));
+                return;
+            }
+        };
+        let interface = match self.interface_system.interfaces.get(&name) {
+            Some(i) => i.clone(),
+            None => {
+                self.diags.push(Diagnostic::error(format!("interface '{}' not found", name), s.span));
+                return;
+            }
+        };
+        if args.len() != interface.generic_params.len() {
+            self.diags.push(Diagnostic::error(
+                format!("interface '{}' expects {} type arguments, found {}", name, interface.generic_params.len(), args.len()),
+                s.span,
+            ));
+            return;
+        }
+
+        let self_ty = Type::Named(s.name.clone());
+        let mut subst: HashMap<String, Type> =
+            interface.generic_params.iter().map(|p| p.name.clone()).zip(args).collect();
+        subst.insert("Self".to_string(), self_ty.clone());
+
+        let mut conforms = true;
+        for method in &interface.methods {
+            let expected = self.interface_method_sig(method).substitute(&subst);
+            let candidates = self.struct_methods(s, &method.name);
+            if candidates.is_empty() {
+                if method.body.is_none() {
+                    self.diags.push(Diagnostic::error(
+                        format!("struct '{}' does not implement method '{}' from interface '{}'", s.name, method.name, name),
+                        s.span,
+                    ));
+                    self.diags.push(Diagnostic::note(format!("'{}.{}' is declared here", name, method.name), method.span));
+                    conforms = false;
+                }
+                continue;
+            }
+            if !candidates.iter().any(|found| signatures_match(&expected, found)) {
+                self.diags.push(Diagnostic::error(
+                    format!(
+                        "method '{}.{}' does not match interface '{}': expected {:?}, found {:?}",
+                        s.name, method.name, name, expected, candidates[0]
+                    ),
+                    s.span,
+                ));
+                self.diags.push(Diagnostic::note(format!("'{}.{}' is declared here", name, method.name), method.span));
+                conforms = false;
+            }
+        }
+
+        if conforms {
+            self.interface_system.register_implementation(&name, self_ty);
+        }
+    }
+
+    /// Type-checks the default body of an interface method.
+    pub(crate) fn check_default_method(&mut self, interface: &InterfaceDecl, method: &FunSig, body: &Block) {
+        self.push_scope();
+        self.define_var("self", interface_self_type(interface), method.span);
+        for p in &method.params {
+            let t = self.type_from_ref(&p.ty);
+            self.define_var(&p.name, t, p.span);
+        }
+        let body_ty = self.check_block(body);
+        if let Some(ret) = &method.ret {
+            let ret_ty = self.type_from_ref(ret);
+            if !(ret_ty == Type::Int && body_ty == Type::Unit) {
+                self.unify(&ret_ty, &body_ty, method.span);
+            }
+        }
+        self.pop_scope();
+    }
+
+    /// Resolves `name` on a value whose type is an interface, or on a struct
+    /// that inherits a default method from one of the interfaces it implements.
+    pub(crate) fn interface_method(&self, ty: &Type, name: &str) -> Option<Type> {
+        let (ty_name, args) = match ty {
+            Type::Named(n) => (n, &[][..]),
+            Type::Generic(n, args) => (n, args.as_slice()),
+            _ => return None,
+        };
+        if let Some(interface) = self.interface_system.interfaces.get(ty_name) {
+            let method = interface.methods.iter().find(|m| m.name == name)?;
+            let subst: HashMap<String, Type> =
+                interface.generic_params.iter().map(|p| p.name.clone()).zip(args.iter().cloned()).collect();
+            return Some(self.interface_method_sig(method).substitute(&subst));
+        }
+        let s = self.structs.get(ty_name)?;
+        for interface_ref in &s.implements {
+            let (i_name, i_args) = match self.type_from_ref(interface_ref) {
+                Type::Named(n) => (n, Vec::new()),
+                Type::Generic(n, args) => (n, args),
+                _ => continue,
+            };
+            let Some(interface) = self.interface_system.interfaces.get(&i_name) else { continue };
+            if let Some(method) = interface.methods.iter().find(|m| m.name == name && m.body.is_some()) {
+                let mut subst: HashMap<String, Type> =
+                    interface.generic_params.iter().map(|p| p.name.clone()).zip(i_args).collect();
+                subst.insert("Self".to_string(), ty.clone());
+                return Some(self.interface_method_sig(method).substitute(&subst));
+            }
+        }
+        None
+    }
+
+    fn interface_method_sig(&self, method: &FunSig) -> Type {
+        let params = method.params.iter().map(|p| self.type_from_ref(&p.ty)).collect();
+        let ret = method.ret.as_ref().map(|t| self.type_from_ref(t)).unwrap_or(Type::Unit);
+        Type::Func(params, Box::new(ret))
+    }
+
+    /// Signatures of the extension methods named `name` declared on `s`, with
+    /// the receiver's type parameters renamed to the struct's own.
+    fn struct_methods(&self, s: &StructDecl, name: &str) -> Vec<Type> {
+        let Some(exts) = self.extensions.get(name) else { return Vec::new() };
+        exts.iter()
+            .filter_map(|(recv, sig)| match recv {
+                Type::Named(n) if n == &s.name => Some(sig.clone()),
+                Type::Generic(n, recv_args) if n == &s.name => {
+                    let subst: HashMap<String, Type> = recv_args
+                        .iter()
+                        .zip(&s.generic_params)
+                        .filter_map(|(a, p)| match a {
+                            Type::Named(a) => Some((a.clone(), Type::Named(p.name.clone()))),
+                            _ => None,
+                        })
+                        .collect();
+                    Some(sig.substitute(&subst))
+                }
+                _ => None,
+            })
+            .collect()
+    }
+}
+
+/// Type of `self` inside a default method: the interface applied to its own parameters.
+pub(crate) fn interface_self_type(interface: &InterfaceDecl) -> Type {
+    if interface.generic_params.is_empty() {
+        Type::Named(interface.name.clone())
+    } else {
+        Type::Generic(interface.name.clone(), interface.generic_params.iter().map(|p| Type::Named(p.name.clone())).collect())
+    }
+}
+
+fn signatures_match(expected: &Type, found: &Type) -> bool {
+    match (expected, found) {
+        (Type::Func(ep, er), Type::Func(fp, fr)) => {
+            ep.len() == fp.len() && ep.iter().zip(fp).all(|(e, f)| types_match(e, f)) && types_match(er, fr)
+        }
+        _ => types_match(expected, found),
+    }
+}
+
+fn types_match(expected: &Type, found: &Type) -> bool {
+    matches!(expected, Type::Unknown | Type::Any) || matches!(found, Type::Unknown | Type::Any) || expected == found
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::lexer::Lexer;
+    use crate::parser::Parser;
+    use crate::sema::Sema;
+    use crate::types::Type;
+
+    fn analyze(src: &str) -> (Sema, Vec<String>) {
+        let tokens = Lexer::new(src).tokenize().expect("lex");
+        let program = Parser::new(tokens).parse_program().expect("parse");
+        let mut sema = Sema::new();
+        let diags = match sema.analyze(&program) {
+            Ok(warnings) => warnings,
+            Err(diags) => diags,
+        };
+        let messages = diags.into_iter().map(|d| d.message).collect();
+        (sema, messages)
+    }
+
+    #[test]
+    fn mismatched_signature_is_reported() {
+        let src = "interface Shape { fun area(scale: Int) -> Float; }\n\
+                   struct Square { side: Float } implements Shape;\n\
+                   fun Square.area(scale: Float) -> Float { 1.0 }";
+        let (sema, messages) = analyze(src);
+        assert_eq!(
+            messages,
+            [
+                "method 'Square.area' does not match interface 'Shape': expected Func([Int], Float), found Func([Float], Float)",
+                "'Shape.area' is declared here",
+            ]
+        );
+        assert!(sema.interfaces().implementors("Shape").is_empty());
+    }
+
+    #[test]
+    fn generic_interface_arguments_are_substituted() {
+        let src = "interface Source<T> { fun next() -> T; }\n\
+                   struct Counter { n: Int } implements Source<Int>;\n\
+                   struct Names { n: Int } implements Source<String>;\n\
+                   fun Counter.next() -> Int { 1 }\n\
+                   fun Names.next() -> Int { 1 }";
+        let (sema, messages) = analyze(src);
+        assert_eq!(
+            messages,
+            [
+                "method 'Names.next' does not match interface 'Source': expected Func([], String), found Func([], Int)",
+                "'Source.next' is declared here",
+            ]
+        );
+        assert_eq!(sema.interfaces().implementors("Source"), [Type::Named("Counter".to_string())]);
+    }
+
+    #[test]
+    fn default_methods_satisfy_conformance() {
+        let src = "interface Greeter { fun name() -> String; fun greet() -> String { \"hello\" } }\n\
+                   struct En { id: Int } implements Greeter;\n\
+                   fun En.name() -> String { \"en\" }\n\
+                   fun main() -> Int { let e = En { id: 1 }; let s: String = e.greet(); 0 }";
+        let (sema, messages) = analyze(src);
+        assert!(messages.is_empty(), "{:?}", messages);
+        assert!(sema.interfaces().implements(&Type::Named("En".to_string()), "Greeter"));
+    }
+
+    #[test]
+    fn structs_coerce_only_to_interfaces_they_implement() {
+        let src = "interface Shape { fun area() -> Float; }\n\
+                   struct Square { side: Float } implements Shape;\n\
+                   struct Point { x: Float }\n\
+                   fun Square.area() -> Float { self.side * self.side }\n\
+                   fun main() -> Int {\n\
+                       let shapes: List<Shape> = [Square { side: 1.0 }];\n\
+                       let p: Shape = Point { x: 1.0 };\n\
+                       0\n\
+                   }";
+        let (_, messages) = analyze(src);
+        assert_eq!(messages.len(), 1, "{:?}", messages);
+        assert!(messages[0].contains("Point"), "{:?}", messages);
     }
 }
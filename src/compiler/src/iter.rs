@@ -0,0 +1,111 @@
+use crate::ast::{Expr, Item};
+use crate::diag::{Diagnostic, Span};
+use crate::lexer::Lexer;
+use crate::parser::Parser;
+use crate::sema::Sema;
+use crate::types::Type;
+
+/// The iteration protocol, declared for every program. `for x in e` accepts
+/// ranges, lists, iterators (`next() -> T?`, which yields until it returns
+/// null) and iterables (`iter()`, which returns one of the others).
+const PRELUDE: &str = "interface Iterator<T> { fun next() -> T?; }\n\
+                       interface Iterable<T> { fun iter() -> Iterator<T>; }\n";
+
+pub fn prelude() -> Vec<Item> {
+    let tokens = Lexer::new(PRELUDE).tokenize().expect("prelude lexes");
+    Parser::new(tokens).parse_program().expect("prelude parses").items
+}
+
+impl Sema {
+    /// `start..end` ranges over an integer type shared by both bounds.
+    pub(crate) fn range_type(&mut self, start: &Expr, end: &Expr, nogc: bool) -> Type {
+        let st = self.check_expr_with(start, nogc);
+        let et = self.check_expr_with(end, nogc);
+        let st = self.adapt_literal(&et, start, st);
+        let et = self.adapt_literal(&st, end, et);
+        let elem = self.expect_int(st, self.span_of(start));
+        let et = self.expect_int(et, self.span_of(end));
+        if elem != Type::Unknown && et != Type::Unknown {
+            self.unify(&elem, &et, self.span_of(end));
+        }
+        Type::Generic("Range".to_string(), vec![elem])
+    }
+
+    /// The type of `x` in `for x in e`, given the type of `e`.
+    pub(crate) fn element_type(&mut self, ty: &Type, span: Span) -> Type {
+        if matches!(ty, Type::Unknown | Type::Any) {
+            return Type::Unknown;
+        }
+        if let Some(elem) = self.iterable_item(ty) {
+            return elem;
+        }
+        let iterator = match self.method_type(ty, "iter") {
+            Some(Type::Func(params, ret)) if params.is_empty() => Some(*ret),
+            _ => None,
+        };
+        if let Some(elem) = iterator.and_then(|it| self.iterable_item(&it)) {
+            return elem;
+        }
+        self.diags.push(Diagnostic::error(
+            format!("cannot iterate over {:?}: expected a range, a list, an Iterator or an Iterable", ty),
+            span,
+        ));
+        Type::Unknown
+    }
+
+    /// Element type of a range, a list or an iterator.
+    fn iterable_item(&self, ty: &Type) -> Option<Type> {
+        if let Some(elem) = ty.range_element().or_else(|| ty.list_element()) {
+            return Some(elem);
+        }
+        match self.method_type(ty, "next")? {
+            Type::Func(params, ret) if params.is_empty() => match *ret {
+                Type::Optional(item) => Some(*item),
+                _ => None,
+            },
+            _ => None,
+        }
+    }
+
+    fn method_type(&self, ty: &Type, name: &str) -> Option<Type> {
+        crate::extension::resolve_extension_method(self, ty, name).or_else(|| self.interface_method(ty, name))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::test_support::errors;
+
+    #[test]
+    fn ranges_lists_and_iterators_are_iterable() {
+        let src = "struct Countdown { n: Int } implements Iterator<Int>;\n\
+                   fun Countdown.next() -> Int? { if (self.n == 0) { return null; } self.n = self.n - 1; self.n }\n\
+                   struct Timer { from: Int } implements Iterable<Int>;\n\
+                   fun Timer.iter() -> Iterator<Int> { Countdown { n: self.from } }\n\
+                   struct Bag { items: List<UInt8> }\n\
+                   fun Bag.iter() -> List<UInt8> { self.items }\n\
+                   fun f(n: Int8, timer: Timer, bag: Bag) -> Int {\n\
+                       var total = 0;\n\
+                       for i in 0..n { let x: Int8 = i; }\n\
+                       for i in 1..=10 { total += i; }\n\
+                       for c in (Countdown { n: 3 }) { total += c; }\n\
+                       for t in timer { total += t; }\n\
+                       for b in bag { let y: UInt8 = b; }\n\
+                       total\n\
+                   }";
+        assert!(errors(src).is_empty(), "{:?}", errors(src));
+    }
+
+    #[test]
+    fn rejects_non_integer_ranges_and_non_iterables() {
+        let src = "fun f(s: String) -> Int {\n\
+                       let r = 0..2.5;\n\
+                       for x in 3 { }\n\
+                       0\n\
+                   }";
+        assert_eq!(
+            errors(src),
+            ["expected integer type", "cannot iterate over Int: expected a range, a list, an Iterator or an Iterable"]
+        );
+    }
+}
@@ -6,6 +6,9 @@ pub enum TokenKind {
     Identifier(String),
     IntLiteral(i64),
     FloatLiteral(f64),
+    /// Numeric literals with a type suffix, carrying the suffix's type name.
+    SuffixedInt(i64, &'static str),
+    SuffixedFloat(f64, &'static str),
     StringLiteral(String),
     CharLiteral(char),
     BoolLiteral(bool),
@@ -59,6 +62,9 @@ pub enum TokenKind {
     Semi,
     Colon,
     Dot,
+    DotDot,
+    DotDotEq,
+    Ellipsis,
 
     InterpStart, // "@{"
     InterpEnd,   // "}"
@@ -182,9 +188,9 @@ impl<'a> Lexer<'a> {
         let s: String = self.chars[start_idx..self.pos].iter().collect();
         let kind = match s.as_str() {
             "fun" | "gpu" | "let" | "var" | "if" | "else" | "match" | "for" | "while" |
-            "break" | "continue" | "return" | "view" | "resource" | "state" |
+            "loop" | "defer" | "break" | "continue" | "return" | "view" | "resource" | "state" |
             "spawn" | "@nogc" | "import" | "as" | "struct" | "enum" | "type" |
-            "in" | "mut" | "interface" | "sealed" | "implements" | "class" => TokenKind::Keyword(Box::leak(s.into_boxed_str())),
+            "in" | "mut" | "interface" | "sealed" | "implements" | "impl" | "extern" | "class" => TokenKind::Keyword(Box::leak(s.into_boxed_str())),
             "true" => TokenKind::BoolLiteral(true),
             "false" => TokenKind::BoolLiteral(false),
             _ => TokenKind::Identifier(s),
@@ -195,50 +201,34 @@ impl<'a> Lexer<'a> {
     fn lex_number(&mut self) -> Result<Token, Diagnostic> {
         let start_pos = self.position();
         let start = self.pos;
-        if self.peek() == '0' && (self.peek_next() == 'x' || self.peek_next() == 'X') {
-            self.advance();
-            self.advance();
-            let hex_start = self.pos;
-            while !self.is_eof() && self.peek().is_ascii_hexdigit() {
+        for (marker, radix, what) in [('x', 16, "hex"), ('b', 2, "binary")] {
+            if self.peek() == '0' && self.peek_next().to_ascii_lowercase() == marker {
                 self.advance();
-            }
-            if hex_start == self.pos {
-                return Err(Diagnostic::error("invalid hex literal", Span::new(start_pos, self.position())));
-            }
-            let s: String = self.chars[hex_start..self.pos].iter().collect();
-            let v = i64::from_str_radix(&s, 16)
-                .map_err(|_| Diagnostic::error("invalid hex literal", Span::new(start_pos, self.position())))?;
-            return Ok(Token { kind: TokenKind::IntLiteral(v), span: Span::new(start_pos, self.position()) });
-        }
-        if self.peek() == '0' && (self.peek_next() == 'b' || self.peek_next() == 'B') {
-            self.advance();
-            self.advance();
-            let bin_start = self.pos;
-            while !self.is_eof() && matches!(self.peek(), '0' | '1') {
                 self.advance();
+                let digits_start = self.pos;
+                while !self.is_eof() && self.peek().is_digit(radix) {
+                    self.advance();
+                }
+                let s: String = self.chars[digits_start..self.pos].iter().collect();
+                let v = i128::from_str_radix(&s, radix)
+                    .map_err(|_| Diagnostic::error(format!("invalid {} literal", what), Span::new(start_pos, self.position())))?;
+                return self.finish_int(v, start_pos);
             }
-            if bin_start == self.pos {
-                return Err(Diagnostic::error("invalid binary literal", Span::new(start_pos, self.position())));
-            }
-            let s: String = self.chars[bin_start..self.pos].iter().collect();
-            let v = i64::from_str_radix(&s, 2)
-                .map_err(|_| Diagnostic::error("invalid binary literal", Span::new(start_pos, self.position())))?;
-            return Ok(Token { kind: TokenKind::IntLiteral(v), span: Span::new(start_pos, self.position()) });
         }
         while !self.is_eof() && self.peek().is_ascii_digit() {
             self.advance();
         }
         let mut is_float = false;
-        if !self.is_eof() && self.peek() == '.' {
-            if self.peek_next().is_ascii_digit() {
-                is_float = true;
+        // After a member `.`, digits are a tuple index: `t.0.1` is two accesses.
+        let tuple_index = start > 0 && self.chars[start - 1] == '.' && (start < 2 || self.chars[start - 2] != '.');
+        if !tuple_index && !self.is_eof() && self.peek() == '.' && self.peek_next().is_ascii_digit() {
+            is_float = true;
+            self.advance();
+            while !self.is_eof() && self.peek().is_ascii_digit() {
                 self.advance();
-                while !self.is_eof() && self.peek().is_ascii_digit() {
-                    self.advance();
-                }
             }
         }
-        if !self.is_eof() && (self.peek() == 'e' || self.peek() == 'E') {
+        if !tuple_index && !self.is_eof() && (self.peek() == 'e' || self.peek() == 'E') {
             is_float = true;
             self.advance();
             if self.peek() == '+' || self.peek() == '-' {
@@ -253,12 +243,68 @@ impl<'a> Lexer<'a> {
         if is_float {
             let v = s.parse::<f64>()
                 .map_err(|_| Diagnostic::error("invalid float literal", Span::new(start_pos, self.position())))?;
-            Ok(Token { kind: TokenKind::FloatLiteral(v), span: Span::new(start_pos, self.position()) })
-        } else {
-            let v = s.parse::<i64>()
-                .map_err(|_| Diagnostic::error("invalid int literal", Span::new(start_pos, self.position())))?;
-            Ok(Token { kind: TokenKind::IntLiteral(v), span: Span::new(start_pos, self.position()) })
+            return match self.lex_number_suffix() {
+                Some(ty) if ty.starts_with("Float") => {
+                    Ok(Token { kind: TokenKind::SuffixedFloat(v, ty), span: Span::new(start_pos, self.position()) })
+                }
+                Some(ty) => Err(Diagnostic::error(format!("float literal cannot have type {}", ty), Span::new(start_pos, self.position()))),
+                None => Ok(Token { kind: TokenKind::FloatLiteral(v), span: Span::new(start_pos, self.position()) }),
+            };
         }
+        let v = s.parse::<i128>()
+            .map_err(|_| Diagnostic::error("invalid int literal", Span::new(start_pos, self.position())))?;
+        self.finish_int(v, start_pos)
+    }
+
+    /// Applies an optional type suffix (`255u8`, `2f32`) to an integer literal.
+    fn finish_int(&mut self, v: i128, start_pos: Position) -> Result<Token, Diagnostic> {
+        let suffix = self.lex_number_suffix();
+        let span = Span::new(start_pos, self.position());
+        let (min, max) = match suffix {
+            // One past the maximum is left for the parser to accept after a `-`.
+            Some("Int8") => (i8::MIN as i128, i8::MAX as i128 + 1),
+            Some("Int16") => (i16::MIN as i128, i16::MAX as i128 + 1),
+            Some("Int32") => (i32::MIN as i128, i32::MAX as i128 + 1),
+            Some("Int64") => (i64::MIN as i128, i64::MAX as i128 + 1),
+            Some("UInt8") => (0, u8::MAX as i128),
+            Some("UInt16") => (0, u16::MAX as i128),
+            Some("UInt32") => (0, u32::MAX as i128),
+            Some("UInt64") => (0, u64::MAX as i128),
+            Some("Float32") | Some("Float64") => (i128::MIN, i128::MAX),
+            _ => (i64::MIN as i128, i64::MAX as i128),
+        };
+        if v < min || v > max {
+            let ty = suffix.unwrap_or("Int");
+            return Err(Diagnostic::error(format!("integer literal out of range for {}", ty), span));
+        }
+        let kind = match suffix {
+            Some(ty) if ty.starts_with("Float") => TokenKind::SuffixedFloat(v as f64, ty),
+            // UInt64 literals above i64::MAX, and 2^63i64, keep their bit pattern.
+            Some(ty) => TokenKind::SuffixedInt(v as u64 as i64, ty),
+            None => TokenKind::IntLiteral(v as i64),
+        };
+        Ok(Token { kind, span })
+    }
+
+    fn lex_number_suffix(&mut self) -> Option<&'static str> {
+        const SUFFIXES: [(&str, &str); 10] = [
+            ("i8", "Int8"), ("i16", "Int16"), ("i32", "Int32"), ("i64", "Int64"),
+            ("u8", "UInt8"), ("u16", "UInt16"), ("u32", "UInt32"), ("u64", "UInt64"),
+            ("f32", "Float32"), ("f64", "Float64"),
+        ];
+        for (suffix, ty) in SUFFIXES {
+            let end = self.pos + suffix.len();
+            let matches = self.chars.len() >= end
+                && self.chars[self.pos..end].iter().copied().eq(suffix.chars())
+                && !self.chars.get(end).is_some_and(|c| c.is_ascii_alphanumeric() || *c == '_');
+            if matches {
+                for _ in 0..suffix.len() {
+                    self.advance();
+                }
+                return Some(ty);
+            }
+        }
+        None
     }
 
     fn lex_string_segment(&mut self) -> Result<Option<Token>, Diagnostic> {
@@ -445,7 +491,7 @@ impl<'a> Lexer<'a> {
             let name: String = self.chars[ident_start..self.pos].iter().collect();
             let full = format!("@{}", name);
             let kind = match name.as_str() {
-                "nogc" | "import" | "bridge" => TokenKind::Keyword(Box::leak(full.into_boxed_str())),
+                "nogc" | "import" | "bridge" | "export" => TokenKind::Keyword(Box::leak(full.into_boxed_str())),
                 _ => TokenKind::Identifier(full),
             };
             return Ok(Token { kind, span: Span::new(start, self.position()) });
@@ -462,6 +508,19 @@ impl<'a> Lexer<'a> {
             ('>', '=') => { self.advance(); self.advance(); TokenKind::GtEq }
             ('&', '&') => { self.advance(); self.advance(); TokenKind::AndAnd }
             ('|', '|') => { self.advance(); self.advance(); TokenKind::OrOr }
+            ('.', '.') => {
+                self.advance();
+                self.advance();
+                if self.peek() == '=' {
+                    self.advance();
+                    TokenKind::DotDotEq
+                } else if self.peek() == '.' {
+                    self.advance();
+                    TokenKind::Ellipsis
+                } else {
+                    TokenKind::DotDot
+                }
+            }
             ('.', '+') => { self.advance(); self.advance(); TokenKind::DotPlus }
             ('.', '-') => { self.advance(); self.advance(); TokenKind::DotMinus }
             ('.', '*') => { self.advance(); self.advance(); TokenKind::DotStar }
@@ -786,4 +845,34 @@ mod tests {
         assert!(has_char_literal(&tokens, '\n'));
     }
 
+    #[test]
+    fn digits_after_a_member_dot_are_tuple_indices() {
+        let tokens = tokens("t.0.1 1.5 0..2");
+        let kinds: Vec<_> = tokens.iter().map(|t| t.kind.clone()).collect();
+        assert_eq!(
+            kinds[..9],
+            [
+                TokenKind::Identifier("t".to_string()),
+                TokenKind::Dot,
+                TokenKind::IntLiteral(0),
+                TokenKind::Dot,
+                TokenKind::IntLiteral(1),
+                TokenKind::FloatLiteral(1.5),
+                TokenKind::IntLiteral(0),
+                TokenKind::DotDot,
+                TokenKind::IntLiteral(2),
+            ]
+        );
+    }
+
+    #[test]
+    fn numeric_suffixes_are_lexed_and_range_checked() {
+        let tokens = tokens("255u8 0x7fi8 1.5f32");
+        assert!(matches!(tokens[0].kind, TokenKind::SuffixedInt(255, "UInt8")));
+        assert!(matches!(tokens[1].kind, TokenKind::SuffixedInt(127, "Int8")));
+        assert!(matches!(tokens[2].kind, TokenKind::SuffixedFloat(v, "Float32") if v == 1.5));
+        let err = Lexer::new("256u8").tokenize().unwrap_err();
+        assert_eq!(err[0].message, "integer literal out of range for UInt8");
+    }
+
 }
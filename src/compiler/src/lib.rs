@@ -11,9 +11,15 @@ pub mod ast;
 pub mod sema;
 pub mod codegen;
 pub mod linker;
+pub mod mangle;
 pub mod escape;
 pub mod typeck;
+pub mod shape;
+pub mod iter;
+pub mod method;
 pub mod pattern;
+pub mod exhaustive;
+pub mod cfg;
 pub mod borrowck;
 pub mod lifetime;
 pub mod moveck;
@@ -22,5 +28,9 @@ pub mod templates;
 pub mod concepts;
 pub mod specialization;
 pub mod constexpr;
+pub mod coverage;
 pub mod backend;
 pub mod ffi;
+
+#[cfg(test)]
+mod test_support;
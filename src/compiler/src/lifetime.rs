@@ -10,10 +10,40 @@ pub enum Lifetime {
     Param(String),
 }
 
+/// One region a reference value points into. `origin` names the local that
+/// owns the storage, declared at `span`; an unnamed origin is a temporary.
+/// `site` is where the storage was allocated.
+#[derive(Debug, Clone)]
+pub struct Loan {
+    pub lifetime: Lifetime,
+    pub origin: Option<String>,
+    pub span: Span,
+    pub site: Span,
+}
+
+struct Binding {
+    ty: Type,
+    loans: Vec<Loan>,
+}
+
+/// Region inference for `@nogc` functions. Values of Move types are
+/// references into the region that owns them: a parameter, or the stack of
+/// the block that allocated them. A value lives as long as the shortest of
+/// its loans and must not be returned or stored past that.
 pub struct LifetimeChecker<'a> {
     sema: &'a mut Sema,
     current_depth: usize,
-    bindings: HashMap<String, Lifetime>,
+    scopes: Vec<HashMap<String, Binding>>,
+    /// Per callee, the parameters its result may point into.
+    summaries: HashMap<String, Vec<usize>>,
+    /// Parameters seen flowing into a return while summarizing.
+    returned: Vec<String>,
+    /// Allocation sites whose storage outlives their block.
+    escapes: Vec<Span>,
+    /// Labels of the enclosing `loop` expressions and the loans of the
+    /// values they are broken out of with.
+    breaks: Vec<(Option<String>, Vec<Loan>)>,
+    report: bool,
 }
 
 impl<'a> LifetimeChecker<'a> {
@@ -21,46 +51,100 @@ impl<'a> LifetimeChecker<'a> {
         Self {
             sema,
             current_depth: 0,
-            bindings: HashMap::new(),
+            scopes: vec![HashMap::new()],
+            summaries: HashMap::new(),
+            returned: Vec::new(),
+            escapes: Vec::new(),
+            breaks: Vec::new(),
+            report: true,
         }
     }
 
     pub fn enter_block(&mut self) {
         self.current_depth += 1;
+        self.scopes.push(HashMap::new());
     }
 
     pub fn exit_block(&mut self) {
         self.current_depth -= 1;
+        self.scopes.pop();
+    }
+
+    fn bind(&mut self, name: &str, ty: Type, loans: Vec<Loan>) {
+        if let Some(scope) = self.scopes.last_mut() {
+            scope.insert(name.to_string(), Binding { ty, loans });
+        }
     }
 
-    pub fn bind(&mut self, name: String, lifetime: Lifetime) {
-        self.bindings.insert(name, lifetime);
+    fn lookup(&self, name: &str) -> Option<&Binding> {
+        self.scopes.iter().rev().find_map(|s| s.get(name))
     }
 
-    pub fn check_block(&mut self, block: &Block) {
+    /// Depth of the scope that declares `name`; parameters live at depth 0.
+    fn depth_of(&self, name: &str) -> Option<usize> {
+        self.scopes.iter().rposition(|s| s.contains_key(name))
+    }
+
+    pub fn check_fun(&mut self, f: &FunDecl) {
+        if let Some(recv) = &f.receiver {
+            let ty = self.sema.type_from_ref(recv);
+            self.bind("self", ty, vec![Loan { lifetime: Lifetime::Param("self".to_string()), origin: None, span: f.span, site: f.span }]);
+        }
+        for p in &f.params {
+            let loans = vec![Loan { lifetime: Lifetime::Param(p.name.clone()), origin: None, span: p.span, site: p.span }];
+            self.bind(&p.name, self.sema.type_from_ref(&p.ty), loans);
+        }
         self.enter_block();
-        for stmt in &block.stmts {
+        for stmt in &f.body.stmts {
             self.check_stmt(stmt);
         }
-        if let Some(tail) = &block.tail {
-            self.check_expr(tail);
+        if let Some(tail) = &f.body.tail {
+            let loans = self.check_expr(tail);
+            self.check_escape(&loans, self.span_of(tail));
         }
         self.exit_block();
     }
 
+    pub fn check_block(&mut self, block: &Block) -> Vec<Loan> {
+        self.enter_block();
+        for stmt in &block.stmts {
+            self.check_stmt(stmt);
+        }
+        let loans = match &block.tail {
+            Some(tail) => self.check_expr(tail),
+            None => Vec::new(),
+        };
+        self.exit_block();
+        loans
+    }
+
     pub fn check_stmt(&mut self, stmt: &Stmt) {
         match stmt {
             Stmt::Var(v) => {
-                let life = self.check_expr(&v.value);
-                self.bind(v.name.clone(), life);
+                let ty = match &v.ty {
+                    Some(ann) => self.sema.type_from_ref(ann),
+                    None => self.type_of(&v.value),
+                };
+                let mut loans = self.check_expr(&v.value);
+                if let Some(pat) = &v.pattern {
+                    self.bind_pattern(pat, &ty, &loans);
+                    return;
+                }
+                // A fresh allocation is owned by the binding it initializes.
+                for loan in &mut loans {
+                    if loan.origin.is_none() && matches!(loan.lifetime, Lifetime::Block(_)) {
+                        loan.origin = Some(v.name.clone());
+                        loan.span = v.span;
+                    }
+                }
+                self.bind(&v.name, ty, loans);
             }
             Stmt::Expr(e, _) => {
                 self.check_expr(e);
             }
-            Stmt::Return(e, _) => {
-                if let Some(expr) = e {
-                    self.check_expr(expr);
-                }
+            Stmt::Return(Some(expr), span) => {
+                let loans = self.check_expr(expr);
+                self.check_escape(&loans, *span);
             }
             Stmt::If(cond, then_block, else_stmt, _) => {
                 self.check_expr(cond);
@@ -69,10 +153,42 @@ impl<'a> LifetimeChecker<'a> {
                     self.check_stmt(else_s);
                 }
             }
-            Stmt::While(cond, body, _) => {
+            Stmt::Break(label, Some(value), _) => {
+                let loans = self.check_expr(value);
+                let target = match label {
+                    Some(label) => self.breaks.iter().rposition(|(l, _)| l.as_ref() == Some(label)),
+                    None => self.breaks.len().checked_sub(1),
+                };
+                if let Some(i) = target {
+                    self.breaks[i].1.extend(loans);
+                }
+            }
+            Stmt::While(cond, body, ..) => {
                 self.check_expr(cond);
                 self.check_block(body);
             }
+            Stmt::Defer(body, _) => {
+                self.check_block(body);
+            }
+            Stmt::For(name, iter, body, ..) => {
+                let elem = match self.type_of(iter) {
+                    Type::Array(elem) => *elem,
+                    _ => Type::Unknown,
+                };
+                let loans = self.check_expr(iter);
+                self.enter_block();
+                self.bind(name, elem.clone(), self.value_loans(&elem, loans));
+                for stmt in &body.stmts {
+                    self.check_stmt(stmt);
+                }
+                if let Some(tail) = &body.tail {
+                    self.check_expr(tail);
+                }
+                self.exit_block();
+            }
+            Stmt::Match(expr, arms, _) => {
+                self.check_match(expr, arms);
+            }
             Stmt::Block(b) => {
                 self.check_block(b);
             }
@@ -80,38 +196,378 @@ impl<'a> LifetimeChecker<'a> {
         }
     }
 
-    pub fn check_expr(&mut self, expr: &Expr) -> Lifetime {
-        match expr {
-            Expr::Literal(_, _) => Lifetime::Static,
-            Expr::Ident(name, _) => {
-                self.bindings.get(name).cloned().unwrap_or(Lifetime::Block(self.current_depth))
+    pub fn check_expr(&mut self, expr: &Expr) -> Vec<Loan> {
+        let loans = match expr {
+            Expr::Literal(_, _) => Vec::new(),
+            Expr::Ident(name, _) => self.lookup(name).map(|b| b.loans.clone()).unwrap_or_default(),
+            Expr::StructLit { fields, span, .. } => {
+                for (_, value) in fields {
+                    self.check_expr(value);
+                }
+                vec![Loan { lifetime: Lifetime::Block(self.current_depth), origin: None, span: *span, site: *span }]
+            }
+            Expr::Array(items, span) => {
+                for item in items {
+                    self.check_expr(item);
+                }
+                vec![Loan { lifetime: Lifetime::Block(self.current_depth), origin: None, span: *span, site: *span }]
             }
-            Expr::Call { args, .. } => {
-                // Return minimum lifetime of arguments for now
-                let min_life = Lifetime::Static;
-                for arg in args {
-                    let _life = self.check_expr(arg);
-                    // logic to find shortest lifetime...
+            // Tuples are plain values that carry the loans of their elements.
+            Expr::Tuple(items, _) => items.iter().flat_map(|item| self.check_expr(item)).collect(),
+            Expr::Call { callee, args, .. } => {
+                let arg_loans: Vec<Vec<Loan>> = args.iter().map(|a| self.check_expr(a)).collect();
+                match &**callee {
+                    // Collected functions may return an argument as well, so
+                    // every callee with a body is summarized.
+                    Expr::Ident(name, _) if self.sema.fun_decls.contains_key(name) => {
+                        let flows = self.summary(name);
+                        flows.iter().filter_map(|i| arg_loans.get(*i)).flatten().cloned().collect()
+                    }
+                    other => {
+                        self.check_expr(other);
+                        arg_loans.into_iter().flatten().collect()
+                    }
                 }
-                min_life
             }
-            _ => Lifetime::Block(self.current_depth),
+            Expr::Member { target, .. } => self.check_expr(target),
+            Expr::Index { target, index, .. } => {
+                self.check_expr(index);
+                self.check_expr(target)
+            }
+            Expr::Assign { left, right, span, .. } => {
+                let loans = self.check_expr(right);
+                self.check_expr(left);
+                self.check_store(left, &loans, *span);
+                Vec::new()
+            }
+            Expr::Unary { expr, .. } | Expr::Cast { expr, .. } => {
+                self.check_expr(expr);
+                Vec::new()
+            }
+            Expr::Range { start, end, .. } => {
+                self.check_expr(start);
+                self.check_expr(end);
+                Vec::new()
+            }
+            Expr::Binary { left, op, right, .. } => {
+                let mut loans = self.check_expr(left);
+                let rhs = self.check_expr(right);
+                if matches!(op, BinaryOp::NullCoalesce) {
+                    loans.extend(rhs);
+                    loans
+                } else {
+                    Vec::new()
+                }
+            }
+            Expr::If { cond, then_block, else_block, .. } => {
+                self.check_expr(cond);
+                let mut loans = self.check_block(then_block);
+                loans.extend(self.check_block(else_block));
+                loans
+            }
+            Expr::Match { expr, arms, .. } => self.check_match(expr, arms),
+            Expr::Block(b) => self.check_block(b),
+            Expr::Loop { label, body, .. } => {
+                self.breaks.push((label.clone(), Vec::new()));
+                self.check_block(body);
+                self.breaks.pop().map(|(_, loans)| loans).unwrap_or_default()
+            }
+            Expr::Interpolated { parts, .. } => {
+                for p in parts {
+                    self.check_expr(p);
+                }
+                Vec::new()
+            }
+            Expr::Instantiate { .. } | Expr::Tensor(_, _) => Vec::new(),
+        };
+        let ty = self.type_of(expr);
+        self.value_loans(&ty, loans)
+    }
+
+    fn check_match(&mut self, expr: &Expr, arms: &[MatchArm]) -> Vec<Loan> {
+        let scrutinee = self.check_expr(expr);
+        let ty = self.type_of(expr);
+        let mut loans = Vec::new();
+        for arm in arms {
+            self.enter_block();
+            self.bind_pattern(&arm.pat, &ty, &scrutinee);
+            loans.extend(self.check_expr(&arm.body));
+            self.exit_block();
         }
+        loans
     }
 
-    pub fn validate_assignment(&mut self, target_life: &Lifetime, value_life: &Lifetime, span: Span) {
-        // value must outlive target
-        match (target_life, value_life) {
-            (Lifetime::Static, Lifetime::Static) => {}
-            (Lifetime::Static, _) => {
-                self.sema.diags.push(Diagnostic::error("assigning short-lived value to static target", span));
+    fn bind_pattern(&mut self, pat: &Pattern, ty: &Type, loans: &[Loan]) {
+        match pat {
+            Pattern::Ident(name, _) => self.bind(name, ty.clone(), loans.to_vec()),
+            Pattern::Tuple(items, _) => {
+                for (i, p) in items.iter().enumerate() {
+                    let item_ty = match ty {
+                        Type::Tuple(tys) => tys.get(i).cloned().unwrap_or(Type::Unknown),
+                        _ => Type::Unknown,
+                    };
+                    self.bind_pattern(p, &item_ty, loans);
+                }
             }
-            (Lifetime::Block(td), Lifetime::Block(vd)) => {
-                if vd > td {
-                    self.sema.diags.push(Diagnostic::error("value does not live long enough", span));
+            Pattern::Variant { args: items, .. } => {
+                for p in items {
+                    self.bind_pattern(p, &Type::Unknown, loans);
                 }
             }
-            _ => {}
+            Pattern::Struct { fields, .. } => {
+                for (_, p) in fields {
+                    self.bind_pattern(p, &Type::Unknown, loans);
+                }
+            }
+            Pattern::Is(target, inner, _) => {
+                let ty = self.sema.type_from_ref(target);
+                self.bind_pattern(inner, &ty, loans);
+            }
+            Pattern::Wildcard(_) | Pattern::Literal(_, _) => {}
         }
     }
+
+    /// Copies own no storage, so only Move values keep their loans.
+    fn value_loans(&self, ty: &Type, loans: Vec<Loan>) -> Vec<Loan> {
+        if self.sema.is_copy(ty) && *ty != Type::Unknown {
+            Vec::new()
+        } else {
+            loans
+        }
+    }
+
+    /// A returned reference must point into a parameter or static storage.
+    fn check_escape(&mut self, loans: &[Loan], span: Span) {
+        for loan in loans {
+            match &loan.lifetime {
+                Lifetime::Param(p) => {
+                    if !self.returned.contains(p) {
+                        self.returned.push(p.clone());
+                    }
+                }
+                Lifetime::Block(_) => {
+                    self.escapes.push(loan.site);
+                    let (msg, note) = match &loan.origin {
+                        Some(name) => (
+                            format!("cannot return a reference to local '{}'", name),
+                            format!("'{}' is declared here and dropped when the function returns", name),
+                        ),
+                        None => (
+                            "cannot return a reference to a temporary value".to_string(),
+                            "the value is allocated here and dropped when the function returns".to_string(),
+                        ),
+                    };
+                    self.error(msg, span, note, loan.span);
+                }
+                Lifetime::Static => {}
+            }
+        }
+    }
+
+    /// Storing into `target` requires every loan of the value to outlive the
+    /// binding that `target` is rooted in.
+    fn check_store(&mut self, target: &Expr, loans: &[Loan], span: Span) {
+        let Some(root) = root_binding(target) else { return };
+        let Some(target_depth) = self.depth_of(root) else { return };
+        for loan in loans {
+            self.validate_assignment(&Lifetime::Block(target_depth), loan, span);
+        }
+        if let (Expr::Ident(name, _), Some(scope)) = (target, self.scopes.get_mut(target_depth)) {
+            if let Some(binding) = scope.get_mut(name) {
+                binding.loans = loans.to_vec();
+            }
+        }
+    }
+
+    pub fn validate_assignment(&mut self, target_life: &Lifetime, value: &Loan, span: Span) {
+        // value must outlive target
+        let Lifetime::Block(vd) = value.lifetime else { return };
+        let outlives = match target_life {
+            Lifetime::Static | Lifetime::Param(_) => false,
+            Lifetime::Block(td) => vd <= *td,
+        };
+        if !outlives {
+            self.escapes.push(value.site);
+            let name = value.origin.as_deref().unwrap_or("value");
+            self.error(
+                format!("'{}' does not live long enough", name),
+                span,
+                format!("'{}' is declared here and dropped at the end of its block", name),
+                value.span,
+            );
+        }
+    }
+
+    /// Escape analysis for any function: the allocation sites in `f` whose
+    /// storage is returned or stored past its block, and so cannot live on
+    /// the stack.
+    pub fn escaping_allocations(sema: &mut Sema, f: &FunDecl) -> Vec<Span> {
+        let mut checker = LifetimeChecker::new(sema);
+        checker.report = false;
+        checker.check_fun(f);
+        checker.escapes
+    }
+
+    /// The callee parameters whose regions flow into its result, found by
+    /// checking the callee's body. A recursive call assumes every parameter
+    /// flows.
+    fn summary(&mut self, name: &str) -> Vec<usize> {
+        if let Some(flows) = self.summaries.get(name) {
+            return flows.clone();
+        }
+        let Some(decl) = self.sema.fun_decls.get(name).cloned() else { return Vec::new() };
+        self.summaries.insert(name.to_string(), (0..decl.params.len()).collect());
+        let summaries = std::mem::take(&mut self.summaries);
+        let mut inner = LifetimeChecker::new(self.sema);
+        inner.summaries = summaries;
+        inner.report = false;
+        inner.check_fun(&decl);
+        let flows: Vec<usize> = decl
+            .params
+            .iter()
+            .enumerate()
+            .filter(|(_, p)| inner.returned.contains(&p.name))
+            .map(|(i, _)| i)
+            .collect();
+        self.summaries = std::mem::take(&mut inner.summaries);
+        self.summaries.insert(name.to_string(), flows.clone());
+        flows
+    }
+
+    /// Best-effort static type of `expr`, used to tell Copy values apart.
+    fn type_of(&self, expr: &Expr) -> Type {
+        match expr {
+            Expr::Literal(lit, _) => self.sema.type_of_literal(lit),
+            Expr::Ident(name, _) => self.lookup(name).map(|b| b.ty.clone()).unwrap_or(Type::Unknown),
+            Expr::StructLit { name, .. } => Type::Named(name.clone()),
+            Expr::Array(_, _) => Type::Array(Box::new(Type::Unknown)),
+            Expr::Tuple(items, _) => Type::Tuple(items.iter().map(|e| self.type_of(e)).collect()),
+            Expr::Interpolated { .. } => Type::String,
+            Expr::Call { callee, .. } => match &**callee {
+                Expr::Ident(name, _) => match self.sema.functions.get(name) {
+                    Some(Type::Func(_, ret)) => (**ret).clone(),
+                    _ => Type::Unknown,
+                },
+                _ => Type::Unknown,
+            },
+            Expr::Member { target, name, .. } => {
+                let mut ty = self.type_of(target);
+                if let Type::Optional(inner) = ty {
+                    ty = *inner;
+                }
+                match ty {
+                    Type::Named(s) => self
+                        .sema
+                        .structs
+                        .get(&s)
+                        .and_then(|d| d.fields.iter().find(|f| &f.name == name))
+                        .map(|f| Type::from_type_ref(&f.ty))
+                        .unwrap_or(Type::Unknown),
+                    _ => Type::Unknown,
+                }
+            }
+            Expr::Index { target, .. } => match self.type_of(target) {
+                Type::Array(elem) => *elem,
+                _ => Type::Unknown,
+            },
+            Expr::If { then_block, .. } => then_block.tail.as_ref().map(|t| self.type_of(t)).unwrap_or(Type::Unit),
+            Expr::Block(b) => b.tail.as_ref().map(|t| self.type_of(t)).unwrap_or(Type::Unit),
+            Expr::Unary { .. } | Expr::Assign { .. } => Type::Unit,
+            _ => Type::Unknown,
+        }
+    }
+
+    fn span_of(&self, expr: &Expr) -> Span {
+        match expr {
+            Expr::Literal(_, s) | Expr::Ident(_, s) | Expr::Array(_, s) | Expr::Tuple(_, s) | Expr::Tensor(_, s) => *s,
+            Expr::Instantiate { span, .. }
+            | Expr::StructLit { span, .. }
+            | Expr::Unary { span, .. }
+            | Expr::Binary { span, .. }
+            | Expr::Assign { span, .. }
+            | Expr::Call { span, .. }
+            | Expr::Member { span, .. }
+            | Expr::Index { span, .. }
+            | Expr::Range { span, .. }
+            | Expr::Cast { span, .. }
+            | Expr::If { span, .. }
+            | Expr::Match { span, .. }
+            | Expr::Loop { span, .. }
+            | Expr::Interpolated { span, .. } => *span,
+            Expr::Block(b) => b.span,
+        }
+    }
+
+    fn error(&mut self, msg: String, span: Span, note: String, note_span: Span) {
+        if self.report {
+            self.sema.diags.push(Diagnostic::error(msg, span));
+            self.sema.diags.push(Diagnostic::note(note, note_span));
+        }
+    }
+}
+
+/// The local a place expression such as `a.b[i]` stores into.
+fn root_binding(expr: &Expr) -> Option<&str> {
+    match expr {
+        Expr::Ident(name, _) => Some(name),
+        Expr::Member { target, .. } | Expr::Index { target, .. } => root_binding(target),
+        _ => None,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::test_support::errors;
+
+    const DATA: &str = "struct Data { value: Int; next: Data? }\n";
+
+    #[test]
+    fn returning_a_parameter_is_allowed() {
+        let src = format!("{DATA}@nogc fun pick(a: Data, b: Data) -> Data {{ if (a.value > 0) {{ return a; }} b }}\n\
+                           @nogc fun f(d: Data) -> Data {{ let e = pick(d, d); e }}");
+        assert!(errors(&src).is_empty(), "{:?}", errors(&src));
+    }
+
+    #[test]
+    fn returning_a_local_is_reported() {
+        let src = format!("{DATA}@nogc fun f() -> Data {{ let d = Data {{ value: 1, next: null }}; d }}");
+        assert_eq!(
+            errors(&src),
+            ["cannot return a reference to local 'd'", "'d' is declared here and dropped when the function returns"]
+        );
+    }
+
+    #[test]
+    fn call_results_borrow_from_their_arguments() {
+        let src = format!("{DATA}@nogc fun id(x: Data) -> Data {{ x }}\n\
+                           @nogc fun value(x: Data) -> Int {{ x.value }}\n\
+                           @nogc fun f(p: Data) -> Data {{ let d = Data {{ value: value(p), next: null }}; let q = id(p); id(d) }}");
+        assert_eq!(
+            errors(&src),
+            ["cannot return a reference to local 'd'", "'d' is declared here and dropped when the function returns"]
+        );
+    }
+
+    #[test]
+    fn results_of_collected_functions_borrow_from_their_arguments() {
+        let src = format!("{DATA}fun either(a: Data, b: Data, c: Bool) -> Data {{ if (c) {{ return a; }} b }}\n\
+                           @nogc fun f(a: Data) -> Data {{ let local = Data {{ value: 1, next: null }}; either(a, local, true) }}");
+        assert_eq!(
+            errors(&src),
+            ["cannot return a reference to local 'local'", "'local' is declared here and dropped when the function returns"]
+        );
+    }
+
+    #[test]
+    fn storing_an_inner_local_in_an_outer_binding_is_reported() {
+        let src = format!("{DATA}@nogc fun f(p: Data) -> Int {{\n\
+                               var r = p;\n\
+                               if (r.value > 0) {{ let t = Data {{ value: 2, next: null }}; r = t; }}\n\
+                               r.value\n\
+                           }}");
+        assert_eq!(
+            errors(&src),
+            ["'t' does not live long enough", "'t' is declared here and dropped at the end of its block"]
+        );
+    }
 }
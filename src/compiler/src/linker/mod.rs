@@ -10,6 +10,73 @@ pub struct LinkerConfig {
     pub lto: Option<LtoMode>,
     pub pgo_generate: bool,
     pub pgo_use: Option<PathBuf>,
+    pub crate_type: CrateType,
+    pub sanitizers: Vec<Sanitizer>,
+}
+
+/// What `korlang build` produces, chosen with `--crate-type`.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum CrateType {
+    Bin,
+    /// An archive holding the program and the runtime, for linking into a
+    /// C or C++ program.
+    StaticLib,
+    /// A shared library, loaded by the host at run time.
+    CDylib,
+}
+
+impl CrateType {
+    pub fn parse(s: &str) -> Option<Self> {
+        match s {
+            "bin" => Some(CrateType::Bin),
+            "staticlib" => Some(CrateType::StaticLib),
+            "cdylib" => Some(CrateType::CDylib),
+            _ => None,
+        }
+    }
+
+    pub fn is_library(self) -> bool {
+        self != CrateType::Bin
+    }
+
+    /// `lib<stem>.a` or `lib<stem>.so` (`.dylib` on macOS) for libraries.
+    pub fn default_output(self, stem: &str) -> PathBuf {
+        match self {
+            CrateType::Bin => PathBuf::from("a.out"),
+            CrateType::StaticLib => PathBuf::from(format!("lib{}.a", stem)),
+            CrateType::CDylib if cfg!(target_os = "macos") => PathBuf::from(format!("lib{}.dylib", stem)),
+            CrateType::CDylib => PathBuf::from(format!("lib{}.so", stem)),
+        }
+    }
+}
+
+/// A sanitizer chosen with `--sanitize`. Korlang code is instrumented for
+/// it (see `backend::sanitize`), and the C compiler driver links its
+/// runtime.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Sanitizer {
+    Address,
+    Undefined,
+    Thread,
+}
+
+impl Sanitizer {
+    pub fn parse(s: &str) -> Option<Self> {
+        match s {
+            "address" => Some(Sanitizer::Address),
+            "undefined" => Some(Sanitizer::Undefined),
+            "thread" => Some(Sanitizer::Thread),
+            _ => None,
+        }
+    }
+
+    pub fn name(self) -> &'static str {
+        match self {
+            Sanitizer::Address => "address",
+            Sanitizer::Undefined => "undefined",
+            Sanitizer::Thread => "thread",
+        }
+    }
 }
 
 #[derive(Debug, Clone, Copy)]
@@ -18,7 +85,17 @@ pub enum LtoMode {
     Thin,
 }
 
+/// A static library starts as a copy of the runtime archive (see
+/// `prepare_static_archive`); the program's object is then added to it.
 pub fn build_link_command(object_file: &Path, config: &LinkerConfig) -> Vec<String> {
+    if config.crate_type == CrateType::StaticLib {
+        return vec![
+            archiver(),
+            "rs".to_string(),
+            config.output.display().to_string(),
+            object_file.display().to_string(),
+        ];
+    }
     let mut cmd = vec![
         "cc".to_string(),
         object_file.display().to_string(),
@@ -26,6 +103,13 @@ pub fn build_link_command(object_file: &Path, config: &LinkerConfig) -> Vec<Stri
         "-o".to_string(),
         config.output.display().to_string(),
     ];
+    if config.crate_type == CrateType::CDylib {
+        cmd.insert(1, "-shared".to_string());
+    }
+    if !config.sanitizers.is_empty() {
+        let names: Vec<&str> = config.sanitizers.iter().map(|s| s.name()).collect();
+        cmd.push(format!("-fsanitize={}", names.join(",")));
+    }
     if let Some(mode) = config.lto {
         match mode {
             LtoMode::Full => cmd.push("-flto".to_string()),
@@ -41,3 +125,19 @@ pub fn build_link_command(object_file: &Path, config: &LinkerConfig) -> Vec<Stri
     cmd.extend(config.extra_args.iter().cloned());
     cmd
 }
+
+/// Copies the runtime archive to the output of a static library, so the
+/// archive is self-contained.
+pub fn prepare_static_archive(config: &LinkerConfig) -> std::io::Result<()> {
+    std::fs::copy(&config.runtime_lib, &config.output).map(|_| ())
+}
+
+/// GNU ar rebuilds the archive index through its LLVM plugin and drops the
+/// symbols of runtime members that plugin cannot read, so `llvm-ar` is
+/// preferred when it is installed.
+fn archiver() -> String {
+    let found = std::env::var_os("PATH")
+        .map(|paths| std::env::split_paths(&paths).any(|dir| dir.join("llvm-ar").is_file()))
+        .unwrap_or(false);
+    if found { "llvm-ar" } else { "ar" }.to_string()
+}
@@ -0,0 +1,158 @@
+//! Korlang symbol mangling, described in `docs/symbol-mangling.md`.
+//!
+//! Every function is emitted as `_K`, its path, its generic arguments and its
+//! parameter types, so methods of different types, overloads and generic
+//! specializations never share a symbol, and no Korlang function can clash
+//! with a C symbol. `main` keeps its name.
+
+use crate::types::Type;
+
+pub use korlang_demangle::{demangle, demangle_text};
+use korlang_demangle::PREFIX;
+
+/// Symbol of a function called `name`, declared on `receiver` if it is a
+/// method. Dots in `name` separate path segments, as in the static method
+/// `Point.origin`.
+pub fn function_symbol(receiver: Option<&Type>, name: &str, generic_args: &[Type], params: &[Type]) -> String {
+    if receiver.is_none() && name == "main" {
+        return name.to_string();
+    }
+    let mut out = String::from(PREFIX);
+    out.push('N');
+    match receiver {
+        Some(Type::Named(ty)) => push_ident(&mut out, ty),
+        Some(Type::Generic(ty, args)) => {
+            push_ident(&mut out, ty);
+            push_types('I', args, &mut out);
+        }
+        Some(other) => {
+            // Methods on builtin types such as `Int.hash` are qualified by the
+            // type's encoding.
+            out.push('Y');
+            encode(other, &mut out);
+        }
+        None => {}
+    }
+    for segment in name.split('.') {
+        push_ident(&mut out, segment);
+    }
+    out.push('E');
+    if !generic_args.is_empty() {
+        push_types('I', generic_args, &mut out);
+    }
+    push_types('Z', params, &mut out);
+    out
+}
+
+fn push_ident(out: &mut String, ident: &str) {
+    out.push_str(&ident.len().to_string());
+    out.push_str(ident);
+}
+
+fn push_types(open: char, tys: &[Type], out: &mut String) {
+    out.push(open);
+    for ty in tys {
+        encode(ty, out);
+    }
+    out.push('E');
+}
+
+fn encode(ty: &Type, out: &mut String) {
+    match ty {
+        Type::Int => out.push('l'),
+        Type::UInt => out.push('m'),
+        Type::Float => out.push('d'),
+        Type::Float32 => out.push('f'),
+        Type::SizedInt { bits, signed } => out.push(match (bits, signed) {
+            (8, true) => 'a',
+            (16, true) => 's',
+            (32, true) => 'i',
+            (8, false) => 'h',
+            (16, false) => 't',
+            (32, false) => 'j',
+            (_, true) => 'l',
+            (_, false) => 'm',
+        }),
+        Type::Bool => out.push('b'),
+        Type::Char => out.push('c'),
+        Type::String => out.push('S'),
+        Type::Unit => out.push('v'),
+        Type::Any => out.push('X'),
+        Type::Nothing => out.push('z'),
+        Type::Unknown => out.push('u'),
+        Type::Named(name) => push_ident(out, name),
+        Type::Generic(name, args) => {
+            push_ident(out, name);
+            push_types('I', args, out);
+        }
+        Type::Parameter(name) => {
+            out.push('P');
+            push_ident(out, name);
+        }
+        Type::Optional(inner) => {
+            out.push('O');
+            encode(inner, out);
+        }
+        Type::Array(inner) => {
+            out.push('A');
+            encode(inner, out);
+        }
+        Type::Tensor(inner, _) => {
+            out.push('W');
+            encode(inner, out);
+        }
+        Type::Tuple(elems) => push_types('T', elems, out),
+        Type::Func(params, ret) => {
+            out.push('F');
+            encode(ret, out);
+            for p in params {
+                encode(p, out);
+            }
+            out.push('E');
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn named(name: &str) -> Type {
+        Type::Named(name.to_string())
+    }
+
+    #[test]
+    fn functions_methods_and_generics_round_trip() {
+        let add = function_symbol(None, "add", &[], &[Type::Int, Type::Int]);
+        assert_eq!(add, "_KN3addEZllE");
+        assert_eq!(demangle(&add).as_deref(), Some("add(Int, Int)"));
+
+        let len = function_symbol(Some(&named("Point")), "len", &[], &[]);
+        assert_eq!(len, "_KN5Point3lenEZE");
+        assert_eq!(demangle(&len).as_deref(), Some("Point.len()"));
+
+        let origin = function_symbol(None, "Point.origin", &[], &[]);
+        assert_eq!(demangle(&origin).as_deref(), Some("Point.origin()"));
+
+        let boxed = Type::Generic("Box".to_string(), vec![Type::Parameter("T".to_string())]);
+        let get = function_symbol(Some(&boxed), "get", &[Type::Parameter("T".to_string())], &[Type::Optional(Box::new(Type::String))]);
+        assert_eq!(demangle(&get).as_deref(), Some("Box<T>.get<T>(String?)"));
+
+        let hash = function_symbol(Some(&Type::Int), "hash", &[], &[Type::Tuple(vec![Type::SizedInt { bits: 8, signed: false }])]);
+        assert_eq!(demangle(&hash).as_deref(), Some("Int.hash((UInt8,))"));
+
+        assert_eq!(function_symbol(None, "main", &[], &[]), "main");
+        assert_ne!(
+            function_symbol(Some(&named("A")), "area", &[], &[]),
+            function_symbol(Some(&named("B")), "area", &[], &[])
+        );
+    }
+
+    #[test]
+    fn demangles_symbols_inside_text() {
+        let sym = function_symbol(None, "parse", &[], &[Type::Array(Box::new(Type::Char))]);
+        let frame = format!("  3: {}+0x1c\n  4: main\n  5: _KNotasymbol", sym);
+        assert_eq!(demangle_text(&frame), "  3: parse([Char])+0x1c\n  4: main\n  5: _KNotasymbol");
+        assert_eq!(demangle("strlen"), None);
+    }
+}
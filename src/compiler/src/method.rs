@@ -0,0 +1,220 @@
+use crate::ast::{Expr, Item, SelfParam};
+use crate::diag::{Diagnostic, Span};
+use crate::sema::Sema;
+use crate::specialization::{Resolution, SpecQuery};
+use crate::types::Type;
+
+impl Sema {
+    /// `Point.origin()` calls the static method `Point.origin` when `Point` is
+    /// a type rather than a value in scope. Returns `None` for ordinary
+    /// method calls.
+    pub(crate) fn check_static_call(&mut self, target: &Expr, name: &str, args: &[Expr], span: Span, nogc: bool) -> Option<Type> {
+        let Expr::Ident(ty, ty_span) = target else { return None };
+        if self.is_local(ty) {
+            return None;
+        }
+        let qualified = format!("{}.{}", ty, name);
+        if self.functions.contains_key(&qualified) {
+            let call = Expr::Call { callee: Box::new(Expr::Ident(qualified, *ty_span)), args: args.to_vec(), span };
+            return Some(self.check_expr_with(&call, nogc));
+        }
+        let is_type = self.structs.contains_key(ty) || self.enums.contains_key(ty);
+        let is_method = self
+            .specializations
+            .candidates(name, true)
+            .iter()
+            .any(|c| c.receiver.as_ref().and_then(type_name) == Some(ty.as_str()));
+        if !is_type || !is_method {
+            return None;
+        }
+        self.diags.push(Diagnostic::error(
+            format!("'{}' is a method of '{}' and needs a receiver: call it on a value", name, ty),
+            span,
+        ));
+        for arg in args {
+            self.check_expr_with(arg, nogc);
+        }
+        Some(Type::Unknown)
+    }
+
+    /// A method that takes `self` rather than `mut self` may not assign
+    /// through it.
+    pub(crate) fn check_self_assign(&mut self, place: &Expr, span: Span) {
+        if self.method_self == Some(SelfParam::Value) && is_self_place(place) {
+            self.diags.push(Diagnostic::error(
+                "cannot assign through 'self' in a method that takes 'self'; declare it 'mut self'",
+                span,
+            ));
+        }
+    }
+
+    /// Nor may it call a `mut self` method on `self`.
+    pub(crate) fn check_self_call(&mut self, target: &Expr, target_ty: &Type, name: &str, span: Span) {
+        if self.method_self != Some(SelfParam::Value) || !is_self_place(target) {
+            return;
+        }
+        if self.takes_mut_self(target_ty, name) {
+            self.diags.push(Diagnostic::error(
+                format!("cannot call 'mut self' method '{}' on 'self' in a method that takes 'self'", name),
+                span,
+            ));
+        }
+    }
+
+    /// A `mut self` method needs a receiver declared with `var`: calling one
+    /// on a `let` binding, or on a field of one, is an error.
+    pub(crate) fn check_mut_receiver(&mut self, target: &Expr, target_ty: &Type, name: &str, span: Span) {
+        let Some(binding) = root_binding(target) else { return };
+        let immutable = self
+            .scopes
+            .iter()
+            .rev()
+            .find(|s| s.vars.contains_key(binding))
+            .is_some_and(|s| s.immutable.contains(binding));
+        if immutable && self.takes_mut_self(target_ty, name) {
+            self.diags.push(Diagnostic::error(
+                format!("cannot call 'mut self' method '{}' on '{}', which is not declared with 'var'", name, binding),
+                span,
+            ));
+        }
+    }
+
+    fn takes_mut_self(&self, target_ty: &Type, name: &str) -> bool {
+        let query = SpecQuery { generic_args: &[], receiver: Some(target_ty), args: None };
+        matches!(
+            self.specializations.find_best_match(name, &query),
+            Resolution::Selected(spec, _) if spec.self_param == SelfParam::Mut
+        )
+    }
+
+    /// Static and instance methods share the symbol `Type.name`, so a type
+    /// cannot have both under one name.
+    pub(crate) fn check_method_names(&mut self, items: &[Item]) {
+        for item in items {
+            let Item::Fun(f) = item else { continue };
+            let Some((ty, name)) = f.name.split_once('.').filter(|_| f.receiver.is_none()) else { continue };
+            let clash = items.iter().any(|other| match other {
+                Item::Fun(m) => m.name == name && m.receiver.as_ref().map(Type::from_type_ref).as_ref().and_then(type_name) == Some(ty),
+                _ => false,
+            });
+            if clash {
+                self.diags.push(Diagnostic::error(
+                    format!("'{}' is declared both as a static method and as a method taking 'self'", f.name),
+                    f.span,
+                ));
+            }
+        }
+    }
+}
+
+pub(crate) fn type_name(ty: &Type) -> Option<&str> {
+    match ty {
+        Type::Named(name) | Type::Generic(name, _) => Some(name),
+        _ => None,
+    }
+}
+
+/// `self`, or a field or element reached through it.
+fn is_self_place(expr: &Expr) -> bool {
+    match expr {
+        Expr::Ident(name, _) => name == "self",
+        Expr::Member { target, .. } | Expr::Index { target, .. } => is_self_place(target),
+        _ => false,
+    }
+}
+
+/// The variable a place is reached through.
+fn root_binding(expr: &Expr) -> Option<&str> {
+    match expr {
+        Expr::Ident(name, _) => Some(name),
+        Expr::Member { target, .. } | Expr::Index { target, .. } => root_binding(target),
+        _ => None,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::test_support::errors;
+
+    #[test]
+    fn impl_blocks_declare_static_and_instance_methods() {
+        let src = "struct Point { x: Int; y: Int; }\n\
+                   struct Size { w: Int; h: Int; }\n\
+                   impl Point {\n\
+                       fun origin() -> Point { Point { x: 0, y: 0 } }\n\
+                       fun area(self) -> Int { self.x * self.y }\n\
+                       fun shift(mut self, dx: Int) { self.x += dx; }\n\
+                   }\n\
+                   impl Size { fun area(self) -> Int { self.w * self.h } }\n\
+                   fun Size.grow(n: Int) { self.w = self.w + n; }\n\
+                   fun f() -> Int {\n\
+                       var p = Point.origin();\n\
+                       p.shift(2);\n\
+                       let s = Size { w: 1, h: 2 };\n\
+                       s.grow(1);\n\
+                       p.area() + s.area()\n\
+                   }";
+        assert!(errors(src).is_empty(), "{:?}", errors(src));
+    }
+
+    #[test]
+    fn self_methods_may_not_mutate_self() {
+        let src = "struct Counter { n: Int; }\n\
+                   impl Counter {\n\
+                       fun bump(mut self) { self.n += 1; }\n\
+                       fun peek(self) -> Int { self.n = 0; self.bump(); self.n }\n\
+                   }";
+        assert_eq!(
+            errors(src),
+            [
+                "cannot assign through 'self' in a method that takes 'self'; declare it 'mut self'",
+                "cannot call 'mut self' method 'bump' on 'self' in a method that takes 'self'",
+            ]
+        );
+    }
+
+    #[test]
+    fn mut_self_methods_need_a_var_receiver() {
+        let src = "struct Point { x: Int; }\n\
+                   struct Line { a: Point; }\n\
+                   impl Point { fun shift(mut self, dx: Int) { self.x += dx; } fun get(self) -> Int { self.x } }\n\
+                   fun f(q: Point) -> Int {\n\
+                       let p = Point { x: 1 };\n\
+                       p.shift(1);\n\
+                       let l = Line { a: p };\n\
+                       l.a.shift(1);\n\
+                       var m = Point { x: 2 };\n\
+                       m.shift(1);\n\
+                       let mut n = Point { x: 3 };\n\
+                       n.shift(1);\n\
+                       q.shift(1);\n\
+                       if (true) { var p = Point { x: 4 }; p.shift(1); }\n\
+                       p.get() + m.get()\n\
+                   }";
+        assert_eq!(
+            errors(src),
+            [
+                "cannot call 'mut self' method 'shift' on 'p', which is not declared with 'var'",
+                "cannot call 'mut self' method 'shift' on 'l', which is not declared with 'var'",
+            ]
+        );
+    }
+
+    #[test]
+    fn rejects_receiverless_calls_and_clashing_names() {
+        let src = "struct Point { x: Int; }\n\
+                   impl Point {\n\
+                       fun get(self) -> Int { self.x }\n\
+                       fun make() -> Point { Point { x: 1 } }\n\
+                   }\n\
+                   fun Point.make() -> Point { self }\n\
+                   fun f(p: Point) -> Int { Point.get(p) }";
+        assert_eq!(
+            errors(src),
+            [
+                "'Point.make' is declared both as a static method and as a method taking 'self'",
+                "'get' is a method of 'Point' and needs a receiver: call it on a value",
+            ]
+        );
+    }
+}
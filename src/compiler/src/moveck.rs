@@ -1,90 +1,262 @@
-use crate::ast::*;
+use crate::cfg::{BlockId, Cfg, Event, VarId};
 use crate::sema::{Type, Sema};
 use crate::diag::{Diagnostic, Span};
-use std::collections::HashSet;
+use std::collections::{HashMap, HashSet};
 
+/// Initialization state of one variable at a program point.
+#[derive(Debug, Clone, Copy)]
+enum VarState {
+    /// Not yet in scope on this path.
+    Uninit,
+    Init,
+    Moved(Span),
+    /// Moved on some incoming paths only.
+    MaybeMoved(Span),
+}
+
+impl VarState {
+    fn join(self, other: VarState) -> VarState {
+        use VarState::*;
+        match (self, other) {
+            (Uninit, s) | (s, Uninit) => s,
+            (Init, Init) => Init,
+            (Moved(a), Moved(_)) => Moved(a),
+            (MaybeMoved(a), _) | (_, MaybeMoved(a)) | (Moved(a), Init) | (Init, Moved(a)) => MaybeMoved(a),
+        }
+    }
+
+    fn same(self, other: VarState) -> bool {
+        use VarState::*;
+        matches!((self, other), (Uninit, Uninit) | (Init, Init) | (Moved(_), Moved(_)) | (MaybeMoved(_), MaybeMoved(_)))
+    }
+}
+
+/// Forward dataflow over a function's CFG that tracks which variables hold a
+/// value. Copy types are never moved; everything else is moved by
+/// initializers, assignments, returns, struct fields and list elements.
+/// Call arguments are only lent to the callee.
 pub struct MoveChecker<'a> {
     sema: &'a mut Sema,
-    moved_locals: HashSet<String>,
+    cfg: &'a Cfg,
+    copy: Vec<bool>,
 }
 
 impl<'a> MoveChecker<'a> {
-    pub fn new(sema: &'a mut Sema) -> Self {
-        Self {
-            sema,
-            moved_locals: HashSet::new(),
-        }
+    pub fn new(sema: &'a mut Sema, cfg: &'a Cfg) -> Self {
+        let copy = cfg.vars.iter().map(|v| sema.is_shared(&v.ty)).collect();
+        Self { sema, cfg, copy }
     }
 
-    pub fn check_block(&mut self, block: &Block) {
-        for stmt in &block.stmts {
-            self.check_stmt(stmt);
+    pub fn check(&mut self) {
+        let order = self.cfg.reverse_postorder();
+        let preds = self.cfg.preds();
+        let mut outs: HashMap<BlockId, Vec<VarState>> = HashMap::new();
+        let mut changed = true;
+        while changed {
+            changed = false;
+            for &b in &order {
+                let mut state = self.entry_state(b, &preds, &outs);
+                self.transfer(b, &mut state, false);
+                let differs = match outs.get(&b) {
+                    Some(old) => old.iter().zip(&state).any(|(a, b)| !a.same(*b)),
+                    None => true,
+                };
+                if differs {
+                    outs.insert(b, state);
+                    changed = true;
+                }
+            }
         }
-        if let Some(tail) = &block.tail {
-            self.check_expr(tail);
+        for &b in &order {
+            let mut state = self.entry_state(b, &preds, &outs);
+            self.transfer(b, &mut state, true);
         }
     }
 
-    pub fn check_stmt(&mut self, stmt: &Stmt) {
-        match stmt {
-            Stmt::Var(v) => {
-                self.check_expr(&v.value);
-            }
-            Stmt::Expr(e, _) => {
-                self.check_expr(e);
-            }
-            Stmt::Return(e, _) => {
-                if let Some(expr) = e {
-                    self.check_expr(expr);
+    fn entry_state(&self, block: BlockId, preds: &[Vec<BlockId>], outs: &HashMap<BlockId, Vec<VarState>>) -> Vec<VarState> {
+        let mut state = vec![VarState::Uninit; self.cfg.vars.len()];
+        for p in &preds[block] {
+            if let Some(out) = outs.get(p) {
+                for (s, o) in state.iter_mut().zip(out) {
+                    *s = s.join(*o);
                 }
             }
-            Stmt::If(cond, then_block, else_stmt, _) => {
-                self.check_expr(cond);
-                self.check_block(then_block);
-                if let Some(else_s) = else_stmt {
-                    self.check_stmt(else_s);
-                }
-            }
-            Stmt::While(cond, body, _) => {
-                self.check_expr(cond);
-                self.check_block(body);
-            }
-            Stmt::Block(b) => {
-                self.check_block(b);
-            }
-            _ => {}
         }
+        state
     }
 
-    pub fn check_expr(&mut self, expr: &Expr) {
-        match expr {
-            Expr::Ident(name, span) => {
-                if self.moved_locals.contains(name) {
-                    self.sema.diags.push(Diagnostic::error(
-                        format!("value '{}' used here after move", name),
-                        *span,
-                    ));
+    fn transfer(&mut self, block: BlockId, state: &mut [VarState], report: bool) {
+        let in_loop = self.cfg.blocks[block].loop_depth > 0;
+        for event in &self.cfg.blocks[block].events {
+            match *event {
+                Event::Def(v) | Event::Assign(v, _) => state[v] = VarState::Init,
+                Event::Use(v, span) | Event::Borrow(v, span) => {
+                    if report {
+                        self.check_available(v, span, state, in_loop);
+                    }
                 }
-            }
-            Expr::Call { args, .. } => {
-                for arg in args {
-                    // Check before marking as moved
-                    self.check_expr(arg);
-                    
-                    if let Expr::Ident(name, _) = arg {
-                        // Mark as moved AFTER checking it
-                        self.moved_locals.insert(name.clone());
+                Event::Move(v, span) => {
+                    if report {
+                        self.check_available(v, span, state, in_loop);
+                    }
+                    if !self.copy[v] {
+                        state[v] = VarState::Moved(span);
                     }
                 }
+                Event::Release(_) => {}
             }
-            Expr::Assign { left, right, .. } => {
-                self.check_expr(right);
-                if let Expr::Ident(name, _) = &**left {
-                    // Re-assignment makes it available again
-                    self.moved_locals.remove(name);
+        }
+    }
+
+    fn check_available(&mut self, v: VarId, span: Span, state: &mut [VarState], in_loop: bool) {
+        if self.copy[v] {
+            return;
+        }
+        let name = &self.cfg.vars[v].name;
+        let (msg, moved_at) = match state[v] {
+            VarState::Moved(at) => (format!("use of moved value: '{}'", name), at),
+            VarState::MaybeMoved(at) if in_loop && same_span(at, span) => {
+                (format!("value '{}' is moved in a previous iteration of the loop", name), at)
+            }
+            VarState::MaybeMoved(at) => (format!("use of possibly-moved value: '{}'", name), at),
+            VarState::Init | VarState::Uninit => return,
+        };
+        self.sema.diags.push(Diagnostic::error(msg, span));
+        self.sema.diags.push(Diagnostic::note(format!("'{}' was moved here", name), moved_at));
+        // One report per move is enough; later uses on this path stay quiet.
+        state[v] = VarState::Init;
+    }
+}
+
+fn same_span(a: Span, b: Span) -> bool {
+    a.start.offset == b.start.offset && a.end.offset == b.end.offset
+}
+
+impl Sema {
+    /// Values of these types are copied instead of moved: primitives other
+    /// than `String`, and structs, enums and nullables built only from them.
+    /// Types that cannot be resolved are treated as Copy so they never cause
+    /// spurious move errors.
+    pub(crate) fn is_copy(&self, ty: &Type) -> bool {
+        self.is_copy_inner(ty, false, &mut HashSet::new())
+    }
+
+    /// Values of these types are used without being moved: Copy types, and
+    /// recursive structs, which live on the heap and are shared by
+    /// reference wherever their fields are otherwise Copy.
+    pub(crate) fn is_shared(&self, ty: &Type) -> bool {
+        self.is_copy_inner(ty, true, &mut HashSet::new())
+    }
+
+    fn is_copy_inner(&self, ty: &Type, shared: bool, visiting: &mut HashSet<String>) -> bool {
+        match ty {
+            Type::Int | Type::UInt | Type::Float | Type::Bool | Type::Char | Type::Unit | Type::Nothing => true,
+            Type::SizedInt { .. } | Type::Float32 => true,
+            Type::Unknown | Type::Any | Type::Parameter(_) | Type::Func(..) => true,
+            Type::String | Type::Array(_) | Type::Tensor(..) => false,
+            Type::Optional(inner) => self.is_copy_inner(inner, shared, visiting),
+            Type::Tuple(elems) => elems.iter().all(|e| self.is_copy_inner(e, shared, visiting)),
+            Type::Named(name) | Type::Generic(name, _) => {
+                let args: &[Type] = match ty {
+                    Type::Generic(_, args) => args,
+                    _ => &[],
+                };
+                if name == "List" {
+                    return false;
+                }
+                if !visiting.insert(name.clone()) {
+                    // A recursive type holds itself by reference.
+                    return shared;
                 }
+                let copy = if let Some(s) = self.structs.get(name) {
+                    let subst: HashMap<String, Type> =
+                        s.generic_params.iter().map(|p| p.name.clone()).zip(args.iter().cloned()).collect();
+                    s.fields.iter().all(|f| self.is_copy_inner(&Type::from_type_ref(&f.ty).substitute(&subst), shared, visiting))
+                } else if let Some(e) = self.enums.get(name) {
+                    e.variants.iter().all(|v| v.payload.is_empty())
+                } else {
+                    !(self.interface_system.interfaces.contains_key(name) || self.sealed_types.contains_key(name))
+                };
+                visiting.remove(name);
+                copy
             }
-            _ => {}
         }
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use crate::test_support::errors;
+
+    #[test]
+    fn primitives_are_copied() {
+        let src = "fun f(n: Int) -> Int { let a = n; let b = n; a + b + n }";
+        assert!(errors(src).is_empty(), "{:?}", errors(src));
+    }
+
+    #[test]
+    fn conditional_move_is_reported_after_the_join() {
+        let src = "fun f(c: Bool, s: String) -> String {\n\
+                       if (c) { let t = s; } else { }\n\
+                       s\n\
+                   }";
+        assert_eq!(errors(src), ["use of possibly-moved value: 's'", "'s' was moved here"]);
+    }
+
+    #[test]
+    fn move_inside_loop_is_reported() {
+        let src = "fun f(s: String) -> Int {\n\
+                       var i = 0;\n\
+                       while (i < 3) { let t = s; i = i + 1; }\n\
+                       i\n\
+                   }";
+        assert_eq!(errors(src), ["value 's' is moved in a previous iteration of the loop", "'s' was moved here"]);
+    }
+
+    #[test]
+    fn assignment_reinitializes() {
+        let src = "fun f(s: String) -> String {\n\
+                       var a = s;\n\
+                       let b = a;\n\
+                       a = \"x\";\n\
+                       println(a);\n\
+                       b\n\
+                   }";
+        assert!(errors(src).is_empty(), "{:?}", errors(src));
+    }
+
+    #[test]
+    fn only_list_push_keeps_its_argument() {
+        let src = "struct Log { count: Int; }\n\
+                   impl Log { fun push(self, s: String) -> Int { self.count } }\n\
+                   fun f(log: Log, s: String) -> String {\n\
+                       log.push(s);\n\
+                       s\n\
+                   }\n\
+                   fun g(s: String) -> String {\n\
+                       var xs: [String] = [];\n\
+                       xs.push(s);\n\
+                       s\n\
+                   }";
+        assert_eq!(errors(src), ["use of moved value: 's'", "'s' was moved here"]);
+    }
+
+    #[test]
+    fn struct_literal_fields_borrow_before_moving() {
+        let src = "struct View { base: String; length: Int; }\n\
+                   fun f(s: String) -> View { View { base: s, length: s.len() } }\n\
+                   fun g(s: String) -> String { let v = View { base: s, length: 0 }; s }";
+        assert_eq!(errors(src), ["use of moved value: 's'", "'s' was moved here"]);
+    }
+
+    #[test]
+    fn recursive_structs_are_shared() {
+        let src = "struct Node { value: Int; next: Node? }\n\
+                   fun f() -> Int {\n\
+                       let tail = Node { value: 2, next: null };\n\
+                       let head = Node { value: 1, next: tail };\n\
+                       head.value + tail.value\n\
+                   }";
+        assert!(errors(src).is_empty(), "{:?}", errors(src));
+    }
+}
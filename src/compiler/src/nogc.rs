@@ -1,78 +1,457 @@
 use crate::ast::*;
+use crate::lifetime::LifetimeChecker;
+use crate::method::type_name;
 use crate::sema::{Sema, Type};
 use crate::diag::{Diagnostic, Span};
+use std::collections::{HashMap, HashSet};
 
+/// Something a function body does, in source order.
+#[derive(Debug, Clone)]
+enum Site {
+    /// A GC allocation. `stack` marks struct literals, which only reach the
+    /// heap when escape analysis finds them escaping.
+    Alloc { what: &'static str, span: Span, stack: bool },
+    Call { name: String, span: Span },
+}
+
+/// One step of a call chain that ends in an allocation.
+#[derive(Debug, Clone)]
+struct Link {
+    func: String,
+    note: String,
+    span: Span,
+}
+
+/// Direct allocations and calls of every function in the program. Methods
+/// are nodes named `Type.name`, like static methods.
+pub struct CallGraph {
+    nodes: HashMap<String, Vec<Site>>,
+    nogc: HashSet<String>,
+    /// First allocating chain reachable from each function, if any.
+    chains: HashMap<String, Option<Vec<Link>>>,
+}
+
+impl CallGraph {
+    pub fn build(sema: &mut Sema, program: &Program) -> Self {
+        let mut nodes = HashMap::new();
+        let mut nogc = HashSet::new();
+        // Return types of methods and static methods, by node name.
+        let methods: HashMap<String, Type> = program
+            .items
+            .iter()
+            .filter_map(|item| match item {
+                Item::Fun(f) if f.receiver.is_some() || f.name.contains('.') => {
+                    Some((node_name(f), f.ret.as_ref().map(|r| sema.type_from_ref(r)).unwrap_or(Type::Unit)))
+                }
+                _ => None,
+            })
+            .collect();
+        for item in &program.items {
+            if let Item::Fun(f) = item {
+                let escapes = LifetimeChecker::escaping_allocations(sema, f);
+                let mut collector = SiteCollector::new(sema, &methods, f, escapes);
+                collector.block(&f.body);
+                nodes.insert(node_name(f), collector.sites);
+                if f.nogc {
+                    nogc.insert(node_name(f));
+                }
+            }
+            // C functions never reach the Korlang GC, so `@nogc` code can
+            // manage memory with `korlang_alloc`/`korlang_free` or `malloc`.
+            if let Item::Extern(e) = item {
+                nodes.entry(e.name.clone()).or_insert_with(Vec::new);
+            }
+        }
+        Self { nodes, nogc, chains: HashMap::new() }
+    }
+
+    /// The chain through which calling `name` reaches the GC. `@nogc`
+    /// callees are verified on their own and end the search.
+    fn chain(&mut self, name: &str, visiting: &mut HashSet<String>) -> Option<Vec<Link>> {
+        if let Some(chain) = self.chains.get(name) {
+            return chain.clone();
+        }
+        if self.nogc.contains(name) || !visiting.insert(name.to_string()) {
+            return None;
+        }
+        let sites = self.nodes.get(name).cloned().unwrap_or_default();
+        let mut found = None;
+        for site in sites {
+            match site {
+                Site::Alloc { what, span, stack } => {
+                    let note = if stack {
+                        format!("'{}' allocates here: {} escapes to the heap", name, what)
+                    } else {
+                        format!("'{}' allocates here: {}", name, what)
+                    };
+                    found = Some(vec![Link { func: name.to_string(), note, span }]);
+                }
+                Site::Call { name: callee, span } if !self.nodes.contains_key(&callee) => {
+                    let note = format!("'{}' calls non-@nogc function '{}' here", name, callee);
+                    found = Some(vec![Link { func: name.to_string(), note, span }]);
+                }
+                Site::Call { name: callee, span } => {
+                    if let Some(rest) = self.chain(&callee, visiting) {
+                        let note = format!("'{}' calls '{}' here", name, callee);
+                        let mut chain = vec![Link { func: name.to_string(), note, span }];
+                        chain.extend(rest);
+                        found = Some(chain);
+                    }
+                }
+            }
+            if found.is_some() {
+                break;
+            }
+        }
+        visiting.remove(name);
+        self.chains.insert(name.to_string(), found.clone());
+        found
+    }
+}
+
+/// Verifies that a `@nogc` function never reaches the GC, directly or
+/// through any function it calls.
 pub struct NoGcChecker<'a> {
     sema: &'a mut Sema,
+    graph: &'a mut CallGraph,
 }
 
 impl<'a> NoGcChecker<'a> {
-    pub fn new(sema: &'a mut Sema) -> Self {
-        Self { sema }
+    pub fn new(sema: &'a mut Sema, graph: &'a mut CallGraph) -> Self {
+        Self { sema, graph }
     }
 
     pub fn check_fun(&mut self, f: &FunDecl) {
-        if f.nogc {
-            self.check_block(&f.body);
+        if !f.nogc {
+            return;
+        }
+        let caller = node_name(f);
+        let sites = self.graph.nodes.get(&caller).cloned().unwrap_or_default();
+        for site in sites {
+            match site {
+                // Escaping struct literals are reported by the lifetime checker.
+                Site::Alloc { stack: true, .. } => {}
+                Site::Alloc { what, span, .. } => {
+                    self.sema.diags.push(Diagnostic::error(format!("allocation not allowed in @nogc context: {}", what), span));
+                }
+                Site::Call { name, span } if !self.graph.nodes.contains_key(&name) => {
+                    self.sema.diags.push(Diagnostic::error(format!("call to non-@nogc function '{}' from @nogc context", name), span));
+                }
+                Site::Call { name, span } => {
+                    let mut visiting = HashSet::from([caller.clone()]);
+                    if let Some(chain) = self.graph.chain(&name, &mut visiting) {
+                        self.report_chain(&caller, &name, span, &chain);
+                    }
+                }
+            }
+        }
+    }
+
+    fn report_chain(&mut self, caller: &str, callee: &str, span: Span, chain: &[Link]) {
+        self.sema.diags.push(Diagnostic::error(
+            format!("call to '{}' from @nogc function '{}' may allocate", callee, caller),
+            span,
+        ));
+        for link in chain {
+            self.sema.diags.push(Diagnostic::note(link.note.clone(), link.span));
+        }
+        let path: Vec<&str> = std::iter::once(caller).chain(chain.iter().map(|l| l.func.as_str())).collect();
+        self.sema.diags.push(Diagnostic::note(format!("call chain: {}", path.join(" -> ")), span));
+    }
+}
+
+/// The call-graph node of `f`.
+fn node_name(f: &FunDecl) -> String {
+    match f.receiver.as_ref().map(Type::from_type_ref).as_ref().and_then(type_name) {
+        Some(ty) => format!("{}.{}", ty, f.name),
+        None => f.name.clone(),
+    }
+}
+
+/// Walks a whole function body and records its allocation and call sites.
+/// Local types are tracked just far enough to resolve method calls.
+struct SiteCollector<'a> {
+    sema: &'a Sema,
+    methods: &'a HashMap<String, Type>,
+    locals: HashMap<String, Type>,
+    escapes: Vec<Span>,
+    sites: Vec<Site>,
+}
+
+impl<'a> SiteCollector<'a> {
+    fn new(sema: &'a Sema, methods: &'a HashMap<String, Type>, f: &FunDecl, escapes: Vec<Span>) -> Self {
+        let mut locals: HashMap<String, Type> = f.params.iter().map(|p| (p.name.clone(), sema.type_from_ref(&p.ty))).collect();
+        if let Some(recv) = &f.receiver {
+            locals.insert("self".to_string(), sema.type_from_ref(recv));
+        }
+        Self { sema, methods, locals, escapes, sites: Vec::new() }
+    }
+
+    /// The node of `target.name(..)`: a static method when `target` names a
+    /// type, else a method of the receiver's type. A receiver of unknown
+    /// type resolves only when one method has that name.
+    fn method(&self, target: &Expr, name: &str) -> Option<String> {
+        let ty = match target {
+            Expr::Ident(ty, _) if !self.locals.contains_key(ty) && self.sema.structs.contains_key(ty) => Type::Named(ty.clone()),
+            _ => match self.type_of(target) {
+                Type::Optional(inner) => *inner,
+                ty => ty,
+            },
+        };
+        if let Some(ty) = type_name(&ty) {
+            let key = format!("{}.{}", ty, name);
+            return self.methods.contains_key(&key).then_some(key);
+        }
+        if ty != Type::Unknown {
+            return None;
         }
+        let suffix = format!(".{}", name);
+        let mut matching = self.methods.keys().filter(|k| k.ends_with(&suffix));
+        match (matching.next(), matching.next()) {
+            (Some(key), None) => Some(key.clone()),
+            _ => None,
+        }
+    }
+
+    fn type_of(&self, expr: &Expr) -> Type {
+        match expr {
+            Expr::Literal(lit, _) => self.sema.type_of_literal(lit),
+            Expr::Ident(name, _) => self.locals.get(name).cloned().unwrap_or(Type::Unknown),
+            Expr::StructLit { name, .. } => Type::Named(name.clone()),
+            Expr::Call { callee, .. } => match &**callee {
+                Expr::Ident(name, _) => match self.sema.functions.get(name) {
+                    Some(Type::Func(_, ret)) => (**ret).clone(),
+                    _ => Type::Unknown,
+                },
+                Expr::Member { target, name, .. } => self
+                    .method(target, name)
+                    .and_then(|key| self.methods.get(&key).cloned())
+                    .unwrap_or(Type::Unknown),
+                _ => Type::Unknown,
+            },
+            Expr::Member { target, name, .. } => {
+                let ty = match self.type_of(target) {
+                    Type::Optional(inner) => *inner,
+                    ty => ty,
+                };
+                type_name(&ty)
+                    .and_then(|s| self.sema.structs.get(s))
+                    .and_then(|d| d.fields.iter().find(|f| &f.name == name))
+                    .map(|f| self.sema.type_from_ref(&f.ty))
+                    .unwrap_or(Type::Unknown)
+            }
+            _ => Type::Unknown,
+        }
+    }
+
+    fn alloc(&mut self, what: &'static str, span: Span) {
+        self.sites.push(Site::Alloc { what, span, stack: false });
     }
 
-    fn check_block(&mut self, block: &Block) {
+    fn block(&mut self, block: &Block) {
         for stmt in &block.stmts {
-            self.check_stmt(stmt);
+            self.stmt(stmt);
         }
         if let Some(tail) = &block.tail {
-            self.check_expr(tail);
+            self.expr(tail);
         }
     }
 
-    fn check_stmt(&mut self, stmt: &Stmt) {
+    fn stmt(&mut self, stmt: &Stmt) {
         match stmt {
             Stmt::Var(v) => {
-                self.check_expr(&v.value);
-            }
-            Stmt::Expr(e, _) => {
-                self.check_expr(e);
+                self.expr(&v.value);
+                let ty = match &v.ty {
+                    Some(ann) => self.sema.type_from_ref(ann),
+                    None => self.type_of(&v.value),
+                };
+                self.locals.insert(v.name.clone(), ty);
             }
+            Stmt::Expr(e, _) => self.expr(e),
+            Stmt::Return(Some(e), _) => self.expr(e),
             Stmt::If(cond, then_block, else_stmt, _) => {
-                self.check_expr(cond);
-                self.check_block(then_block);
+                self.expr(cond);
+                self.block(then_block);
                 if let Some(else_s) = else_stmt {
-                    self.check_stmt(else_s);
+                    self.stmt(else_s);
                 }
             }
-            Stmt::While(cond, body, _) => {
-                self.check_expr(cond);
-                self.check_block(body);
+            Stmt::While(cond, body, ..) => {
+                self.expr(cond);
+                self.block(body);
+            }
+            Stmt::For(_, iter, body, ..) => {
+                self.expr(iter);
+                self.block(body);
+            }
+            Stmt::Match(expr, arms, _) => {
+                self.expr(expr);
+                for arm in arms {
+                    self.expr(&arm.body);
+                }
             }
-            _ => {}
+            Stmt::Block(b) | Stmt::Defer(b, _) => self.block(b),
+            Stmt::Break(_, Some(e), _) => self.expr(e),
+            Stmt::Return(None, _) | Stmt::Break(_, None, _) | Stmt::Continue(..) => {}
         }
     }
 
-    fn check_expr(&mut self, expr: &Expr) {
+    fn expr(&mut self, expr: &Expr) {
         match expr {
-            Expr::Array(_, span) => {
-                self.sema.diags.push(Diagnostic::error("allocation not allowed in @nogc context", *span));
+            Expr::Literal(_, _) | Expr::Ident(_, _) | Expr::Instantiate { .. } => {}
+            Expr::StructLit { fields, span, .. } => {
+                if self.escapes.iter().any(|s| s.start.offset == span.start.offset) {
+                    self.sites.push(Site::Alloc { what: "struct literal", span: *span, stack: true });
+                }
+                for (_, value) in fields {
+                    self.expr(value);
+                }
+            }
+            Expr::Array(items, span) => {
+                self.alloc("array literal", *span);
+                for item in items {
+                    self.expr(item);
+                }
+            }
+            Expr::Tuple(items, _) => {
+                for item in items {
+                    self.expr(item);
+                }
+            }
+            Expr::Tensor(rows, span) => {
+                self.alloc("tensor literal", *span);
+                for item in rows.iter().flatten() {
+                    self.expr(item);
+                }
             }
-            Expr::StructLit { span, .. } => {
-                // Struct literals are okay if they are on stack, but currently, we treat them as managed
-                // if they are not explicitly handled.
-                // For now, let's flag all managed allocations.
-                self.sema.diags.push(Diagnostic::error("managed allocation not allowed in @nogc context", *span));
+            Expr::Interpolated { parts, span } => {
+                self.alloc("string interpolation", *span);
+                for p in parts {
+                    self.expr(p);
+                }
             }
             Expr::Call { callee, args, span } => {
-                if let Expr::Ident(name, _) = &**callee {
-                    if !self.sema.is_nogc_function(name) {
-                        self.sema.diags.push(Diagnostic::error(format!("call to non-@nogc function '{}' from @nogc context", name), *span));
+                match &**callee {
+                    Expr::Ident(name, _) | Expr::Instantiate { name, .. } => {
+                        if name != "staticAssert" {
+                            self.sites.push(Site::Call { name: name.clone(), span: *span });
+                        }
+                    }
+                    Expr::Member { target, name, .. } => {
+                        match self.method(target, name) {
+                            Some(method) => self.sites.push(Site::Call { name: method, span: *span }),
+                            None if name == "push" => self.alloc("list growth", *span),
+                            None => {}
+                        }
+                        self.expr(target);
                     }
+                    other => self.expr(other),
                 }
                 for arg in args {
-                    self.check_expr(arg);
+                    self.expr(arg);
                 }
             }
-            _ => {
-                // Recurse for nested expressions
+            Expr::Unary { expr, .. } | Expr::Cast { expr, .. } => self.expr(expr),
+            Expr::Binary { left, right, .. }
+            | Expr::Assign { left, right, .. }
+            | Expr::Range { start: left, end: right, .. } => {
+                self.expr(left);
+                self.expr(right);
             }
+            Expr::Member { target, .. } => self.expr(target),
+            Expr::Index { target, index, .. } => {
+                self.expr(target);
+                self.expr(index);
+            }
+            Expr::If { cond, then_block, else_block, .. } => {
+                self.expr(cond);
+                self.block(then_block);
+                self.block(else_block);
+            }
+            Expr::Match { expr, arms, .. } => {
+                self.expr(expr);
+                for arm in arms {
+                    self.expr(&arm.body);
+                }
+            }
+            Expr::Block(b) | Expr::Loop { body: b, .. } => self.block(b),
         }
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use crate::test_support::errors;
+
+    const NODE: &str = "struct Node { value: Int; next: Node? }\n";
+
+    #[test]
+    fn allocation_in_a_callee_is_reported_with_its_chain() {
+        let src = format!("{NODE}fun leaf() -> Int {{ let xs = [1, 2]; 0 }}\n\
+                           fun middle() -> Int {{ leaf() }}\n\
+                           @nogc fun f() -> Int {{ if (true) {{ return middle(); }} 0 }}");
+        assert_eq!(
+            errors(&src),
+            [
+                "call to 'middle' from @nogc function 'f' may allocate",
+                "'middle' calls 'leaf' here",
+                "'leaf' allocates here: array literal",
+                "call chain: f -> middle -> leaf",
+            ]
+        );
+    }
+
+    #[test]
+    fn stack_promotable_struct_literals_are_allowed() {
+        let src = format!("{NODE}fun sum(a: Int, b: Int) -> Int {{ let n = Node {{ value: a + b, next: null }}; n.value }}\n\
+                           @nogc fun f(x: Int) -> Int {{ let m = Node {{ value: x, next: null }}; sum(m.value, 1) }}");
+        assert!(errors(&src).is_empty(), "{:?}", errors(&src));
+    }
+
+    #[test]
+    fn escaping_struct_literal_in_a_callee_is_an_allocation() {
+        let src = format!("{NODE}fun make(v: Int) -> Node {{ Node {{ value: v, next: null }} }}\n\
+                           @nogc fun f() -> Int {{ match (1) {{ _ => make(1).value }} }}");
+        assert_eq!(
+            errors(&src),
+            [
+                "call to 'make' from @nogc function 'f' may allocate",
+                "'make' allocates here: struct literal escapes to the heap",
+                "call chain: f -> make",
+            ]
+        );
+    }
+
+    #[test]
+    fn extern_functions_can_be_called() {
+        let src = format!("{NODE}extern \"C\" {{\n\
+                               fun korlang_alloc(size: UInt, align: UInt) -> Node?;\n\
+                               fun korlang_free(n: Node?, size: UInt, align: UInt);\n\
+                           }}\n\
+                           fun owned() -> Node? {{ korlang_alloc(16, 8) }}\n\
+                           @nogc fun f() -> Int {{ let n = owned(); korlang_free(n, 16, 8); 0 }}");
+        assert!(errors(&src).is_empty(), "{:?}", errors(&src));
+    }
+
+    #[test]
+    fn allocation_in_a_method_is_reported() {
+        let src = "struct P { x: Int; }\n\
+                   impl P { fun grow(self) -> Int { let xs = [self.x, self.x]; xs[0] } }\n\
+                   @nogc fun f(p: P) -> Int { p.grow() }";
+        assert_eq!(
+            errors(src),
+            [
+                "call to 'P.grow' from @nogc function 'f' may allocate",
+                "'P.grow' allocates here: array literal",
+                "call chain: f -> P.grow",
+            ]
+        );
+    }
+
+    #[test]
+    fn string_literals_are_static() {
+        let src = "extern \"C\" { fun puts(s: CString) -> Int32; }\n\
+                   @nogc fun f() -> Int { puts(\"hello\"); 0 }";
+        assert!(errors(src).is_empty(), "{:?}", errors(src));
+    }
+}
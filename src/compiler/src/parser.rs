@@ -6,6 +6,12 @@ pub struct Parser {
     tokens: Vec<Token>,
     pos: usize,
     diags: Vec<Diagnostic>,
+    /// Labels of the loops being parsed, so `break name` can tell a label
+    /// from a value.
+    labels: Vec<String>,
+    /// Set while parsing the iterable of a `for`, where `{` opens the body
+    /// rather than a struct literal.
+    no_struct_lit: bool,
 }
 
 impl Parser {
@@ -14,12 +20,28 @@ impl Parser {
             tokens,
             pos: 0,
             diags: Vec::new(),
+            labels: Vec::new(),
+            no_struct_lit: false,
         }
     }
 
     pub fn parse_program(mut self) -> Result<Program, Vec<Diagnostic>> {
         let mut items = Vec::new();
         while !self.at_eof() {
+            if self.match_keyword("impl") {
+                match self.parse_impl() {
+                    Ok(methods) => items.extend(methods.into_iter().map(Item::Fun)),
+                    Err(_) => self.synchronize(),
+                }
+                continue;
+            }
+            if self.match_keyword("extern") {
+                match self.parse_extern() {
+                    Ok(decls) => items.extend(decls.into_iter().map(Item::Extern)),
+                    Err(_) => self.synchronize(),
+                }
+                continue;
+            }
             match self.parse_item() {
                 Ok(item) => items.push(item),
                 Err(_) => self.synchronize(),
@@ -34,6 +56,13 @@ impl Parser {
     }
 
     fn parse_item(&mut self) -> Result<Item, ()> {
+        if self.match_keyword("@export") {
+            let nogc = self.match_keyword("@nogc");
+            self.expect_keyword("fun")?;
+            let mut fun = self.parse_fun(nogc, false)?;
+            fun.export = true;
+            return Ok(Item::Fun(fun));
+        }
         if self.match_keyword("@nogc") {
             self.expect_keyword("fun")?;
             return self.parse_fun(true, false).map(Item::Fun);
@@ -96,8 +125,13 @@ impl Parser {
             } else {
                 None
             };
-            self.expect_kind(TokenKind::Semi)?;
-            methods.push(FunSig { name: m_name, params, ret, is_async, span: self.prev_span() });
+            let body = if self.check_kind(TokenKind::LBrace) {
+                Some(self.parse_block()?)
+            } else {
+                self.expect_kind(TokenKind::Semi)?;
+                None
+            };
+            methods.push(FunSig { name: m_name, params, ret, is_async, body, span: self.prev_span() });
         }
         let end = self.expect_kind(TokenKind::RBrace)?.span;
         Ok(InterfaceDecl { name, generic_params, methods, span: Span::new(start.start, end.end) })
@@ -125,11 +159,18 @@ impl Parser {
     fn parse_fun(&mut self, nogc: bool, is_async: bool) -> Result<FunDecl, ()> {
         let start = self.prev_span();
         
-        // Handle receiver for extension functions: fun Type.name(...)
+        // Handle receiver for extension functions: fun Type.name(...) or fun Box<T>.name<T>(...)
         let mut receiver = None;
+        let name_span = self.current_span();
         let mut name = self.expect_ident()?;
+        let receiver_args = if self.check_kind(TokenKind::Lt) {
+            self.try_parse_type_args(TokenKind::Dot)
+        } else {
+            None
+        };
         if self.match_kind(TokenKind::Dot) {
-            receiver = Some(TypeRef::Named(name, Vec::new(), self.prev_span()));
+            let span = Span::new(name_span.start, self.prev_span().end);
+            receiver = Some(TypeRef::Named(name, receiver_args.unwrap_or_default(), span));
             name = self.expect_ident()?;
         }
 
@@ -139,7 +180,11 @@ impl Parser {
             Vec::new()
         };
 
-        let params = self.parse_param_list()?;
+        let (mut self_param, params) = self.parse_fun_params()?;
+        // Extension functions may spell out `self`; it stays implicit there.
+        if receiver.is_some() && self_param == SelfParam::Value {
+            self_param = SelfParam::Implicit;
+        }
         let ret = if self.match_kind(TokenKind::Arrow) {
             Some(self.parse_type_ref()?)
         } else {
@@ -147,7 +192,66 @@ impl Parser {
         };
         let body = self.parse_block()?;
         let end = body.span.end;
-        Ok(FunDecl { receiver, name, generic_params, params, ret, body, nogc, is_async, span: Span::new(start.start, end) })
+        Ok(FunDecl {
+            receiver,
+            self_param,
+            name,
+            generic_params,
+            params,
+            ret,
+            body,
+            nogc,
+            is_async,
+            export: false,
+            span: Span::new(start.start, end),
+        })
+    }
+
+    /// `impl Type { fun ... }` groups methods with a type. Functions that take
+    /// `self` or `mut self` become methods on `Type`; the rest are static and
+    /// are called as `Type.name(...)`. Type arguments spelled as bare names
+    /// (`impl Box<T>`) are generic in every method, as in `fun Box<T>.get<T>()`.
+    fn parse_impl(&mut self) -> Result<Vec<FunDecl>, ()> {
+        let ty_start = self.current_span();
+        let ty_name = self.expect_ident()?;
+        let args = if self.check_kind(TokenKind::Lt) { self.parse_type_args()? } else { Vec::new() };
+        let ty_span = Span::new(ty_start.start, self.prev_span().end);
+        let impl_params: Vec<GenericParam> = args
+            .iter()
+            .filter_map(|arg| match arg {
+                TypeRef::Named(name, inner, span) if inner.is_empty() => {
+                    Some(GenericParam { name: name.clone(), constraints: Vec::new(), span: *span })
+                }
+                _ => None,
+            })
+            .collect();
+        self.expect_kind(TokenKind::LBrace)?;
+        let mut methods = Vec::new();
+        while !self.check_kind(TokenKind::RBrace) && !self.at_eof() {
+            let nogc = self.match_keyword("@nogc");
+            let is_async = !nogc && self.match_keyword("async");
+            self.expect_keyword("fun")?;
+            let mut fun = self.parse_fun(nogc, is_async)?;
+            if let Some(recv) = &fun.receiver {
+                let span = match recv {
+                    TypeRef::Named(_, _, span) => *span,
+                    _ => fun.span,
+                };
+                self.error_at(span, "methods in an 'impl' block take their receiver from the block");
+            }
+            let mut generic_params = impl_params.clone();
+            generic_params.append(&mut fun.generic_params);
+            fun.generic_params = generic_params;
+            if fun.self_param == SelfParam::Implicit {
+                fun.receiver = None;
+                fun.name = format!("{}.{}", ty_name, fun.name);
+            } else {
+                fun.receiver = Some(TypeRef::Named(ty_name.clone(), args.clone(), ty_span));
+            }
+            methods.push(fun);
+        }
+        self.expect_kind(TokenKind::RBrace)?;
+        Ok(methods)
     }
 
     fn parse_struct(&mut self) -> Result<StructDecl, ()> {
@@ -319,14 +423,24 @@ impl Parser {
         }
         if self.match_keyword("break") {
             let start = self.prev_span();
+            let label = self.parse_jump_label(start, |p, name| p.labels.iter().any(|l| l == name));
+            let value = if self.value_follows(start) { Some(self.parse_expr()?) } else { None };
             let end = self.consume_stmt_terminator();
-            return Ok(Stmt::Break(Span::new(start.start, end.end)));
+            return Ok(Stmt::Break(label, value, Span::new(start.start, end.end)));
         }
         if self.match_keyword("continue") {
             let start = self.prev_span();
+            let label = self.parse_jump_label(start, |_, _| true);
             let end = self.consume_stmt_terminator();
-            return Ok(Stmt::Continue(Span::new(start.start, end.end)));
+            return Ok(Stmt::Continue(label, Span::new(start.start, end.end)));
         }
+        if self.match_keyword("defer") {
+            let start = self.prev_span();
+            let body = self.parse_block()?;
+            let end = body.span.end;
+            return Ok(Stmt::Defer(body, Span::new(start.start, end)));
+        }
+        let label = if self.at_label(&["while", "for"]) { Some(self.parse_label()) } else { None };
         if self.match_keyword("if") {
             let start = self.prev_span();
             let cond = self.parse_expr()?;
@@ -353,18 +467,21 @@ impl Parser {
         if self.match_keyword("while") {
             let start = self.prev_span();
             let cond = self.parse_expr()?;
-            let body = self.parse_block()?;
+            let body = self.parse_loop_body(&label)?;
             let end = body.span.end;
-            return Ok(Stmt::While(cond, body, Span::new(start.start, end)));
+            return Ok(Stmt::While(cond, body, label, Span::new(start.start, end)));
         }
         if self.match_keyword("for") {
             let start = self.prev_span();
             let name = self.expect_ident()?;
             self.expect_keyword("in")?;
-            let iter = self.parse_expr()?;
-            let body = self.parse_block()?;
+            self.no_struct_lit = true;
+            let iter = self.parse_expr();
+            self.no_struct_lit = false;
+            let iter = iter?;
+            let body = self.parse_loop_body(&label)?;
             let end = body.span.end;
-            return Ok(Stmt::For(name, iter, body, Span::new(start.start, end)));
+            return Ok(Stmt::For(name, iter, body, label, Span::new(start.start, end)));
         }
         if self.match_keyword("match") {
             let start = self.prev_span();
@@ -402,7 +519,11 @@ impl Parser {
             is_mut = true;
         }
 
-        let name = self.expect_ident()?;
+        let (name, pattern) = if self.check_kind(TokenKind::LParen) {
+            (String::new(), Some(self.parse_pattern()?))
+        } else {
+            (self.expect_ident()?, None)
+        };
         let ty = if self.match_kind(TokenKind::Colon) {
             Some(self.parse_type_ref()?)
         } else {
@@ -411,7 +532,7 @@ impl Parser {
         self.expect_kind(TokenKind::Eq)?;
         let value = self.parse_expr()?;
         let end = self.consume_stmt_terminator();
-        Ok(VarDecl { mutable: is_mut, name, ty, value, span: Span::new(start.start, end.end) })
+        Ok(VarDecl { mutable: is_mut, name, pattern, ty, value, span: Span::new(start.start, end.end) })
     }
 
     fn parse_block(&mut self) -> Result<Block, ()> {
@@ -422,7 +543,7 @@ impl Parser {
             if self.check_keyword("let") || self.check_keyword("var") || self.check_keyword("return") ||
                 self.check_keyword("break") || self.check_keyword("continue") || self.check_keyword("if") ||
                 self.check_keyword("while") || self.check_keyword("for") || self.check_keyword("match") ||
-                self.check_kind(TokenKind::LBrace) {
+                self.check_keyword("defer") || self.at_label(&["while", "for"]) || self.check_kind(TokenKind::LBrace) {
                 stmts.push(self.parse_stmt()?);
                 continue;
             }
@@ -455,9 +576,9 @@ impl Parser {
             if self.match_kind(TokenKind::LParen) {
                 let mut args = Vec::new();
                 if !self.check_kind(TokenKind::RParen) {
-                    args.push(self.parse_expr()?);
+                    args.push(self.parse_nested_expr()?);
                     while self.match_kind(TokenKind::Comma) {
-                        args.push(self.parse_expr()?);
+                        args.push(self.parse_nested_expr()?);
                     }
                 }
                 let end = self.expect_kind(TokenKind::RParen)?.span;
@@ -466,7 +587,13 @@ impl Parser {
                 continue;
             }
             if self.match_kind(TokenKind::Dot) {
-                let name = self.expect_ident()?;
+                let name = match self.current().kind {
+                    TokenKind::IntLiteral(index) => {
+                        self.advance();
+                        index.to_string()
+                    }
+                    _ => self.expect_ident()?,
+                };
                 let span = Span::new(self.span_of(&lhs).start, self.prev_span().end);
                 lhs = Expr::Member { target: Box::new(lhs), name, span };
                 continue;
@@ -478,6 +605,12 @@ impl Parser {
                 lhs = Expr::Index { target: Box::new(lhs), index: Box::new(index), span };
                 continue;
             }
+            if self.match_keyword("as") {
+                let ty = self.parse_type_ref()?;
+                let span = Span::new(self.span_of(&lhs).start, self.prev_span().end);
+                lhs = Expr::Cast { expr: Box::new(lhs), ty, span };
+                continue;
+            }
             if self.match_kind(TokenKind::Question) {
                 // Treat try operator as a no-op for now to keep parsing self-hosted code.
                 continue;
@@ -496,12 +629,21 @@ impl Parser {
             lhs = match op {
                 InfixOp::Binary(op) => Expr::Binary { left: Box::new(lhs), op, right: Box::new(rhs), span },
                 InfixOp::Assign(op) => Expr::Assign { left: Box::new(lhs), op, right: Box::new(rhs), span },
+                InfixOp::Range(inclusive) => Expr::Range { start: Box::new(lhs), end: Box::new(rhs), inclusive, span },
             };
         }
 
         Ok(lhs)
     }
 
+    /// An expression inside brackets, where struct literals are allowed again.
+    fn parse_nested_expr(&mut self) -> Result<Expr, ()> {
+        let saved = std::mem::replace(&mut self.no_struct_lit, false);
+        let expr = self.parse_expr();
+        self.no_struct_lit = saved;
+        expr
+    }
+
     fn parse_prefix(&mut self) -> Result<Expr, ()> {
         let tok = self.current().clone();
         match tok.kind {
@@ -513,6 +655,18 @@ impl Parser {
                 self.advance();
                 Ok(Expr::Literal(Literal::Float(v), tok.span))
             }
+            // `255u8` is sugar for `255 as UInt8`; the lexer checked the range.
+            TokenKind::SuffixedInt(v, ty) => {
+                self.advance();
+                if past_signed_max(v, ty) {
+                    self.error_at(tok.span, &format!("integer literal out of range for {}", ty));
+                }
+                Ok(suffixed(Literal::Int(v), ty, tok.span))
+            }
+            TokenKind::SuffixedFloat(v, ty) => {
+                self.advance();
+                Ok(suffixed(Literal::Float(v), ty, tok.span))
+            }
             TokenKind::StringLiteral(s) => {
                 self.advance();
                 if self.check_kind(TokenKind::InterpStart) {
@@ -529,14 +683,25 @@ impl Parser {
                 self.advance();
                 Ok(Expr::Literal(Literal::Bool(b), tok.span))
             }
+            TokenKind::Identifier(_) if self.at_label(&["loop"]) => {
+                let label = self.parse_label();
+                self.parse_loop_expr(Some(label), tok.span)
+            }
+            TokenKind::Keyword("loop") => self.parse_loop_expr(None, tok.span),
             TokenKind::Identifier(name) => {
                 self.advance();
                 if name == "tensor" && self.check_kind(TokenKind::LBracket) {
                     return self.parse_tensor_literal(tok.span);
                 }
-                if self.check_kind(TokenKind::LBrace) {
+                if self.check_kind(TokenKind::LBrace) && !self.no_struct_lit {
                     return self.parse_struct_lit(name, tok.span);
                 }
+                if self.check_kind(TokenKind::Lt) {
+                    if let Some(type_args) = self.try_parse_type_args(TokenKind::LParen) {
+                        let span = Span::new(tok.span.start, self.prev_span().end);
+                        return Ok(Expr::Instantiate { name, type_args, span });
+                    }
+                }
                 Ok(Expr::Ident(name, tok.span))
             }
             TokenKind::Keyword("if") => self.parse_if_expr(),
@@ -548,9 +713,22 @@ impl Parser {
             }
             TokenKind::LParen => {
                 self.advance();
-                let expr = self.parse_expr()?;
-                self.expect_kind(TokenKind::RParen)?;
-                Ok(expr)
+                if self.check_kind(TokenKind::RParen) {
+                    let end = self.advance().span;
+                    return Ok(Expr::Tuple(Vec::new(), Span::new(tok.span.start, end.end)));
+                }
+                let first = self.parse_nested_expr()?;
+                if !self.check_kind(TokenKind::Comma) {
+                    self.expect_kind(TokenKind::RParen)?;
+                    return Ok(first);
+                }
+                // `(a,)` is a one-element tuple; `(a)` is just `a`.
+                let mut items = vec![first];
+                while self.match_kind(TokenKind::Comma) && !self.check_kind(TokenKind::RParen) {
+                    items.push(self.parse_nested_expr()?);
+                }
+                let end = self.expect_kind(TokenKind::RParen)?.span;
+                Ok(Expr::Tuple(items, Span::new(tok.span.start, end.end)))
             }
             TokenKind::LBracket => self.parse_array_literal(),
             TokenKind::LBrace => {
@@ -559,6 +737,13 @@ impl Parser {
             }
             TokenKind::Minus => {
                 self.advance();
+                // `-128i8` is a single literal, so the minimum of each type is writable.
+                if let TokenKind::SuffixedInt(v, ty) = self.current().kind {
+                    if !self.peek_kind(TokenKind::Dot) && !self.peek_kind(TokenKind::LParen) && !self.peek_kind(TokenKind::LBracket) {
+                        let span = Span::new(tok.span.start, self.advance().span.end);
+                        return Ok(suffixed(Literal::Int(v.wrapping_neg()), ty, span));
+                    }
+                }
                 let expr = self.parse_expr_bp(70)?;
                 let span = Span::new(tok.span.start, self.span_of(&expr).end);
                 Ok(Expr::Unary { op: UnaryOp::Neg, expr: Box::new(expr), span })
@@ -582,6 +767,58 @@ impl Parser {
         }
     }
 
+    fn parse_loop_expr(&mut self, label: Option<String>, start: Span) -> Result<Expr, ()> {
+        self.expect_keyword("loop")?;
+        let body = self.parse_loop_body(&label)?;
+        let span = Span::new(start.start, body.span.end);
+        Ok(Expr::Loop { label, body, span })
+    }
+
+    fn parse_loop_body(&mut self, label: &Option<String>) -> Result<Block, ()> {
+        self.labels.extend(label.clone());
+        let body = self.parse_block();
+        if label.is_some() {
+            self.labels.pop();
+        }
+        body
+    }
+
+    /// `name:` in front of one of `keywords`.
+    fn at_label(&self, keywords: &[&str]) -> bool {
+        matches!(self.current().kind, TokenKind::Identifier(_))
+            && self.peek_kind(TokenKind::Colon)
+            && matches!(self.tokens.get(self.pos + 2).map(|t| &t.kind), Some(TokenKind::Keyword(k)) if keywords.contains(k))
+    }
+
+    fn parse_label(&mut self) -> String {
+        let name = match &self.advance().kind {
+            TokenKind::Identifier(name) => name.clone(),
+            _ => unreachable!("checked by at_label"),
+        };
+        self.advance();
+        name
+    }
+
+    /// The label after `break`/`continue`, which must sit on the same line.
+    fn parse_jump_label(&mut self, jump: Span, is_label: fn(&Self, &str) -> bool) -> Option<String> {
+        match &self.current().kind {
+            TokenKind::Identifier(name) if self.current_span().start.line == jump.end.line && is_label(self, name) => {
+                let name = name.clone();
+                self.advance();
+                Some(name)
+            }
+            _ => None,
+        }
+    }
+
+    /// Whether `break` is followed by a value on the same line.
+    fn value_follows(&self, jump: Span) -> bool {
+        self.current_span().start.line == jump.end.line
+            && !self.check_kind(TokenKind::Semi)
+            && !self.check_kind(TokenKind::RBrace)
+            && self.is_expr_start_here()
+    }
+
     fn parse_if_expr(&mut self) -> Result<Expr, ()> {
         let start = self.current_span();
         self.advance();
@@ -694,6 +931,15 @@ impl Parser {
                 self.advance();
                 Ok(Pattern::Literal(Literal::Float(v), tok.span))
             }
+            // The scrutinee fixes the width, so the suffix only documents it.
+            TokenKind::SuffixedInt(v, _) => {
+                self.advance();
+                Ok(Pattern::Literal(Literal::Int(v), tok.span))
+            }
+            TokenKind::SuffixedFloat(v, _) => {
+                self.advance();
+                Ok(Pattern::Literal(Literal::Float(v), tok.span))
+            }
             TokenKind::StringLiteral(s) => {
                 self.advance();
                 Ok(Pattern::Literal(Literal::String(s), tok.span))
@@ -805,6 +1051,18 @@ impl Parser {
     }
 
     fn parse_type_ref(&mut self) -> Result<TypeRef, ()> {
+        // `*T` is `Ptr<T>`, and `*T?` a nullable one.
+        if self.match_kind(TokenKind::Star) {
+            let start = self.prev_span();
+            let pointee = self.parse_type_ref()?;
+            let span = Span::new(start.start, self.prev_span().end);
+            return Ok(match pointee {
+                TypeRef::Optional(inner, q) => {
+                    TypeRef::Optional(Box::new(TypeRef::Named("Ptr".to_string(), vec![*inner], span)), q)
+                }
+                other => TypeRef::Named("Ptr".to_string(), vec![other], span),
+            });
+        }
         let mut base = if self.match_kind(TokenKind::LParen) {
             let start = self.prev_span();
             let mut elems = Vec::new();
@@ -819,8 +1077,14 @@ impl Parser {
         } else if self.match_kind(TokenKind::LBracket) {
             let start = self.prev_span();
             let inner = self.parse_type_ref()?;
-            let end = self.expect_kind(TokenKind::RBracket)?.span;
-            TypeRef::Array(Box::new(inner), Span::new(start.start, end.end))
+            if self.match_kind(TokenKind::Comma) {
+                let len = self.parse_expr()?;
+                let end = self.expect_kind(TokenKind::RBracket)?.span;
+                TypeRef::FixedArray { elem: Box::new(inner), len: Box::new(len), span: Span::new(start.start, end.end) }
+            } else {
+                let end = self.expect_kind(TokenKind::RBracket)?.span;
+                TypeRef::Array(Box::new(inner), Span::new(start.start, end.end))
+            }
         } else {
             let name = self.parse_qualified_ident()?;
             let span = self.prev_span();
@@ -856,6 +1120,31 @@ impl Parser {
         Ok(base)
     }
 
+    /// Speculatively parses `<T, ...>` and keeps it only when `follow` comes next,
+    /// so `a < b` comparisons are left to the expression parser.
+    fn try_parse_type_args(&mut self, follow: TokenKind) -> Option<Vec<TypeRef>> {
+        let saved_pos = self.pos;
+        let saved_diags = self.diags.len();
+        match self.parse_type_args() {
+            Ok(args) if self.check_kind(follow) => Some(args),
+            _ => {
+                self.pos = saved_pos;
+                self.diags.truncate(saved_diags);
+                None
+            }
+        }
+    }
+
+    fn parse_type_args(&mut self) -> Result<Vec<TypeRef>, ()> {
+        self.expect_kind(TokenKind::Lt)?;
+        let mut args = vec![self.parse_type_ref()?];
+        while self.match_kind(TokenKind::Comma) {
+            args.push(self.parse_type_ref()?);
+        }
+        self.expect_kind(TokenKind::Gt)?;
+        Ok(args)
+    }
+
     fn parse_shape_ref(&mut self) -> Result<Vec<ShapeDim>, ()> {
         self.expect_kind(TokenKind::LBracket)?;
         let mut dims = Vec::new();
@@ -870,24 +1159,14 @@ impl Parser {
     }
 
     fn parse_shape_dim(&mut self) -> Result<ShapeDim, ()> {
-        let tok = self.current().clone();
-        match tok.kind {
-            TokenKind::IntLiteral(v) => {
-                self.advance();
-                Ok(ShapeDim::Int(v))
-            }
-            TokenKind::Identifier(name) => {
-                self.advance();
-                if name == "_" {
-                    Ok(ShapeDim::Unknown)
-                } else {
-                    Ok(ShapeDim::Ident(name))
-                }
-            }
-            _ => {
-                self.error_at(tok.span, "invalid shape dimension");
-                Err(())
-            }
+        if matches!(&self.current().kind, TokenKind::Identifier(name) if name == "_") {
+            self.advance();
+            return Ok(ShapeDim::Unknown);
+        }
+        match self.parse_expr()? {
+            Expr::Literal(Literal::Int(v), _) => Ok(ShapeDim::Int(v)),
+            Expr::Ident(name, _) => Ok(ShapeDim::Ident(name)),
+            expr => Ok(ShapeDim::Expr(expr)),
         }
     }
 
@@ -904,6 +1183,82 @@ impl Parser {
         Ok(params)
     }
 
+    /// `extern "C" fun f(...) -> R;`, or a block of such declarations sharing
+    /// one ABI: `extern "C" { fun f(); fun g(); }`.
+    fn parse_extern(&mut self) -> Result<Vec<ExternDecl>, ()> {
+        let start = self.prev_span();
+        let abi = match &self.current().kind {
+            TokenKind::StringLiteral(abi) => abi.clone(),
+            _ => {
+                self.error("expected an ABI string such as \"C\" after 'extern'");
+                return Err(());
+            }
+        };
+        self.advance();
+        if !self.match_kind(TokenKind::LBrace) {
+            self.expect_keyword("fun")?;
+            return Ok(vec![self.parse_extern_fun(&abi, start)?]);
+        }
+        let mut decls = Vec::new();
+        while !self.check_kind(TokenKind::RBrace) && !self.at_eof() {
+            self.expect_keyword("fun")?;
+            let fun_start = self.prev_span();
+            decls.push(self.parse_extern_fun(&abi, fun_start)?);
+        }
+        self.expect_kind(TokenKind::RBrace)?;
+        Ok(decls)
+    }
+
+    fn parse_extern_fun(&mut self, abi: &str, start: Span) -> Result<ExternDecl, ()> {
+        let name = self.expect_ident()?;
+        self.expect_kind(TokenKind::LParen)?;
+        let mut params = Vec::new();
+        let mut variadic = false;
+        while !self.check_kind(TokenKind::RParen) && !self.at_eof() {
+            if self.match_kind(TokenKind::Ellipsis) {
+                variadic = true;
+                break;
+            }
+            params.push(self.parse_param()?);
+            if !self.match_kind(TokenKind::Comma) {
+                break;
+            }
+        }
+        self.expect_kind(TokenKind::RParen)?;
+        let ret = if self.match_kind(TokenKind::Arrow) {
+            Some(self.parse_type_ref()?)
+        } else {
+            None
+        };
+        let end = self.expect_kind(TokenKind::Semi)?.span;
+        Ok(ExternDecl { abi: abi.to_string(), name, params, variadic, ret, span: Span::new(start.start, end.end) })
+    }
+
+    /// A function's parameter list, which may open with `self` or `mut self`.
+    fn parse_fun_params(&mut self) -> Result<(SelfParam, Vec<Param>), ()> {
+        self.expect_kind(TokenKind::LParen)?;
+        let is_self = |tok: Option<&Token>| matches!(tok.map(|t| &t.kind), Some(TokenKind::Identifier(s)) if s == "self");
+        let self_param = if self.check_keyword("mut") && is_self(self.tokens.get(self.pos + 1)) {
+            self.advance();
+            self.advance();
+            SelfParam::Mut
+        } else if is_self(Some(self.current())) && !self.peek_kind(TokenKind::Colon) {
+            self.advance();
+            SelfParam::Value
+        } else {
+            SelfParam::Implicit
+        };
+        let mut params = Vec::new();
+        if self_param == SelfParam::Implicit && !self.check_kind(TokenKind::RParen) {
+            params.push(self.parse_param()?);
+        }
+        while self.match_kind(TokenKind::Comma) {
+            params.push(self.parse_param()?);
+        }
+        self.expect_kind(TokenKind::RParen)?;
+        Ok((self_param, params))
+    }
+
     fn parse_param(&mut self) -> Result<Param, ()> {
         let start = self.current_span();
         let name = self.expect_ident()?;
@@ -967,6 +1322,8 @@ impl Parser {
             TokenKind::NotEq => Some((35, 36, InfixOp::Binary(BinaryOp::NotEq))),
             TokenKind::AndAnd => Some((30, 31, InfixOp::Binary(BinaryOp::And))),
             TokenKind::OrOr => Some((25, 26, InfixOp::Binary(BinaryOp::Or))),
+            TokenKind::DotDot => Some((22, 23, InfixOp::Range(false))),
+            TokenKind::DotDotEq => Some((22, 23, InfixOp::Range(true))),
             TokenKind::NullCoalesce => Some((20, 20, InfixOp::Binary(BinaryOp::NullCoalesce))),
             TokenKind::Pipe => Some((15, 16, InfixOp::Binary(BinaryOp::Pipe))),
             TokenKind::Arrow => Some((15, 16, InfixOp::Binary(BinaryOp::Arrow))),
@@ -996,6 +1353,7 @@ impl Parser {
         match expr {
             Expr::Literal(_, s) => *s,
             Expr::Ident(_, s) => *s,
+            Expr::Instantiate { span, .. } => *span,
             Expr::StructLit { span, .. } => *span,
             Expr::Unary { span, .. } => *span,
             Expr::Binary { span, .. } => *span,
@@ -1003,10 +1361,13 @@ impl Parser {
             Expr::Call { span, .. } => *span,
             Expr::Member { span, .. } => *span,
             Expr::Index { span, .. } => *span,
+            Expr::Range { span, .. } => *span,
+            Expr::Cast { span, .. } => *span,
             Expr::If { span, .. } => *span,
             Expr::Match { span, .. } => *span,
+            Expr::Loop { span, .. } => *span,
             Expr::Block(b) => b.span,
-            Expr::Array(_, s) => *s,
+            Expr::Array(_, s) | Expr::Tuple(_, s) => *s,
             Expr::Tensor(_, s) => *s,
             Expr::Interpolated { span, .. } => *span,
         }
@@ -1066,6 +1427,8 @@ impl Parser {
             || self.check_keyword("while")
             || self.check_keyword("for")
             || self.check_keyword("match")
+            || self.check_keyword("defer")
+            || self.at_label(&["while", "for"])
             || self.check_keyword("interface")
             || self.check_keyword("sealed")
             || self.check_kind(TokenKind::LBrace)
@@ -1083,6 +1446,8 @@ impl Parser {
             self.current().kind,
             TokenKind::IntLiteral(_)
                 | TokenKind::FloatLiteral(_)
+                | TokenKind::SuffixedInt(..)
+                | TokenKind::SuffixedFloat(..)
                 | TokenKind::StringLiteral(_)
                 | TokenKind::CharLiteral(_)
                 | TokenKind::BoolLiteral(_)
@@ -1093,7 +1458,7 @@ impl Parser {
                 | TokenKind::Minus
                 | TokenKind::Plus
                 | TokenKind::Not
-        ) || matches!(self.current().kind, TokenKind::Keyword("if" | "match" | "interface" | "sealed"))
+        ) || matches!(self.current().kind, TokenKind::Keyword("if" | "match" | "loop" | "interface" | "sealed"))
     }
 
     fn consume_stmt_terminator(&mut self) -> Span {
@@ -1170,4 +1535,21 @@ impl Parser {
 enum InfixOp {
     Binary(BinaryOp),
     Assign(AssignOp),
+    Range(bool),
+}
+
+fn suffixed(lit: Literal, ty: &str, span: Span) -> Expr {
+    let ty = TypeRef::Named(ty.to_string(), Vec::new(), span);
+    Expr::Cast { expr: Box::new(Expr::Literal(lit, span)), ty, span }
+}
+
+/// Signed suffixed literals one past the maximum are only valid negated.
+fn past_signed_max(v: i64, ty: &str) -> bool {
+    match ty {
+        "Int8" => v > i8::MAX as i64,
+        "Int16" => v > i16::MAX as i64,
+        "Int32" => v > i32::MAX as i64,
+        "Int64" => v == i64::MIN,
+        _ => false,
+    }
 }
@@ -1,6 +1,7 @@
-use crate::ast::Pattern;
+use crate::ast::{Item, MatchArm, Pattern};
 use crate::sema::{Type, Sema};
 use crate::diag::Span;
+use std::collections::HashMap;
 
 pub struct PatternChecker<'a> {
     sema: &'a mut Sema,
@@ -18,7 +19,17 @@ impl<'a> PatternChecker<'a> {
                 self.sema.unify(expected_ty, &lit_ty, *span);
             }
             Pattern::Ident(name, span) => {
-                self.sema.define_var(name, expected_ty.clone(), *span);
+                // A name that resolves to a variant is a constructor, not a binding.
+                if let Some((e, v)) = self.sema.lookup_variant(name, expected_ty) {
+                    self.expect_owner(&e, expected_ty, *span);
+                    let owner = owner_type(&e, expected_ty);
+                    let arity = self.sema.variant_payload(&e, &v, &owner).len();
+                    if arity != 0 {
+                        self.sema.report_error(format!("variant '{}.{}' expects {} payload values, found 0", e, v, arity), *span);
+                    }
+                } else {
+                    self.sema.define_var(name, expected_ty.clone(), *span);
+                }
             }
             Pattern::Wildcard(_) => {}
             Pattern::Tuple(parts, span) => {
@@ -30,44 +41,243 @@ impl<'a> PatternChecker<'a> {
                             self.check_pattern(p, et);
                         }
                     }
+                } else if matches!(expected_ty, Type::Unknown | Type::Any) {
+                    for p in parts {
+                        self.check_pattern(p, &Type::Unknown);
+                    }
                 } else {
                     self.sema.report_error(format!("expected tuple type for tuple pattern, got {:?}", expected_ty), *span);
                 }
             }
-            Pattern::Variant { name: _name, args, span: _span } => {
-                // Simplified variant check: assume it belongs to an enum
-                // In a real implementation, we would look up the enum definition
-                for arg in args {
-                    self.check_pattern(arg, &Type::Any);
+            Pattern::Variant { name, args, span } => {
+                if let Some((e, v)) = self.sema.lookup_variant(name, expected_ty) {
+                    self.expect_owner(&e, expected_ty, *span);
+                    let payload = self.sema.variant_payload(&e, &v, &owner_type(&e, expected_ty));
+                    if payload.len() != args.len() {
+                        self.sema.report_error(
+                            format!("variant '{}.{}' expects {} payload values, found {}", e, v, payload.len(), args.len()),
+                            *span,
+                        );
+                    }
+                    self.check_sub_patterns(args, &payload);
+                } else if self.sema.structs.contains_key(name) {
+                    // Positional form of a struct pattern: `Point(x, y)`.
+                    self.expect_owner(name, expected_ty, *span);
+                    let fields: Vec<Type> = self
+                        .sema
+                        .struct_field_types(name, &owner_type(name, expected_ty))
+                        .into_iter()
+                        .map(|(_, t)| t)
+                        .collect();
+                    if fields.len() != args.len() {
+                        self.sema.report_error(
+                            format!("struct '{}' has {} fields, but the pattern has {}", name, fields.len(), args.len()),
+                            *span,
+                        );
+                    }
+                    self.check_sub_patterns(args, &fields);
+                } else {
+                    let msg = match expected_ty {
+                        Type::Named(n) | Type::Generic(n, _) if self.sema.enums.contains_key(n) => {
+                            format!("enum '{}' has no variant named '{}'", n, name)
+                        }
+                        _ => format!("unknown variant '{}' in pattern", name),
+                    };
+                    self.sema.report_error(msg, *span);
+                    self.check_sub_patterns(args, &[]);
                 }
             }
-            Pattern::Struct { name: _name, fields, span: _span } => {
-                // Simplified struct check
-                for (_, p) in fields {
-                    self.check_pattern(p, &Type::Any);
+            Pattern::Struct { name, fields, span } => {
+                if !self.sema.structs.contains_key(name) {
+                    self.sema.report_error(format!("unknown struct '{}' in pattern", name), *span);
+                    for (_, p) in fields {
+                        self.check_pattern(p, &Type::Unknown);
+                    }
+                    return;
+                }
+                self.expect_owner(name, expected_ty, *span);
+                let declared = self.sema.struct_field_types(name, &owner_type(name, expected_ty));
+                let mut seen = std::collections::HashSet::new();
+                for (field, p) in fields {
+                    if !seen.insert(field.as_str()) {
+                        self.sema.report_error(format!("field '{}' is bound more than once", field), *span);
+                    }
+                    match declared.iter().find(|(f, _)| f == field) {
+                        Some((_, fty)) => self.check_pattern(p, fty),
+                        None => {
+                            self.sema.report_error(format!("struct '{}' has no field named '{}'", name, field), *span);
+                            self.check_pattern(p, &Type::Unknown);
+                        }
+                    }
                 }
             }
-            Pattern::Is(ty_ref, inner_pat, _span) => {
+            Pattern::Is(ty_ref, inner_pat, span) => {
                 let target_ty = self.sema.type_from_ref(ty_ref);
+                // `is Name(a, b)` destructures positionally, like a variant pattern.
+                if let (Type::Named(name), Pattern::Tuple(parts, _)) = (&target_ty, &**inner_pat) {
+                    let name = name.clone();
+                    let as_variant = Pattern::Variant { name, args: parts.clone(), span: *span };
+                    self.check_pattern(&as_variant, expected_ty);
+                    return;
+                }
                 // check if expected_ty can be cast to target_ty
                 // for simplicity, we assume success if they are named types or related
                 self.check_pattern(inner_pat, &target_ty);
             }
         }
     }
-    pub fn check_exhaustiveness(&mut self, matched_types: &[Type], sealed_name: &str, span: Span) {
-        let enforcer = crate::sealed::SealedEnforcer::new(self.sema);
-        let expected = enforcer.get_exhaustiveness_info(sealed_name);
-        let mut matched_names = std::collections::HashSet::new();
-        for ty in matched_types {
-            if let Type::Named(name) = ty {
-                matched_names.insert(name.clone());
-            }
+
+    /// Checks positional sub-patterns; any beyond the declared ones are still
+    /// bound (as `Unknown`) so the arm body does not report undefined names.
+    fn check_sub_patterns(&mut self, args: &[Pattern], tys: &[Type]) {
+        for (i, p) in args.iter().enumerate() {
+            let ty = tys.get(i).cloned().unwrap_or(Type::Unknown);
+            self.check_pattern(p, &ty);
         }
-        for name in expected {
-            if !matched_names.contains(&name) {
-                self.sema.report_error(format!("match is not exhaustive: missing variant '{}'", name), span);
-            }
+    }
+
+    /// A constructor of `owner` (an enum, struct or sealed child) must match a
+    /// scrutinee of that type, or of the sealed type that contains it.
+    fn expect_owner(&mut self, owner: &str, expected_ty: &Type, span: Span) {
+        if self.sema.permissive {
+            return;
+        }
+        let ok = match expected_ty {
+            Type::Named(n) | Type::Generic(n, _) => n == owner || self.sema.sealed_child(n, owner),
+            Type::Unknown | Type::Any => true,
+            _ => false,
+        };
+        if !ok {
+            self.sema.report_error(format!("mismatched pattern: expected {:?}, found a pattern of type '{}'", expected_ty, owner), span);
         }
     }
+
+    /// Reports missing patterns (error) and unreachable arms (warning) for a `match`.
+    pub fn check_exhaustiveness(&mut self, scrutinee_ty: &Type, arms: &[MatchArm], span: Span) {
+        let diags = crate::exhaustive::check_match(self.sema, scrutinee_ty, arms, span);
+        self.sema.diags.extend(diags);
+    }
+}
+
+impl Sema {
+    /// Resolves a pattern name to `(enum, variant)`. Accepts `Enum.Variant`, a bare
+    /// variant of the expected enum (or of an enum inside the expected sealed
+    /// type), and, when the expected type is unknown, a bare name that belongs
+    /// to exactly one enum.
+    pub(crate) fn lookup_variant(&self, name: &str, expected: &Type) -> Option<(String, String)> {
+        let has = |e: &str, v: &str| self.enums.get(e).is_some_and(|d| d.variants.iter().any(|x| x.name == v));
+        if let Some((prefix, v)) = name.rsplit_once('.') {
+            return has(prefix, v).then(|| (prefix.to_string(), v.to_string()));
+        }
+        let candidates: Vec<&String> = match expected {
+            Type::Named(n) | Type::Generic(n, _) if self.enums.contains_key(n) => vec![n],
+            Type::Named(n) if self.sealed_types.contains_key(n) => self.sealed_types[n]
+                .items
+                .iter()
+                .filter_map(|item| match item {
+                    Item::Enum(e) => Some(&e.name),
+                    _ => None,
+                })
+                .collect(),
+            Type::Unknown | Type::Any => self.enums.keys().collect(),
+            _ => Vec::new(),
+        };
+        let mut found = candidates.into_iter().filter(|e| has(e, name));
+        match (found.next(), found.next()) {
+            (Some(e), None) => Some((e.clone(), name.to_string())),
+            _ => None,
+        }
+    }
+
+    /// Payload types of a variant, with the enum's generic parameters replaced by
+    /// the arguments in `ty` (or `Unknown` when they are not known).
+    pub(crate) fn variant_payload(&self, enum_name: &str, variant: &str, ty: &Type) -> Vec<Type> {
+        let Some(decl) = self.enums.get(enum_name) else {
+            return Vec::new();
+        };
+        let subst = generic_subst(&decl.generic_params, enum_name, ty);
+        decl.variants
+            .iter()
+            .find(|v| v.name == variant)
+            .map(|v| v.payload.iter().map(|p| self.type_from_ref(p).substitute(&subst)).collect())
+            .unwrap_or_default()
+    }
+
+    /// Declared fields of a struct, in order, with generic parameters substituted.
+    pub(crate) fn struct_field_types(&self, struct_name: &str, ty: &Type) -> Vec<(String, Type)> {
+        let Some(decl) = self.structs.get(struct_name) else {
+            return Vec::new();
+        };
+        let subst = generic_subst(&decl.generic_params, struct_name, ty);
+        decl.fields
+            .iter()
+            .map(|f| (f.name.clone(), self.type_from_ref(&f.ty).substitute(&subst)))
+            .collect()
+    }
+
+    pub(crate) fn sealed_child(&self, sealed: &str, name: &str) -> bool {
+        self.sealed_types.get(sealed).is_some_and(|s| {
+            s.items.iter().any(|item| match item {
+                Item::Struct(st) => st.name == name,
+                Item::Enum(e) => e.name == name,
+                _ => false,
+            })
+        })
+    }
+}
+
+/// The scrutinee type itself when it is `owner` (keeping its generic arguments),
+/// otherwise `owner` without arguments.
+fn owner_type(owner: &str, expected_ty: &Type) -> Type {
+    match expected_ty {
+        Type::Named(n) | Type::Generic(n, _) if n == owner => expected_ty.clone(),
+        _ => Type::Named(owner.to_string()),
+    }
+}
+
+fn generic_subst(params: &[crate::ast::GenericParam], decl_name: &str, ty: &Type) -> HashMap<String, Type> {
+    let args = match ty {
+        Type::Generic(n, args) if n == decl_name && args.len() == params.len() => args.clone(),
+        _ => vec![Type::Unknown; params.len()],
+    };
+    params.iter().map(|p| p.name.clone()).zip(args).collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::test_support::errors;
+
+    const DECLS: &str = "enum Option<T> { Some(T); None; }\nstruct Point { x: Int; y: Int; }\n";
+
+    #[test]
+    fn variant_payloads_bind_substituted_types() {
+        let ok = format!("{}fun f(o: Option<Int>) -> Int {{ match (o) {{ Some(v) => v + 1; None => 0; }} }}", DECLS);
+        assert!(errors(&ok).is_empty(), "{:?}", errors(&ok));
+        let bad = format!("{}fun f(o: Option<String>) -> Int {{ match (o) {{ Some(v) => v * 2; None => 0; }} }}", DECLS);
+        assert_eq!(errors(&bad), ["expected numeric type"]);
+    }
+
+    #[test]
+    fn unknown_variants_and_wrong_arity_are_reported() {
+        let src = format!(
+            "{}fun f(o: Option<Int>) -> Int {{ match (o) {{ Som(v) => v; Some(a, b) => a; _ => 0; }} }}",
+            DECLS
+        );
+        assert_eq!(
+            errors(&src),
+            ["enum 'Option' has no variant named 'Som'", "variant 'Option.Some' expects 1 payload values, found 2"]
+        );
+    }
+
+    #[test]
+    fn struct_pattern_fields_are_checked() {
+        let src = format!(
+            "{}fun f(p: Point) -> Int {{ match (p) {{ Point {{ x, z }} => x; Pointt {{ x }} => x; }} }}",
+            DECLS
+        );
+        assert_eq!(
+            errors(&src),
+            ["struct 'Point' has no field named 'z'", "unknown struct 'Pointt' in pattern"]
+        );
+    }
 }
@@ -1,16 +1,33 @@
 use crate::ast::*;
-use crate::diag::{Diagnostic, Span};
+use crate::diag::{Diagnostic, DiagnosticLevel, Span};
 use crate::borrowck::BorrowChecker;
+use crate::cfg::Cfg;
+use crate::constexpr::{ConstValue, ConstexprEval};
 use crate::lifetime::LifetimeChecker;
 use crate::moveck::MoveChecker;
-use std::collections::HashMap;
+use crate::shape::DimSubst;
+use std::collections::{HashMap, HashSet};
 
 pub use crate::types::Type;
+use crate::types::Dim;
 use crate::interface::InterfaceSystem;
+use crate::specialization::{Resolution, SpecQuery, SpecializationEngine};
 
 #[derive(Default)]
 pub(crate) struct Scope {
     pub(crate) vars: HashMap<String, Type>,
+    /// Flow-sensitive types that shadow a variable's declared type, e.g. `T`
+    /// for a `T?` after a null check.
+    pub(crate) narrowed: HashMap<String, Type>,
+    /// Variables bound by `let` rather than `var` or `let mut`.
+    pub(crate) immutable: HashSet<String>,
+}
+
+/// A loop enclosing the code being checked. `breaks` collects the types of
+/// the values that leave a `loop` expression; other loops take no value.
+struct LoopScope {
+    label: Option<String>,
+    breaks: Option<Vec<Type>>,
 }
 
 pub struct Sema {
@@ -21,9 +38,22 @@ pub struct Sema {
     pub(crate) interface_system: InterfaceSystem,
     pub(crate) sealed_types: HashMap<String, SealedDecl>,
     pub(crate) structs: HashMap<String, StructDecl>,
+    pub(crate) enums: HashMap<String, EnumDecl>,
     pub(crate) fun_decls: HashMap<String, FunDecl>,
+    /// `extern` declarations by name.
+    pub(crate) externs: HashMap<String, ExternDecl>,
     pub(crate) nogc_functions: HashMap<String, bool>,
     pub(crate) templates: crate::templates::TemplateSystem,
+    pub(crate) specializations: SpecializationEngine,
+    pub(crate) consts: ConstexprEval,
+    /// Types of the local bindings of the function being checked, keyed by
+    /// name and declaration offset, for the ownership checkers.
+    pub(crate) binding_types: HashMap<(String, usize), Type>,
+    loops: Vec<LoopScope>,
+    /// Number of `defer` blocks being checked; jumps may not leave them.
+    defer_depth: usize,
+    /// How the method being checked takes `self`; `None` outside methods.
+    pub(crate) method_self: Option<SelfParam>,
     pub(crate) permissive: bool,
 }
 
@@ -37,9 +67,17 @@ impl Sema {
             interface_system: InterfaceSystem::new(),
             sealed_types: HashMap::new(),
             structs: HashMap::new(),
+            enums: HashMap::new(),
             fun_decls: HashMap::new(),
+            externs: HashMap::new(),
             nogc_functions: HashMap::new(),
             templates: crate::templates::TemplateSystem::new(),
+            specializations: SpecializationEngine::new(),
+            consts: ConstexprEval::new(),
+            binding_types: HashMap::new(),
+            loops: Vec::new(),
+            defer_depth: 0,
+            method_self: None,
             permissive: std::env::var("KORLANG_SEMA_PERMISSIVE").ok().as_deref() == Some("1"),
         };
         s.push_scope();
@@ -52,10 +90,28 @@ impl Sema {
         s.define_builtin("println", Type::Func(vec![Type::Any], Box::new(Type::Unit)));
         s.define_builtin("readLine", Type::Func(vec![], Box::new(Type::String)));
         s.define_builtin("uiWindowDemo", Type::Func(vec![], Box::new(Type::Int)));
+        s.define_builtin("staticAssert", Type::Func(vec![Type::Bool, Type::String], Box::new(Type::Unit)));
+        s.define_builtin("Range", Type::Named("Range".to_string()));
+        s.define_builtin("Ptr", Type::Named("Ptr".to_string()));
+        s.define_builtin("CString", Type::Named("CString".to_string()));
+        for item in crate::iter::prelude() {
+            if let Item::Interface(i) = item {
+                s.define_builtin(&i.name, Type::Named(i.name.clone()));
+                s.interface_system.register_interface(i);
+            }
+        }
         s
     }
 
-    pub fn check_program(mut self, program: &Program) -> Result<(), Vec<Diagnostic>> {
+    /// Checks `program`. Returns the warnings when there are no errors, and
+    /// every diagnostic, warnings included, otherwise.
+    pub fn check_program(mut self, program: &Program) -> Result<Vec<Diagnostic>, Vec<Diagnostic>> {
+        self.analyze(program)
+    }
+
+    /// Like `check_program`, but keeps the collected declarations around so
+    /// tools can query them afterwards (e.g. `interfaces().implementors(..)`).
+    pub fn analyze(&mut self, program: &Program) -> Result<Vec<Diagnostic>, Vec<Diagnostic>> {
         // Predeclare types (structs/enums/aliases/interfaces/sealed)
         for item in &program.items {
             match item {
@@ -63,10 +119,13 @@ impl Sema {
                     self.structs.insert(s.name.clone(), s.clone());
                     self.define_builtin(&s.name, Type::Named(s.name.clone()));
                 }
-                Item::Enum(e) => self.define_builtin(&e.name, Type::Named(e.name.clone())),
+                Item::Enum(e) => {
+                    self.enums.insert(e.name.clone(), e.clone());
+                    self.define_builtin(&e.name, Type::Named(e.name.clone()));
+                }
                 Item::TypeAlias(t) => self.define_builtin(&t.name, Type::Named(t.name.clone())),
                 Item::Interface(i) => {
-                    self.interface_system.interfaces.insert(i.name.clone(), i.clone());
+                    self.interface_system.register_interface(i.clone());
                     self.define_builtin(&i.name, Type::Named(i.name.clone()));
                 }
                 Item::Sealed(s) => {
@@ -79,7 +138,10 @@ impl Sema {
                                 self.structs.insert(st.name.clone(), st.clone());
                                 self.define_builtin(&st.name, Type::Named(st.name.clone()));
                             }
-                            Item::Enum(e) => self.define_builtin(&e.name, Type::Named(e.name.clone())),
+                            Item::Enum(e) => {
+                                self.enums.insert(e.name.clone(), e.clone());
+                                self.define_builtin(&e.name, Type::Named(e.name.clone()));
+                            }
                             _ => {}
                         }
                     }
@@ -87,6 +149,10 @@ impl Sema {
                 _ => {}
             }
         }
+        self.specializations = SpecializationEngine::from_program(program);
+        let overlaps = self.specializations.check_overlaps();
+        self.diags.extend(overlaps);
+        self.check_method_names(&program.items);
         for item in &program.items {
             if let Item::Fun(f) = item {
                 let sig = self.fun_sig(f);
@@ -101,6 +167,19 @@ impl Sema {
                 }
             }
         }
+        for item in &program.items {
+            if let Item::Extern(e) = item {
+                self.declare_extern(e);
+            }
+        }
+
+        self.consts = ConstexprEval::from_program(program);
+        let const_diags = self.consts.eval_consts(program);
+        self.diags.extend(const_diags);
+
+        // Conformance is settled before any body is checked, so struct values
+        // coerce to their interfaces regardless of declaration order.
+        self.check_implementations(&program.items);
 
         for item in &program.items {
             self.check_item(item);
@@ -108,13 +187,18 @@ impl Sema {
 
         self.validate_nogc(program);
 
-        if self.diags.is_empty() {
-            Ok(())
+        let diags = std::mem::take(&mut self.diags);
+        if diags.iter().any(|d| matches!(d.level, DiagnosticLevel::Error | DiagnosticLevel::Bug)) {
+            Err(diags)
         } else {
-            Err(self.diags)
+            Ok(diags)
         }
     }
 
+    pub fn interfaces(&self) -> &InterfaceSystem {
+        &self.interface_system
+    }
+
     fn check_item(&mut self, item: &Item) {
         match item {
             Item::Fun(f) => self.check_fun(f),
@@ -125,16 +209,19 @@ impl Sema {
             Item::Resource(_) => {}
             Item::Interface(i) => self.check_interface(i),
             Item::Sealed(s) => self.check_sealed(s),
+            Item::Extern(e) => self.check_extern(e),
             Item::Const(v) => {
                 let ty = self.check_expr(&v.value);
                 let final_ty = if let Some(ann) = &v.ty {
+                    self.check_annotation(ann, &v.value);
                     let ann_ty = self.type_from_ref(ann);
+                    let ty = self.adapt_literal(&ann_ty, &v.value, ty);
                     self.unify(&ann_ty, &ty, v.span);
                     ann_ty
                 } else {
                     ty
                 };
-                self.define_var(&v.name, final_ty, v.span);
+                self.bind_var_decl(v, final_ty);
             }
             Item::Stmt(s) => {
                 self.check_stmt(s);
@@ -147,24 +234,8 @@ impl Sema {
         for p in &s.generic_params {
             self.define_var(&p.name, Type::Parameter(p.name.clone()), p.span);
         }
-        for interface_ref in &s.implements {
-            let interface_ty = self.type_from_ref(interface_ref);
-            if let Type::Named(name) = interface_ty {
-                if let Some(interface) = self.interface_system.interfaces.get(&name).cloned() {
-                    for method in &interface.methods {
-                        // Check if struct has this method as an extension
-                        let has_method = self.extensions.get(&method.name).map(|exts| {
-                            exts.iter().any(|(recv, _)| recv == &Type::Named(s.name.clone()))
-                        }).unwrap_or(false);
-                        
-                        if !has_method {
-                            self.diags.push(Diagnostic::error(format!("struct '{}' does not implement method '{}' from interface '{}'", s.name, method.name, name), s.span));
-                        }
-                    }
-                } else {
-                    self.diags.push(Diagnostic::error(format!("interface '{}' not found", name), s.span));
-                }
-            }
+        for field in &s.fields {
+            self.check_type_ref(&field.ty);
         }
         self.pop_scope();
     }
@@ -174,7 +245,17 @@ impl Sema {
         for p in &i.generic_params {
             self.define_var(&p.name, Type::Parameter(p.name.clone()), p.span);
         }
-        // In a more complete implementation, we would also validate parameters/returns of method signatures here
+        for method in &i.methods {
+            for p in &method.params {
+                self.check_type_ref(&p.ty);
+            }
+            if let Some(ret) = &method.ret {
+                self.check_type_ref(ret);
+            }
+            if let Some(body) = &method.body {
+                self.check_default_method(i, method, body);
+            }
+        }
         self.pop_scope();
     }
 
@@ -194,14 +275,29 @@ impl Sema {
     }
 
     fn check_fun(&mut self, fun: &FunDecl) {
+        self.binding_types.clear();
+        self.method_self = fun.receiver.as_ref().map(|_| fun.self_param);
+        if fun.export {
+            self.check_export(fun);
+        }
         self.push_scope();
+        if let Some(recv) = &fun.receiver {
+            let recv_ty = self.type_from_ref(recv);
+            self.define_var("self", recv_ty, fun.span);
+        }
         for p in &fun.params {
+            self.check_type_ref(&p.ty);
             let t = self.type_from_ref(&p.ty);
             self.define_var(&p.name, t, p.span);
         }
         let body_ty = self.check_block_with(&fun.body, fun.nogc);
         if let Some(ret) = &fun.ret {
+            self.check_type_ref(ret);
             let ret_ty = self.type_from_ref(ret);
+            let body_ty = match &fun.body.tail {
+                Some(tail) => self.adapt_literal(&ret_ty, tail, body_ty),
+                None => body_ty,
+            };
             // Relax return check for main and common Int returners that end in Stmt
             if (fun.name == "main" || ret_ty == Type::Int) && body_ty == Type::Unit {
                 // OK: Codegen will handle default return 0 for Int
@@ -210,6 +306,18 @@ impl Sema {
             }
         }
         self.pop_scope();
+        self.check_ownership(fun);
+    }
+
+    /// Move and borrow checking over the function's control-flow graph.
+    fn check_ownership(&mut self, fun: &FunDecl) {
+        let cfg = Cfg::build(self, fun);
+        MoveChecker::new(self, &cfg).check();
+        BorrowChecker::new(self, &cfg).check();
+    }
+
+    pub(crate) fn binding_type(&self, name: &str, span: Span) -> Option<Type> {
+        self.binding_types.get(&(name.to_string(), span.start.offset)).cloned()
     }
 
     fn check_stmt(&mut self, stmt: &Stmt) -> Type {
@@ -221,64 +329,116 @@ impl Sema {
             Stmt::Var(v) => {
                 let ty = self.check_expr_with(&v.value, nogc);
                 let final_ty = if let Some(ann) = &v.ty {
+                    self.check_annotation(ann, &v.value);
                     let ann_ty = self.type_from_ref(ann);
+                    let ty = self.adapt_literal(&ann_ty, &v.value, ty);
                     self.unify(&ann_ty, &ty, v.span);
                     ann_ty
                 } else {
                     ty
                 };
-                self.define_var(&v.name, final_ty, v.span);
+                self.bind_var_decl(v, final_ty);
                 Type::Unit
             }
             Stmt::Expr(e, _) => {
                 self.check_expr_with(e, nogc);
                 Type::Unit
             }
-            Stmt::Return(expr, _) => {
+            Stmt::Return(expr, span) => {
+                if self.defer_depth > 0 {
+                    self.diags.push(Diagnostic::error("'return' cannot leave a defer block", *span));
+                }
                 if let Some(e) = expr {
                     self.check_expr_with(e, nogc)
                 } else {
                     Type::Unit
                 }
             }
-            Stmt::Break(_) | Stmt::Continue(_) => Type::Nothing,
+            // The parser only reads `break name` as a label when `name` labels an
+            // enclosing loop; a name that is bound to nothing was meant as one too.
+            Stmt::Break(None, Some(Expr::Ident(name, _)), span) if !self.is_bound(name) => {
+                self.jump_target(Some(name), "break", *span);
+                Type::Nothing
+            }
+            Stmt::Break(label, value, span) => {
+                let value_ty = value.as_ref().map(|e| self.check_expr_with(e, nogc));
+                if let Some(i) = self.jump_target(label.as_deref(), "break", *span) {
+                    match (&mut self.loops[i].breaks, value_ty) {
+                        (Some(breaks), ty) => breaks.push(ty.unwrap_or(Type::Unit)),
+                        (None, Some(_)) => {
+                            self.diags.push(Diagnostic::error("'break' with a value is only allowed in 'loop'", *span));
+                        }
+                        (None, None) => {}
+                    }
+                }
+                Type::Nothing
+            }
+            Stmt::Continue(label, span) => {
+                self.jump_target(label.as_deref(), "continue", *span);
+                Type::Nothing
+            }
+            Stmt::Defer(body, _) => {
+                let loops = std::mem::take(&mut self.loops);
+                self.defer_depth += 1;
+                self.check_block_with(body, nogc);
+                self.defer_depth -= 1;
+                self.loops = loops;
+                Type::Unit
+            }
             Stmt::If(cond, then_block, else_stmt, _) => {
                 let cond_ty = self.check_expr_with(cond, nogc);
                 self.unify(&Type::Bool, &cond_ty, self.span_of(cond));
+                let (on_true, on_false) = self.null_facts(cond);
+                self.push_scope();
+                self.narrow(&on_true);
                 let then_ty = self.check_block_with(then_block, nogc);
+                self.pop_scope();
                 let else_ty = if let Some(stmt) = else_stmt {
-                    self.check_stmt_with(stmt, nogc)
+                    self.push_scope();
+                    self.narrow(&on_false);
+                    let ty = self.check_stmt_with(stmt, nogc);
+                    self.pop_scope();
+                    ty
                 } else {
                     Type::Unit
                 };
+                // A branch that always leaves (`if (x == null) { return 0; }`)
+                // proves the opposite condition for the rest of the block.
+                if crate::typeck::block_diverges(then_block) {
+                    self.narrow(&on_false);
+                }
+                if else_stmt.as_deref().is_some_and(crate::typeck::stmt_diverges) {
+                    self.narrow(&on_true);
+                }
                 self.join_types(then_ty, else_ty)
             }
-            Stmt::While(cond, body, _) => {
+            Stmt::While(cond, body, label, _) => {
+                self.forget_assigned(body);
                 let cond_ty = self.check_expr_with(cond, nogc);
                 self.unify(&Type::Bool, &cond_ty, self.span_of(cond));
-                self.check_block_with(body, nogc);
+                let (on_true, _) = self.null_facts(cond);
+                self.push_scope();
+                self.narrow(&on_true);
+                self.check_loop_body(label, None, body, nogc);
+                self.pop_scope();
                 Type::Unit
             }
-            Stmt::For(name, iter, body, span) => {
+            Stmt::For(name, iter, body, label, span) => {
+                self.forget_assigned(body);
                 let iter_ty = self.check_expr_with(iter, nogc);
-                let elem = match iter_ty {
-                    Type::Array(t) => *t,
-                    _ => {
-                        self.diags.push(Diagnostic::error("for-in expects array", *span));
-                        Type::Unknown
-                    }
-                };
+                let elem = self.element_type(&iter_ty, self.span_of(iter));
                 self.push_scope();
                 self.define_var(name, elem, *span);
-                self.check_block_with(body, nogc);
+                self.check_loop_body(label, None, body, nogc);
                 self.pop_scope();
                 Type::Unit
             }
-            Stmt::Match(expr, arms, _span) => {
+            Stmt::Match(expr, arms, span) => {
                 let actual_ty = self.check_expr_with(expr, nogc);
                 let mut ty = Type::Nothing;
                 for arm in arms {
                     self.push_scope();
+                    self.narrow_by_pattern(expr, &arm.pat);
                     {
                         let mut pc = crate::pattern::PatternChecker::new(self);
                         pc.check_pattern(&arm.pat, &actual_ty);
@@ -287,13 +447,14 @@ impl Sema {
                     self.pop_scope();
                     ty = self.join_types(ty, arm_ty);
                 }
+                crate::pattern::PatternChecker::new(self).check_exhaustiveness(&actual_ty, arms, *span);
                 ty
             }
             Stmt::Block(b) => self.check_block_with(b, nogc),
         }
     }
 
-    fn check_block(&mut self, block: &Block) -> Type {
+    pub(crate) fn check_block(&mut self, block: &Block) -> Type {
         self.check_block_with(block, false)
     }
 
@@ -317,20 +478,22 @@ impl Sema {
 
     pub fn check_expr_with(&mut self, expr: &Expr, nogc: bool) -> Type {
         match expr {
-            Expr::Literal(l, span) => {
-                if nogc {
-                    if matches!(l, Literal::String(_)) {
-                        self.diags.push(Diagnostic::error("allocation not allowed in @nogc", *span));
-                    }
-                }
-                self.type_of_literal(l)
-            }
+            Expr::Literal(l, _) => self.type_of_literal(l),
             Expr::Ident(name, span) => {
                 if name == "null" {
                     return Type::Nothing;
                 }
                 self.lookup_var(name, *span)
             }
+            Expr::Instantiate { name, type_args, span } => {
+                let callee_ty = self.lookup_var(name, *span);
+                if matches!(callee_ty, Type::Func(_, _)) {
+                    let arg_tys = type_args.iter().map(|t| self.type_from_ref(t)).collect();
+                    Type::Generic(name.clone(), arg_tys)
+                } else {
+                    callee_ty
+                }
+            }
             Expr::StructLit { name, fields, .. } => {
                 for (_, value) in fields {
                     self.check_expr_with(value, nogc);
@@ -344,27 +507,46 @@ impl Sema {
                         self.unify(&Type::Bool, &t, *span);
                         Type::Bool
                     }
-                    UnaryOp::Neg | UnaryOp::Pos => self.expect_number(t, *span),
+                    UnaryOp::Neg | UnaryOp::Pos => match self.expect_number(t.clone(), *span) {
+                        Type::Float => t,
+                        other => other,
+                    },
                     UnaryOp::BitNot => self.expect_int(t, *span),
                 }
             }
             Expr::Binary { left, op, right, span } => {
                 let lt = self.check_expr_with(left, nogc);
-                let rt = self.check_expr_with(right, nogc);
+                let rt = match op {
+                    // The right operand only runs when the left one decided nothing.
+                    BinaryOp::And | BinaryOp::Or => {
+                        let (on_true, on_false) = self.null_facts(left);
+                        self.push_scope();
+                        self.narrow(if matches!(op, BinaryOp::And) { &on_true } else { &on_false });
+                        let rt = self.check_expr_with(right, nogc);
+                        self.pop_scope();
+                        rt
+                    }
+                    _ => self.check_expr_with(right, nogc),
+                };
+                let lt = self.adapt_literal(&rt, left, lt);
+                let rt = match (&lt, op) {
+                    (Type::Optional(inner), BinaryOp::NullCoalesce) => self.adapt_literal(inner, right, rt),
+                    _ => self.adapt_literal(&lt, right, rt),
+                };
                 match op {
+                    BinaryOp::Add if lt == Type::String && rt == Type::String => Type::String,
                     BinaryOp::Add | BinaryOp::Sub | BinaryOp::Mul | BinaryOp::Div | BinaryOp::Mod => {
                         self.expect_number(lt.clone(), *span);
                         self.expect_number(rt.clone(), *span);
-                        match (lt, rt) {
-                            (Type::Int, Type::Int) => Type::Int,
-                            (Type::UInt, Type::UInt) => Type::UInt,
-                            _ => Type::Float,
-                        }
+                        self.arith_type(lt, rt, *span)
                     }
                     BinaryOp::DotAdd | BinaryOp::DotSub | BinaryOp::DotMul | BinaryOp::DotDiv | BinaryOp::MatMul => {
-                        Type::Tensor(Box::new(Type::Float))
+                        self.tensor_op_type(*op, &lt, &rt, *span)
                     }
                     BinaryOp::Eq | BinaryOp::NotEq | BinaryOp::Lt | BinaryOp::LtEq | BinaryOp::Gt | BinaryOp::GtEq => {
+                        if lt.is_numeric() && rt.is_numeric() {
+                            self.arith_type(lt, rt, *span);
+                        }
                         Type::Bool
                     }
                     BinaryOp::And | BinaryOp::Or => {
@@ -372,13 +554,19 @@ impl Sema {
                         self.unify(&Type::Bool, &rt, *span);
                         Type::Bool
                     }
-                    BinaryOp::NullCoalesce => self.join_types(lt, rt),
+                    BinaryOp::NullCoalesce => self.coalesce_type(lt, rt, *span),
                     BinaryOp::Pipe | BinaryOp::Arrow => rt,
                 }
             }
             Expr::Assign { left, right, span, .. } => {
+                // Assignment ends any narrowing; the declared type applies again.
+                if let Expr::Ident(name, _) = &**left {
+                    self.forget_narrowing(name);
+                }
+                self.check_self_assign(left, *span);
                 let lt = self.check_expr_with(left, nogc);
                 let rt = self.check_expr_with(right, nogc);
+                let rt = self.adapt_literal(&lt, right, rt);
                 
                 // Allow re-typing if the target is currently Nothing (likely initialized from null)
                 if lt == Type::Nothing {
@@ -404,6 +592,15 @@ impl Sema {
             }
             Expr::Call { callee, args, span } => {
                 if let Expr::Ident(name, _) = &**callee {
+                    if let Some(decl) = self.externs.get(name).filter(|_| !self.is_local(name)).cloned() {
+                        return self.check_extern_call(&decl, args, *span, nogc);
+                    }
+                    if name == "staticAssert" && !self.is_local(name) {
+                        return self.check_static_assert(args, *span, nogc);
+                    }
+                    if crate::typeck::INT_INTRINSICS.contains(&name.as_str()) && !self.is_local(name) && !self.functions.contains_key(name) {
+                        return self.check_int_intrinsic(name, args, *span, nogc);
+                    }
                     if name == "@import" || name == "@bridge" {
                         if args.is_empty() {
                             self.diags.push(Diagnostic::error("FFI call requires a string argument", *span));
@@ -411,32 +608,59 @@ impl Sema {
                             self.diags.push(Diagnostic::error("FFI call requires string literal", self.span_of(&args[0])));
                         }
                     }
-                    if nogc && !self.is_nogc_function(name) {
-                        self.diags.push(Diagnostic::error("call to non-@nogc function in @nogc", *span));
-                    }
                 }
                 
                 // Handle Extension Functions / Methods
                 if let Expr::Member { target, name, span: m_span } = &**callee {
+                    if let Some(ty) = self.check_static_call(target, name, args, *span, nogc) {
+                        return ty;
+                    }
                     let target_ty = self.check_expr_with(target, nogc);
-                    if let Some(sig) = crate::extension::resolve_extension_method(self, &target_ty, name) {
+                    self.check_self_call(target, &target_ty, name, *m_span);
+                    self.check_mut_receiver(target, &target_ty, name, *m_span);
+                    let method = crate::extension::resolve_extension_method(self, &target_ty, name)
+                        .or_else(|| self.interface_method(&target_ty, name));
+                    if let Some(sig) = method {
                         if let Type::Func(params, ret) = sig {
                             if params.len() != args.len() {
                                 self.diags.push(Diagnostic::error("argument count mismatch", *span));
                             }
                             for (arg, p) in args.iter().zip(params.iter()) {
                                 let at = self.check_expr_with(arg, nogc);
+                                let at = self.adapt_literal(p, arg, at);
                                 self.unify(p, &at, self.span_of(arg));
                             }
                             return *ret;
                         }
                     }
+                    if name == "len" && args.is_empty() && (target_ty == Type::String || target_ty.list_element().is_some()) {
+                        return Type::Int;
+                    }
+                    if name == "push" && args.len() == 1 {
+                        if let Some(elem) = target_ty.list_element() {
+                            let at = self.check_expr_with(&args[0], nogc);
+                            let at = self.adapt_literal(&elem, &args[0], at);
+                            self.unify(&elem, &at, self.span_of(&args[0]));
+                            return Type::Unit;
+                        }
+                    }
                     if !self.permissive {
                         self.diags.push(Diagnostic::error(format!("no method '{}' found for type {:?}", name, target_ty), *m_span));
                     }
                     return Type::Unknown;
                 }
 
+                let selected = match &**callee {
+                    Expr::Ident(name, _) => Some((name, &[][..])),
+                    Expr::Instantiate { name, type_args, .. } => Some((name, type_args.as_slice())),
+                    _ => None,
+                };
+                if let Some((name, type_args)) = selected {
+                    if !self.is_local(name) && self.specializations.needs_selection(name) {
+                        return self.check_specialized_call(name, type_args, args, *span, nogc);
+                    }
+                }
+
                 let mut ct = self.check_expr_with(callee, nogc);
 
                 // Handle Generic Function Instantiation
@@ -470,11 +694,14 @@ impl Sema {
                         if params.len() != args.len() {
                             self.diags.push(Diagnostic::error("argument count mismatch", *span));
                         }
+                        let mut dims = DimSubst::default();
                         for (arg, p) in args.iter().zip(params.iter()) {
                             let at = self.check_expr_with(arg, nogc);
-                            self.unify(p, &at, self.span_of(arg));
+                            let at = self.adapt_literal(p, arg, at);
+                            let p = dims.bind(p, &at);
+                            self.unify(&p, &at, self.span_of(arg));
                         }
-                        *ret
+                        dims.apply(&ret)
                     }
                     Type::Unknown | Type::Any => Type::Unknown,
                     _ => {
@@ -492,7 +719,21 @@ impl Sema {
                     target_ty = *inner;
                 }
 
-                // 1. Check for struct fields
+                // 1. Tuple fields are numbered from 0
+                if let (Type::Tuple(items), Ok(index)) = (&target_ty, name.parse::<usize>()) {
+                    return match items.get(index) {
+                        Some(ty) => ty.clone(),
+                        None => {
+                            self.diags.push(Diagnostic::error(
+                                format!("tuple index {} out of range for a tuple of {} elements", index, items.len()),
+                                *span,
+                            ));
+                            Type::Unknown
+                        }
+                    };
+                }
+
+                // 2. Check for struct fields
                 if let Type::Named(s_name) = &target_ty {
                     if let Some(s_decl) = self.structs.get(s_name).cloned() {
                         if let Some(field) = s_decl.fields.iter().find(|f| &f.name == name) {
@@ -501,20 +742,32 @@ impl Sema {
                     }
                 }
 
-                // 2. Check if it's an extension function reference
+                // 3. Check if it's an extension function reference
                 if let Some(sig) = crate::extension::resolve_extension_method(self, &target_ty, name) {
                     return sig;
                 }
+                if let Some(sig) = self.interface_method(&target_ty, name) {
+                    return sig;
+                }
                 if !self.permissive {
                     self.diags.push(Diagnostic::error(format!("unknown member '{}' for type {:?}", name, target_ty), *span));
                 }
                 Type::Unknown
             }
+            Expr::Range { start, end, .. } => self.range_type(start, end, nogc),
+            Expr::Cast { expr: inner, ty, span } => {
+                let from = self.check_expr_with(inner, nogc);
+                self.check_type_ref(ty);
+                let to = self.type_from_ref(ty);
+                self.check_cast(&from, &to, *span);
+                to
+            }
             Expr::Index { target, index, span } => {
                 let t = self.check_expr_with(target, nogc);
                 let _ = self.check_expr_with(index, nogc);
                 match t {
                     Type::Array(inner) => *inner,
+                    Type::Generic(name, args) if name == "List" && args.len() == 1 => args[0].clone(),
                     Type::Named(name) if name == "List" => Type::Unknown,
                     Type::Unknown | Type::Any => Type::Unknown,
                     _ => {
@@ -526,15 +779,23 @@ impl Sema {
             Expr::If { cond, then_block, else_block, span: _span } => {
                 let ct = self.check_expr_with(cond, nogc);
                 self.unify(&Type::Bool, &ct, self.span_of(cond));
+                let (on_true, on_false) = self.null_facts(cond);
+                self.push_scope();
+                self.narrow(&on_true);
                 let tt = self.check_block_with(then_block, nogc);
+                self.pop_scope();
+                self.push_scope();
+                self.narrow(&on_false);
                 let et = self.check_block_with(else_block, nogc);
+                self.pop_scope();
                 self.join_types(tt, et)
             }
-            Expr::Match { expr, arms, span: _span } => {
+            Expr::Match { expr, arms, span } => {
                 let actual_ty = self.check_expr_with(expr, nogc);
                 let mut ty = Type::Unknown;
                 for arm in arms {
                     self.push_scope();
+                    self.narrow_by_pattern(expr, &arm.pat);
                     {
                         let mut pc = crate::pattern::PatternChecker::new(self);
                         pc.check_pattern(&arm.pat, &actual_ty);
@@ -543,13 +804,22 @@ impl Sema {
                     self.pop_scope();
                     ty = if ty == Type::Unknown { at } else { self.join_types(ty, at) };
                 }
+                crate::pattern::PatternChecker::new(self).check_exhaustiveness(&actual_ty, arms, *span);
                 ty
             }
             Expr::Block(b) => self.check_block_with(b, nogc),
-            Expr::Array(items, _) => {
-                if nogc {
-                    self.diags.push(Diagnostic::error("allocation not allowed in @nogc", self.span_of(expr)));
+            Expr::Loop { label, body, .. } => {
+                self.forget_assigned(body);
+                let breaks = self.check_loop_body(label, Some(Vec::new()), body, nogc).unwrap_or_default();
+                // A `loop` that is never broken out of does not produce a value.
+                let mut breaks = breaks.into_iter();
+                match breaks.next() {
+                    Some(first) => breaks.fold(first, |acc, t| self.join_types(acc, t)),
+                    None => Type::Nothing,
                 }
+            }
+            Expr::Tuple(items, _) => Type::Tuple(items.iter().map(|it| self.check_expr_with(it, nogc)).collect()),
+            Expr::Array(items, _) => {
                 let mut ty = Type::Unknown;
                 for it in items {
                     let t = self.check_expr_with(it, nogc);
@@ -557,16 +827,8 @@ impl Sema {
                 }
                 Type::Array(Box::new(ty))
             }
-            Expr::Tensor(_, _) => {
-                if nogc {
-                    self.diags.push(Diagnostic::error("allocation not allowed in @nogc", self.span_of(expr)));
-                }
-                Type::Tensor(Box::new(Type::Float))
-            }
+            Expr::Tensor(rows, span) => self.tensor_literal_type(rows, *span, nogc),
             Expr::Interpolated { parts, .. } => {
-                if nogc {
-                    self.diags.push(Diagnostic::error("allocation not allowed in @nogc", self.span_of(expr)));
-                }
                 for p in parts {
                     self.check_expr_with(p, nogc);
                 }
@@ -575,12 +837,111 @@ impl Sema {
         }
     }
 
+    /// `staticAssert(cond)` / `staticAssert(cond, "message")`: the condition is
+    /// evaluated by the constexpr interpreter and must hold.
+    fn check_static_assert(&mut self, args: &[Expr], span: Span, nogc: bool) -> Type {
+        if args.is_empty() || args.len() > 2 {
+            self.diags.push(Diagnostic::error("staticAssert expects a condition and an optional message", span));
+            return Type::Unit;
+        }
+        let cond_ty = self.check_expr_with(&args[0], nogc);
+        self.unify(&Type::Bool, &cond_ty, self.span_of(&args[0]));
+        let message = match args.get(1) {
+            Some(Expr::Literal(Literal::String(s), _)) => Some(s.clone()),
+            Some(other) => {
+                self.diags.push(Diagnostic::error("staticAssert message must be a string literal", self.span_of(other)));
+                None
+            }
+            None => None,
+        };
+        match self.consts.eval(&args[0]) {
+            Ok(ConstValue::Bool(false)) => {
+                let msg = match message {
+                    Some(m) => format!("static assertion failed: {}", m),
+                    None => "static assertion failed".to_string(),
+                };
+                self.diags.push(Diagnostic::error(msg, span));
+            }
+            Ok(_) => {}
+            Err(e) => self.diags.extend(e.report("static assertion condition", span)),
+        }
+        Type::Unit
+    }
+
+    /// Checks the compile-time parts of an annotation, and that an array literal
+    /// initializer has exactly the length a `[T, N]` annotation asks for.
+    fn check_annotation(&mut self, ann: &TypeRef, value: &Expr) {
+        self.check_type_ref(ann);
+        if let (TypeRef::FixedArray { len, .. }, Expr::Array(items, span)) = (ann, value) {
+            if let Ok(n) = self.consts.eval_length(len) {
+                if items.len() as i64 != n {
+                    self.diags.push(Diagnostic::error(
+                        format!("array literal has {} elements but the type requires {}", items.len(), n),
+                        *span,
+                    ));
+                }
+            }
+        }
+    }
+
+    /// Evaluates fixed array lengths and tensor dimensions inside a type reference.
+    pub(crate) fn check_type_ref(&mut self, tr: &TypeRef) {
+        match tr {
+            TypeRef::Named(_, args, _) | TypeRef::Tuple(args, _) => {
+                for a in args {
+                    self.check_type_ref(a);
+                }
+            }
+            TypeRef::Array(inner, _) | TypeRef::Optional(inner, _) | TypeRef::NonNull(inner, _) => {
+                self.check_type_ref(inner);
+            }
+            TypeRef::FixedArray { elem, len, span } => {
+                self.check_type_ref(elem);
+                if let Err(e) = self.consts.eval_length(len) {
+                    self.diags.extend(e.report("array length", *span));
+                }
+            }
+            TypeRef::Tensor { elem, shape, span } => {
+                self.check_type_ref(elem);
+                for dim in shape {
+                    if let Err(e) = self.consts.eval_dim(dim, *span) {
+                        self.diags.extend(e.report("tensor dimension", *span));
+                    }
+                }
+            }
+        }
+    }
+
     fn define_builtin(&mut self, name: &str, ty: Type) {
         if let Some(scope) = self.scopes.last_mut() {
             scope.vars.insert(name.to_string(), ty);
         }
     }
 
+    /// Defines the name of a `let`, or every name of its destructuring
+    /// pattern, which must not be able to fail.
+    fn bind_var_decl(&mut self, v: &VarDecl, ty: Type) {
+        let pat = match &v.pattern {
+            Some(pat) => pat,
+            None => {
+                self.define_var(&v.name, ty, v.span);
+                if !v.mutable {
+                    if let Some(scope) = self.scopes.last_mut() {
+                        scope.immutable.insert(v.name.clone());
+                    }
+                }
+                return;
+            }
+        };
+        let reported = self.diags.len();
+        crate::pattern::PatternChecker::new(self).check_pattern(pat, &ty);
+        if self.diags.len() == reported && !matches!(ty, Type::Unknown | Type::Any) {
+            if let Some(diag) = crate::exhaustive::check_irrefutable(self, &ty, pat) {
+                self.diags.push(diag);
+            }
+        }
+    }
+
     fn bind_pattern(&mut self, pat: &Pattern) {
         match pat {
             Pattern::Ident(name, span) => {
@@ -617,33 +978,13 @@ impl Sema {
     }
 
 
+    /// Like `Type::from_type_ref`, with tensor dimensions that name constants
+    /// resolved to their values.
     pub fn type_from_ref(&self, tr: &TypeRef) -> Type {
-        match tr {
-            TypeRef::Named(name, args, _span) => {
-                if args.is_empty() {
-                    match name.as_str() {
-                        "Int" => Type::Int,
-                        "UInt" => Type::UInt,
-                        "Float" => Type::Float,
-                        "Bool" => Type::Bool,
-                        "Char" => Type::Char,
-                        "String" => Type::String,
-                        "Void" | "Unit" => Type::Unit,
-                        "Any" => Type::Any,
-                        "Nothing" => Type::Nothing,
-                        _ => Type::Named(name.clone()),
-                    }
-                } else {
-                    let arg_tys = args.iter().map(|a| self.type_from_ref(a)).collect();
-                    Type::Generic(name.clone(), arg_tys)
-                }
-            }
-            TypeRef::Tuple(elems, _) => Type::Tuple(elems.iter().map(|e| self.type_from_ref(e)).collect()),
-            TypeRef::Array(inner, _) => Type::Array(Box::new(self.type_from_ref(inner))),
-            TypeRef::Tensor { elem, .. } => Type::Tensor(Box::new(self.type_from_ref(elem))),
-            TypeRef::Optional(inner, _) => Type::Optional(Box::new(self.type_from_ref(inner))),
-            TypeRef::NonNull(inner, _) => self.type_from_ref(inner),
-        }
+        Type::from_type_ref_with(tr, &|dim, span| match self.consts.eval_dim(dim, span) {
+            Ok(Some(n)) => Dim::Known(n),
+            _ => Dim::from_shape_dim(dim, span),
+        })
     }
 
     fn fun_sig(&mut self, f: &FunDecl) -> Type {
@@ -657,9 +998,59 @@ impl Sema {
         Type::Func(params, Box::new(ret))
     }
 
+    /// Resolves a call to an overloaded or generic function by picking the most
+    /// specific implementation for the argument types.
+    fn check_specialized_call(&mut self, name: &str, type_args: &[TypeRef], args: &[Expr], span: Span, nogc: bool) -> Type {
+        let generic_args: Vec<Type> = type_args.iter().map(|t| self.type_from_ref(t)).collect();
+        let arg_tys: Vec<Type> = args.iter().map(|a| self.check_expr_with(a, nogc)).collect();
+        let query = SpecQuery { generic_args: &generic_args, receiver: None, args: Some(&arg_tys) };
+        let (spec, subst) = match self.specializations.find_best_match(name, &query) {
+            Resolution::Selected(spec, subst) => (spec.clone(), subst),
+            Resolution::Ambiguous(cands) => {
+                let spans: Vec<Span> = cands.iter().map(|c| c.span).collect();
+                self.diags.push(Diagnostic::error(
+                    format!("ambiguous call to '{}': {} implementations are equally specific for {:?}", name, spans.len(), arg_tys),
+                    span,
+                ));
+                for s in spans {
+                    self.diags.push(Diagnostic::note("candidate implementation", s));
+                }
+                return Type::Unknown;
+            }
+            Resolution::NoMatch => {
+                let unresolved = arg_tys.iter().any(|t| matches!(t, Type::Unknown | Type::Any));
+                if !self.permissive && !unresolved {
+                    self.diags.push(Diagnostic::error(
+                        format!("no implementation of '{}' accepts arguments {:?}", name, arg_tys),
+                        span,
+                    ));
+                }
+                return Type::Unknown;
+            }
+        };
+        for (var, bounds) in &spec.bounds {
+            if let Some(actual) = subst.get(var).cloned() {
+                for bound in bounds {
+                    self.check_satisfies(&actual, bound, span);
+                }
+            }
+        }
+        spec.instantiate(&spec.ret, &subst)
+    }
+
+    /// Whether `name` is a variable, global or function.
+    fn is_bound(&self, name: &str) -> bool {
+        name == "null" || self.functions.contains_key(name) || self.scopes.iter().any(|s| s.vars.contains_key(name))
+    }
+
+    pub(crate) fn is_local(&self, name: &str) -> bool {
+        // The outermost scope only holds builtins and globals, which never shadow functions.
+        self.scopes.iter().skip(1).any(|s| s.vars.contains_key(name))
+    }
+
     fn lookup_var(&mut self, name: &str, span: Span) -> Type {
         for scope in self.scopes.iter().rev() {
-            if let Some(t) = scope.vars.get(name) {
+            if let Some(t) = scope.narrowed.get(name).or_else(|| scope.vars.get(name)) {
                 return t.clone();
             }
         }
@@ -675,16 +1066,18 @@ impl Sema {
             if scope.vars.contains_key(name) {
                 self.diags.push(Diagnostic::error(format!("redefinition of '{name}'"), span));
             } else {
+                scope.narrowed.remove(name);
+                self.binding_types.insert((name.to_string(), span.start.offset), ty.clone());
                 scope.vars.insert(name.to_string(), ty);
             }
         }
     }
 
-    fn push_scope(&mut self) {
+    pub(crate) fn push_scope(&mut self) {
         self.scopes.push(Scope::default());
     }
 
-    fn pop_scope(&mut self) {
+    pub(crate) fn pop_scope(&mut self) {
         self.scopes.pop();
     }
 
@@ -702,8 +1095,12 @@ impl Sema {
                 return;
             }
         }
+        if matches!(actual, Type::Optional(_)) && !matches!(expected, Type::Optional(_)) {
+            crate::typeck::check_nullability(self, expected, actual, span);
+            return;
+        }
 
-        if expected != actual {
+        if !self.is_assignable(expected, actual) {
             self.diags.push(Diagnostic::error(
                 format!("type mismatch: expected {:?}, got {:?}", expected, actual),
                 span,
@@ -711,6 +1108,55 @@ impl Sema {
         }
     }
 
+    /// Structural compatibility used by `unify`: unknown parts match anything,
+    /// `List<T>` and `[T]` are interchangeable, and a struct value coerces to
+    /// any interface it implements.
+    pub(crate) fn is_assignable(&self, expected: &Type, actual: &Type) -> bool {
+        if expected == actual || matches!(expected, Type::Unknown | Type::Any) || matches!(actual, Type::Unknown | Type::Any) {
+            return true;
+        }
+        if let (Some(e), Some(a)) = (expected.list_element(), actual.list_element()) {
+            return self.is_assignable(&e, &a);
+        }
+        match (expected, actual) {
+            (Type::Optional(e), Type::Optional(a)) => self.is_assignable(e, a),
+            (Type::Optional(e), a) => matches!(a, Type::Nothing) || self.is_assignable(e, a),
+            (Type::Tuple(es), Type::Tuple(as_)) => es.len() == as_.len() && es.iter().zip(as_).all(|(e, a)| self.is_assignable(e, a)),
+            (Type::Tensor(e, es), Type::Tensor(a, as_)) => self.is_assignable(e, a) && crate::shape::same_shape(es, as_),
+            // Both are NUL-terminated `char*`; `CString` marks the C side.
+            (Type::Named(c), Type::String) | (Type::String, Type::Named(c)) if c == "CString" => true,
+            (Type::Named(i) | Type::Generic(i, _), Type::Named(_)) if self.interface_system.interfaces.contains_key(i) => {
+                self.interface_system.implements(actual, i)
+            }
+            _ => false,
+        }
+    }
+
+    /// Checks a loop body with the loop in scope for `break` and `continue`,
+    /// and returns the types of the values it was broken out of with.
+    fn check_loop_body(&mut self, label: &Option<String>, breaks: Option<Vec<Type>>, body: &Block, nogc: bool) -> Option<Vec<Type>> {
+        self.loops.push(LoopScope { label: label.clone(), breaks });
+        self.check_block_with(body, nogc);
+        self.loops.pop().and_then(|l| l.breaks)
+    }
+
+    /// The loop a `break` or `continue` leaves, innermost first.
+    fn jump_target(&mut self, label: Option<&str>, keyword: &str, span: Span) -> Option<usize> {
+        let target = match label {
+            Some(label) => self.loops.iter().rposition(|l| l.label.as_deref() == Some(label)),
+            None => self.loops.len().checked_sub(1),
+        };
+        if target.is_none() {
+            let msg = match label {
+                _ if self.defer_depth > 0 && self.loops.is_empty() => format!("'{}' cannot leave a defer block", keyword),
+                Some(label) => format!("unknown loop label '{}'", label),
+                None => format!("'{}' outside of a loop", keyword),
+            };
+            self.diags.push(Diagnostic::error(msg, span));
+        }
+        target
+    }
+
     fn join_types(&self, a: Type, b: Type) -> Type {
         if a == b {
             a
@@ -723,12 +1169,12 @@ impl Sema {
         }
     }
 
-    fn expect_number(&mut self, t: Type, span: Span) -> Type {
+    pub(crate) fn expect_number(&mut self, t: Type, span: Span) -> Type {
         if self.permissive {
             return Type::Unknown;
         }
         match t {
-            Type::Int | Type::UInt | Type::Float => Type::Float,
+            t if t.is_numeric() => Type::Float,
             Type::Unknown | Type::Any => Type::Unknown,
             _ => {
                 self.diags.push(Diagnostic::error("expected numeric type", span));
@@ -737,12 +1183,12 @@ impl Sema {
         }
     }
 
-    fn expect_int(&mut self, t: Type, span: Span) -> Type {
+    pub(crate) fn expect_int(&mut self, t: Type, span: Span) -> Type {
         if self.permissive {
             return Type::Unknown;
         }
         match t {
-            Type::Int | Type::UInt => t,
+            Type::Int | Type::UInt | Type::SizedInt { .. } => t,
             Type::Unknown | Type::Any => Type::Unknown,
             _ => {
                 self.diags.push(Diagnostic::error("expected integer type", span));
@@ -751,10 +1197,11 @@ impl Sema {
         }
     }
 
-    fn span_of(&self, expr: &Expr) -> Span {
+    pub(crate) fn span_of(&self, expr: &Expr) -> Span {
         match expr {
             Expr::Literal(_, s) => *s,
             Expr::Ident(_, s) => *s,
+            Expr::Instantiate { span, .. } => *span,
             Expr::StructLit { span, .. } => *span,
             Expr::Unary { span, .. } => *span,
             Expr::Binary { span, .. } => *span,
@@ -762,10 +1209,13 @@ impl Sema {
             Expr::Call { span, .. } => *span,
             Expr::Member { span, .. } => *span,
             Expr::Index { span, .. } => *span,
+            Expr::Range { span, .. } => *span,
+            Expr::Cast { span, .. } => *span,
             Expr::If { span, .. } => *span,
             Expr::Match { span, .. } => *span,
+            Expr::Loop { span, .. } => *span,
             Expr::Block(b) => b.span,
-            Expr::Array(_, s) => *s,
+            Expr::Array(_, s) | Expr::Tuple(_, s) => *s,
             Expr::Tensor(_, s) => *s,
             Expr::Interpolated { span, .. } => *span,
         }
@@ -797,23 +1247,16 @@ impl Sema {
     }
 
     fn validate_nogc(&mut self, program: &Program) {
+        let mut graph = crate::nogc::CallGraph::build(self, program);
         for item in &program.items {
             if let Item::Fun(f) = item {
                 if f.nogc {
-                    {
-                        let mut bck = BorrowChecker::new(self);
-                        bck.check_block(&f.body);
-                    }
-                    {
-                        let mut mck = MoveChecker::new(self);
-                        mck.check_block(&f.body);
-                    }
                     {
                         let mut lck = LifetimeChecker::new(self);
-                        lck.check_block(&f.body);
+                        lck.check_fun(f);
                     }
                     {
-                        let mut ngck = crate::nogc::NoGcChecker::new(self);
+                        let mut ngck = crate::nogc::NoGcChecker::new(self, &mut graph);
                         ngck.check_fun(f);
                     }
                     if f.is_async {
@@ -0,0 +1,213 @@
+use crate::ast::{BinaryOp, Expr};
+use crate::diag::{Diagnostic, Span};
+use crate::sema::Sema;
+use crate::types::{Dim, Type};
+use std::collections::HashMap;
+
+/// Two dimensions that must be equal; `_` takes the other side.
+fn unify_dim(a: &Dim, b: &Dim) -> Option<Dim> {
+    match (a, b) {
+        (Dim::Unknown, d) | (d, Dim::Unknown) => Some(d.clone()),
+        _ if a == b => Some(a.clone()),
+        _ => None,
+    }
+}
+
+/// Whether a tensor of shape `actual` may be used where `expected` is declared.
+pub fn same_shape(expected: &Option<Vec<Dim>>, actual: &Option<Vec<Dim>>) -> bool {
+    match (expected, actual) {
+        (Some(e), Some(a)) => e.len() == a.len() && e.iter().zip(a).all(|(e, a)| unify_dim(e, a).is_some()),
+        _ => true,
+    }
+}
+
+/// Aligns two shapes from their trailing dimensions; a dimension of 1
+/// stretches to match the other side. On failure returns the first pair of
+/// dimensions that cannot be reconciled.
+pub fn broadcast(a: &[Dim], b: &[Dim]) -> Result<Vec<Dim>, (Dim, Dim)> {
+    let rank = a.len().max(b.len());
+    let one = Dim::Known(1);
+    let at = |s: &[Dim], i: usize| if i < rank - s.len() { one.clone() } else { s[i - (rank - s.len())].clone() };
+    (0..rank)
+        .map(|i| {
+            let (x, y) = (at(a, i), at(b, i));
+            match (&x, &y) {
+                (Dim::Known(1), d) | (d, Dim::Known(1)) => Ok(d.clone()),
+                _ => unify_dim(&x, &y).ok_or((x, y)),
+            }
+        })
+        .collect()
+}
+
+/// Shape of `a @ b`: `[.., n, k] @ [.., k, m]` is `[.., n, m]` with the
+/// leading batch dimensions broadcast. A rank-1 operand is a row vector on
+/// the left and a column vector on the right, and its dimension is dropped
+/// from the result.
+pub fn matmul(a: &[Dim], b: &[Dim]) -> Result<Vec<Dim>, String> {
+    if a.is_empty() || b.is_empty() {
+        return Err("operands must have rank 1 or more".to_string());
+    }
+    let lhs = if a.len() == 1 { vec![Dim::Known(1), a[0].clone()] } else { a.to_vec() };
+    let rhs = if b.len() == 1 { vec![b[0].clone(), Dim::Known(1)] } else { b.to_vec() };
+    let (lb, lm) = lhs.split_at(lhs.len() - 2);
+    let (rb, rm) = rhs.split_at(rhs.len() - 2);
+    if unify_dim(&lm[1], &rm[0]).is_none() {
+        return Err(format!("inner dimensions {:?} and {:?} differ", lm[1], rm[0]));
+    }
+    let mut out = broadcast(lb, rb).map_err(|(x, y)| format!("batch dimensions {:?} and {:?} differ", x, y))?;
+    if a.len() > 1 {
+        out.push(lm[0].clone());
+    }
+    if b.len() > 1 {
+        out.push(rm[1].clone());
+    }
+    Ok(out)
+}
+
+/// Symbolic dimensions of a callee's parameters, bound to the argument
+/// shapes at one call site.
+#[derive(Default)]
+pub struct DimSubst {
+    bound: HashMap<String, Dim>,
+}
+
+impl DimSubst {
+    /// Binds the symbols of `param` that are still free to the matching
+    /// dimensions of `arg`, and returns `param` with all bindings applied.
+    pub fn bind(&mut self, param: &Type, arg: &Type) -> Type {
+        if let (Type::Tensor(_, Some(ps)), Type::Tensor(_, Some(args))) = (param, arg) {
+            if ps.len() == args.len() {
+                for (p, a) in ps.iter().zip(args) {
+                    if let Dim::Sym(name) = p {
+                        if *a != Dim::Unknown {
+                            self.bound.entry(name.clone()).or_insert_with(|| a.clone());
+                        }
+                    }
+                }
+            }
+        }
+        self.apply(param)
+    }
+
+    pub fn apply(&self, ty: &Type) -> Type {
+        match ty {
+            Type::Tensor(elem, Some(shape)) => {
+                let shape = shape
+                    .iter()
+                    .map(|d| match d {
+                        Dim::Sym(name) => self.bound.get(name).cloned().unwrap_or_else(|| d.clone()),
+                        d => d.clone(),
+                    })
+                    .collect();
+                Type::Tensor(elem.clone(), Some(shape))
+            }
+            Type::Optional(inner) => Type::Optional(Box::new(self.apply(inner))),
+            Type::Array(inner) => Type::Array(Box::new(self.apply(inner))),
+            Type::Tuple(items) => Type::Tuple(items.iter().map(|t| self.apply(t)).collect()),
+            _ => ty.clone(),
+        }
+    }
+}
+
+fn op_symbol(op: BinaryOp) -> &'static str {
+    match op {
+        BinaryOp::DotAdd => ".+",
+        BinaryOp::DotSub => ".-",
+        BinaryOp::DotMul => ".*",
+        BinaryOp::DotDiv => "./",
+        _ => "@",
+    }
+}
+
+impl Sema {
+    /// Result of the elementwise operators and `@`. Scalars broadcast against
+    /// tensors in elementwise operations; incompatible shapes are reported and
+    /// give a tensor of unknown rank so the error does not cascade.
+    pub(crate) fn tensor_op_type(&mut self, op: BinaryOp, lt: &Type, rt: &Type, span: Span) -> Type {
+        let elem = match (lt, rt) {
+            (Type::Tensor(le, _), Type::Tensor(re, _)) => {
+                self.unify(le, re, span);
+                (**le).clone()
+            }
+            (Type::Tensor(e, _), _) | (_, Type::Tensor(e, _)) => (**e).clone(),
+            _ => return Type::Tensor(Box::new(Type::Float), None),
+        };
+        let unshaped = Type::Tensor(Box::new(elem.clone()), None);
+        let (ls, rs) = match (lt, rt) {
+            (Type::Tensor(_, Some(l)), Type::Tensor(_, Some(r))) => (l, r),
+            (Type::Tensor(..), Type::Tensor(..)) => return unshaped,
+            (Type::Tensor(_, shape), other) | (other, Type::Tensor(_, shape)) => {
+                if matches!(op, BinaryOp::MatMul) && other.is_numeric() {
+                    self.diags.push(Diagnostic::error(format!("'@' needs two tensors, found {:?}", other), span));
+                    return unshaped;
+                }
+                return Type::Tensor(Box::new(elem), shape.clone());
+            }
+            _ => return unshaped,
+        };
+        let shape = match op {
+            BinaryOp::MatMul => matmul(ls, rs).map_err(|why| format!("cannot multiply {:?} @ {:?}: {}", ls, rs, why)),
+            _ => broadcast(ls, rs).map_err(|(x, y)| {
+                format!("cannot broadcast {:?} {} {:?}: dimensions {:?} and {:?} differ", ls, op_symbol(op), rs, x, y)
+            }),
+        };
+        match shape {
+            Ok(shape) => Type::Tensor(Box::new(elem), Some(shape)),
+            Err(msg) => {
+                self.diags.push(Diagnostic::error(msg, span));
+                unshaped
+            }
+        }
+    }
+
+    /// `tensor[[..], ..]` is a matrix whose rows must all have the same length.
+    pub(crate) fn tensor_literal_type(&mut self, rows: &[Vec<Expr>], span: Span, nogc: bool) -> Type {
+        let cols = rows.first().map_or(0, Vec::len);
+        for row in rows {
+            for item in row {
+                let t = self.check_expr_with(item, nogc);
+                self.expect_number(t, self.span_of(item));
+            }
+            if row.len() != cols {
+                self.diags.push(Diagnostic::error(format!("tensor literal rows must all have {} elements", cols), span));
+            }
+        }
+        let shape = vec![Dim::Known(rows.len() as i64), Dim::Known(cols as i64)];
+        Type::Tensor(Box::new(Type::Float), Some(shape))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::test_support::errors;
+
+    #[test]
+    fn matmul_checks_inner_dimensions() {
+        let src = "fun f(x: Tensor<Float, [B, 128]>, w: Tensor<Float, [64, 10]>) -> Int { let y = x @ w; 0 }";
+        assert_eq!(errors(src), ["cannot multiply [B, 128] @ [64, 10]: inner dimensions 128 and 64 differ"]);
+    }
+
+    #[test]
+    fn elementwise_operators_broadcast_from_trailing_dimensions() {
+        let src = "fun f(x: Tensor<Float, [B, 3]>, b: Tensor<Float, [3]>, c: Tensor<Float, [2, 1]>) -> Tensor<Float, [B, 3]> {\n\
+                       let d = x .* 2.0;\n\
+                       let e = x .+ c;\n\
+                       d .+ b\n\
+                   }";
+        assert_eq!(errors(src), ["cannot broadcast [B, 3] .+ [2, 1]: dimensions B and 2 differ"]);
+    }
+
+    #[test]
+    fn symbolic_dimensions_are_bound_per_call() {
+        let src = "fun dense(x: Tensor<Float, [B, K]>, w: Tensor<Float, [K, N]>) -> Tensor<Float, [B, N]> { x @ w }\n\
+                   fun f(a: Tensor<Float, [32, 128]>, w: Tensor<Float, [128, 10]>, v: Tensor<Float, [64, 10]>) -> Int {\n\
+                       let y: Tensor<Float, [32, 10]> = dense(a, w);\n\
+                       let z = dense(a, v);\n\
+                       0\n\
+                   }";
+        assert_eq!(
+            errors(src),
+            ["type mismatch: expected Tensor<Float, [128, 10]>, got Tensor<Float, [64, 10]>"]
+        );
+    }
+}
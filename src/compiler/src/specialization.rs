@@ -1,11 +1,574 @@
 use crate::ast::*;
-use crate::sema::{Type, Sema};
+use crate::diag::{Diagnostic, Span};
+use crate::mangle;
+use crate::sema::Type;
+use std::collections::{HashMap, HashSet};
 
-pub struct SpecializationEngine;
+/// Bindings from type variables to the concrete types they matched.
+pub type Subst = HashMap<String, Type>;
+
+/// One implementation of a function or extension method. Type variables appear
+/// as `Type::Parameter`; everything else in the patterns must match exactly.
+#[derive(Debug, Clone)]
+pub struct Specialization {
+    pub name: String,
+    /// Mangled symbol the implementation is emitted under.
+    pub symbol: String,
+    pub vars: Vec<String>,
+    /// One entry per generic parameter slot, e.g. `fun f<Int>()` pins the slot to `Int`.
+    pub generic_args: Vec<Type>,
+    pub receiver: Option<Type>,
+    pub self_param: SelfParam,
+    pub params: Vec<Type>,
+    pub ret: Type,
+    pub bounds: Vec<(String, Vec<Type>)>,
+    pub span: Span,
+}
+
+pub struct SpecQuery<'q> {
+    pub generic_args: &'q [Type],
+    pub receiver: Option<&'q Type>,
+    /// `None` when only the receiver is known, e.g. for a member reference.
+    pub args: Option<&'q [Type]>,
+}
+
+pub enum Resolution<'a> {
+    Selected(&'a Specialization, Subst),
+    Ambiguous(Vec<&'a Specialization>),
+    NoMatch,
+}
+
+#[derive(Default)]
+pub struct SpecializationEngine {
+    functions: HashMap<String, Vec<Specialization>>,
+    methods: HashMap<String, Vec<Specialization>>,
+}
 
 impl SpecializationEngine {
-    pub fn find_best_match(base_name: &str, args: &[Type]) -> Option<String> {
-        // TODO: Implement specialization selection logic
-        None
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn from_program(program: &Program) -> Self {
+        let mut declared = HashSet::new();
+        for item in &program.items {
+            collect_type_names(item, &mut declared);
+        }
+        let mut engine = Self::new();
+        for item in &program.items {
+            if let Item::Fun(f) = item {
+                engine.register(f, &declared);
+            }
+        }
+        engine
+    }
+
+    fn register(&mut self, f: &FunDecl, declared: &HashSet<String>) {
+        // A generic parameter spelled as a known type (`fun f<Int>()`) pins that slot.
+        let vars: Vec<String> = f
+            .generic_params
+            .iter()
+            .filter(|p| !is_concrete_type_name(&p.name, declared))
+            .map(|p| p.name.clone())
+            .collect();
+        let pattern = |tr: &TypeRef| parameterize(&Type::from_type_ref(tr), &vars);
+        let generic_args: Vec<Type> = f
+            .generic_params
+            .iter()
+            .map(|p| pattern(&TypeRef::Named(p.name.clone(), Vec::new(), p.span)))
+            .collect();
+        let bounds = f
+            .generic_params
+            .iter()
+            .filter(|p| vars.contains(&p.name) && !p.constraints.is_empty())
+            .map(|p| (p.name.clone(), p.constraints.iter().map(&pattern).collect()))
+            .collect();
+        let receiver = f.receiver.as_ref().map(&pattern);
+        let params: Vec<Type> = f.params.iter().map(|p| pattern(&p.ty)).collect();
+        let symbol = if f.export {
+            f.name.clone()
+        } else {
+            mangle::function_symbol(receiver.as_ref(), &f.name, &generic_args, &params)
+        };
+        let bucket = if f.receiver.is_some() { &mut self.methods } else { &mut self.functions };
+        bucket.entry(f.name.clone()).or_default().push(Specialization {
+            name: f.name.clone(),
+            symbol,
+            generic_args,
+            receiver,
+            self_param: f.self_param,
+            params,
+            ret: f.ret.as_ref().map(&pattern).unwrap_or(Type::Unit),
+            bounds,
+            span: f.span,
+            vars,
+        });
+    }
+
+    pub fn candidates(&self, name: &str, method: bool) -> &[Specialization] {
+        let bucket = if method { &self.methods } else { &self.functions };
+        bucket.get(name).map(|v| v.as_slice()).unwrap_or(&[])
+    }
+
+    /// True when calls to `name` cannot be bound by name alone: there are several
+    /// implementations, or the only one is generic and needs its variables solved.
+    pub fn needs_selection(&self, name: &str) -> bool {
+        let cands = self.candidates(name, false);
+        cands.len() > 1 || cands.iter().any(|c| !c.generic_args.is_empty())
+    }
+
+    /// Returns the symbol `f` was registered under.
+    pub fn symbol_of(&self, f: &FunDecl) -> String {
+        self.candidates(&f.name, f.receiver.is_some())
+            .iter()
+            .find(|c| c.span.start.offset == f.span.start.offset && c.span.end.offset == f.span.end.offset)
+            .map(|c| c.symbol.clone())
+            .unwrap_or_else(|| f.name.clone())
+    }
+
+    /// Picks the most specific implementation applicable to `query`. An
+    /// implementation that pins a generic slot is only applicable when the call
+    /// spells out its type arguments.
+    pub fn find_best_match(&self, base_name: &str, query: &SpecQuery) -> Resolution<'_> {
+        let explicit = !query.generic_args.is_empty();
+        let mut applicable = Vec::new();
+        for cand in self.candidates(base_name, query.receiver.is_some()) {
+            if let Some(subst) = cand.applies_to(query) {
+                applicable.push((cand, subst));
+            }
+        }
+        let maximal: Vec<usize> = (0..applicable.len())
+            .filter(|&i| {
+                !applicable.iter().enumerate().any(|(j, other)| {
+                    j != i && strictly_more_specific(other.0, applicable[i].0, explicit)
+                })
+            })
+            .collect();
+        match maximal.len() {
+            0 => Resolution::NoMatch,
+            1 => {
+                let (cand, subst) = applicable.swap_remove(maximal[0]);
+                Resolution::Selected(cand, subst)
+            }
+            _ => Resolution::Ambiguous(maximal.iter().map(|&i| applicable[i].0).collect()),
+        }
+    }
+
+    /// Reports implementations that could both apply to some call without either
+    /// being more specific, as well as duplicates of the same pattern. Calls with
+    /// and without explicit type arguments are checked separately since pinned
+    /// generic slots only take part in the former.
+    pub fn check_overlaps(&self) -> Vec<Diagnostic> {
+        let mut diags = Vec::new();
+        let mut names: Vec<(&String, &Vec<Specialization>)> = self.functions.iter().chain(self.methods.iter()).collect();
+        names.sort_by_key(|(_, cands)| cands.first().map(|c| c.span.start.offset));
+        for (name, cands) in names {
+            for (i, a) in cands.iter().enumerate() {
+                for b in &cands[i + 1..] {
+                    if a.params.len() != b.params.len() {
+                        continue;
+                    }
+                    let mut modes = Vec::new();
+                    if a.all_slots_generic() && b.all_slots_generic() {
+                        modes.push(false);
+                    }
+                    if !a.generic_args.is_empty() && a.generic_args.len() == b.generic_args.len() {
+                        modes.push(true);
+                    }
+                    let mut conflict = false;
+                    let mut ambiguous = false;
+                    for explicit in modes {
+                        let a_in_b = is_instance_of(a, b, explicit);
+                        let b_in_a = is_instance_of(b, a, explicit);
+                        conflict |= a_in_b && b_in_a;
+                        ambiguous |= !a_in_b && !b_in_a && overlaps(a, b, explicit);
+                    }
+                    if conflict {
+                        diags.push(Diagnostic::error(
+                            format!("conflicting implementations of '{}': {} is declared twice", name, describe(b)),
+                            b.span,
+                        ));
+                        diags.push(Diagnostic::note("previous implementation is here", a.span));
+                    } else if ambiguous {
+                        diags.push(Diagnostic::error(
+                            format!(
+                                "ambiguous overlapping implementations of '{}': neither {} nor {} is more specific",
+                                name,
+                                describe(a),
+                                describe(b)
+                            ),
+                            b.span,
+                        ));
+                        diags.push(Diagnostic::note("overlapping implementation is here", a.span));
+                    }
+                }
+            }
+        }
+        diags
+    }
+}
+
+impl Specialization {
+    fn all_slots_generic(&self) -> bool {
+        self.generic_args.iter().all(|g| matches!(g, Type::Parameter(_)))
+    }
+
+    fn applies_to(&self, query: &SpecQuery) -> Option<Subst> {
+        let mut subst = Subst::new();
+        if query.generic_args.is_empty() {
+            if !self.all_slots_generic() {
+                return None;
+            }
+        } else {
+            if query.generic_args.len() != self.generic_args.len() {
+                return None;
+            }
+            for (pat, actual) in self.generic_args.iter().zip(query.generic_args) {
+                if !match_type(pat, actual, &mut subst) {
+                    return None;
+                }
+            }
+        }
+        match (&self.receiver, query.receiver) {
+            (Some(pat), Some(actual)) => {
+                if !match_type(pat, actual, &mut subst) {
+                    return None;
+                }
+            }
+            (None, None) => {}
+            _ => return None,
+        }
+        if let Some(args) = query.args {
+            if args.len() != self.params.len() {
+                return None;
+            }
+            for (pat, actual) in self.params.iter().zip(args) {
+                if !match_type(pat, actual, &mut subst) {
+                    return None;
+                }
+            }
+        }
+        Some(subst)
+    }
+
+    /// Applies `subst` to `ty`; variables that were never bound become `Unknown`.
+    pub fn instantiate(&self, ty: &Type, subst: &Subst) -> Type {
+        substitute(ty, subst)
+    }
+
+    pub fn instantiated_sig(&self, subst: &Subst) -> Type {
+        Type::Func(
+            self.params.iter().map(|p| substitute(p, subst)).collect(),
+            Box::new(substitute(&self.ret, subst)),
+        )
+    }
+}
+
+fn collect_type_names(item: &Item, out: &mut HashSet<String>) {
+    match item {
+        Item::Struct(s) => {
+            out.insert(s.name.clone());
+        }
+        Item::Enum(e) => {
+            out.insert(e.name.clone());
+        }
+        Item::TypeAlias(t) => {
+            out.insert(t.name.clone());
+        }
+        Item::Interface(i) => {
+            out.insert(i.name.clone());
+        }
+        Item::Sealed(s) => {
+            out.insert(s.name.clone());
+            for nested in &s.items {
+                collect_type_names(nested, out);
+            }
+        }
+        _ => {}
+    }
+}
+
+fn is_concrete_type_name(name: &str, declared: &HashSet<String>) -> bool {
+    declared.contains(name) || Type::builtin(name).is_some()
+}
+
+fn parameterize(ty: &Type, vars: &[String]) -> Type {
+    match ty {
+        Type::Named(n) if vars.contains(n) => Type::Parameter(n.clone()),
+        Type::Array(inner) => Type::Array(Box::new(parameterize(inner, vars))),
+        Type::Tensor(inner, shape) => Type::Tensor(Box::new(parameterize(inner, vars)), shape.clone()),
+        Type::Optional(inner) => Type::Optional(Box::new(parameterize(inner, vars))),
+        Type::Tuple(elems) => Type::Tuple(elems.iter().map(|e| parameterize(e, vars)).collect()),
+        Type::Generic(n, args) => Type::Generic(n.clone(), args.iter().map(|a| parameterize(a, vars)).collect()),
+        Type::Func(params, ret) => Type::Func(
+            params.iter().map(|p| parameterize(p, vars)).collect(),
+            Box::new(parameterize(ret, vars)),
+        ),
+        _ => ty.clone(),
+    }
+}
+
+fn substitute(ty: &Type, subst: &Subst) -> Type {
+    match ty {
+        Type::Parameter(v) => subst.get(v).cloned().unwrap_or(Type::Unknown),
+        Type::Array(inner) => Type::Array(Box::new(substitute(inner, subst))),
+        Type::Tensor(inner, shape) => Type::Tensor(Box::new(substitute(inner, subst)), shape.clone()),
+        Type::Optional(inner) => Type::Optional(Box::new(substitute(inner, subst))),
+        Type::Tuple(elems) => Type::Tuple(elems.iter().map(|e| substitute(e, subst)).collect()),
+        Type::Generic(n, args) => Type::Generic(n.clone(), args.iter().map(|a| substitute(a, subst)).collect()),
+        Type::Func(params, ret) => Type::Func(
+            params.iter().map(|p| substitute(p, subst)).collect(),
+            Box::new(substitute(ret, subst)),
+        ),
+        _ => ty.clone(),
+    }
+}
+
+/// One-sided matching of a pattern against a call-site type. `Unknown` and `Any`
+/// only satisfy type variables, so an unresolved argument falls back to the most
+/// generic implementation instead of an arbitrary specialized one.
+fn match_type(pat: &Type, actual: &Type, subst: &mut Subst) -> bool {
+    if let Type::Parameter(v) = pat {
+        return match subst.get(v) {
+            None | Some(Type::Unknown | Type::Any) => {
+                subst.insert(v.clone(), actual.clone());
+                true
+            }
+            Some(bound) => bound == actual || matches!(actual, Type::Unknown | Type::Any),
+        };
+    }
+    match (pat, actual) {
+        (_, Type::Unknown | Type::Any) => false,
+        (Type::Optional(_), Type::Nothing) => true,
+        (Type::Optional(p), Type::Optional(a)) => match_type(p, a, subst),
+        (Type::Optional(p), a) => match_type(p, a, subst),
+        (Type::Array(p), Type::Array(a)) | (Type::Tensor(p, _), Type::Tensor(a, _)) => match_type(p, a, subst),
+        (Type::Tuple(ps), Type::Tuple(as_)) => {
+            ps.len() == as_.len() && ps.iter().zip(as_).all(|(p, a)| match_type(p, a, subst))
+        }
+        (Type::Generic(pn, ps), Type::Generic(an, as_)) => {
+            pn == an && ps.len() == as_.len() && ps.iter().zip(as_).all(|(p, a)| match_type(p, a, subst))
+        }
+        (Type::Func(pp, pr), Type::Func(ap, ar)) => {
+            pp.len() == ap.len()
+                && pp.iter().zip(ap).all(|(p, a)| match_type(p, a, subst))
+                && match_type(pr, ar, subst)
+        }
+        _ => pat == actual,
+    }
+}
+
+/// True when every call `a` accepts is also accepted by `b`, i.e. `b`'s patterns
+/// match `a`'s patterns with `a`'s own variables held abstract.
+fn is_instance_of(a: &Specialization, b: &Specialization, explicit: bool) -> bool {
+    let mut subst = Subst::new();
+    let generic_ok = !explicit
+        || (a.generic_args.len() == b.generic_args.len()
+            && b.generic_args.iter().zip(&a.generic_args).all(|(p, t)| match_abstract(p, t, &mut subst)));
+    let receiver_ok = match (&b.receiver, &a.receiver) {
+        (Some(p), Some(t)) => match_abstract(p, t, &mut subst),
+        (None, None) => true,
+        _ => false,
+    };
+    generic_ok
+        && receiver_ok
+        && a.params.len() == b.params.len()
+        && b.params.iter().zip(&a.params).all(|(p, t)| match_abstract(p, t, &mut subst))
+}
+
+fn strictly_more_specific(a: &Specialization, b: &Specialization, explicit: bool) -> bool {
+    is_instance_of(a, b, explicit) && !is_instance_of(b, a, explicit)
+}
+
+/// Like `match_type`, but the right-hand side is itself a pattern whose
+/// variables only equal themselves.
+fn match_abstract(pat: &Type, ty: &Type, subst: &mut Subst) -> bool {
+    if let Type::Parameter(v) = pat {
+        return match subst.get(v) {
+            Some(bound) => bound == ty,
+            None => {
+                subst.insert(v.clone(), ty.clone());
+                true
+            }
+        };
+    }
+    match (pat, ty) {
+        (Type::Optional(p), Type::Optional(t))
+        | (Type::Array(p), Type::Array(t))
+        | (Type::Tensor(p, _), Type::Tensor(t, _)) => match_abstract(p, t, subst),
+        (Type::Tuple(ps), Type::Tuple(ts)) => {
+            ps.len() == ts.len() && ps.iter().zip(ts).all(|(p, t)| match_abstract(p, t, subst))
+        }
+        (Type::Generic(pn, ps), Type::Generic(tn, ts)) => {
+            pn == tn && ps.len() == ts.len() && ps.iter().zip(ts).all(|(p, t)| match_abstract(p, t, subst))
+        }
+        (Type::Func(pp, pr), Type::Func(tp, tr)) => {
+            pp.len() == tp.len()
+                && pp.iter().zip(tp).all(|(p, t)| match_abstract(p, t, subst))
+                && match_abstract(pr, tr, subst)
+        }
+        _ => pat == ty,
+    }
+}
+
+/// Renames the variables of one side so two patterns can be unified without
+/// their variables capturing each other.
+fn tag(ty: &Type, prefix: &str) -> Type {
+    match ty {
+        Type::Parameter(v) if !v.contains('#') => Type::Parameter(format!("{prefix}#{v}")),
+        Type::Array(inner) => Type::Array(Box::new(tag(inner, prefix))),
+        Type::Tensor(inner, shape) => Type::Tensor(Box::new(tag(inner, prefix)), shape.clone()),
+        Type::Optional(inner) => Type::Optional(Box::new(tag(inner, prefix))),
+        Type::Tuple(elems) => Type::Tuple(elems.iter().map(|e| tag(e, prefix)).collect()),
+        Type::Generic(n, args) => Type::Generic(n.clone(), args.iter().map(|a| tag(a, prefix)).collect()),
+        Type::Func(params, ret) => Type::Func(params.iter().map(|p| tag(p, prefix)).collect(), Box::new(tag(ret, prefix))),
+        _ => ty.clone(),
+    }
+}
+
+/// True when some concrete call would be accepted by both `a` and `b`.
+fn overlaps(a: &Specialization, b: &Specialization, explicit: bool) -> bool {
+    let mut subst = Subst::new();
+    let mut pairs: Vec<(Type, Type)> = Vec::new();
+    if explicit {
+        pairs.extend(a.generic_args.iter().zip(&b.generic_args).map(|(x, y)| (tag(x, "a"), tag(y, "b"))));
+    }
+    match (&a.receiver, &b.receiver) {
+        (Some(x), Some(y)) => pairs.push((tag(x, "a"), tag(y, "b"))),
+        (None, None) => {}
+        _ => return false,
+    }
+    pairs.extend(a.params.iter().zip(&b.params).map(|(x, y)| (tag(x, "a"), tag(y, "b"))));
+    pairs.iter().all(|(x, y)| unify(x, y, &mut subst))
+}
+
+fn resolve(ty: &Type, subst: &Subst) -> Type {
+    let mut cur = ty.clone();
+    while let Type::Parameter(v) = &cur {
+        match subst.get(v) {
+            Some(next) => cur = next.clone(),
+            None => break,
+        }
+    }
+    cur
+}
+
+fn unify(x: &Type, y: &Type, subst: &mut Subst) -> bool {
+    let x = resolve(x, subst);
+    let y = resolve(y, subst);
+    match (&x, &y) {
+        (Type::Parameter(a), Type::Parameter(b)) if a == b => true,
+        (Type::Parameter(v), other) | (other, Type::Parameter(v)) => {
+            if occurs(v, other, subst) {
+                return false;
+            }
+            subst.insert(v.clone(), other.clone());
+            true
+        }
+        (Type::Optional(p), Type::Optional(q))
+        | (Type::Array(p), Type::Array(q))
+        | (Type::Tensor(p, _), Type::Tensor(q, _)) => unify(p, q, subst),
+        (Type::Tuple(ps), Type::Tuple(qs)) => ps.len() == qs.len() && ps.iter().zip(qs).all(|(p, q)| unify(p, q, subst)),
+        (Type::Generic(pn, ps), Type::Generic(qn, qs)) => {
+            pn == qn && ps.len() == qs.len() && ps.iter().zip(qs).all(|(p, q)| unify(p, q, subst))
+        }
+        (Type::Func(pp, pr), Type::Func(qp, qr)) => {
+            pp.len() == qp.len() && pp.iter().zip(qp).all(|(p, q)| unify(p, q, subst)) && unify(pr, qr, subst)
+        }
+        _ => x == y,
+    }
+}
+
+fn occurs(var: &str, ty: &Type, subst: &Subst) -> bool {
+    match resolve(ty, subst) {
+        Type::Parameter(v) => v == var,
+        Type::Array(inner) | Type::Tensor(inner, _) | Type::Optional(inner) => occurs(var, &inner, subst),
+        Type::Tuple(elems) | Type::Generic(_, elems) => elems.iter().any(|e| occurs(var, e, subst)),
+        Type::Func(params, ret) => params.iter().any(|p| occurs(var, p, subst)) || occurs(var, &ret, subst),
+        _ => false,
+    }
+}
+
+fn describe(spec: &Specialization) -> String {
+    let mut out = String::new();
+    if let Some(recv) = &spec.receiver {
+        out.push_str(&format!("{:?}.", recv));
+    }
+    out.push_str(&spec.name);
+    if !spec.generic_args.is_empty() {
+        let args: Vec<String> = spec.generic_args.iter().map(|a| format!("{:?}", a)).collect();
+        out.push_str(&format!("<{}>", args.join(", ")));
+    }
+    let params: Vec<String> = spec.params.iter().map(|p| format!("{:?}", p)).collect();
+    out.push_str(&format!("({})", params.join(", ")));
+    format!("'{}'", out)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::lexer::Lexer;
+    use crate::parser::Parser;
+
+    fn engine(src: &str) -> SpecializationEngine {
+        let tokens = Lexer::new(src).tokenize().unwrap();
+        let program = Parser::new(tokens).parse_program().unwrap();
+        SpecializationEngine::from_program(&program)
+    }
+
+    fn select(engine: &SpecializationEngine, name: &str, generic_args: &[Type], args: &[Type]) -> Option<String> {
+        let query = SpecQuery { generic_args, receiver: None, args: Some(args) };
+        match engine.find_best_match(name, &query) {
+            Resolution::Selected(spec, _) => crate::mangle::demangle(&spec.symbol),
+            _ => None,
+        }
+    }
+
+    #[test]
+    fn pinned_generic_slot_wins_for_matching_type_argument() {
+        let e = engine("fun typeName<T>() -> String { \"Generic\" } fun typeName<Int>() -> String { \"Integer\" }");
+        assert_eq!(select(&e, "typeName", &[Type::Int], &[]).as_deref(), Some("typeName<Int>()"));
+        assert_eq!(select(&e, "typeName", &[Type::Float], &[]).as_deref(), Some("typeName<T>()"));
+    }
+
+    #[test]
+    fn most_specific_parameter_pattern_is_selected() {
+        let e = engine("fun show<T>(x: T) -> Int { 0 } fun show<T>(x: [T]) -> Int { 1 } fun show(x: [Int]) -> Int { 2 }");
+        assert_eq!(select(&e, "show", &[], &[Type::Bool]).as_deref(), Some("show<T>(T)"));
+        assert_eq!(select(&e, "show", &[], &[Type::Array(Box::new(Type::Bool))]).as_deref(), Some("show<T>([T])"));
+        assert_eq!(select(&e, "show", &[], &[Type::Array(Box::new(Type::Int))]).as_deref(), Some("show([Int])"));
+        assert!(e.check_overlaps().is_empty());
+    }
+
+    #[test]
+    fn unknown_argument_falls_back_to_generic() {
+        let e = engine("fun show<T>(x: T) -> Int { 0 } fun show(x: Int) -> Int { 1 }");
+        assert_eq!(select(&e, "show", &[], &[Type::Unknown]).as_deref(), Some("show<T>(T)"));
+    }
+
+    #[test]
+    fn incomparable_overlap_is_reported() {
+        let e = engine("fun pair<T>(a: T, b: Int) -> Int { 0 } fun pair<T>(a: Int, b: T) -> Int { 1 }");
+        let diags = e.check_overlaps();
+        assert!(diags.iter().any(|d| d.message.contains("ambiguous overlapping implementations of 'pair'")));
+        let query = SpecQuery { generic_args: &[], receiver: None, args: Some(&[Type::Int, Type::Int]) };
+        assert!(matches!(e.find_best_match("pair", &query), Resolution::Ambiguous(c) if c.len() == 2));
+    }
+
+    #[test]
+    fn duplicate_pattern_is_reported() {
+        let e = engine("fun f(x: Int) -> Int { 0 } fun f(y: Int) -> Int { 1 }");
+        assert!(e.check_overlaps().iter().any(|d| d.message.contains("conflicting implementations of 'f'")));
+    }
+
+    #[test]
+    fn extension_receiver_patterns_specialize() {
+        let e = engine("struct Box<T> { value: T } fun Box<T>.describe<T>() -> Int { 0 } fun Box<Int>.describe() -> Int { 1 }");
+        let recv = Type::Generic("Box".to_string(), vec![Type::Int]);
+        let query = SpecQuery { generic_args: &[], receiver: Some(&recv), args: None };
+        match e.find_best_match("describe", &query) {
+            Resolution::Selected(spec, _) => assert_eq!(crate::mangle::demangle(&spec.symbol).as_deref(), Some("Box<Int>.describe()")),
+            _ => panic!("expected a unique selection"),
+        }
     }
 }
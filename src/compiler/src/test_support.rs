@@ -0,0 +1,21 @@
+use crate::diag::Diagnostic;
+use crate::lexer::Lexer;
+use crate::parser::Parser;
+use crate::sema::Sema;
+
+/// Lexes, parses and checks `src`.
+pub(crate) fn check(src: &str) -> Result<Vec<Diagnostic>, Vec<Diagnostic>> {
+    let tokens = Lexer::new(src).tokenize().expect("lex");
+    let program = Parser::new(tokens).parse_program().expect("parse");
+    Sema::new().check_program(&program)
+}
+
+/// Lexes, parses and checks `src`; returns the messages of the reported
+/// diagnostics, warnings included, in order.
+pub(crate) fn errors(src: &str) -> Vec<String> {
+    let diags = match check(src) {
+        Ok(warnings) => warnings,
+        Err(diags) => diags,
+    };
+    diags.into_iter().map(|d| d.message).collect()
+}
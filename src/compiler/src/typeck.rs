@@ -1,5 +1,10 @@
+use crate::ast::{BinaryOp, Block, Expr, Literal, Pattern, Stmt, UnaryOp};
 use crate::sema::{Type, Sema};
 use crate::diag::{Diagnostic, Span};
+use std::collections::HashSet;
+
+/// Variables and the types they are known to have once a condition is decided.
+pub type Facts = Vec<(String, Type)>;
 
 pub fn check_nullability(sema: &mut Sema, expected: &Type, actual: &Type, span: Span) {
     if let Type::Optional(_) = actual {
@@ -14,7 +19,427 @@ pub fn check_extension_member(sema: &mut Sema, receiver_ty: &Type, name: &str, s
 }
 
 impl Sema {
+    /// What `cond` proves about nullable variables when it is true and when it
+    /// is false. Only direct `x == null` / `x != null` tests and their
+    /// combinations through `!`, `&&` and `||` are understood.
+    pub(crate) fn null_facts(&self, cond: &Expr) -> (Facts, Facts) {
+        match cond {
+            Expr::Binary { left, op: op @ (BinaryOp::Eq | BinaryOp::NotEq), right, .. } => {
+                let name = match (&**left, &**right) {
+                    (Expr::Ident(n, _), Expr::Ident(null, _)) | (Expr::Ident(null, _), Expr::Ident(n, _)) if null == "null" => n,
+                    _ => return (Vec::new(), Vec::new()),
+                };
+                let fact = match self.peek_var(name) {
+                    Some(Type::Optional(inner)) => vec![(name.clone(), *inner)],
+                    _ => Vec::new(),
+                };
+                if matches!(op, BinaryOp::NotEq) {
+                    (fact, Vec::new())
+                } else {
+                    (Vec::new(), fact)
+                }
+            }
+            Expr::Binary { left, op: BinaryOp::And, right, .. } => {
+                let (mut on_true, _) = self.null_facts(left);
+                on_true.extend(self.null_facts(right).0);
+                (on_true, Vec::new())
+            }
+            Expr::Binary { left, op: BinaryOp::Or, right, .. } => {
+                let (_, mut on_false) = self.null_facts(left);
+                on_false.extend(self.null_facts(right).1);
+                (Vec::new(), on_false)
+            }
+            Expr::Unary { op: UnaryOp::Not, expr, .. } => {
+                let (on_true, on_false) = self.null_facts(expr);
+                (on_false, on_true)
+            }
+            _ => (Vec::new(), Vec::new()),
+        }
+    }
+
+    /// Applies `facts` to the innermost scope.
+    pub(crate) fn narrow(&mut self, facts: &Facts) {
+        if let Some(scope) = self.scopes.last_mut() {
+            for (name, ty) in facts {
+                scope.narrowed.insert(name.clone(), ty.clone());
+            }
+        }
+    }
+
+    /// `match x { is T => ... }` treats `x` as a `T` inside the arm.
+    pub(crate) fn narrow_by_pattern(&mut self, scrutinee: &Expr, pat: &Pattern) {
+        if let (Expr::Ident(name, _), Pattern::Is(tr, _, _)) = (scrutinee, pat) {
+            if self.peek_var(name).is_some() {
+                let ty = self.type_from_ref(tr);
+                self.narrow(&vec![(name.clone(), ty)]);
+            }
+        }
+    }
+
+    pub(crate) fn forget_narrowing(&mut self, name: &str) {
+        for scope in &mut self.scopes {
+            scope.narrowed.remove(name);
+        }
+    }
+
+    /// A loop body may run again after it reassigns a variable, so narrowing
+    /// from before the loop does not hold for anything the body assigns.
+    pub(crate) fn forget_assigned(&mut self, body: &Block) {
+        let mut names = HashSet::new();
+        assigned_in_block(body, &mut names);
+        for name in names {
+            self.forget_narrowing(&name);
+        }
+    }
+
+    /// The type `??` produces: the left operand without its `?`, unless the
+    /// fallback is itself nullable.
+    pub(crate) fn coalesce_type(&mut self, lt: Type, rt: Type, span: Span) -> Type {
+        let inner = match lt {
+            Type::Optional(inner) => *inner,
+            Type::Nothing => return rt,
+            other => other,
+        };
+        match rt {
+            Type::Optional(_) | Type::Nothing => {
+                let inner = Type::Optional(Box::new(inner));
+                self.unify(&inner, &rt, span);
+                inner
+            }
+            rt => {
+                self.unify(&inner, &rt, span);
+                inner
+            }
+        }
+    }
+
+    fn peek_var(&self, name: &str) -> Option<Type> {
+        self.scopes
+            .iter()
+            .rev()
+            .find_map(|s| s.narrowed.get(name).or_else(|| s.vars.get(name)))
+            .cloned()
+    }
+
     pub fn report_error(&mut self, msg: impl Into<String>, span: Span) {
         self.diags.push(Diagnostic::error(msg, span));
     }
 }
+
+/// Integer intrinsics: `wrapping*` wrap around on overflow and `checked*`
+/// return null instead of overflowing or dividing by zero.
+pub const INT_INTRINSICS: [&str; 7] =
+    ["wrappingAdd", "wrappingSub", "wrappingMul", "checkedAdd", "checkedSub", "checkedMul", "checkedDiv"];
+
+/// The value of an integer literal, possibly negated.
+fn int_literal(expr: &Expr) -> Option<i128> {
+    match expr {
+        Expr::Literal(Literal::Int(v), _) => Some(*v as i128),
+        Expr::Unary { op: UnaryOp::Neg, expr, .. } => int_literal(expr).map(|v| -v),
+        _ => None,
+    }
+}
+
+fn is_float_literal(expr: &Expr) -> bool {
+    match expr {
+        Expr::Literal(Literal::Float(_), _) => true,
+        Expr::Unary { op: UnaryOp::Neg, expr, .. } => is_float_literal(expr),
+        _ => false,
+    }
+}
+
+impl Sema {
+    /// Unsuffixed numeric literals take the numeric type the context expects,
+    /// provided the value fits it.
+    pub(crate) fn adapt_literal(&mut self, expected: &Type, expr: &Expr, actual: Type) -> Type {
+        if expected.is_integer() && actual == Type::Int {
+            if let Some(v) = int_literal(expr) {
+                if expected.fits_int(v) {
+                    return expected.clone();
+                }
+                self.report_error(format!("integer literal {} does not fit in {:?}", v, expected), self.span_of(expr));
+                return expected.clone();
+            }
+        }
+        if *expected == Type::Float32 && actual == Type::Float && is_float_literal(expr) {
+            return Type::Float32;
+        }
+        if let (Type::Tuple(es), Expr::Tuple(items, _), Type::Tuple(actuals)) = (expected, expr, &actual) {
+            if es.len() == items.len() && items.len() == actuals.len() {
+                let adapted = es.iter().zip(items).zip(actuals).map(|((e, item), a)| self.adapt_literal(e, item, a.clone())).collect();
+                return Type::Tuple(adapted);
+            }
+        }
+        actual
+    }
+
+    /// Result type of an arithmetic operator. `Int` and `UInt` keep their
+    /// historical promotion to `Float` when mixed; sized numbers must match
+    /// exactly.
+    pub(crate) fn arith_type(&mut self, lt: Type, rt: Type, span: Span) -> Type {
+        match (&lt, &rt) {
+            (Type::Int, Type::Int) => Type::Int,
+            (Type::UInt, Type::UInt) => Type::UInt,
+            _ if lt == rt && lt.is_sized() => lt,
+            (Type::Unknown | Type::Any, t) | (t, Type::Unknown | Type::Any) if t.is_sized() => t.clone(),
+            _ if lt.is_sized() || rt.is_sized() => {
+                self.report_error(format!("mismatched numeric types {:?} and {:?}; convert one with 'as'", lt, rt), span);
+                Type::Unknown
+            }
+            _ => Type::Float,
+        }
+    }
+
+    /// `as` converts between numeric types, and between integers and
+    /// `Char`/`Bool`.
+    pub(crate) fn check_cast(&mut self, from: &Type, to: &Type, span: Span) {
+        let ok = from == to
+            || matches!(from, Type::Unknown | Type::Any)
+            || (from.is_numeric() && to.is_numeric())
+            || (matches!(from, Type::Bool | Type::Char) && to.is_integer())
+            || (from.is_integer() && *to == Type::Char);
+        if !ok {
+            self.report_error(format!("cannot cast {:?} to {:?}", from, to), span);
+        }
+    }
+
+    pub(crate) fn check_int_intrinsic(&mut self, name: &str, args: &[Expr], span: Span, nogc: bool) -> Type {
+        if args.len() != 2 {
+            self.report_error(format!("{} expects two integer arguments", name), span);
+            return Type::Unknown;
+        }
+        let a = self.check_expr_with(&args[0], nogc);
+        let b = self.check_expr_with(&args[1], nogc);
+        let a = self.adapt_literal(&b, &args[0], a);
+        let b = self.adapt_literal(&a, &args[1], b);
+        let ty = match (&a, &b) {
+            (Type::Unknown | Type::Any, t) | (t, Type::Unknown | Type::Any) => t.clone(),
+            _ if a == b && a.is_integer() => a,
+            _ => {
+                self.report_error(format!("{} expects two integers of the same type, got {:?} and {:?}", name, a, b), span);
+                return Type::Unknown;
+            }
+        };
+        if name.starts_with("checked") {
+            Type::Optional(Box::new(ty))
+        } else {
+            ty
+        }
+    }
+}
+
+/// Whether control never reaches the end of `block`.
+pub fn block_diverges(block: &Block) -> bool {
+    block.stmts.iter().any(stmt_diverges)
+}
+
+pub fn stmt_diverges(stmt: &Stmt) -> bool {
+    match stmt {
+        Stmt::Return(..) | Stmt::Break(..) | Stmt::Continue(..) => true,
+        Stmt::If(_, then_block, Some(else_stmt), _) => block_diverges(then_block) && stmt_diverges(else_stmt),
+        Stmt::Block(b) => block_diverges(b),
+        _ => false,
+    }
+}
+
+fn assigned_in_block(block: &Block, out: &mut HashSet<String>) {
+    for stmt in &block.stmts {
+        assigned_in_stmt(stmt, out);
+    }
+    if let Some(tail) = &block.tail {
+        assigned_in_expr(tail, out);
+    }
+}
+
+fn assigned_in_stmt(stmt: &Stmt, out: &mut HashSet<String>) {
+    match stmt {
+        Stmt::Var(v) => assigned_in_expr(&v.value, out),
+        Stmt::Expr(e, _) | Stmt::Return(Some(e), _) => assigned_in_expr(e, out),
+        Stmt::If(cond, then_block, else_stmt, _) => {
+            assigned_in_expr(cond, out);
+            assigned_in_block(then_block, out);
+            if let Some(s) = else_stmt {
+                assigned_in_stmt(s, out);
+            }
+        }
+        Stmt::While(cond, body, ..) | Stmt::For(_, cond, body, ..) => {
+            assigned_in_expr(cond, out);
+            assigned_in_block(body, out);
+        }
+        Stmt::Break(_, Some(e), _) => assigned_in_expr(e, out),
+        Stmt::Defer(b, _) => assigned_in_block(b, out),
+        Stmt::Match(e, arms, _) => {
+            assigned_in_expr(e, out);
+            for arm in arms {
+                assigned_in_expr(&arm.body, out);
+            }
+        }
+        Stmt::Block(b) => assigned_in_block(b, out),
+        Stmt::Return(None, _) | Stmt::Break(_, None, _) | Stmt::Continue(..) => {}
+    }
+}
+
+fn assigned_in_expr(expr: &Expr, out: &mut HashSet<String>) {
+    match expr {
+        Expr::Assign { left, right, .. } => {
+            if let Expr::Ident(name, _) = &**left {
+                out.insert(name.clone());
+            }
+            assigned_in_expr(right, out);
+        }
+        Expr::Unary { expr, .. } | Expr::Cast { expr, .. } => assigned_in_expr(expr, out),
+        Expr::Binary { left, right, .. }
+        | Expr::Index { target: left, index: right, .. }
+        | Expr::Range { start: left, end: right, .. } => {
+            assigned_in_expr(left, out);
+            assigned_in_expr(right, out);
+        }
+        Expr::Call { callee, args, .. } => {
+            assigned_in_expr(callee, out);
+            args.iter().for_each(|a| assigned_in_expr(a, out));
+        }
+        Expr::Member { target, .. } => assigned_in_expr(target, out),
+        Expr::If { cond, then_block, else_block, .. } => {
+            assigned_in_expr(cond, out);
+            assigned_in_block(then_block, out);
+            assigned_in_block(else_block, out);
+        }
+        Expr::Match { expr, arms, .. } => {
+            assigned_in_expr(expr, out);
+            for arm in arms {
+                assigned_in_expr(&arm.body, out);
+            }
+        }
+        Expr::Block(b) | Expr::Loop { body: b, .. } => assigned_in_block(b, out),
+        Expr::StructLit { fields, .. } => fields.iter().for_each(|(_, e)| assigned_in_expr(e, out)),
+        Expr::Array(items, _) | Expr::Tuple(items, _) | Expr::Interpolated { parts: items, .. } => items.iter().for_each(|e| assigned_in_expr(e, out)),
+        Expr::Literal(..) | Expr::Ident(..) | Expr::Instantiate { .. } | Expr::Tensor(..) => {}
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::test_support::errors;
+
+    #[test]
+    fn null_checks_narrow_optionals() {
+        let src = "fun f(x: Int?, y: Int?) -> Int {\n\
+                       if (x == null) { return 0; }\n\
+                       let a: Int = x;\n\
+                       let b: Int = if (y != null && y > 0) { y } else { 0 };\n\
+                       let c: Int = y ?: 1;\n\
+                       a + b + c\n\
+                   }";
+        assert!(errors(src).is_empty(), "{:?}", errors(src));
+    }
+
+    #[test]
+    fn narrowing_ends_at_assignment_and_branch_exit() {
+        let src = "fun f(x: Int?) -> Int {\n\
+                       var y: Int? = x;\n\
+                       if (y != null) { y = null; let a: Int = y; }\n\
+                       let b: Int = x;\n\
+                       0\n\
+                   }";
+        assert_eq!(
+            errors(src),
+            [
+                "cannot use nullable value of type Optional(Int) where Int is expected",
+                "cannot use nullable value of type Optional(Int) where Int is expected",
+            ]
+        );
+    }
+
+    #[test]
+    fn tuples_are_typed_by_position() {
+        let src = "fun divmod(a: Int, b: Int) -> (Int, Int) { (a / b, a % b) }\n\
+                   fun f() -> Int {\n\
+                       let (q, r) = divmod(7, 2);\n\
+                       let t: (UInt8, (Bool, String)) = (255, (true, \"x\"));\n\
+                       let ok: Bool = t.1.0;\n\
+                       let n: UInt8 = t.0;\n\
+                       let bad = t.2;\n\
+                       q + r\n\
+                   }";
+        assert_eq!(errors(src), ["tuple index 2 out of range for a tuple of 2 elements"]);
+    }
+
+    #[test]
+    fn sized_numbers_need_explicit_conversions() {
+        let src = "fun f(a: Int32, b: UInt8) -> Int32 {\n\
+                       let c: UInt8 = 256;\n\
+                       let d = a + 1i64;\n\
+                       let e = \"x\" as Int;\n\
+                       a + b as Int32 + 1\n\
+                   }";
+        assert_eq!(
+            errors(src),
+            [
+                "integer literal 256 does not fit in UInt8",
+                "mismatched numeric types Int32 and Int; convert one with 'as'",
+                "cannot cast String to Int",
+            ]
+        );
+    }
+
+    #[test]
+    fn checked_intrinsics_return_nullable_results() {
+        let src = "fun f(a: UInt8) -> UInt8 { let s: UInt8? = checkedAdd(a, 1); wrappingMul(a, 3) + (s ?: 0) }";
+        assert!(errors(src).is_empty(), "{:?}", errors(src));
+    }
+
+    #[test]
+    fn jumps_must_target_an_enclosing_loop() {
+        let src = "fun f(n: Int) -> Int {\n\
+                       let k: Int = loop { if (n > 0) { break n; } break 0; };\n\
+                       outer: while (true) { defer { break; } while (true) { continue outer; } }\n\
+                       while (true) { break 1; }\n\
+                       continue inner;\n\
+                       defer { return 2; }\n\
+                       break;\n\
+                       k\n\
+                   }";
+        assert_eq!(
+            errors(src),
+            [
+                "'break' cannot leave a defer block",
+                "'break' with a value is only allowed in 'loop'",
+                "unknown loop label 'inner'",
+                "'return' cannot leave a defer block",
+                "'break' outside of a loop",
+            ]
+        );
+    }
+
+    #[test]
+    fn labels_are_scoped_to_their_loop() {
+        let src = "fun f() -> Int {\n\
+                       a: while (true) { break a; }\n\
+                       while (true) { continue a; }\n\
+                       b: while (true) { b: while (true) { continue b; } break b; }\n\
+                       c: while (true) { defer { while (true) { break; } continue c; } break c; }\n\
+                       defer { while (true) { return 1; } }\n\
+                       0\n\
+                   }";
+        assert_eq!(
+            errors(src),
+            [
+                "unknown loop label 'a'",
+                "'continue' cannot leave a defer block",
+                "'return' cannot leave a defer block",
+            ]
+        );
+    }
+
+    #[test]
+    fn unknown_break_label_is_reported() {
+        let src = "fun f() -> Int {\n\
+                       outer: while (true) { break nope; }\n\
+                       loop { break nope; }\n\
+                       let n = 1;\n\
+                       let m: Int = loop { break n; };\n\
+                       m\n\
+                   }";
+        assert_eq!(errors(src), ["unknown loop label 'nope'", "unknown loop label 'nope'"]);
+    }
+}
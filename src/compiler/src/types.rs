@@ -1,10 +1,17 @@
+use crate::ast::{ShapeDim, TypeRef};
+use crate::diag::Span;
 use std::collections::HashMap;
+use std::fmt;
 
-#[derive(Debug, Clone, PartialEq)]
+#[derive(Clone, PartialEq)]
 pub enum Type {
     Int,
     UInt,
     Float,
+    /// `Int8`..`Int32` and `UInt8`..`UInt32`. The 64-bit spellings are
+    /// `Int` and `UInt`.
+    SizedInt { bits: u32, signed: bool },
+    Float32,
     Bool,
     Char,
     String,
@@ -13,7 +20,9 @@ pub enum Type {
     Nothing,
     Tuple(Vec<Type>),
     Array(Box<Type>),
-    Tensor(Box<Type>),
+    /// Element type and shape; the shape is `None` when even the rank is
+    /// unknown.
+    Tensor(Box<Type>, Option<Vec<Dim>>),
     Named(String),
     Func(Vec<Type>, Box<Type>),
     Optional(Box<Type>),
@@ -22,18 +31,220 @@ pub enum Type {
     Unknown,
 }
 
+/// One tensor dimension: a constant size, a symbolic size such as `B` that is
+/// bound per call, or `_`, which is compatible with anything.
+#[derive(Clone, PartialEq)]
+pub enum Dim {
+    Known(i64),
+    Sym(String),
+    Unknown,
+}
+
+impl Dim {
+    /// The dimension as written, before any constant is resolved.
+    pub fn from_shape_dim(dim: &ShapeDim, _span: Span) -> Dim {
+        match dim {
+            ShapeDim::Int(v) => Dim::Known(*v),
+            ShapeDim::Ident(name) => Dim::Sym(name.clone()),
+            ShapeDim::Expr(_) | ShapeDim::Unknown => Dim::Unknown,
+        }
+    }
+}
+
+impl fmt::Debug for Dim {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Dim::Known(v) => write!(f, "{}", v),
+            Dim::Sym(name) => f.write_str(name),
+            Dim::Unknown => f.write_str("_"),
+        }
+    }
+}
+
+/// Derived formatting, except that sized numbers print under their source
+/// names so diagnostics read `Int8` rather than the variant's fields.
+impl fmt::Debug for Type {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Type::Int => f.write_str("Int"),
+            Type::UInt => f.write_str("UInt"),
+            Type::Float => f.write_str("Float"),
+            Type::SizedInt { bits, signed } => write!(f, "{}Int{}", if *signed { "" } else { "U" }, bits),
+            Type::Float32 => f.write_str("Float32"),
+            Type::Bool => f.write_str("Bool"),
+            Type::Char => f.write_str("Char"),
+            Type::String => f.write_str("String"),
+            Type::Unit => f.write_str("Unit"),
+            Type::Any => f.write_str("Any"),
+            Type::Nothing => f.write_str("Nothing"),
+            Type::Tuple(items) => f.debug_tuple("Tuple").field(items).finish(),
+            Type::Array(inner) => f.debug_tuple("Array").field(inner).finish(),
+            Type::Tensor(elem, Some(shape)) => write!(f, "Tensor<{:?}, {:?}>", elem, shape),
+            Type::Tensor(elem, None) => write!(f, "Tensor<{:?}>", elem),
+            Type::Named(name) => f.debug_tuple("Named").field(name).finish(),
+            Type::Func(params, ret) => f.debug_tuple("Func").field(params).field(ret).finish(),
+            Type::Optional(inner) => f.debug_tuple("Optional").field(inner).finish(),
+            Type::Generic(name, args) => f.debug_tuple("Generic").field(name).field(args).finish(),
+            Type::Parameter(name) => f.debug_tuple("Parameter").field(name).finish(),
+            Type::Unknown => f.write_str("Unknown"),
+        }
+    }
+}
+
 impl Type {
     pub fn is_primitive(&self) -> bool {
-        matches!(self, Type::Int | Type::UInt | Type::Float | Type::Bool | Type::Char | Type::String | Type::Unit)
+        self.is_numeric() || matches!(self, Type::Bool | Type::Char | Type::String | Type::Unit)
+    }
+
+    /// Sized numbers never mix implicitly with other numeric types.
+    pub fn is_sized(&self) -> bool {
+        matches!(self, Type::SizedInt { .. } | Type::Float32)
     }
 
     pub fn is_numeric(&self) -> bool {
-        matches!(self, Type::Int | Type::UInt | Type::Float)
+        self.is_integer() || self.is_float()
+    }
+
+    pub fn is_integer(&self) -> bool {
+        self.int_info().is_some()
+    }
+
+    pub fn is_float(&self) -> bool {
+        matches!(self, Type::Float | Type::Float32)
+    }
+
+    /// Width and signedness of an integer type.
+    pub fn int_info(&self) -> Option<(u32, bool)> {
+        match self {
+            Type::Int => Some((64, true)),
+            Type::UInt => Some((64, false)),
+            Type::SizedInt { bits, signed } => Some((*bits, *signed)),
+            _ => None,
+        }
+    }
+
+    /// Whether the integer `v` is representable in this integer type.
+    pub fn fits_int(&self, v: i128) -> bool {
+        match self.int_info() {
+            Some((bits, true)) => v >= -(1i128 << (bits - 1)) && v < (1i128 << (bits - 1)),
+            Some((bits, false)) => v >= 0 && v < (1i128 << bits),
+            None => false,
+        }
+    }
+
+    /// Element type of `[T]` and `List<T>`, which share one representation.
+    pub fn list_element(&self) -> Option<Type> {
+        match self {
+            Type::Array(inner) => Some((**inner).clone()),
+            Type::Generic(name, args) if name == "List" && args.len() == 1 => Some(args[0].clone()),
+            _ => None,
+        }
+    }
+
+    pub fn range_element(&self) -> Option<Type> {
+        match self {
+            Type::Generic(name, args) if name == "Range" && args.len() == 1 => Some(args[0].clone()),
+            _ => None,
+        }
     }
 
     pub fn is_nullable(&self) -> bool {
         matches!(self, Type::Optional(_) | Type::Any)
     }
+
+    /// Truncates `v` to this integer type and widens it back to the 64-bit
+    /// carrier, sign- or zero-extending as the type demands. `UInt` values
+    /// keep their bit pattern.
+    pub fn wrap_int(&self, v: i128) -> i64 {
+        match self.int_info() {
+            Some((64, _)) | None => v as i64,
+            Some((bits, signed)) => {
+                let shift = 128 - bits;
+                if signed { ((v << shift) >> shift) as i64 } else { (((v as u128) << shift) >> shift) as i64 }
+            }
+        }
+    }
+
+    /// `f as T` for an integer type: NaN is zero and out-of-range values
+    /// clamp to the nearest bound.
+    pub fn saturate_float(&self, f: f64) -> i64 {
+        match self.int_info() {
+            Some((bits, true)) => (f as i128).clamp(-(1i128 << (bits - 1)), (1i128 << (bits - 1)) - 1) as i64,
+            Some((bits, false)) => (f as i128).clamp(0, (1i128 << bits) - 1) as u64 as i64,
+            None => f as i64,
+        }
+    }
+
+    /// Rounds `f` to the precision of this float type.
+    pub fn round_float(&self, f: f64) -> f64 {
+        if *self == Type::Float32 { f as f32 as f64 } else { f }
+    }
+
+    /// Looks up a builtin type by its source spelling.
+    pub fn builtin(name: &str) -> Option<Type> {
+        match name {
+            "Int" | "Int64" => Some(Type::Int),
+            "UInt" | "UInt64" => Some(Type::UInt),
+            "Float" | "Float64" => Some(Type::Float),
+            "Int8" => Some(Type::SizedInt { bits: 8, signed: true }),
+            "Int16" => Some(Type::SizedInt { bits: 16, signed: true }),
+            "Int32" => Some(Type::SizedInt { bits: 32, signed: true }),
+            "UInt8" => Some(Type::SizedInt { bits: 8, signed: false }),
+            "UInt16" => Some(Type::SizedInt { bits: 16, signed: false }),
+            "UInt32" => Some(Type::SizedInt { bits: 32, signed: false }),
+            "Float32" => Some(Type::Float32),
+            "Bool" => Some(Type::Bool),
+            "Char" => Some(Type::Char),
+            "String" => Some(Type::String),
+            "Void" | "Unit" => Some(Type::Unit),
+            "Any" => Some(Type::Any),
+            "Nothing" => Some(Type::Nothing),
+            _ => None,
+        }
+    }
+
+    /// Converts a syntactic type reference without consulting any scope.
+    pub fn from_type_ref(tr: &TypeRef) -> Type {
+        Type::from_type_ref_with(tr, &Dim::from_shape_dim)
+    }
+
+    /// Like `from_type_ref`, resolving tensor dimensions through `dim`.
+    pub fn from_type_ref_with(tr: &TypeRef, dim: &dyn Fn(&ShapeDim, Span) -> Dim) -> Type {
+        let conv = |t: &TypeRef| Type::from_type_ref_with(t, dim);
+        match tr {
+            TypeRef::Named(name, args, _span) => {
+                if args.is_empty() {
+                    Type::builtin(name).unwrap_or_else(|| Type::Named(name.clone()))
+                } else {
+                    let arg_tys = args.iter().map(conv).collect();
+                    Type::Generic(name.clone(), arg_tys)
+                }
+            }
+            TypeRef::Tuple(elems, _) => Type::Tuple(elems.iter().map(conv).collect()),
+            TypeRef::Array(inner, _) | TypeRef::FixedArray { elem: inner, .. } => Type::Array(Box::new(conv(inner))),
+            TypeRef::Tensor { elem, shape, span } => {
+                Type::Tensor(Box::new(conv(elem)), Some(shape.iter().map(|d| dim(d, *span)).collect()))
+            }
+            TypeRef::Optional(inner, _) => Type::Optional(Box::new(conv(inner))),
+            TypeRef::NonNull(inner, _) => conv(inner),
+        }
+    }
+
+    /// Replaces generic parameters (spelled as `Named`) with their arguments.
+    pub fn substitute(&self, subst: &HashMap<String, Type>) -> Type {
+        match self {
+            Type::Named(name) | Type::Parameter(name) => subst.get(name).cloned().unwrap_or_else(|| self.clone()),
+            Type::Generic(name, args) => Type::Generic(name.clone(), args.iter().map(|a| a.substitute(subst)).collect()),
+            Type::Tuple(elems) => Type::Tuple(elems.iter().map(|e| e.substitute(subst)).collect()),
+            Type::Array(inner) => Type::Array(Box::new(inner.substitute(subst))),
+            Type::Tensor(inner, shape) => Type::Tensor(Box::new(inner.substitute(subst)), shape.clone()),
+            Type::Optional(inner) => Type::Optional(Box::new(inner.substitute(subst))),
+            Type::Func(params, ret) => {
+                Type::Func(params.iter().map(|p| p.substitute(subst)).collect(), Box::new(ret.substitute(subst)))
+            }
+            _ => self.clone(),
+        }
+    }
 }
 
 pub struct GenericContext {
@@ -0,0 +1,151 @@
+//! Demangling of Korlang symbols, described in `docs/symbol-mangling.md`.
+//!
+//! Shared by the compiler and the runtime, which demangles its backtraces, so
+//! it depends on nothing but `std`.
+
+/// Starts every mangled symbol.
+pub const PREFIX: &str = "_K";
+
+/// The source-level spelling of a mangled symbol, e.g. `Point.len()` for
+/// `_KN5Point3lenEZE`. Returns `None` for anything that is not a complete
+/// Korlang symbol.
+pub fn demangle(symbol: &str) -> Option<String> {
+    let mut d = Demangler { s: symbol.as_bytes(), pos: 0 };
+    let out = d.symbol()?;
+    (d.pos == symbol.len()).then_some(out)
+}
+
+/// Replaces every Korlang symbol in `text`, such as a backtrace, with its
+/// demangled form and leaves the rest untouched.
+pub fn demangle_text(text: &str) -> String {
+    let bytes = text.as_bytes();
+    let mut out = String::with_capacity(text.len());
+    let mut i = 0;
+    while i < text.len() {
+        let at_word_start = i == 0 || !is_symbol_char(bytes[i - 1]);
+        if at_word_start && text[i..].starts_with(PREFIX) {
+            let mut d = Demangler { s: &bytes[i..], pos: 0 };
+            if let Some(plain) = d.symbol() {
+                out.push_str(&plain);
+                i += d.pos;
+                continue;
+            }
+        }
+        let ch = text[i..].chars().next().unwrap_or_default();
+        out.push(ch);
+        i += ch.len_utf8().max(1);
+    }
+    out
+}
+
+fn is_symbol_char(b: u8) -> bool {
+    b.is_ascii_alphanumeric() || b == b'_'
+}
+
+struct Demangler<'a> {
+    s: &'a [u8],
+    pos: usize,
+}
+
+impl Demangler<'_> {
+    fn symbol(&mut self) -> Option<String> {
+        self.expect(b'_')?;
+        self.expect(b'K')?;
+        self.expect(b'N')?;
+        let mut segments = Vec::new();
+        if self.eat(b'Y') {
+            segments.push(self.ty()?);
+        }
+        while !self.eat(b'E') {
+            let mut segment = self.ident()?;
+            if self.eat(b'I') {
+                segment.push_str(&format!("<{}>", self.types_until_end()?.join(", ")));
+            }
+            segments.push(segment);
+        }
+        let mut out = segments.join(".");
+        if self.eat(b'I') {
+            out.push_str(&format!("<{}>", self.types_until_end()?.join(", ")));
+        }
+        self.expect(b'Z')?;
+        out.push_str(&format!("({})", self.types_until_end()?.join(", ")));
+        Some(out)
+    }
+
+    fn ty(&mut self) -> Option<String> {
+        let c = *self.s.get(self.pos)?;
+        if c.is_ascii_digit() {
+            let name = self.ident()?;
+            if self.eat(b'I') {
+                return Some(format!("{}<{}>", name, self.types_until_end()?.join(", ")));
+            }
+            return Some(name);
+        }
+        self.pos += 1;
+        let plain = match c {
+            b'l' => "Int",
+            b'm' => "UInt",
+            b'd' => "Float",
+            b'f' => "Float32",
+            b'a' => "Int8",
+            b's' => "Int16",
+            b'i' => "Int32",
+            b'h' => "UInt8",
+            b't' => "UInt16",
+            b'j' => "UInt32",
+            b'b' => "Bool",
+            b'c' => "Char",
+            b'S' => "String",
+            b'v' => "Unit",
+            b'X' => "Any",
+            b'z' => "Nothing",
+            b'u' => "_",
+            b'P' => return self.ident(),
+            b'O' => return Some(format!("{}?", self.ty()?)),
+            b'A' => return Some(format!("[{}]", self.ty()?)),
+            b'W' => return Some(format!("Tensor<{}>", self.ty()?)),
+            b'T' => {
+                let elems = self.types_until_end()?;
+                let trailing = if elems.len() == 1 { "," } else { "" };
+                return Some(format!("({}{})", elems.join(", "), trailing));
+            }
+            b'F' => {
+                let ret = self.ty()?;
+                return Some(format!("fun({}) -> {}", self.types_until_end()?.join(", "), ret));
+            }
+            _ => return None,
+        };
+        Some(plain.to_string())
+    }
+
+    fn types_until_end(&mut self) -> Option<Vec<String>> {
+        let mut tys = Vec::new();
+        while !self.eat(b'E') {
+            tys.push(self.ty()?);
+        }
+        Some(tys)
+    }
+
+    fn ident(&mut self) -> Option<String> {
+        let start = self.pos;
+        while self.s.get(self.pos).is_some_and(u8::is_ascii_digit) {
+            self.pos += 1;
+        }
+        let len: usize = std::str::from_utf8(&self.s[start..self.pos]).ok()?.parse().ok()?;
+        let ident = self.s.get(self.pos..self.pos + len)?;
+        self.pos += len;
+        String::from_utf8(ident.to_vec()).ok()
+    }
+
+    fn eat(&mut self, c: u8) -> bool {
+        let hit = self.s.get(self.pos) == Some(&c);
+        if hit {
+            self.pos += 1;
+        }
+        hit
+    }
+
+    fn expect(&mut self, c: u8) -> Option<()> {
+        self.eat(c).then_some(())
+    }
+}
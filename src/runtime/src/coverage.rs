@@ -0,0 +1,70 @@
+use std::ffi::CStr;
+use std::fmt::Write as _;
+use std::io::Write as _;
+use std::os::raw::c_char;
+use std::sync::Mutex;
+
+/// One instrumented function, as laid out by `backend::coverage` in the
+/// compiler.
+#[repr(C)]
+pub struct CoveredFunction {
+    name: *const c_char,
+    hash: u64,
+    num_counters: u64,
+    counters: *const u64,
+}
+
+struct Table(*const CoveredFunction, usize);
+
+// The tables are static data of the program.
+unsafe impl Send for Table {}
+
+static TABLES: Mutex<Vec<Table>> = Mutex::new(Vec::new());
+
+/// Called from a constructor of every module built with `--coverage`.
+#[no_mangle]
+pub extern "C" fn korlang_coverage_register(table: *const CoveredFunction, len: usize) {
+    let mut tables = TABLES.lock().unwrap();
+    if tables.is_empty() {
+        unsafe {
+            libc::atexit(write_profile);
+        }
+    }
+    tables.push(Table(table, len));
+}
+
+extern "C" fn write_profile() {
+    flush();
+}
+
+/// Appends the counters to `$KORLANG_PROFILE_FILE` (default
+/// `default.proftext`) in the text format of `llvm-profdata merge`, then
+/// forgets them, so a panicking program writes them only once.
+pub fn flush() {
+    let tables = std::mem::take(&mut *TABLES.lock().unwrap());
+    if tables.is_empty() {
+        return;
+    }
+    let mut out = String::new();
+    for Table(table, len) in tables {
+        let functions = unsafe { std::slice::from_raw_parts(table, len) };
+        for f in functions {
+            let name = unsafe { CStr::from_ptr(f.name) }.to_string_lossy();
+            let counters = unsafe { std::slice::from_raw_parts(f.counters, f.num_counters as usize) };
+            let _ = writeln!(out, "{}\n# Func Hash:\n{}\n# Num Counters:\n{}\n# Counter Values:", name, f.hash, f.num_counters);
+            for c in counters {
+                let _ = writeln!(out, "{}", unsafe { std::ptr::read_volatile(c) });
+            }
+            out.push('\n');
+        }
+    }
+    let path = std::env::var("KORLANG_PROFILE_FILE").unwrap_or_else(|_| "default.proftext".to_string());
+    let written = std::fs::OpenOptions::new()
+        .create(true)
+        .append(true)
+        .open(&path)
+        .and_then(|mut f| f.write_all(out.as_bytes()));
+    if let Err(e) = written {
+        eprintln!("korlang: cannot write coverage profile {}: {}", path, e);
+    }
+}
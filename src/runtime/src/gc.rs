@@ -97,7 +97,7 @@ impl GcHeap {
         }
 
         let mut i = 0;
-        let mut updated_roots = roots.clone();
+        let mut moved = Vec::new();
         while i < objs.len() {
             if !objs[i].marked {
                 let obj = objs.remove(i);
@@ -105,15 +105,13 @@ impl GcHeap {
                 let layout = Layout::from_size_align(obj.size, obj.align.max(1)).unwrap();
                 unsafe { dealloc(obj.ptr as *mut u8, layout) };
             } else {
-                if objs[i].gen == 0 {
+                if objs[i].gen == 0 && !crate::pin::korlang_is_pinned(objs[i].ptr as *mut u8) {
                     // Move young objects to reduce fragmentation (best-effort).
                     let layout = Layout::from_size_align(objs[i].size, objs[i].align.max(1)).unwrap();
                     let new_ptr = unsafe { alloc(layout) };
                     if !new_ptr.is_null() {
                         unsafe { std::ptr::copy_nonoverlapping(objs[i].ptr as *mut u8, new_ptr, objs[i].size); }
-                        if updated_roots.remove(&objs[i].ptr) {
-                            updated_roots.insert(new_ptr as usize);
-                        }
+                        moved.push((objs[i].ptr, new_ptr as usize));
                         unsafe { dealloc(objs[i].ptr as *mut u8, layout) };
                         objs[i].ptr = new_ptr as usize;
                     }
@@ -127,7 +125,14 @@ impl GcHeap {
                 i += 1;
             }
         }
-        *self.roots.lock().unwrap() = updated_roots;
+        // Finalizers may have added or removed roots meanwhile.
+        let mut current = self.roots.lock().unwrap();
+        for (old, new) in moved {
+            if current.remove(&old) {
+                current.insert(new);
+            }
+        }
+        drop(current);
         PROFILER.record_gc_pause(start.elapsed());
     }
 
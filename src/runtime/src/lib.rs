@@ -13,7 +13,9 @@ mod scheduler;
 mod channel;
 mod blocking;
 mod stdio;
+mod list;
 mod panic;
+mod coverage;
 mod driver;
 pub mod ui;
 pub mod cloud;
@@ -150,7 +152,10 @@ pub extern "C" fn korlang_int_mul(a: i64, b: i64) -> i64 {
 
 #[no_mangle]
 pub extern "C" fn korlang_int_div(a: i64, b: i64) -> i64 {
-    if b == 0 { 0 } else { a / b }
+    if b == 0 {
+        crate::panic::korlang_panic(c"attempt to divide by zero".as_ptr());
+    }
+    a.wrapping_div(b)
 }
 
 #[no_mangle]
@@ -170,5 +175,6 @@ pub extern "C" fn korlang_float_mul(a: f64, b: f64) -> f64 {
 
 #[no_mangle]
 pub extern "C" fn korlang_float_div(a: f64, b: f64) -> f64 {
-    if b == 0.0 { f64::NAN } else { a / b }
+    // IEEE 754: finite / 0 is an infinity, 0 / 0 is NaN.
+    a / b
 }
@@ -0,0 +1,120 @@
+use std::alloc::{handle_alloc_error, Layout};
+use std::ffi::CString;
+use crate::pin::{korlang_pin, korlang_unpin};
+use crate::{finalizer, gc};
+
+/// Backing store for `[T]` and `List<T>` values. Elements are stored inline
+/// with a fixed `elem_size`; generated code reads and writes them through the
+/// pointer returned by `korlang_list_at`.
+///
+/// The list and its buffer are GC objects. Both are pinned, since generated
+/// code keeps raw pointers to them, and the buffer is a root for as long as
+/// the list owns it; a finalizer on the list gives it back to the collector.
+#[repr(C)]
+pub struct KorList {
+    len: usize,
+    cap: usize,
+    elem_size: usize,
+    data: *mut u8,
+}
+
+const ELEM_ALIGN: usize = 16;
+
+fn gc_alloc(layout: Layout) -> *mut u8 {
+    let ptr = gc::GC.alloc(layout.size(), layout.align());
+    if ptr.is_null() {
+        handle_alloc_error(layout);
+    }
+    korlang_pin(ptr);
+    ptr
+}
+
+fn release(ptr: *mut u8) {
+    gc::GC.remove_root(ptr);
+    korlang_unpin(ptr);
+}
+
+fn finalize(list: *mut u8) {
+    let data = unsafe { (*(list as *mut KorList)).data };
+    if !data.is_null() {
+        release(data);
+    }
+    korlang_unpin(list);
+}
+
+impl KorList {
+    fn grow(&mut self, min_cap: usize) {
+        let new_cap = min_cap.max(self.cap * 2).max(4);
+        let layout = Layout::from_size_align((new_cap * self.elem_size).max(1), ELEM_ALIGN).unwrap();
+        let data = gc_alloc(layout);
+        gc::GC.add_root(data);
+        if !self.data.is_null() {
+            unsafe { std::ptr::copy_nonoverlapping(self.data, data, self.len * self.elem_size) };
+            release(self.data);
+        }
+        self.data = data;
+        self.cap = new_cap;
+    }
+}
+
+#[no_mangle]
+pub extern "C" fn korlang_list_new(elem_size: usize, capacity: usize) -> *mut KorList {
+    let list = gc_alloc(Layout::new::<KorList>()) as *mut KorList;
+    unsafe {
+        list.write(KorList { len: 0, cap: 0, elem_size, data: std::ptr::null_mut() });
+        if capacity > 0 {
+            (*list).grow(capacity);
+        }
+    }
+    finalizer::register(list as *mut u8, finalize);
+    list
+}
+
+#[no_mangle]
+pub extern "C" fn korlang_list_len(list: *const KorList) -> i64 {
+    if list.is_null() {
+        return 0;
+    }
+    unsafe { (*list).len as i64 }
+}
+
+/// Appends the `elem_size` bytes at `elem`.
+#[no_mangle]
+pub extern "C" fn korlang_list_push(list: *mut KorList, elem: *const u8) {
+    let list = unsafe { &mut *list };
+    if list.len == list.cap {
+        list.grow(list.len + 1);
+    }
+    unsafe {
+        std::ptr::copy_nonoverlapping(elem, list.data.add(list.len * list.elem_size), list.elem_size);
+    }
+    list.len += 1;
+}
+
+/// Address of element `index`; panics when it is out of bounds.
+#[no_mangle]
+pub extern "C" fn korlang_list_at(list: *mut KorList, index: i64) -> *mut u8 {
+    let len = korlang_list_len(list);
+    if index < 0 || index >= len {
+        let msg = CString::new(format!("index out of bounds: the len is {} but the index is {}", len, index)).unwrap();
+        crate::panic::korlang_panic(msg.as_ptr());
+    }
+    let list = unsafe { &mut *list };
+    unsafe { list.data.add(index as usize * list.elem_size) }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn push_and_index() {
+        let list = korlang_list_new(8, 0);
+        for v in 0..10i64 {
+            korlang_list_push(list, &v as *const i64 as *const u8);
+        }
+        assert_eq!(korlang_list_len(list), 10);
+        let seventh = unsafe { *(korlang_list_at(list, 7) as *const i64) };
+        assert_eq!(seventh, 7);
+    }
+}
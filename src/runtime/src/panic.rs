@@ -20,14 +20,23 @@ pub extern "C" fn korlang_panic(msg: *const c_char) -> ! {
         h(cstr.as_ptr());
     } else {
         eprintln!("Korlang panic: {}", cstr.to_string_lossy());
+        if std::env::var("KORLANG_BACKTRACE").is_ok_and(|v| v != "0") {
+            eprintln!("stack backtrace:\n{}", backtrace());
+        }
     }
+    crate::coverage::flush();
     std::process::abort()
 }
 
+fn backtrace() -> String {
+    korlang_demangle::demangle_text(&std::backtrace::Backtrace::force_capture().to_string())
+}
+
+/// The current stack, one frame per line with Korlang symbols demangled.
+/// Release it with `korlang_panic_free`.
 #[no_mangle]
 pub extern "C" fn korlang_panic_backtrace() -> *mut c_char {
-    let s = CString::new("<stack trace unavailable>").unwrap();
-    s.into_raw()
+    CString::new(backtrace().replace('\0', "")).unwrap_or_default().into_raw()
 }
 
 #[no_mangle]
@@ -1,43 +1,626 @@
-use std::ffi::{CStr, CString};
-use std::os::raw::c_char;
+//! Embedded CPython, loaded with `dlopen` the first time a bridge function
+//! is called, so programs that never touch Python do not depend on it.
+//!
+//! Every function returning an object hands out a new reference, which the
+//! caller releases with `korlang_py_release`; object arguments are borrowed.
+//! A raised exception makes the call return null (or `false`, 0, -1 for the
+//! scalar results) and is kept as `korlang_py_error`, cleared by the next
+//! call.
 
-#[repr(C)]
-pub struct PyObjectHandle {
-    ptr: *mut u8,
+use std::cell::RefCell;
+use std::ffi::CString;
+use std::os::raw::{c_char, c_int, c_long, c_void};
+use std::sync::OnceLock;
+
+type Obj = *mut c_void;
+
+const PY_FILE_INPUT: c_int = 257;
+const PY_EVAL_INPUT: c_int = 258;
+
+macro_rules! python_api {
+    ($($name:ident: fn($($arg:ty),*) $(-> $ret:ty)?;)*) => {
+        #[allow(non_snake_case)]
+        struct Api {
+            $($name: unsafe extern "C" fn($($arg),*) $(-> $ret)?,)*
+            none: Obj,
+        }
+
+        impl Api {
+            unsafe fn load(lib: *mut c_void) -> Result<Api, String> {
+                Ok(Api {
+                    $($name: std::mem::transmute::<*mut c_void, unsafe extern "C" fn($($arg),*) $(-> $ret)?>(
+                        symbol(lib, concat!(stringify!($name), "\0"))?,
+                    ),)*
+                    none: symbol(lib, "_Py_NoneStruct\0")?,
+                })
+            }
+        }
+    };
+}
+
+python_api! {
+    Py_IsInitialized: fn() -> c_int;
+    Py_InitializeEx: fn(c_int);
+    PyEval_SaveThread: fn() -> *mut c_void;
+    PyGILState_Ensure: fn() -> c_int;
+    PyGILState_Release: fn(c_int);
+    PyImport_AddModule: fn(*const c_char) -> Obj;
+    PyImport_ImportModule: fn(*const c_char) -> Obj;
+    PyModule_GetDict: fn(Obj) -> Obj;
+    PyRun_StringFlags: fn(*const c_char, c_int, Obj, Obj, *mut c_void) -> Obj;
+    PyObject_GetAttrString: fn(Obj, *const c_char) -> Obj;
+    PyObject_SetAttrString: fn(Obj, *const c_char, Obj) -> c_int;
+    PyObject_CallObject: fn(Obj, Obj) -> Obj;
+    PySequence_Tuple: fn(Obj) -> Obj;
+    PyLong_FromLongLong: fn(i64) -> Obj;
+    PyLong_AsLongLong: fn(Obj) -> i64;
+    PyFloat_FromDouble: fn(f64) -> Obj;
+    PyFloat_AsDouble: fn(Obj) -> f64;
+    PyBool_FromLong: fn(c_long) -> Obj;
+    PyObject_IsTrue: fn(Obj) -> c_int;
+    PyUnicode_FromString: fn(*const c_char) -> Obj;
+    PyUnicode_AsUTF8AndSize: fn(Obj, *mut isize) -> *const c_char;
+    PyObject_Str: fn(Obj) -> Obj;
+    PyList_New: fn(isize) -> Obj;
+    PyList_Append: fn(Obj, Obj) -> c_int;
+    PyDict_New: fn() -> Obj;
+    PyObject_Size: fn(Obj) -> isize;
+    PyObject_GetItem: fn(Obj, Obj) -> Obj;
+    PyObject_SetItem: fn(Obj, Obj, Obj) -> c_int;
+    PySequence_GetItem: fn(Obj, isize) -> Obj;
+    Py_IncRef: fn(Obj);
+    Py_DecRef: fn(Obj);
+    PyErr_Occurred: fn() -> Obj;
+    PyErr_Fetch: fn(*mut Obj, *mut Obj, *mut Obj);
+    PyErr_NormalizeException: fn(*mut Obj, *mut Obj, *mut Obj);
+}
+
+// The loaded library is never unloaded, so its function pointers stay valid
+// for the whole process.
+unsafe impl Send for Api {}
+unsafe impl Sync for Api {}
+
+static API: OnceLock<Result<Api, String>> = OnceLock::new();
+
+thread_local! {
+    static LAST_ERROR: RefCell<Option<String>> = const { RefCell::new(None) };
+}
+
+unsafe fn symbol(lib: *mut c_void, name: &str) -> Result<*mut c_void, String> {
+    let sym = libc::dlsym(lib, name.as_ptr() as *const c_char);
+    if sym.is_null() {
+        Err(format!("libpython has no symbol {}", name.trim_end_matches('\0')))
+    } else {
+        Ok(sym)
+    }
+}
+
+/// `KORLANG_PYTHON_LIB` names the library to load; otherwise the usual
+/// sonames of Python 3 are tried, newest first.
+fn library_candidates() -> Vec<String> {
+    let mut names = Vec::new();
+    if let Ok(path) = std::env::var("KORLANG_PYTHON_LIB") {
+        names.push(path);
+    }
+    let ext = if cfg!(target_os = "macos") { "dylib" } else { "so" };
+    names.push(format!("libpython3.{}", ext));
+    for minor in (6..=14).rev() {
+        names.push(format!("libpython3.{}.{}.1.0", minor, ext));
+        names.push(format!("libpython3.{}.{}", minor, ext));
+    }
+    names
+}
+
+fn load() -> Result<Api, String> {
+    let candidates = library_candidates();
+    for name in &candidates {
+        let cname = CString::new(name.as_str()).map_err(|e| e.to_string())?;
+        // RTLD_GLOBAL lets extension modules such as `math` resolve the
+        // interpreter's symbols.
+        let lib = unsafe { libc::dlopen(cname.as_ptr(), libc::RTLD_NOW | libc::RTLD_GLOBAL) };
+        if lib.is_null() {
+            continue;
+        }
+        let api = unsafe { Api::load(lib)? };
+        unsafe {
+            if (api.Py_IsInitialized)() == 0 {
+                (api.Py_InitializeEx)(0);
+                // Release the GIL taken by initialization; every call
+                // acquires it with PyGILState_Ensure.
+                (api.PyEval_SaveThread)();
+            }
+        }
+        return Ok(api);
+    }
+    Err(format!("libpython3 not found (tried {}); set KORLANG_PYTHON_LIB", candidates.join(", ")))
+}
+
+fn set_error(msg: String) {
+    LAST_ERROR.with(|e| *e.borrow_mut() = Some(msg));
+}
+
+/// Runs `f` holding the GIL, after loading Python if needed. `f` returns
+/// `None` as soon as a call raises, having released what it holds, and the
+/// exception is recorded as the last error. `fail` is the result then and
+/// when Python is unavailable.
+fn with_python<T>(fail: T, f: impl FnOnce(&Api) -> Option<T>) -> T {
+    LAST_ERROR.with(|e| *e.borrow_mut() = None);
+    let api = match API.get_or_init(load) {
+        Ok(api) => api,
+        Err(msg) => {
+            set_error(msg.clone());
+            return fail;
+        }
+    };
+    unsafe {
+        let gil = (api.PyGILState_Ensure)();
+        let out = f(api);
+        if out.is_none() {
+            let raised = !(api.PyErr_Occurred)().is_null();
+            set_error(if raised { fetch_exception(api) } else { "Python call failed".to_string() });
+        }
+        (api.PyGILState_Release)(gil);
+        out.unwrap_or(fail)
+    }
+}
+
+/// A new reference returned by the C API, which returns null when it raises.
+fn new_ref(obj: Obj) -> Option<Obj> {
+    (!obj.is_null()).then_some(obj)
+}
+
+/// A scalar returned by the C API, whose error value is also a valid result,
+/// so only a pending exception tells them apart.
+unsafe fn unless_raised<T>(api: &Api, value: T) -> Option<T> {
+    (api.PyErr_Occurred)().is_null().then_some(value)
 }
 
+/// The result of a C API call returning 0 on success and -1 when it raises.
+fn succeeded(status: c_int) -> Option<bool> {
+    (status == 0).then_some(true)
+}
+
+/// Takes the pending exception as `Type: message`, clearing it.
+unsafe fn fetch_exception(api: &Api) -> String {
+    let (mut ty, mut value, mut tb) = (std::ptr::null_mut(), std::ptr::null_mut(), std::ptr::null_mut());
+    (api.PyErr_Fetch)(&mut ty, &mut value, &mut tb);
+    (api.PyErr_NormalizeException)(&mut ty, &mut value, &mut tb);
+    let name = if ty.is_null() {
+        None
+    } else {
+        let n = (api.PyObject_GetAttrString)(ty, c"__name__".as_ptr());
+        let s = owned_str(api, n);
+        (api.Py_DecRef)(n);
+        s
+    };
+    let msg = if value.is_null() {
+        None
+    } else {
+        let s = (api.PyObject_Str)(value);
+        let text = owned_str(api, s);
+        (api.Py_DecRef)(s);
+        text
+    };
+    // Py_DecRef accepts null.
+    for obj in [ty, value, tb] {
+        (api.Py_DecRef)(obj);
+    }
+    // Fetching the name or message must not leave a second exception set.
+    if !(api.PyErr_Occurred)().is_null() {
+        let (mut a, mut b, mut c) = (std::ptr::null_mut(), std::ptr::null_mut(), std::ptr::null_mut());
+        (api.PyErr_Fetch)(&mut a, &mut b, &mut c);
+        for obj in [a, b, c] {
+            (api.Py_DecRef)(obj);
+        }
+    }
+    match (name, msg) {
+        (Some(n), Some(m)) if !m.is_empty() => format!("{}: {}", n, m),
+        (Some(n), _) => n,
+        (None, Some(m)) => m,
+        (None, None) => "Python exception".to_string(),
+    }
+}
+
+unsafe fn owned_str(api: &Api, s: Obj) -> Option<String> {
+    if s.is_null() {
+        return None;
+    }
+    let mut len = 0isize;
+    let p = (api.PyUnicode_AsUTF8AndSize)(s, &mut len);
+    if p.is_null() {
+        return None;
+    }
+    let bytes = std::slice::from_raw_parts(p as *const u8, len as usize);
+    Some(String::from_utf8_lossy(bytes).into_owned())
+}
+
+/// Copies `s` to the Korlang heap as a NUL-terminated string.
+fn korlang_string(s: &[u8]) -> *mut c_char {
+    let p = crate::gc::GC.alloc(s.len() + 1, 1);
+    unsafe {
+        std::ptr::copy_nonoverlapping(s.as_ptr(), p, s.len());
+        *p.add(s.len()) = 0;
+    }
+    p as *mut c_char
+}
+
+fn check_obj(obj: Obj) -> bool {
+    if obj.is_null() {
+        set_error("null Python object".to_string());
+    }
+    !obj.is_null()
+}
+
+unsafe fn run(api: &Api, code: *const c_char, start: c_int) -> Obj {
+    let main = (api.PyImport_AddModule)(c"__main__".as_ptr());
+    if main.is_null() {
+        return std::ptr::null_mut();
+    }
+    let globals = (api.PyModule_GetDict)(main);
+    (api.PyRun_StringFlags)(code, start, globals, globals, std::ptr::null_mut())
+}
+
+/// Loads the interpreter; false, with the reason as the last error, when
+/// no libpython3 can be found.
 #[no_mangle]
 pub extern "C" fn korlang_py_init() -> bool {
-    // Placeholder: real implementation will call into the Python C API.
-    true
+    with_python(false, |_| Some(true))
 }
 
+/// Evaluates an expression in the globals of `__main__`.
 #[no_mangle]
-pub extern "C" fn korlang_py_eval(code: *const c_char) -> PyObjectHandle {
+pub extern "C" fn korlang_py_eval(code: *const c_char) -> Obj {
     if code.is_null() {
-        return PyObjectHandle { ptr: std::ptr::null_mut() };
+        return std::ptr::null_mut();
+    }
+    with_python(std::ptr::null_mut(), |api| unsafe { new_ref(run(api, code, PY_EVAL_INPUT)) })
+}
+
+/// Runs statements, such as `def` or `import`, in the globals of `__main__`.
+#[no_mangle]
+pub extern "C" fn korlang_py_exec(code: *const c_char) -> bool {
+    if code.is_null() {
+        return false;
+    }
+    with_python(false, |api| unsafe {
+        let result = new_ref(run(api, code, PY_FILE_INPUT))?;
+        (api.Py_DecRef)(result);
+        Some(true)
+    })
+}
+
+#[no_mangle]
+pub extern "C" fn korlang_py_import(name: *const c_char) -> Obj {
+    if name.is_null() {
+        return std::ptr::null_mut();
+    }
+    with_python(std::ptr::null_mut(), |api| unsafe { new_ref((api.PyImport_ImportModule)(name)) })
+}
+
+/// Calls `callable` with the items of `args`, a list or tuple, or with no
+/// arguments when `args` is null.
+#[no_mangle]
+pub extern "C" fn korlang_py_call(callable: Obj, args: Obj) -> Obj {
+    if !check_obj(callable) {
+        return std::ptr::null_mut();
+    }
+    with_python(std::ptr::null_mut(), |api| unsafe {
+        if args.is_null() {
+            return new_ref((api.PyObject_CallObject)(callable, std::ptr::null_mut()));
+        }
+        let tuple = new_ref((api.PySequence_Tuple)(args))?;
+        let result = (api.PyObject_CallObject)(callable, tuple);
+        (api.Py_DecRef)(tuple);
+        new_ref(result)
+    })
+}
+
+#[no_mangle]
+pub extern "C" fn korlang_py_getattr(obj: Obj, name: *const c_char) -> Obj {
+    if !check_obj(obj) || name.is_null() {
+        return std::ptr::null_mut();
+    }
+    with_python(std::ptr::null_mut(), |api| unsafe { new_ref((api.PyObject_GetAttrString)(obj, name)) })
+}
+
+#[no_mangle]
+pub extern "C" fn korlang_py_setattr(obj: Obj, name: *const c_char, value: Obj) -> bool {
+    if !check_obj(obj) || !check_obj(value) || name.is_null() {
+        return false;
+    }
+    with_python(false, |api| unsafe { succeeded((api.PyObject_SetAttrString)(obj, name, value)) })
+}
+
+#[no_mangle]
+pub extern "C" fn korlang_py_from_int(v: i64) -> Obj {
+    with_python(std::ptr::null_mut(), |api| unsafe { new_ref((api.PyLong_FromLongLong)(v)) })
+}
+
+#[no_mangle]
+pub extern "C" fn korlang_py_from_float(v: f64) -> Obj {
+    with_python(std::ptr::null_mut(), |api| unsafe { new_ref((api.PyFloat_FromDouble)(v)) })
+}
+
+#[no_mangle]
+pub extern "C" fn korlang_py_from_bool(v: bool) -> Obj {
+    with_python(std::ptr::null_mut(), |api| unsafe { new_ref((api.PyBool_FromLong)(v as c_long)) })
+}
+
+#[no_mangle]
+pub extern "C" fn korlang_py_from_str(s: *const c_char) -> Obj {
+    if s.is_null() {
+        return std::ptr::null_mut();
+    }
+    with_python(std::ptr::null_mut(), |api| unsafe { new_ref((api.PyUnicode_FromString)(s)) })
+}
+
+#[no_mangle]
+pub extern "C" fn korlang_py_none() -> Obj {
+    with_python(std::ptr::null_mut(), |api| unsafe {
+        (api.Py_IncRef)(api.none);
+        Some(api.none)
+    })
+}
+
+#[no_mangle]
+pub extern "C" fn korlang_py_is_none(obj: Obj) -> bool {
+    match API.get() {
+        Some(Ok(api)) => obj == api.none,
+        _ => false,
+    }
+}
+
+/// The value of a Python `int`; a `TypeError` or `OverflowError` otherwise.
+#[no_mangle]
+pub extern "C" fn korlang_py_to_int(obj: Obj) -> i64 {
+    if !check_obj(obj) {
+        return 0;
     }
-    let _ = unsafe { CStr::from_ptr(code) };
-    // Placeholder: return null handle until Python VM is integrated.
-    PyObjectHandle { ptr: std::ptr::null_mut() }
+    with_python(0, |api| unsafe { unless_raised(api, (api.PyLong_AsLongLong)(obj)) })
 }
 
+/// The value of a Python `float`, or of an `int` converted to one.
 #[no_mangle]
-pub extern "C" fn korlang_py_call(fn_handle: PyObjectHandle, _argc: usize, _argv: *const PyObjectHandle) -> PyObjectHandle {
-    let _ = fn_handle.ptr;
-    PyObjectHandle { ptr: std::ptr::null_mut() }
+pub extern "C" fn korlang_py_to_float(obj: Obj) -> f64 {
+    if !check_obj(obj) {
+        return 0.0;
+    }
+    with_python(0.0, |api| unsafe { unless_raised(api, (api.PyFloat_AsDouble)(obj)) })
+}
+
+/// The truth value of `obj`, as Python's `bool()` computes it.
+#[no_mangle]
+pub extern "C" fn korlang_py_to_bool(obj: Obj) -> bool {
+    if !check_obj(obj) {
+        return false;
+    }
+    with_python(false, |api| unsafe {
+        match (api.PyObject_IsTrue)(obj) {
+            -1 => None,
+            truth => Some(truth == 1),
+        }
+    })
 }
 
+/// `str(obj)`, copied to the Korlang heap.
 #[no_mangle]
-pub extern "C" fn korlang_py_str(handle: PyObjectHandle) -> *mut c_char {
-    let _ = handle.ptr;
-    let s = CString::new("<pyobject>").unwrap();
-    s.into_raw()
+pub extern "C" fn korlang_py_to_str(obj: Obj) -> *mut c_char {
+    if !check_obj(obj) {
+        return std::ptr::null_mut();
+    }
+    with_python(std::ptr::null_mut(), |api| unsafe {
+        let s = new_ref((api.PyObject_Str)(obj))?;
+        let mut len = 0isize;
+        let p = (api.PyUnicode_AsUTF8AndSize)(s, &mut len);
+        let out = if p.is_null() {
+            None
+        } else {
+            Some(korlang_string(std::slice::from_raw_parts(p as *const u8, len as usize)))
+        };
+        (api.Py_DecRef)(s);
+        out
+    })
 }
 
 #[no_mangle]
-pub extern "C" fn korlang_py_free_str(s: *mut c_char) {
-    if s.is_null() { return; }
-    unsafe { CString::from_raw(s); }
+pub extern "C" fn korlang_py_list_new() -> Obj {
+    with_python(std::ptr::null_mut(), |api| unsafe { new_ref((api.PyList_New)(0)) })
+}
+
+/// Appends `item` to `list`; the list takes its own reference.
+#[no_mangle]
+pub extern "C" fn korlang_py_list_append(list: Obj, item: Obj) -> bool {
+    if !check_obj(list) || !check_obj(item) {
+        return false;
+    }
+    with_python(false, |api| unsafe { succeeded((api.PyList_Append)(list, item)) })
 }
 
+#[no_mangle]
+pub extern "C" fn korlang_py_dict_new() -> Obj {
+    with_python(std::ptr::null_mut(), |api| unsafe { new_ref((api.PyDict_New)()) })
+}
+
+/// `len(obj)`, or -1.
+#[no_mangle]
+pub extern "C" fn korlang_py_len(obj: Obj) -> i64 {
+    if !check_obj(obj) {
+        return -1;
+    }
+    with_python(-1, |api| unsafe {
+        match (api.PyObject_Size)(obj) {
+            -1 => None,
+            len => Some(len as i64),
+        }
+    })
+}
+
+/// `obj[key]`.
+#[no_mangle]
+pub extern "C" fn korlang_py_get_item(obj: Obj, key: Obj) -> Obj {
+    if !check_obj(obj) || !check_obj(key) {
+        return std::ptr::null_mut();
+    }
+    with_python(std::ptr::null_mut(), |api| unsafe { new_ref((api.PyObject_GetItem)(obj, key)) })
+}
+
+/// `obj[key] = value`.
+#[no_mangle]
+pub extern "C" fn korlang_py_set_item(obj: Obj, key: Obj, value: Obj) -> bool {
+    if !check_obj(obj) || !check_obj(key) || !check_obj(value) {
+        return false;
+    }
+    with_python(false, |api| unsafe { succeeded((api.PyObject_SetItem)(obj, key, value)) })
+}
+
+/// `obj[index]` for a sequence; negative indices count from the end.
+#[no_mangle]
+pub extern "C" fn korlang_py_get_index(obj: Obj, index: i64) -> Obj {
+    if !check_obj(obj) {
+        return std::ptr::null_mut();
+    }
+    with_python(std::ptr::null_mut(), |api| unsafe { new_ref((api.PySequence_GetItem)(obj, index as isize)) })
+}
+
+/// Takes another reference to `obj`.
+#[no_mangle]
+pub extern "C" fn korlang_py_retain(obj: Obj) {
+    if obj.is_null() {
+        return;
+    }
+    if let Some(Ok(api)) = API.get() {
+        unsafe {
+            let gil = (api.PyGILState_Ensure)();
+            (api.Py_IncRef)(obj);
+            (api.PyGILState_Release)(gil);
+        }
+    }
+}
+
+/// Releases a reference returned by the bridge.
+#[no_mangle]
+pub extern "C" fn korlang_py_release(obj: Obj) {
+    if obj.is_null() {
+        return;
+    }
+    if let Some(Ok(api)) = API.get() {
+        unsafe {
+            let gil = (api.PyGILState_Ensure)();
+            (api.Py_DecRef)(obj);
+            (api.PyGILState_Release)(gil);
+        }
+    }
+}
+
+/// The exception raised by the last bridge call on this thread, as
+/// `Type: message`, or null.
+#[no_mangle]
+pub extern "C" fn korlang_py_error() -> *mut c_char {
+    LAST_ERROR.with(|e| match &*e.borrow() {
+        Some(msg) => korlang_string(msg.as_bytes()),
+        None => std::ptr::null_mut(),
+    })
+}
+
+#[no_mangle]
+pub extern "C" fn korlang_py_clear_error() {
+    LAST_ERROR.with(|e| *e.borrow_mut() = None);
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::ffi::CStr;
+
+    fn text(p: *mut c_char) -> String {
+        assert!(!p.is_null());
+        unsafe { CStr::from_ptr(p) }.to_string_lossy().into_owned()
+    }
+
+    #[test]
+    fn evaluates_calls_and_converts_values() {
+        if !korlang_py_init() {
+            eprintln!("skipping: {}", text(korlang_py_error()));
+            return;
+        }
+        assert!(korlang_py_exec(c"def scale(xs, k):\n    return [x * k for x in xs]\n".as_ptr()));
+        let scale = korlang_py_eval(c"scale".as_ptr());
+        let xs = korlang_py_list_new();
+        for v in [1, 2, 3] {
+            let item = korlang_py_from_int(v);
+            assert!(korlang_py_list_append(xs, item));
+            korlang_py_release(item);
+        }
+        let k = korlang_py_from_float(0.5);
+        let args = korlang_py_list_new();
+        korlang_py_list_append(args, xs);
+        korlang_py_list_append(args, k);
+        let out = korlang_py_call(scale, args);
+        assert_eq!(korlang_py_len(out), 3);
+        let last = korlang_py_get_index(out, -1);
+        assert_eq!(korlang_py_to_float(last), 1.5);
+        assert_eq!(text(korlang_py_to_str(out)), "[0.5, 1.0, 1.5]");
+
+        let d = korlang_py_dict_new();
+        let key = korlang_py_from_str(c"name".as_ptr());
+        let value = korlang_py_from_str(c"korlang".as_ptr());
+        assert!(korlang_py_set_item(d, key, value));
+        let got = korlang_py_get_item(d, key);
+        assert_eq!(text(korlang_py_to_str(got)), "korlang");
+
+        let math = korlang_py_import(c"math".as_ptr());
+        let pi = korlang_py_getattr(math, c"pi".as_ptr());
+        assert!((korlang_py_to_float(pi) - std::f64::consts::PI).abs() < 1e-12);
+        for obj in [scale, xs, k, args, out, last, d, key, value, got, math, pi] {
+            korlang_py_release(obj);
+        }
+    }
+
+    #[test]
+    fn exceptions_become_the_last_error() {
+        if !korlang_py_init() {
+            return;
+        }
+        assert!(korlang_py_eval(c"1 / 0".as_ptr()).is_null());
+        assert_eq!(text(korlang_py_error()), "ZeroDivisionError: division by zero");
+        let s = korlang_py_from_str(c"x".as_ptr());
+        assert_eq!(korlang_py_to_int(s), 0);
+        assert!(text(korlang_py_error()).starts_with("TypeError"));
+        korlang_py_release(s);
+        let none = korlang_py_none();
+        assert!(korlang_py_is_none(none));
+        assert!(korlang_py_error().is_null());
+        korlang_py_release(none);
+    }
+
+    #[test]
+    fn raising_calls_keep_no_references() {
+        if !korlang_py_init() {
+            return;
+        }
+        assert!(korlang_py_exec(c"import sys\ndef boom(x):\n    raise ValueError('bad ' + str(x))\n".as_ptr()));
+        let boom = korlang_py_eval(c"boom".as_ptr());
+        let getrefcount = korlang_py_eval(c"sys.getrefcount".as_ptr());
+        let refcount = |obj: Obj| {
+            let args = korlang_py_list_new();
+            korlang_py_list_append(args, obj);
+            let count = korlang_py_call(getrefcount, args);
+            korlang_py_release(args);
+            let n = korlang_py_to_int(count);
+            korlang_py_release(count);
+            n
+        };
+        let x = korlang_py_from_str(c"x".as_ptr());
+        let args = korlang_py_list_new();
+        korlang_py_list_append(args, x);
+        let before = refcount(x);
+        assert!(korlang_py_call(boom, args).is_null());
+        assert_eq!(text(korlang_py_error()), "ValueError: bad x");
+        assert_eq!(refcount(x), before);
+        assert!(!korlang_py_exec(c"boom(1)".as_ptr()));
+        assert_eq!(text(korlang_py_error()), "ValueError: bad 1");
+        for obj in [boom, getrefcount, x, args] {
+            korlang_py_release(obj);
+        }
+    }
+}
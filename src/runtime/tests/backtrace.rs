@@ -0,0 +1,28 @@
+use std::ffi::CStr;
+use std::os::raw::c_char;
+
+// Links the runtime, which defines the functions below.
+use korlang_rt as _;
+
+extern "C" {
+    fn korlang_panic_backtrace() -> *mut c_char;
+    fn korlang_panic_free(s: *mut c_char);
+}
+
+/// Stands in for the Korlang method `Point.len()`.
+#[allow(non_snake_case)]
+#[inline(never)]
+#[no_mangle]
+fn _KN5Point3lenEZE() -> String {
+    let trace = unsafe { korlang_panic_backtrace() };
+    let text = unsafe { CStr::from_ptr(trace) }.to_string_lossy().into_owned();
+    unsafe { korlang_panic_free(trace) };
+    text
+}
+
+#[test]
+fn backtrace_frames_are_demangled() {
+    let trace = _KN5Point3lenEZE();
+    assert!(trace.contains("Point.len()"), "{}", trace);
+    assert!(!trace.contains("_KN5Point3lenEZE"), "{}", trace);
+}
@@ -49,6 +49,37 @@ mod tests {
         unsafe { korlang_gc_collect() };
     }
 
+    extern "C" {
+        fn korlang_list_new(elem_size: usize, capacity: usize) -> *mut u8;
+        fn korlang_list_push(list: *mut u8, elem: *const u8);
+        fn korlang_list_at(list: *mut u8, index: i64) -> *mut u8;
+        fn korlang_list_len(list: *const u8) -> i64;
+    }
+
+    #[test]
+    fn list_contents_survive_collections() {
+        let list = unsafe { korlang_list_new(8, 0) };
+        unsafe { korlang_gc_add_root(list) };
+        for v in 0..1_000i64 {
+            unsafe { korlang_list_push(list, &v as *const i64 as *const u8) };
+            if v % 100 == 0 {
+                unsafe { korlang_gc_collect() };
+            }
+        }
+        unsafe { korlang_gc_collect() };
+        assert_eq!(unsafe { korlang_list_len(list) }, 1_000);
+        for v in 0..1_000i64 {
+            assert_eq!(unsafe { *(korlang_list_at(list, v) as *const i64) }, v);
+        }
+        unsafe { korlang_gc_remove_root(list) };
+        for _ in 0..100 {
+            let garbage = unsafe { korlang_list_new(8, 16) };
+            unsafe { korlang_list_push(garbage, &7i64 as *const i64 as *const u8) };
+        }
+        unsafe { korlang_gc_collect() };
+        unsafe { korlang_gc_collect() };
+    }
+
     #[test]
     fn arc_tier3_reference_counting_stress() {
         for i in 0..10_000usize {
@@ -9,14 +9,21 @@ use std::process::{Command, Stdio};
 use std::thread;
 use std::time::Instant;
 
+use korlang_compiler::ast::{Item, Program};
 use korlang_compiler::codegen::Codegen;
+use korlang_compiler::coverage::SourceMap;
 use korlang_compiler::diag::{Diagnostic, DiagnosticLevel};
+use korlang_compiler::ffi::bindgen::generate_bindings_with;
+use korlang_compiler::ffi::cpp::CppTemplateInterop;
+use korlang_compiler::ffi::python::builtin_module;
+use korlang_compiler::ffi::rust::{crate_name, RustTraitMapper};
 use korlang_compiler::lexer::Lexer;
 use korlang_compiler::parser::Parser;
 use korlang_compiler::sema::Sema;
-use korlang_compiler::linker::{build_link_command, LinkerConfig, LtoMode};
+use korlang_compiler::linker::{build_link_command, prepare_static_archive, CrateType, LinkerConfig, LtoMode, Sanitizer};
+use korlang_compiler::mangle;
 use inkwell::context::Context;
-use inkwell::targets::{InitializationConfig, Target, TargetMachine, FileType};
+use inkwell::targets::{InitializationConfig, Target, TargetMachine, FileType, RelocMode};
 
 fn main() {
     let mut args: Vec<String> = env::args().collect();
@@ -47,10 +54,13 @@ fn main() {
         "build" => build(args, false, verbose, debug),
         "run" => build(args, true, verbose, debug),
         "new" => new_project(args),
-        "test" => run_tests(),
+        "test" => run_tests(args),
         "doc" => generate_docs(),
         "bootstrap" => bootstrap(),
         "repl" => repl(),
+        "demangle" => demangle(args),
+        "bindgen" => bindgen(args),
+        "ffi" => ffi(args),
         "--version" => {
             println!("Korlang Compiler v0.1.1");
             println!("Target: {}-{}", std::env::consts::OS, std::env::consts::ARCH);
@@ -74,24 +84,49 @@ fn print_help() {
     println!("    build <file>     Compile a Korlang file");
     println!("    run <file>       Compile and run a Korlang file");
     println!("    new <project>    Create a new Korlang project");
-    println!("    test             Run the test suite");
+    println!("    test [files]     Build the tests (--coverage: run them and report coverage)");
     println!("    doc              Generate documentation");
     println!("    bootstrap        Bootstrap the compiler");
     println!("    repl             Start interactive REPL");
+    println!("    demangle [sym]   Demangle symbols, or every symbol read from stdin");
+    println!("    bindgen <header.h> [-o out.kor]  Generate extern bindings for a C header");
+    println!("    ffi rust <file.kor> [-o dir]     Generate a Rust crate for a library's exports");
     println!("");
     println!("OPTIONS:");
     println!("    -o <output>      Output file name");
+    println!("    --crate-type <bin|staticlib|cdylib>  Build a program, or a C library and header of its @export functions");
     println!("    --static         Static linking");
     println!("    --lto            Link-time optimization");
     println!("    --thinlto        Thin LTO");
     println!("    --pgo-generate   Generate PGO profile");
     println!("    --pgo-use <file> Use PGO profile");
+    println!("    --release        Wrap on integer overflow instead of panicking");
+    println!("    -C overflow-checks[=on|off]  Panic on integer overflow (default unless --release)");
+    println!("    --sanitize <address|thread|undefined>  Instrument the program and link the sanitizer runtime");
+    println!("    --coverage       Count executed regions; run and test report them in .korlang/coverage");
     println!("    --verbose, -v    Verbose output");
     println!("    --debug, -d      Detailed debug logging");
     println!("    --version        Show version");
     println!("    --help, -h       Show this help");
 }
 
+/// Parses the value of `-C`; only `overflow-checks[=on|off]` is supported.
+fn parse_codegen_option(opt: &str) -> bool {
+    let (name, value) = opt.split_once('=').unwrap_or((opt, "on"));
+    if name != "overflow-checks" {
+        eprintln!("\x1b[31merror\x1b[0m: unknown codegen option: {}", name);
+        std::process::exit(1);
+    }
+    match value {
+        "on" | "yes" | "y" | "true" => true,
+        "off" | "no" | "n" | "false" => false,
+        other => {
+            eprintln!("\x1b[31merror\x1b[0m: invalid value for overflow-checks: {} (expected on or off)", other);
+            std::process::exit(1);
+        }
+    }
+}
+
 fn build(args: Vec<String>, run: bool, verbose: bool, debug: bool) {
     let start_time = Instant::now();
 
@@ -121,17 +156,28 @@ fn build(args: Vec<String>, run: bool, verbose: bool, debug: bool) {
         std::process::exit(1);
     }
     
-    let mut output = PathBuf::from("a.out");
+    let mut output = None;
+    let mut crate_type = CrateType::Bin;
     let mut static_link = false;
     let mut lto = None;
     let mut pgo_generate = false;
     let mut pgo_use: Option<PathBuf> = None;
+    let mut release = false;
+    let mut overflow_checks: Option<bool> = None;
+    let mut sanitizers: Vec<Sanitizer> = Vec::new();
+    let mut coverage = false;
 
     let mut i = 1;
     while i < build_args.len() {
         if build_args[i] == "-o" && i + 1 < build_args.len() {
-            output = PathBuf::from(&build_args[i + 1]);
+            output = Some(PathBuf::from(&build_args[i + 1]));
+            i += 2;
+        } else if build_args[i] == "--crate-type" && i + 1 < build_args.len() {
+            crate_type = parse_crate_type(&build_args[i + 1]);
             i += 2;
+        } else if let Some(kind) = build_args[i].strip_prefix("--crate-type=") {
+            crate_type = parse_crate_type(kind);
+            i += 1;
         } else if build_args[i] == "--static" {
             static_link = true;
             i += 1;
@@ -147,14 +193,44 @@ fn build(args: Vec<String>, run: bool, verbose: bool, debug: bool) {
         } else if build_args[i] == "--pgo-use" && i + 1 < build_args.len() {
             pgo_use = Some(PathBuf::from(&build_args[i + 1]));
             i += 2;
+        } else if build_args[i] == "--release" {
+            release = true;
+            i += 1;
+        } else if build_args[i] == "-C" && i + 1 < build_args.len() {
+            overflow_checks = Some(parse_codegen_option(&build_args[i + 1]));
+            i += 2;
+        } else if let Some(opt) = build_args[i].strip_prefix("-C") {
+            overflow_checks = Some(parse_codegen_option(opt));
+            i += 1;
+        } else if build_args[i] == "--sanitize" && i + 1 < build_args.len() {
+            parse_sanitizers(&build_args[i + 1], &mut sanitizers);
+            i += 2;
+        } else if let Some(names) = build_args[i].strip_prefix("--sanitize=") {
+            parse_sanitizers(names, &mut sanitizers);
+            i += 1;
+        } else if build_args[i] == "--coverage" {
+            coverage = true;
+            i += 1;
         } else {
             i += 1;
         }
     }
 
+    let overflow_checks = overflow_checks.unwrap_or(!release);
+    if sanitizers.contains(&Sanitizer::Address) && sanitizers.contains(&Sanitizer::Thread) {
+        eprintln!("\x1b[31merror\x1b[0m: the address and thread sanitizers cannot be combined");
+        std::process::exit(1);
+    }
+    if run && crate_type.is_library() {
+        eprintln!("\x1b[31merror\x1b[0m: a library cannot be run; build it with 'korlang build'");
+        std::process::exit(1);
+    }
+    let stem = input.file_stem().map(|s| s.to_string_lossy().to_string()).unwrap_or_else(|| "main".to_string());
+    let output = output.unwrap_or_else(|| crate_type.default_output(&stem));
+
     println!("    \x1b[32mCompiling\x1b[0m {} ...", input.display());
     
-    let src = match resolve_source_with_imports(&input) {
+    let (src, source_map) = match resolve_source_with_map(&input) {
         Ok(s) => s,
         Err(e) => {
             eprintln!("\x1b[31merror\x1b[0m: failed to resolve source {}: {}", input.display(), e);
@@ -173,8 +249,8 @@ fn build(args: Vec<String>, run: bool, verbose: bool, debug: bool) {
         None => "none",
     };
     let pgo_use_tag = pgo_use.as_ref().map(|p| p.display().to_string()).unwrap_or_else(|| "none".to_string());
-    let cache_key = format!("{}|input={}|static={}|lto={}|pgo-gen={}|pgo-use={}|run={}", 
-        hash_str(&src), input.display(), static_link, lto_tag, pgo_generate, pgo_use_tag, run);
+    let cache_key = format!("{}|input={}|static={}|lto={}|pgo-gen={}|pgo-use={}|overflow-checks={}|crate-type={:?}|sanitize={:?}|coverage={}|run={}", 
+        hash_str(&src), input.display(), static_link, lto_tag, pgo_generate, pgo_use_tag, overflow_checks, crate_type, sanitizers, coverage, run);
     let output_key = output.to_string_lossy().to_string();
     let cache_file = cache_dir.join(format!("{}.cache", hash_str(&output_key)));
     
@@ -183,8 +259,8 @@ fn build(args: Vec<String>, run: bool, verbose: bool, debug: bool) {
             if prev == cache_key {
                 if verbose { println!("Using incremental cache for {}", output.display()); }
                 if run {
-                    let run_status = run_cached_binary(&output, &run_args);
-                    std::process::exit(run_status.code().unwrap_or(0));
+                    let run_status = run_binary(&output, &run_args, coverage);
+                    std::process::exit(exit_code(&run_status));
                 } else {
                     println!("    \x1b[32mFinished\x1b[0m (cached) ready at {}", output.display());
                 }
@@ -221,15 +297,23 @@ fn build(args: Vec<String>, run: bool, verbose: bool, debug: bool) {
     
     // Semantic analysis phase
     if debug { println!("\x1b[34m[DEBUG]\x1b[0m Phase 3: Semantic analysis..."); }
-    if let Err(diags) = Sema::new().check_program(&program) {
-        print_diags("sema", &input, &diags);
-        std::process::exit(1);
+    match Sema::new().check_program(&program) {
+        Ok(warnings) => print_diags("sema", &input, &warnings),
+        Err(diags) => {
+            print_diags("sema", &input, &diags);
+            std::process::exit(1);
+        }
     }
 
     // Code generation phase
     if debug { println!("\x1b[34m[DEBUG]\x1b[0m Phase 4: Code generation..."); }
     let context = Context::create();
-    let codegen = Codegen::new(&context, "main");
+    let codegen = Codegen::new(&context, "main")
+        .with_overflow_checks(overflow_checks)
+        .with_source_name(&input.display().to_string())
+        .with_library(crate_type.is_library())
+        .with_sanitizers(&sanitizers)
+        .with_coverage(coverage.then_some(source_map));
     let module = match codegen.emit_program(&program) {
         Ok(m) => m,
         Err(diags) => {
@@ -243,14 +327,18 @@ fn build(args: Vec<String>, run: bool, verbose: bool, debug: bool) {
         std::process::exit(1);
     }
 
-    let runtime_lib = locate_runtime().unwrap_or_else(|| PathBuf::from("../../runtime/target/debug/libkorlang_rt.a"));
+    let runtime_lib = locate_sanitized_runtime(&sanitizers)
+        .or_else(locate_runtime)
+        .unwrap_or_else(|| PathBuf::from("../../runtime/target/debug/libkorlang_rt.a"));
     
     let mut extra_args = Vec::new();
     if static_link { extra_args.push("-static".to_string()); }
-    if cfg!(target_os = "linux") { extra_args.push("-no-pie".to_string()); }
+    if cfg!(target_os = "linux") && !crate_type.is_library() { extra_args.push("-no-pie".to_string()); }
 
     if debug { println!("\x1b[34m[DEBUG]\x1b[0m Phase 5: LLVM IR to object compilation..."); }
-    if !compile_ir_to_obj(&module, &out_obj) {
+    // Library code may end up in a shared object or a PIE host.
+    let reloc = if crate_type.is_library() { RelocMode::PIC } else { RelocMode::Default };
+    if !compile_ir_to_obj(&module, &out_obj, reloc, &sanitizers) {
         eprintln!("\x1b[31merror\x1b[0m: failed to compile LLVM IR to object file");
         std::process::exit(1);
     }
@@ -259,14 +347,30 @@ fn build(args: Vec<String>, run: bool, verbose: bool, debug: bool) {
         let _ = fs::create_dir_all(parent);
     }
 
-    let link = build_link_command(&out_obj, &LinkerConfig {
+    let link_config = LinkerConfig {
         output: output.clone(),
         runtime_lib,
         extra_args,
         lto,
         pgo_generate,
         pgo_use,
-    });
+        crate_type,
+        sanitizers,
+    };
+    if crate_type == CrateType::StaticLib {
+        if let Err(e) = prepare_static_archive(&link_config) {
+            eprintln!("\x1b[31merror\x1b[0m: failed to copy the runtime into {}: {}", output.display(), e);
+            std::process::exit(1);
+        }
+    }
+    if crate_type.is_library() {
+        let header = output.with_file_name(format!("{}.h", stem));
+        if let Err(e) = fs::write(&header, library_header(&stem, &program)) {
+            eprintln!("\x1b[31merror\x1b[0m: failed to write header {}: {}", header.display(), e);
+            std::process::exit(1);
+        }
+    }
+    let link = build_link_command(&out_obj, &link_config);
 
     if debug { println!("\x1b[34m[DEBUG]\x1b[0m Phase 6: Linking..."); }
     let link_result = Command::new(&link[0])
@@ -284,8 +388,8 @@ fn build(args: Vec<String>, run: bool, verbose: bool, debug: bool) {
                 println!("    \x1b[32mFinished\x1b[0m in {:.2}s", elapsed.as_secs_f32());
 
                 if run {
-                    let run_status = run_cached_binary(&output, &run_args);
-                    std::process::exit(run_status.code().unwrap_or(0));
+                    let run_status = run_binary(&output, &run_args, coverage);
+                    std::process::exit(exit_code(&run_status));
                 }
             } else {
                 eprintln!("\x1b[31merror\x1b[0m: linking failed");
@@ -337,6 +441,19 @@ fn resolve_run_target(output: &PathBuf) -> PathBuf {
     output.clone()
 }
 
+/// A program killed by a signal (a panic aborts) exits with 128 + the
+/// signal number, as in the shell.
+fn exit_code(status: &std::process::ExitStatus) -> i32 {
+    #[cfg(unix)]
+    {
+        use std::os::unix::process::ExitStatusExt;
+        if let Some(sig) = status.signal() {
+            return 128 + sig;
+        }
+    }
+    status.code().unwrap_or(1)
+}
+
 fn split_run_args(args: &[String]) -> (Vec<String>, Vec<String>) {
     if let Some(idx) = args.iter().position(|a| a == "--") {
         (args[..idx].to_vec(), args[idx + 1..].to_vec())
@@ -392,33 +509,142 @@ fn run_cached_binary(output: &PathBuf, run_args: &[String]) -> std::process::Exi
     status
 }
 
+/// Runs the program; with `--coverage`, into a fresh profile that is then
+/// reported on.
+fn run_binary(output: &PathBuf, run_args: &[String], coverage: bool) -> std::process::ExitStatus {
+    if !coverage {
+        return run_cached_binary(output, run_args);
+    }
+    start_coverage_profile();
+    let status = run_cached_binary(output, run_args);
+    coverage_report(std::slice::from_ref(output));
+    status
+}
+
+const COVERAGE_DIR: &str = ".korlang/coverage";
+
+/// Points `KORLANG_PROFILE_FILE` of the programs run next at an empty
+/// profile in `.korlang/coverage`.
+fn start_coverage_profile() {
+    let dir = PathBuf::from(COVERAGE_DIR);
+    let _ = fs::create_dir_all(&dir);
+    let profile = fs::canonicalize(&dir).unwrap_or(dir).join("coverage.proftext");
+    let _ = fs::remove_file(&profile);
+    env::set_var("KORLANG_PROFILE_FILE", &profile);
+}
+
+/// Merges the profile with `llvm-profdata` and writes the `llvm-cov`
+/// reports: a per-file summary (printed, and `report.txt`), annotated
+/// source (`coverage.txt`), `lcov.info` and `html/index.html`.
+fn coverage_report(binaries: &[PathBuf]) {
+    if let Err(e) = write_coverage_reports(binaries) {
+        eprintln!("\x1b[33mwarning\x1b[0m: no coverage report: {}", e);
+    }
+}
+
+fn write_coverage_reports(binaries: &[PathBuf]) -> Result<(), String> {
+    let dir = PathBuf::from(COVERAGE_DIR);
+    let profile = dir.join("coverage.proftext");
+    let profdata = dir.join("coverage.profdata");
+    if !profile.exists() {
+        return Err("the program wrote no coverage profile".to_string());
+    }
+    let profdata_tool = llvm_tool("llvm-profdata")?;
+    llvm_tool_output(&profdata_tool, &["merge".into(), "-o".into(), profdata.display().to_string(), profile.display().to_string()])?;
+
+    let cov = llvm_tool("llvm-cov")?;
+    let mut objects = Vec::new();
+    for (i, b) in binaries.iter().enumerate() {
+        if i > 0 { objects.push("-object".to_string()); }
+        objects.push(b.display().to_string());
+    }
+    objects.push(format!("-instr-profile={}", profdata.display()));
+    let with = |args: &[&str]| -> Vec<String> { args.iter().map(|a| a.to_string()).chain(objects.iter().cloned()).collect() };
+
+    let summary = llvm_tool_output(&cov, &with(&["report"]))?;
+    print!("{}", summary);
+    let annotated = llvm_tool_output(&cov, &with(&["show", "-format=text"]))?;
+    let lcov = llvm_tool_output(&cov, &with(&["export", "-format=lcov"]))?;
+    let html_dir = format!("-output-dir={}", dir.join("html").display());
+    llvm_tool_output(&cov, &with(&["show", "-format=html", &html_dir]))?;
+    for (name, contents) in [("report.txt", summary), ("coverage.txt", annotated), ("lcov.info", lcov)] {
+        fs::write(dir.join(name), contents).map_err(|e| format!("{}: {}", dir.join(name).display(), e))?;
+    }
+    println!("    \x1b[32mCoverage\x1b[0m written to {}/ (report.txt, coverage.txt, lcov.info, html/index.html)", COVERAGE_DIR);
+    Ok(())
+}
+
+/// `$LLVM_PROFDATA` or `$LLVM_COV`, else the tool on `PATH`, possibly with
+/// a version suffix.
+fn llvm_tool(name: &str) -> Result<String, String> {
+    let env_name = name.to_uppercase().replace('-', "_");
+    let mut candidates: Vec<String> = env::var(&env_name).into_iter().collect();
+    candidates.push(name.to_string());
+    candidates.extend(["18", "17", "16", "15", "14"].iter().map(|v| format!("{}-{}", name, v)));
+    candidates
+        .into_iter()
+        .find(|c| Command::new(c).arg("--help").output().is_ok())
+        .ok_or_else(|| format!("{} not found; set {} to its path", name, env_name))
+}
+
+fn llvm_tool_output(tool: &str, args: &[String]) -> Result<String, String> {
+    let out = Command::new(tool).args(args).output().map_err(|e| format!("{}: {}", tool, e))?;
+    if !out.status.success() {
+        return Err(format!("{} {} failed: {}", tool, args.first().map(String::as_str).unwrap_or(""), String::from_utf8_lossy(&out.stderr).trim()));
+    }
+    Ok(String::from_utf8_lossy(&out.stdout).to_string())
+}
+
 fn resolve_source_with_imports(input: &Path) -> Result<String, String> {
+    resolve_source_with_map(input).map(|(src, _)| src)
+}
+
+/// The program with its imports inlined, and the file and line each of its
+/// lines came from.
+fn resolve_source_with_map(input: &Path) -> Result<(String, SourceMap), String> {
     let mut seen = HashSet::new();
     let mut out = String::new();
+    let mut map = SourceMap::new();
     let project_root = find_project_root_from(input.parent().unwrap_or_else(|| Path::new(".")));
-    collect_source_recursive(input, project_root.as_deref(), &mut seen, &mut out)?;
-    Ok(out)
+    collect_source_recursive(input, project_root.as_deref(), &mut seen, &mut out, &mut map)?;
+    Ok((out, map))
 }
 
-fn collect_source_recursive(file: &Path, project_root: Option<&Path>, seen: &mut HashSet<PathBuf>, out: &mut String) -> Result<(), String> {
+fn collect_source_recursive(file: &Path, project_root: Option<&Path>, seen: &mut HashSet<PathBuf>, out: &mut String, map: &mut SourceMap) -> Result<(), String> {
     let canonical = fs::canonicalize(file).map_err(|e| format!("{}: {}", file.display(), e))?;
     if !seen.insert(canonical.clone()) { return Ok(()); }
     let src = fs::read_to_string(&canonical).map_err(|e| format!("{}: {}", canonical.display(), e))?;
     let base_dir = canonical.parent().unwrap_or_else(|| Path::new("."));
 
-    for line in src.lines() {
+    let path = canonical.display().to_string();
+    for (index, line) in src.lines().enumerate() {
         let trimmed = line.trim();
         if trimmed.starts_with("module ") { continue; }
         if let Some(mod_name) = parse_import(trimmed) {
-            let dep = resolve_import_path(base_dir, project_root, &mod_name)
-                .ok_or_else(|| format!("import '{}' not found from {}", mod_name, canonical.display()))?;
-            collect_source_recursive(&dep, project_root, seen, out)?;
+            match resolve_import_path(base_dir, project_root, &mod_name) {
+                Some(dep) => collect_source_recursive(&dep, project_root, seen, out, map)?,
+                None => {
+                    let builtin = builtin_module(&mod_name)
+                        .ok_or_else(|| format!("import '{}' not found from {}", mod_name, canonical.display()))?;
+                    if seen.insert(PathBuf::from(format!("<builtin>/{}", mod_name))) {
+                        for line in builtin.lines().filter(|l| !l.trim().starts_with("module ")) {
+                            out.push_str(line);
+                            out.push('\n');
+                            map.push_generated();
+                        }
+                        out.push('\n');
+                        map.push_generated();
+                    }
+                }
+            }
             continue;
         }
         out.push_str(line);
         out.push('\n');
+        map.push_line(&path, index + 1);
     }
     out.push('\n');
+    map.push_generated();
     Ok(())
 }
 
@@ -467,7 +693,7 @@ fn find_project_root_from(start: &Path) -> Option<PathBuf> {
     None
 }
 
-fn compile_ir_to_obj(module: &inkwell::module::Module, obj: &PathBuf) -> bool {
+fn compile_ir_to_obj(module: &inkwell::module::Module, obj: &PathBuf, reloc: RelocMode, sanitizers: &[Sanitizer]) -> bool {
     Target::initialize_all(&InitializationConfig::default());
     let triple = TargetMachine::get_default_triple();
     module.set_triple(&triple);
@@ -477,17 +703,74 @@ fn compile_ir_to_obj(module: &inkwell::module::Module, obj: &PathBuf) -> bool {
     };
     let machine = match target.create_target_machine(
         &triple, "generic", "", inkwell::OptimizationLevel::Default,
-        inkwell::targets::RelocMode::Default, inkwell::targets::CodeModel::Default,
+        reloc, inkwell::targets::CodeModel::Default,
     ) {
         Some(m) => m,
         None => return false,
     };
+    if let Err(e) = korlang_compiler::backend::sanitize::instrument(module, &machine, sanitizers) {
+        eprintln!("\x1b[31merror\x1b[0m: {}", e);
+        return false;
+    }
     machine.write_to_file(module, FileType::Object, obj).is_ok()
 }
 
+/// Adds each name of a comma-separated `--sanitize` value; the flag may
+/// also be repeated.
+fn parse_sanitizers(names: &str, sanitizers: &mut Vec<Sanitizer>) {
+    for name in names.split(',') {
+        match Sanitizer::parse(name) {
+            Some(s) if !sanitizers.contains(&s) => sanitizers.push(s),
+            Some(_) => {}
+            None => {
+                eprintln!("\x1b[31merror\x1b[0m: unknown sanitizer '{}' (expected address, thread or undefined)", name);
+                std::process::exit(1);
+            }
+        }
+    }
+}
+
+fn parse_crate_type(s: &str) -> CrateType {
+    CrateType::parse(s).unwrap_or_else(|| {
+        eprintln!("\x1b[31merror\x1b[0m: unknown crate type '{}' (expected bin, staticlib or cdylib)", s);
+        std::process::exit(1);
+    })
+}
+
+/// The C header for a library's `@export` functions, e.g. `geom.h` with the
+/// guard `GEOM_H`.
+fn library_header(stem: &str, program: &Program) -> String {
+    let mut structs = Vec::new();
+    let mut exports = Vec::new();
+    for item in &program.items {
+        match item {
+            Item::Struct(s) => structs.push(s.clone()),
+            Item::Fun(f) if f.export => exports.push(f.clone()),
+            _ => {}
+        }
+    }
+    let guard: String = stem.chars().map(|c| if c.is_ascii_alphanumeric() { c.to_ascii_uppercase() } else { '_' }).collect();
+    CppTemplateInterop::new().generate_header(&format!("{}_H", guard), &structs, &exports)
+}
+
 fn locate_runtime() -> Option<PathBuf> {
     let lib_name = if cfg!(windows) { "korlang_rt.lib" } else { "libkorlang_rt.a" };
-    
+    locate_lib(lib_name)
+}
+
+/// The runtime built by `scripts/build_sanitized_runtime.sh`, so races and
+/// memory errors inside it (channels, lock-free stacks, `korlang_free`) are
+/// reported too. Without it, only Korlang code is instrumented.
+fn locate_sanitized_runtime(sanitizers: &[Sanitizer]) -> Option<PathBuf> {
+    let san = sanitizers.iter().find(|s| **s != Sanitizer::Undefined)?;
+    let lib = locate_lib(&format!("libkorlang_rt_{}.a", san.name()));
+    if lib.is_none() {
+        eprintln!("\x1b[33mwarning\x1b[0m: no libkorlang_rt_{}.a found; the runtime is not instrumented", san.name());
+    }
+    lib
+}
+
+fn locate_lib(lib_name: &str) -> Option<PathBuf> {
     if let Ok(home) = env::var("KORLANG_HOME") {
         let p = PathBuf::from(home).join("lib").join(lib_name);
         if p.exists() { return Some(p); }
@@ -575,21 +858,46 @@ fn parse_project_flavor(args: &[String]) -> ProjectFlavor {
     ProjectFlavor::App
 }
 
-fn run_tests() {
+/// Builds each test file (default `examples/hello.kor`) into `tests/bin`.
+/// With `--coverage` the tests are also run, all counting into one profile,
+/// and the reports cover every test binary.
+fn run_tests(args: Vec<String>) {
     println!("Running Korlang tests...");
+    let coverage = args.iter().any(|a| a == "--coverage");
+    let mut files: Vec<&String> = args.iter().filter(|a| !a.starts_with('-')).collect();
+    let default = "examples/hello.kor".to_string();
+    if files.is_empty() { files.push(&default); }
     let exe = env::current_exe().unwrap_or_else(|_| PathBuf::from("korlang"));
     let build_out = PathBuf::from("tests/bin");
     let _ = fs::create_dir_all(&build_out);
-    let mut cmd = Command::new(&exe);
-    cmd.arg("build").arg("examples/hello.kor").arg("-o").arg(build_out.join("hello"));
-    let status = match cmd.status() {
-        Ok(s) => s,
-        Err(e) => {
-            eprintln!("\x1b[31merror\x1b[0m: failed to execute korlang for tests: {}", e);
-            std::process::exit(1);
+    if coverage { start_coverage_profile(); }
+    let mut binaries = Vec::new();
+    let mut failed = 0;
+    for file in files {
+        let stem = Path::new(file).file_stem().map(|s| s.to_string_lossy().to_string()).unwrap_or_else(|| "test".to_string());
+        let binary = build_out.join(&stem);
+        let mut cmd = Command::new(&exe);
+        cmd.arg("build").arg(file).arg("-o").arg(&binary);
+        if coverage { cmd.arg("--coverage"); }
+        let status = match cmd.status() {
+            Ok(s) => s,
+            Err(e) => {
+                eprintln!("\x1b[31merror\x1b[0m: failed to execute korlang for tests: {}", e);
+                std::process::exit(1);
+            }
+        };
+        if !status.success() { std::process::exit(status.code().unwrap_or(1)); }
+        if coverage {
+            let status = run_cached_binary(&binary, &[]);
+            if !status.success() {
+                eprintln!("\x1b[31merror\x1b[0m: test {} failed with exit code {}", file, exit_code(&status));
+                failed += 1;
+            }
         }
-    };
-    if !status.success() { std::process::exit(status.code().unwrap_or(1)); }
+        binaries.push(binary);
+    }
+    if coverage { coverage_report(&binaries); }
+    if failed > 0 { std::process::exit(1); }
     println!("    \x1b[32mPassed\x1b[0m all tests");
 }
 
@@ -608,6 +916,134 @@ fn generate_docs() {
     println!("    \x1b[32mGenerated\x1b[0m docs at {}/index.html", out_dir.display());
 }
 
+/// Prints each symbol given on the command line in its source form, or
+/// copies stdin to stdout with every Korlang symbol demangled, so a backtrace
+/// can be piped through it.
+fn demangle(args: Vec<String>) {
+    let symbols: Vec<&String> = args.iter().filter(|a| !a.starts_with('-')).collect();
+    if !symbols.is_empty() {
+        for sym in symbols {
+            println!("{}", mangle::demangle(sym).unwrap_or_else(|| sym.clone()));
+        }
+        return;
+    }
+    let stdout = io::stdout();
+    let mut out = stdout.lock();
+    for line in io::stdin().lock().lines() {
+        let Ok(line) = line else { break };
+        if writeln!(out, "{}", mangle::demangle_text(&line)).is_err() {
+            break;
+        }
+    }
+}
+
+/// `korlang bindgen header.h [-o out.kor]` prints the bindings, or writes
+/// them to the `-o` file. `#include "..."` is resolved next to the header.
+fn bindgen(args: Vec<String>) {
+    let mut header = None;
+    let mut output = None;
+    let mut i = 0;
+    while i < args.len() {
+        if args[i] == "-o" && i + 1 < args.len() {
+            output = Some(PathBuf::from(&args[i + 1]));
+            i += 2;
+        } else {
+            header = Some(PathBuf::from(&args[i]));
+            i += 1;
+        }
+    }
+    let Some(header) = header else {
+        eprintln!("korlang bindgen <header.h> [-o out.kor]");
+        std::process::exit(1);
+    };
+    let src = match fs::read_to_string(&header) {
+        Ok(s) => s,
+        Err(e) => {
+            eprintln!("\x1b[31merror\x1b[0m: failed to read {}: {}", header.display(), e);
+            std::process::exit(1);
+        }
+    };
+    let name = header.file_name().map(|n| n.to_string_lossy().to_string()).unwrap_or_default();
+    let dir = header.parent().map(Path::to_path_buf).unwrap_or_default();
+    let bindings = generate_bindings_with(&src, &name, &mut |file| fs::read_to_string(dir.join(file)).ok());
+    match output {
+        Some(path) => {
+            if let Err(e) = fs::write(&path, bindings) {
+                eprintln!("\x1b[31merror\x1b[0m: failed to write {}: {}", path.display(), e);
+                std::process::exit(1);
+            }
+            println!("    \x1b[32mGenerated\x1b[0m {}", path.display());
+        }
+        None => print!("{}", bindings),
+    }
+}
+
+fn ffi(args: Vec<String>) {
+    let usage = "korlang ffi rust <file.kor> [-o dir]";
+    if args.first().map(String::as_str) != Some("rust") {
+        eprintln!("{}", usage);
+        std::process::exit(1);
+    }
+    let mut input = None;
+    let mut output = None;
+    let mut i = 1;
+    while i < args.len() {
+        if args[i] == "-o" && i + 1 < args.len() {
+            output = Some(PathBuf::from(&args[i + 1]));
+            i += 2;
+        } else {
+            input = Some(PathBuf::from(&args[i]));
+            i += 1;
+        }
+    }
+    let Some(input) = input else {
+        eprintln!("{}", usage);
+        std::process::exit(1);
+    };
+    let src = match resolve_source_with_imports(&input) {
+        Ok(s) => s,
+        Err(e) => {
+            eprintln!("\x1b[31merror\x1b[0m: failed to resolve source {}: {}", input.display(), e);
+            std::process::exit(1);
+        }
+    };
+    let tokens = match Lexer::new(&src).tokenize() {
+        Ok(t) => t,
+        Err(diags) => {
+            print_diags("lexer", &input, &diags);
+            std::process::exit(1);
+        }
+    };
+    let program = match Parser::new(tokens).parse_program() {
+        Ok(p) => p,
+        Err(diags) => {
+            print_diags("parser", &input, &diags);
+            std::process::exit(1);
+        }
+    };
+    match Sema::new().check_program(&program) {
+        Ok(warnings) => print_diags("sema", &input, &warnings),
+        Err(diags) => {
+            print_diags("sema", &input, &diags);
+            std::process::exit(1);
+        }
+    }
+
+    // The crate links lib<stem>.a, the default output of
+    // `korlang build <file.kor> --crate-type=staticlib`.
+    let stem = input.file_stem().map(|s| s.to_string_lossy().to_string()).unwrap_or_else(|| "main".to_string());
+    let dir = output.unwrap_or_else(|| PathBuf::from(format!("{}-rs", stem)));
+    for (path, contents) in RustTraitMapper::new(&program).generate_crate(&crate_name(&stem), &stem) {
+        let path = dir.join(path);
+        let written = path.parent().map_or(Ok(()), fs::create_dir_all).and_then(|_| fs::write(&path, contents));
+        if let Err(e) = written {
+            eprintln!("\x1b[31merror\x1b[0m: failed to write {}: {}", path.display(), e);
+            std::process::exit(1);
+        }
+    }
+    println!("    \x1b[32mGenerated\x1b[0m {}", dir.display());
+}
+
 fn repl() {
     println!("Korlang REPL (type :help, :quit)");
     // ... basic REPL same as before but with colors
@@ -0,0 +1,46 @@
+use std::path::PathBuf;
+use std::process::Command;
+
+const PROGRAM: &str = "fun bump(x: Int) -> Int {\n    x + 1\n}\n\nfun main() -> Int {\n    println(bump(9223372036854775807));\n    println(bump(1) / bump(-1));\n    0\n}\n";
+
+/// Runs `PROGRAM` with `korlang run` and `flags` in a fresh directory;
+/// returns the exit code, stdout and stderr.
+fn run(name: &str, flags: &[&str]) -> (Option<i32>, String, String) {
+    let dir: PathBuf = std::env::temp_dir().join(format!("korlang-overflow-{}-{}", std::process::id(), name));
+    std::fs::create_dir_all(&dir).expect("temp dir");
+    std::fs::write(dir.join("ovf.kor"), PROGRAM).expect("write program");
+    let out = Command::new(env!("CARGO_BIN_EXE_korlang"))
+        .arg("run")
+        .arg("ovf.kor")
+        .args(flags)
+        .current_dir(&dir)
+        .output()
+        .expect("korlang run");
+    let _ = std::fs::remove_dir_all(&dir);
+    (out.status.code(), String::from_utf8_lossy(&out.stdout).into_owned(), String::from_utf8_lossy(&out.stderr).into_owned())
+}
+
+#[test]
+fn overflow_panics_by_default() {
+    let (code, stdout, stderr) = run("debug", &[]);
+    assert_ne!(code, Some(0));
+    assert!(!stdout.contains("-9223372036854775808"), "{}", stdout);
+    assert!(stderr.contains("attempt to add with overflow at ovf.kor:2:5"), "{}", stderr);
+}
+
+#[test]
+fn release_wraps_but_still_checks_division_by_zero() {
+    let (code, stdout, stderr) = run("release", &["--release"]);
+    assert_ne!(code, Some(0));
+    assert!(stdout.contains("-9223372036854775808"), "{}", stdout);
+    assert!(stderr.contains("attempt to divide by zero at ovf.kor:7:13"), "{}", stderr);
+}
+
+#[test]
+fn codegen_option_overrides_the_profile() {
+    let (_, stdout, _) = run("off", &["-C", "overflow-checks=off"]);
+    assert!(stdout.contains("-9223372036854775808"), "{}", stdout);
+    let (_, stdout, stderr) = run("on", &["--release", "-Coverflow-checks"]);
+    assert!(!stdout.contains("-9223372036854775808"), "{}", stdout);
+    assert!(stderr.contains("attempt to add with overflow at ovf.kor:2:5"), "{}", stderr);
+}
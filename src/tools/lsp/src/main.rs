@@ -1,3 +1,4 @@
+use korlang_compiler::diag::DiagnosticLevel;
 use korlang_compiler::lexer::Lexer;
 use korlang_compiler::parser::Parser;
 use korlang_compiler::sema::Sema;
@@ -133,18 +134,25 @@ impl Backend {
         let mut diags = Vec::new();
         if let Ok(tokens) = Lexer::new(text).tokenize() {
             if let Ok(program) = Parser::new(tokens).parse_program() {
-                if let Err(errs) = Sema::new().check_program(&program) {
-                    for e in errs {
-                        diags.push(Diagnostic {
-                            range: Range::new(
-                                Position::new((e.span.start.line - 1) as u32, (e.span.start.column - 1) as u32),
-                                Position::new((e.span.end.line - 1) as u32, (e.span.end.column - 1) as u32),
-                            ),
-                            severity: Some(DiagnosticSeverity::ERROR),
-                            message: e.message,
-                            ..Default::default()
-                        });
-                    }
+                let reported = match Sema::new().check_program(&program) {
+                    Ok(warnings) => warnings,
+                    Err(errs) => errs,
+                };
+                for e in reported {
+                    let severity = match e.level {
+                        DiagnosticLevel::Warning => DiagnosticSeverity::WARNING,
+                        DiagnosticLevel::Note => DiagnosticSeverity::INFORMATION,
+                        DiagnosticLevel::Error | DiagnosticLevel::Bug => DiagnosticSeverity::ERROR,
+                    };
+                    diags.push(Diagnostic {
+                        range: Range::new(
+                            Position::new((e.span.start.line - 1) as u32, (e.span.start.column - 1) as u32),
+                            Position::new((e.span.end.line - 1) as u32, (e.span.end.column - 1) as u32),
+                        ),
+                        severity: Some(severity),
+                        message: e.message,
+                        ..Default::default()
+                    });
                 }
             }
         }
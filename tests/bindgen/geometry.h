@@ -0,0 +1,99 @@
+/* geometry.h - a small C API exercising `korlang bindgen`. */
+#ifndef GEOMETRY_H
+#define GEOMETRY_H
+
+#include <stddef.h>
+#include <stdint.h>
+#include <stdbool.h>
+#include "geometry_types.h"
+
+#ifdef __cplusplus
+extern "C" {
+#endif
+
+#define GEOM_VERSION 0x010203
+#define GEOM_NAME_MAX 32
+#define GEOM_FLAGS_ALL (GEOM_FLAG_A | GEOM_FLAG_B)
+#define GEOM_FLAG_A (1u << 0)
+#define GEOM_FLAG_B (1u << 1)
+#define GEOM_ORIGIN_X 0
+#define GEOM_ERROR (-1)
+#define GEOM_SQUARE(x) ((x) * (x))
+#define GEOM_PI 3.14159
+
+typedef enum geom_status {
+    GEOM_OK = 0,
+    GEOM_EINVAL = -22,
+    GEOM_ENOMEM,
+    GEOM_LAST = GEOM_ENOMEM + 10
+} geom_status;
+
+enum geom_log_level { GEOM_LOG_DEBUG, GEOM_LOG_INFO, GEOM_LOG_WARN = 1 << 3 };
+
+typedef struct geom_point {
+    double x;
+    double y;
+} geom_point;
+
+typedef struct {
+    geom_point *from;
+    struct geom_point *to;
+    float weight;
+    bool closed;
+    unsigned char tag;
+    const char *label;
+    struct geom_shape *owner;
+} geom_segment;
+
+struct geom_shape {
+    char name[GEOM_NAME_MAX];
+    size_t count;
+};
+
+struct geom_flags {
+    unsigned int visible : 1;
+    unsigned int dirty : 1;
+};
+
+typedef struct geom_box {
+    geom_point min;
+    geom_point max;
+} geom_box;
+
+typedef union geom_value {
+    int64_t i;
+    double f;
+} geom_value;
+
+typedef struct geom_context geom_context;
+typedef int (*geom_visitor)(const geom_point *point, void *user_data);
+
+GEOM_API geom_point *geom_point_new(double x, double y);
+GEOM_API geom_status geom_point_free(geom_point *point);
+GEOM_API geom_point *geom_translate GEOM_ARGS((geom_point *point, geom_coord_t dx, double dy));
+GEOM_API double geom_segment_length(const geom_segment *segment);
+GEOM_API geom_context *geom_context_open(const char *path, geom_flags_t flags);
+GEOM_API void geom_context_close(geom_context *ctx);
+GEOM_API size_t geom_names(geom_context *ctx, char **names, size_t capacity);
+GEOM_API int geom_read(geom_context *, unsigned char *buf, unsigned long len);
+GEOM_API int geom_visit(geom_context *ctx, geom_visitor visit, void *user_data);
+GEOM_API void geom_log(enum geom_log_level level, const char *format, ...);
+GEOM_API unsigned int geom_version(void);
+GEOM_API int geom_set_type(geom_context *ctx, int type, short in);
+GEOM_API geom_box geom_bounds(const geom_context *ctx);
+GEOM_API double geom_shape_area(struct geom_shape *shape);
+GEOM_API int32_t geom_sum(const int32_t values[], size_t n);
+
+#ifdef GEOM_ENABLE_EXPERIMENTAL
+GEOM_API int geom_experimental(void);
+#endif
+
+static inline double geom_dot(geom_point a, geom_point b) {
+    return a.x * b.x + a.y * b.y;
+}
+
+#ifdef __cplusplus
+}
+#endif
+
+#endif /* GEOMETRY_H */
@@ -0,0 +1,28 @@
+/* geometry_types.h - included by geometry.h. */
+#ifndef GEOMETRY_TYPES_H
+#define GEOMETRY_TYPES_H
+
+#if defined(_WIN32) && !defined(GEOM_STATIC)
+#  define GEOM_API __declspec(dllimport)
+#elif defined(__GNUC__) && __GNUC__ >= 4
+#  define GEOM_API __attribute__((visibility("default")))
+#else
+#  define GEOM_API
+#endif
+
+/* zlib-style prototype wrapper */
+#ifdef GEOM_NO_PROTOTYPES
+#  define GEOM_ARGS(args) ()
+#else
+#  define GEOM_ARGS(args) args
+#endif
+
+#if __SIZEOF_POINTER__ == 8
+typedef long long geom_coord_t;
+#else
+typedef int geom_coord_t;
+#endif
+
+typedef unsigned int geom_flags_t;
+
+#endif /* GEOMETRY_TYPES_H */